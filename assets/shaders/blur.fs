@@ -0,0 +1,31 @@
+#version 330
+
+// Gaussian blur for `BlurBackground`, drawn over raylib's default vertex shader.
+
+in vec2 fragTexCoord;
+in vec4 fragColor;
+
+uniform sampler2D texture0;
+uniform vec4 colDiffuse;
+
+// Blur radius in pixels
+uniform float strength;
+// Size of the sampled texture in pixels
+uniform vec2 resolution;
+
+out vec4 finalColor;
+
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main()
+{
+    vec2 texel = strength / 4.0 / resolution;
+    vec4 sum = vec4(0.0);
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            float weight = weights[abs(x)] * weights[abs(y)];
+            sum += texture(texture0, fragTexCoord + vec2(x, y) * texel) * weight;
+        }
+    }
+    finalColor = sum * colDiffuse * fragColor;
+}
@@ -0,0 +1,262 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{Player, Sprite, Velocity};
+use crate::input::{Action, InputState};
+use crate::resources::{DamageEvent, Time};
+use crate::utils::HashMap;
+
+pub const IDLE: &str = "idle";
+pub const WALK: &str = "walk";
+pub const RUN: &str = "run";
+pub const HIT: &str = "hit";
+
+/// Slower than this counts as standing still, so leftover drift doesn't read as walking.
+const IDLE_SPEED: f32 = 1.0;
+
+/// Opacity is snapped to this many steps, so a slow pulse only writes the sprite, and
+/// redraws its layer, a few times a second instead of every tick.
+const OPACITY_STEPS: f32 = 16.0;
+
+/// A clip without textures: the sprite takes `color` and its opacity pulses `rate` times a
+/// second, down to `1 - depth`.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationClip {
+    pub color: Color,
+    pub rate: f32,
+    pub depth: f32,
+    /// Length of one-shot clips, looping clips have none.
+    pub duration: Option<f32>,
+}
+
+impl AnimationClip {
+    pub fn looping(color: Color, rate: f32, depth: f32) -> Self {
+        Self {
+            color,
+            rate,
+            depth,
+            duration: None,
+        }
+    }
+
+    pub fn opacity(&self, elapsed: f32) -> f32 {
+        let wave = 0.5 - 0.5 * (elapsed * self.rate * std::f32::consts::TAU).cos();
+        1.0 - self.depth * wave
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct AnimationState {
+    pub clips: HashMap<&'static str, AnimationClip>,
+    pub current: &'static str,
+    /// Seconds since `current` started, only reset when the state changes.
+    pub elapsed: f32,
+    /// One-shot state playing over the movement one, dropped once its clip ends.
+    pub one_shot: Option<&'static str>,
+    /// Facing left, for clips with frames to mirror.
+    pub flip_x: bool,
+}
+
+impl AnimationState {
+    pub fn new(clips: impl IntoIterator<Item = (&'static str, AnimationClip)>) -> Self {
+        Self {
+            clips: clips.into_iter().collect(),
+            current: IDLE,
+            elapsed: 0.0,
+            one_shot: None,
+            flip_x: false,
+        }
+    }
+
+    /// The player's clips, colored after the player's red.
+    pub fn player() -> Self {
+        Self::new([
+            (IDLE, AnimationClip::looping(Color::RED, 0.5, 0.15)),
+            (WALK, AnimationClip::looping(Color::RED, 2.0, 0.25)),
+            (RUN, AnimationClip::looping(Color::ORANGE, 4.0, 0.35)),
+            (
+                HIT,
+                AnimationClip {
+                    color: Color::WHITE,
+                    rate: 10.0,
+                    depth: 0.8,
+                    duration: Some(0.3),
+                },
+            ),
+        ])
+    }
+
+    pub fn clip(&self) -> Option<&AnimationClip> {
+        self.clips.get(self.current)
+    }
+
+    /// Plays `state` from the start on top of the movement state.
+    pub fn play_once(&mut self, state: &'static str) {
+        self.one_shot = Some(state);
+        self.current = state;
+        self.elapsed = 0.0;
+    }
+
+    /// Advances by `delta` and switches to `movement` unless a one-shot is still playing.
+    /// Returns whether the state changed, staying in the same state never restarts it.
+    pub fn update(&mut self, movement: &'static str, delta: f32) -> bool {
+        self.elapsed += delta;
+        if self.one_shot.is_some() {
+            let duration = self.clip().and_then(|clip| clip.duration).unwrap_or(0.0);
+            if self.elapsed < duration {
+                return false;
+            }
+            self.one_shot = None;
+        }
+        if self.current == movement {
+            return false;
+        }
+        self.current = movement;
+        self.elapsed = 0.0;
+        true
+    }
+}
+
+/// Movement state for a velocity, sprinting only makes a difference while moving.
+pub fn movement_state(velocity: Vector2, sprinting: bool) -> &'static str {
+    let speed = (velocity.x * velocity.x + velocity.y * velocity.y).sqrt();
+    match (speed < IDLE_SPEED, sprinting) {
+        (true, _) => IDLE,
+        (false, true) => RUN,
+        (false, false) => WALK,
+    }
+}
+
+/// Picks the player's state from its velocity, plays `HIT` when it takes damage and drives
+/// the sprite from the current clip. Facing only flips on horizontal movement, so moving
+/// straight up or down keeps the last one.
+pub fn player_animation_system(
+    time: Res<Time>,
+    input: Res<InputState>,
+    mut ev_damage: MessageReader<DamageEvent>,
+    player: Single<(Entity, &Velocity, &mut AnimationState, &mut Sprite), With<Player>>,
+) {
+    let (entity, velocity, mut state, mut sprite) = player.into_inner();
+    if ev_damage.read().any(|ev| ev.target == entity) {
+        state.play_once(HIT);
+    }
+    if velocity.x != 0.0 {
+        state.flip_x = velocity.x < 0.0;
+    }
+
+    let movement = movement_state(velocity.0, input.is_held(Action::Sprint));
+    state.update(movement, time.delta());
+    let Some(clip) = state.clip().copied() else {
+        return;
+    };
+    let opacity = (clip.opacity(state.elapsed) * OPACITY_STEPS).round() / OPACITY_STEPS;
+    let rgba = |color: Color| (color.r, color.g, color.b, color.a);
+    // Only touch the sprite when it changes, a write redraws its layer
+    if rgba(sprite.color) != rgba(clip.color) || sprite.opacity != opacity {
+        sprite.color = clip.color;
+        sprite.opacity = opacity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_movement_state_table() {
+        let cases = [
+            ((0.0, 0.0), false, IDLE),
+            ((0.0, 0.0), true, IDLE),
+            ((0.5, -0.5), false, IDLE),
+            ((300.0, 0.0), false, WALK),
+            ((0.0, -300.0), false, WALK),
+            ((-900.0, 0.0), true, RUN),
+            ((212.0, 212.0), true, RUN),
+        ];
+        for ((x, y), sprinting, expected) in cases {
+            assert_eq!(
+                movement_state(Vector2::new(x, y), sprinting),
+                expected,
+                "({x}, {y}) sprinting: {sprinting}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_state_only_restarts_on_change() {
+        let mut state = AnimationState::player();
+        assert!(!state.update(IDLE, 0.25));
+        assert_eq!(state.elapsed, 0.25);
+        assert!(state.update(WALK, 0.25));
+        assert_eq!((state.current, state.elapsed), (WALK, 0.0));
+        assert!(!state.update(WALK, 0.25));
+        assert_eq!(state.elapsed, 0.25);
+    }
+
+    #[test]
+    fn test_one_shot_returns_to_movement_state() {
+        let mut state = AnimationState::player();
+        state.update(WALK, 0.0);
+        state.play_once(HIT);
+        // Movement changes underneath don't cut the hit short
+        assert!(!state.update(RUN, 0.25));
+        assert_eq!(state.current, HIT);
+        // Once the 0.3s clip is over, whatever the movement is now takes over
+        assert!(state.update(RUN, 0.125));
+        assert_eq!(
+            (state.current, state.elapsed, state.one_shot),
+            (RUN, 0.0, None)
+        );
+
+        // Ending on the same movement state it started from still restarts that state
+        state.play_once(HIT);
+        assert!(state.update(RUN, 0.5));
+        assert_eq!(state.current, RUN);
+    }
+
+    #[test]
+    fn test_idle_pulse_skips_unchanged_writes() {
+        #[derive(Resource, Default)]
+        struct Writes(u32);
+        fn count_writes(sprites: Query<(), Changed<Sprite>>, mut writes: ResMut<Writes>) {
+            writes.0 += sprites.iter().count() as u32;
+        }
+
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.init_resource::<InputState>();
+        world.insert_resource(Messages::<DamageEvent>::default());
+        world.init_resource::<Writes>();
+        world.spawn((
+            Player,
+            Velocity::default(),
+            AnimationState::player(),
+            Sprite {
+                color: Color::RED,
+                ..Default::default()
+            },
+        ));
+        let mut schedule = Schedule::default();
+        schedule.add_systems((player_animation_system, count_writes).chain());
+        schedule.run(&mut world);
+        world.resource_mut::<Writes>().0 = 0;
+
+        // A second of standing still fades from 1 to 0.85, which is two steps of 1/16
+        for _ in 0..64 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(world.resource::<Writes>().0, 2);
+        assert_eq!(
+            world.query::<&Sprite>().single(&world).unwrap().opacity,
+            0.875
+        );
+    }
+
+    #[test]
+    fn test_clip_opacity_pulses() {
+        let clip = AnimationClip::looping(Color::RED, 1.0, 0.5);
+        assert_eq!(clip.opacity(0.0), 1.0);
+        assert!((clip.opacity(0.5) - 0.5).abs() < 1e-6);
+        assert!((clip.opacity(1.0) - 1.0).abs() < 1e-6);
+    }
+}
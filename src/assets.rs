@@ -0,0 +1,399 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::resources::Time;
+
+/// Drawn in place of a texture that's still loading, or failed to.
+pub const PLACEHOLDER_COLOR: Color = Color::MAGENTA;
+
+/// Stable index into an `AssetStore`, stays valid across reloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureId(u32);
+
+pub type Loader<T> = fn(&Path) -> Result<T, String>;
+/// Half of a queued load that runs on the thread pool, reads and decodes the file.
+pub type Decoder = fn(&Path) -> Result<Vec<u8>, String>;
+/// Half of a queued load that runs on the main thread, e.g. the GPU upload.
+pub type Uploader<T> = fn(&Path, Vec<u8>) -> Result<T, String>;
+/// Runs a decode job somewhere, `rayon::spawn` unless a test wants it inline.
+pub type Spawner = fn(Box<dyn FnOnce() + Send>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadState {
+    /// Queued or decoding, `get` returns `None` until it's done.
+    Pending,
+    Loaded,
+    /// The first load failed. A reload can still bring it in once the file changes.
+    Failed(String),
+}
+
+struct AssetEntry<T> {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    asset: Option<T>,
+    state: LoadState,
+}
+
+type DecodeResults = Arc<Mutex<Vec<(usize, Result<Vec<u8>, String>)>>>;
+
+/// Assets loaded from disk, polled for changes and reloaded in place.
+pub struct AssetStore<T> {
+    entries: Vec<AssetEntry<T>>,
+    loader: Loader<T>,
+    /// Seconds between mtime checks.
+    pub check_interval: f32,
+    since_check: f32,
+    /// Splits queued loads between the thread pool and the main thread, without it they're
+    /// loaded with `loader` on the main thread.
+    pipeline: Option<(Decoder, Uploader<T>)>,
+    /// Queued entries whose decode hasn't started.
+    queue: VecDeque<usize>,
+    in_flight: usize,
+    decoded: DecodeResults,
+    /// Most queued loads started, and finished, per `process_queue`.
+    pub load_budget: usize,
+    pub spawner: Spawner,
+}
+
+impl<T> AssetStore<T> {
+    pub fn new(loader: Loader<T>) -> Self {
+        Self {
+            entries: Vec::new(),
+            loader,
+            check_interval: 1.0,
+            since_check: 0.0,
+            pipeline: None,
+            queue: VecDeque::new(),
+            in_flight: 0,
+            decoded: DecodeResults::default(),
+            load_budget: 4,
+            spawner: |job| rayon::spawn(job),
+        }
+    }
+
+    pub fn with_pipeline(mut self, decoder: Decoder, uploader: Uploader<T>) -> Self {
+        self.pipeline = Some((decoder, uploader));
+        self
+    }
+
+    pub fn load(&mut self, path: impl Into<PathBuf>) -> Result<TextureId, String> {
+        let path = path.into();
+        let modified = modified_time(&path);
+        let asset = (self.loader)(&path)?;
+        self.entries.push(AssetEntry {
+            path,
+            modified,
+            asset: Some(asset),
+            state: LoadState::Loaded,
+        });
+        Ok(TextureId(self.entries.len() as u32 - 1))
+    }
+
+    /// Hands out the id right away, the asset arrives over the next `process_queue` calls.
+    pub fn queue_load(&mut self, path: impl Into<PathBuf>) -> TextureId {
+        self.entries.push(AssetEntry {
+            path: path.into(),
+            modified: None,
+            asset: None,
+            state: LoadState::Pending,
+        });
+        self.queue.push_back(self.entries.len() - 1);
+        TextureId(self.entries.len() as u32 - 1)
+    }
+
+    pub fn get(&self, id: TextureId) -> Option<&T> {
+        self.entries.get(id.0 as usize)?.asset.as_ref()
+    }
+
+    pub fn state(&self, id: TextureId) -> Option<&LoadState> {
+        self.entries.get(id.0 as usize).map(|entry| &entry.state)
+    }
+
+    /// Share of the entries that are no longer pending, 1 when there's nothing to load.
+    pub fn loading_progress(&self) -> f32 {
+        if self.entries.is_empty() {
+            return 1.0;
+        }
+        let done = self
+            .entries
+            .iter()
+            .filter(|entry| entry.state != LoadState::Pending)
+            .count();
+        done as f32 / self.entries.len() as f32
+    }
+
+    /// Uploads what finished decoding and starts decoding what's next in the queue, at most
+    /// `load_budget` of each. Returns the entries that left `LoadState::Pending`.
+    pub fn process_queue(&mut self) -> Vec<TextureId> {
+        let mut finished = Vec::new();
+        let ready = {
+            let mut decoded = self.decoded.lock().unwrap();
+            let count = decoded.len().min(self.load_budget);
+            decoded.drain(..count).collect::<Vec<_>>()
+        };
+        for (index, result) in ready {
+            self.in_flight -= 1;
+            let Some((_, upload)) = self.pipeline else {
+                continue;
+            };
+            let result = result.and_then(|bytes| upload(&self.entries[index].path, bytes));
+            self.finish(index, result);
+            finished.push(TextureId(index as u32));
+        }
+
+        for _ in self.in_flight..self.load_budget {
+            let Some(index) = self.queue.pop_front() else {
+                break;
+            };
+            let path = self.entries[index].path.clone();
+            match self.pipeline {
+                Some((decode, _)) => {
+                    self.in_flight += 1;
+                    let decoded = self.decoded.clone();
+                    (self.spawner)(Box::new(move || {
+                        let result = decode(&path);
+                        decoded.lock().unwrap().push((index, result));
+                    }));
+                }
+                None => {
+                    let result = (self.loader)(&path);
+                    self.finish(index, result);
+                    finished.push(TextureId(index as u32));
+                }
+            }
+        }
+        finished
+    }
+
+    fn finish(&mut self, index: usize, result: Result<T, String>) {
+        let entry = &mut self.entries[index];
+        entry.modified = modified_time(&entry.path);
+        match result {
+            Ok(asset) => {
+                entry.asset = Some(asset);
+                entry.state = LoadState::Loaded;
+            }
+            Err(err) => {
+                println!("Failed to load {}: {err}", entry.path.display());
+                entry.state = LoadState::Failed(err);
+            }
+        }
+    }
+
+    /// Counts `dt` towards the next check, returns what got reloaded if one was due.
+    pub fn poll(&mut self, dt: f32) -> Vec<TextureId> {
+        self.since_check += dt;
+        if self.since_check < self.check_interval {
+            return Vec::new();
+        }
+        self.since_check = 0.0;
+        self.reload_changed()
+    }
+
+    /// Reloads every asset whose mtime differs from the last load. A failed load keeps the
+    /// old asset, and isn't retried until the file changes again.
+    pub fn reload_changed(&mut self) -> Vec<TextureId> {
+        let mut reloaded = Vec::new();
+        for (index, entry) in self.entries.iter_mut().enumerate() {
+            // Queued loads pick up the mtime when they finish
+            if entry.state == LoadState::Pending {
+                continue;
+            }
+            let modified = modified_time(&entry.path);
+            if modified == entry.modified {
+                continue;
+            }
+            entry.modified = modified;
+            match (self.loader)(&entry.path) {
+                Ok(asset) => {
+                    entry.asset = Some(asset);
+                    entry.state = LoadState::Loaded;
+                    reloaded.push(TextureId(index as u32));
+                }
+                Err(err) => println!("Failed to reload {}: {err}", entry.path.display()),
+            }
+        }
+        reloaded
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[derive(Resource)]
+pub struct TextureAssets(pub AssetStore<OwnedTexture>);
+
+impl Default for TextureAssets {
+    fn default() -> Self {
+        Self(AssetStore::new(load_texture).with_pipeline(read_file, upload_texture))
+    }
+}
+
+impl std::ops::Deref for TextureAssets {
+    type Target = AssetStore<OwnedTexture>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for TextureAssets {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+fn load_texture(path: &Path) -> Result<OwnedTexture, String> {
+    OwnedTexture::load(&path.to_string_lossy()).map_err(|err| format!("{err:?}"))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Decodes from memory and uploads, GL contexts are bound to the main thread.
+fn upload_texture(path: &Path, bytes: Vec<u8>) -> Result<OwnedTexture, String> {
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    let image =
+        OwnedImage::load_from_memory(&extension, &bytes).map_err(|err| format!("{err:?}"))?;
+    OwnedTexture::from_image(&image).map_err(|err| format!("{err:?}"))
+}
+
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssetReloaded(pub TextureId);
+
+/// Only flags `TextureAssets` as changed when something was reloaded, so layers redraw
+/// once instead of every frame.
+pub fn reload_changed_assets_system(
+    time: Res<Time>,
+    mut textures: ResMut<TextureAssets>,
+    mut ev_reloaded: MessageWriter<AssetReloaded>,
+) {
+    let reloaded = textures.bypass_change_detection().poll(time.delta());
+    if reloaded.is_empty() {
+        return;
+    }
+    textures.set_changed();
+    ev_reloaded.write_batch(reloaded.into_iter().map(AssetReloaded));
+}
+
+/// Moves queued textures along, layers redraw once something finished loading.
+pub fn process_asset_queue_system(mut textures: ResMut<TextureAssets>) {
+    if !textures
+        .bypass_change_detection()
+        .process_queue()
+        .is_empty()
+    {
+        textures.set_changed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Stands in for a texture, fails on files starting with "bad".
+    fn load_text(path: &Path) -> Result<String, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        match text.starts_with("bad") {
+            true => Err("bad file".to_string()),
+            false => Ok(text),
+        }
+    }
+
+    fn write_at(path: &Path, contents: &str, secs: u64) {
+        std::fs::write(path, contents).unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap();
+    }
+
+    /// Stands in for reading and decoding, the "file" is its name. Fails on names
+    /// starting with "bad".
+    fn decode_name(path: &Path) -> Result<Vec<u8>, String> {
+        let name = path.to_string_lossy().into_owned();
+        match name.starts_with("bad") {
+            true => Err("bad file".to_string()),
+            false => Ok(name.into_bytes()),
+        }
+    }
+
+    fn upload_text(_: &Path, bytes: Vec<u8>) -> Result<String, String> {
+        String::from_utf8(bytes).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn test_queued_loads_respect_the_budget() {
+        let mut store = AssetStore::new(load_text).with_pipeline(decode_name, upload_text);
+        store.spawner = |job| job();
+        store.load_budget = 2;
+        let ids = ["a", "b", "bad", "c", "d"].map(|name| store.queue_load(name));
+        assert_eq!(store.state(ids[0]), Some(&LoadState::Pending));
+        assert_eq!(store.get(ids[0]), None);
+        assert_eq!(store.loading_progress(), 0.0);
+
+        // The first frame only starts decodes, two of them
+        assert!(store.process_queue().is_empty());
+        assert_eq!(store.decoded.lock().unwrap().len(), 2);
+        assert_eq!(store.loading_progress(), 0.0);
+
+        // Each later frame uploads what the last one decoded
+        assert_eq!(store.process_queue(), vec![ids[0], ids[1]]);
+        assert_eq!(store.get(ids[1]).map(String::as_str), Some("b"));
+        assert_eq!(store.process_queue(), vec![ids[2], ids[3]]);
+        assert!(matches!(store.state(ids[2]), Some(LoadState::Failed(_))));
+        assert_eq!(store.get(ids[2]), None);
+        assert_eq!(store.state(ids[3]), Some(&LoadState::Loaded));
+        assert_eq!(store.loading_progress(), 0.8);
+        assert_eq!(store.process_queue(), vec![ids[4]]);
+        assert_eq!(store.loading_progress(), 1.0);
+        assert!(store.process_queue().is_empty());
+    }
+
+    #[test]
+    fn test_reload_keeps_ids_and_old_asset_on_failure() {
+        let dir = std::env::temp_dir().join(format!("asset_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        write_at(&a, "a1", 1000);
+        write_at(&b, "b1", 1000);
+
+        let mut store = AssetStore::new(load_text);
+        let id_a = store.load(&a).unwrap();
+        let id_b = store.load(&b).unwrap();
+        assert!(store.reload_changed().is_empty());
+
+        // Only the file whose mtime moved is reloaded, under the same id
+        write_at(&a, "a2", 2000);
+        assert_eq!(store.reload_changed(), vec![id_a]);
+        assert_eq!(store.get(id_a).map(String::as_str), Some("a2"));
+        assert_eq!(store.get(id_b).map(String::as_str), Some("b1"));
+
+        // A broken save keeps the last good asset and isn't retried until it changes again
+        write_at(&b, "bad", 3000);
+        assert!(store.reload_changed().is_empty());
+        assert_eq!(store.get(id_b).map(String::as_str), Some("b1"));
+        assert!(store.reload_changed().is_empty());
+        write_at(&b, "b2", 4000);
+        assert_eq!(store.reload_changed(), vec![id_b]);
+
+        // Polling waits for the interval
+        write_at(&a, "a3", 5000);
+        assert!(store.poll(0.5).is_empty());
+        assert_eq!(store.poll(0.5), vec![id_a]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
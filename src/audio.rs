@@ -0,0 +1,256 @@
+use bevy_ecs::prelude::*;
+#[cfg(feature = "audio")]
+use rustyray::prelude::*;
+
+use crate::components::Player;
+use crate::resources::{ContactManifold, Time};
+#[cfg(feature = "audio")]
+use crate::utils::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SoundId {
+    Bump,
+}
+
+impl SoundId {
+    pub const ALL: [SoundId; 1] = [SoundId::Bump];
+
+    pub fn path(&self) -> &'static str {
+        match self {
+            SoundId::Bump => "assets/bump.wav",
+        }
+    }
+}
+
+/// Played by `audio_system` at the end of the frame.
+#[derive(Message, Debug, Clone, Copy, PartialEq)]
+pub struct PlaySound {
+    pub id: SoundId,
+    /// Before `AudioSettings::master_volume` is applied.
+    pub volume: f32,
+    pub pitch: f32,
+}
+
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct AudioSettings {
+    pub master_volume: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    /// Volume a sound is actually played at, `None` when it wouldn't be heard.
+    pub fn final_volume(&self, volume: f32) -> Option<f32> {
+        let volume = (volume * self.master_volume).clamp(0.0, 1.0);
+        (!self.muted && volume > 0.0).then_some(volume)
+    }
+}
+
+/// Loaded sound handles. Not `Send`, so it's a non-send resource and only the
+/// single threaded render schedule touches it.
+#[cfg(feature = "audio")]
+pub struct AudioAssets {
+    _device: AudioDevice,
+    sounds: HashMap<SoundId, Sound>,
+}
+
+#[cfg(feature = "audio")]
+impl AudioAssets {
+    /// Opens the audio device and loads every `SoundId`, missing files are reported and skipped.
+    pub fn load() -> Self {
+        let device = AudioDevice::init();
+        let mut sounds = HashMap::default();
+        for id in SoundId::ALL {
+            match Sound::load(id.path()) {
+                Ok(sound) => {
+                    sounds.insert(id, sound);
+                }
+                Err(err) => println!("Failed to load sound {}: {err:?}", id.path()),
+            }
+        }
+        Self {
+            _device: device,
+            sounds,
+        }
+    }
+}
+
+#[cfg(feature = "audio")]
+pub fn audio_system(
+    mut ev_play: MessageReader<PlaySound>,
+    assets: NonSend<AudioAssets>,
+    settings: Res<AudioSettings>,
+) {
+    for ev in ev_play.read() {
+        let Some(volume) = settings.final_volume(ev.volume) else {
+            continue;
+        };
+        if let Some(sound) = assets.sounds.get(&ev.id) {
+            sound.set_volume(volume);
+            sound.set_pitch(ev.pitch);
+            sound.play();
+        }
+    }
+}
+
+/// Without the `audio` feature sounds are only drained, so tests and headless runs stay silent.
+#[cfg(not(feature = "audio"))]
+pub fn audio_system(mut ev_play: MessageReader<PlaySound>) {
+    ev_play.clear();
+}
+
+/// Plays the bump sound when the player hits something hard enough. Any bump restarts the
+/// cooldown, so sliding along a wall plays it once instead of every tick.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct BumpSound {
+    /// Penetration depth a contact needs before it counts as a bump.
+    pub depth_threshold: f32,
+    /// Seconds without any bump before the sound can play again.
+    pub cooldown: f32,
+    quiet_for: f32,
+}
+
+impl Default for BumpSound {
+    fn default() -> Self {
+        Self {
+            depth_threshold: 2.0,
+            cooldown: 0.25,
+            quiet_for: f32::INFINITY,
+        }
+    }
+}
+
+impl BumpSound {
+    /// Advances the cooldown by `dt` and returns whether this frame's bump should be heard.
+    pub fn step(&mut self, dt: f32, bumped: bool) -> bool {
+        if !bumped {
+            self.quiet_for += dt;
+            return false;
+        }
+        let play = self.quiet_for >= self.cooldown;
+        self.quiet_for = 0.0;
+        play
+    }
+}
+
+pub fn bump_sound_system(
+    time: Res<Time>,
+    manifold: Res<ContactManifold>,
+    players: Query<(), With<Player>>,
+    mut bump: ResMut<BumpSound>,
+    mut ev_play: MessageWriter<PlaySound>,
+) {
+    let depth = manifold
+        .contacts
+        .iter()
+        .filter(|c| players.contains(c.entity_a) || players.contains(c.entity_b))
+        .map(|c| c.depth)
+        .fold(0.0, f32::max);
+    let bumped = depth > bump.depth_threshold;
+    if bump.step(time.delta(), bumped) {
+        ev_play.write(PlaySound {
+            id: SoundId::Bump,
+            // Harder hits are louder, full volume at four times the threshold
+            volume: (depth / (bump.depth_threshold * 4.0)).min(1.0),
+            pitch: 1.0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_final_volume() {
+        let mut settings = AudioSettings {
+            master_volume: 0.5,
+            muted: false,
+        };
+        assert_eq!(settings.final_volume(0.5), Some(0.25));
+        assert_eq!(settings.final_volume(4.0), Some(1.0));
+        assert_eq!(settings.final_volume(0.0), None);
+
+        settings.muted = true;
+        assert_eq!(settings.final_volume(1.0), None);
+    }
+
+    #[test]
+    fn test_bump_throttle() {
+        let mut bump = BumpSound {
+            cooldown: 0.25,
+            ..Default::default()
+        };
+        let dt = 1.0 / 64.0;
+        assert!(bump.step(dt, true));
+
+        // Sliding along a wall bumps every frame but only the first one is heard
+        for _ in 0..60 {
+            assert!(!bump.step(dt, true));
+        }
+
+        // Quiet for just under the cooldown, still throttled
+        for _ in 0..15 {
+            bump.step(dt, false);
+        }
+        assert!(!bump.step(dt, true));
+
+        for _ in 0..16 {
+            bump.step(dt, false);
+        }
+        assert!(bump.step(dt, true));
+    }
+
+    #[test]
+    fn test_bump_sound_system_needs_player_and_depth() {
+        use crate::resources::Contact;
+        use rustyray::prelude::Vector2;
+
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.init_resource::<ContactManifold>();
+        world.init_resource::<BumpSound>();
+        world.insert_resource(Messages::<PlaySound>::default());
+        let player = world.spawn(Player).id();
+        let wall = world.spawn(()).id();
+        let other = world.spawn(()).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(bump_sound_system);
+        let mut run = |world: &mut World, contact: Contact| {
+            world.resource_mut::<ContactManifold>().contacts = vec![contact];
+            schedule.run(world);
+            world
+                .resource_mut::<Messages<PlaySound>>()
+                .drain()
+                .collect::<Vec<_>>()
+        };
+        let contact = |a, depth| Contact {
+            entity_a: a,
+            entity_b: wall,
+            point: Vector2::new(0.0, 0.0),
+            normal: Vector2::new(1.0, 0.0),
+            depth,
+        };
+
+        // Not the player, then too shallow
+        assert!(run(&mut world, contact(other, 8.0)).is_empty());
+        assert!(run(&mut world, contact(player, 1.0)).is_empty());
+        assert_eq!(
+            run(&mut world, contact(player, 4.0)),
+            vec![PlaySound {
+                id: SoundId::Bump,
+                volume: 0.5,
+                pitch: 1.0,
+            }]
+        );
+    }
+}
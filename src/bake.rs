@@ -0,0 +1,329 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{
+    Collider, ColliderKind, CompoundCollider, ForceField, GlobalTransform, Velocity,
+};
+use crate::spatial_hash::SpatialHash;
+use crate::utils::{HashMap, HashSet};
+
+/// Static rectangle colliders merged into as few rects as a greedy pass finds. Movers
+/// resolve against these instead of every tile, so flush neighbors leave no seams.
+#[derive(Resource)]
+pub struct BakedStaticColliders {
+    /// Merged rects, keyed by the first entity merged into each.
+    pub rects: HashMap<Entity, Rectangle>,
+    pub hash: SpatialHash,
+    /// Every entity covered by `rects`, the broad phase skips these.
+    pub sources: HashSet<Entity>,
+    /// Static colliders were added, moved or removed since the last bake.
+    pub dirty: bool,
+}
+
+impl Default for BakedStaticColliders {
+    fn default() -> Self {
+        Self {
+            rects: HashMap::default(),
+            hash: SpatialHash::flat(96.0),
+            sources: HashSet::default(),
+            dirty: true,
+        }
+    }
+}
+
+impl BakedStaticColliders {
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.sources.contains(&entity)
+    }
+
+    pub fn query(&self, rect: Rectangle) -> impl Iterator<Item = (Entity, Rectangle)> + '_ {
+        self.hash
+            .query(rect)
+            .into_iter()
+            .map(|entity| (entity, self.rects[&entity]))
+    }
+}
+
+/// Greedy merge: rects sharing a row (same `y` and `height`) that touch or overlap are
+/// joined first, then the rows sharing a column (same `x` and `width`) are stacked.
+/// Each merged rect keeps the key of its first source.
+pub fn merge_rects<K: Copy>(
+    rects: impl IntoIterator<Item = (K, Rectangle)>,
+) -> Vec<(K, Rectangle)> {
+    let mut rects: Vec<(K, Rectangle)> = rects.into_iter().collect();
+
+    rects.sort_by(|(_, a), (_, b)| {
+        (a.y.total_cmp(&b.y))
+            .then(a.height.total_cmp(&b.height))
+            .then(a.x.total_cmp(&b.x))
+    });
+    let mut rows = merge_runs(rects, |current, next| {
+        (next.y == current.y
+            && next.height == current.height
+            && next.x <= current.x + current.width)
+            .then(|| Rectangle {
+                width: (next.x + next.width).max(current.x + current.width) - current.x,
+                ..*current
+            })
+    });
+
+    rows.sort_by(|(_, a), (_, b)| {
+        (a.x.total_cmp(&b.x))
+            .then(a.width.total_cmp(&b.width))
+            .then(a.y.total_cmp(&b.y))
+    });
+    merge_runs(rows, |current, next| {
+        (next.x == current.x && next.width == current.width && next.y <= current.y + current.height)
+            .then(|| Rectangle {
+                height: (next.y + next.height).max(current.y + current.height) - current.y,
+                ..*current
+            })
+    })
+}
+
+/// Folds each sorted rect into the previous one while `join` returns the combined rect.
+fn merge_runs<K: Copy>(
+    sorted: Vec<(K, Rectangle)>,
+    join: impl Fn(&Rectangle, &Rectangle) -> Option<Rectangle>,
+) -> Vec<(K, Rectangle)> {
+    let mut merged: Vec<(K, Rectangle)> = Vec::with_capacity(sorted.len());
+    for (key, rect) in sorted {
+        if let Some((_, current)) = merged.last_mut() {
+            if let Some(joined) = join(current, &rect) {
+                *current = joined;
+                continue;
+            }
+        }
+        merged.push((key, rect));
+    }
+    merged
+}
+
+/// Run condition: static colliders changed since they were last baked.
+pub fn bake_is_dirty(baked: Res<BakedStaticColliders>) -> bool {
+    baked.dirty
+}
+
+/// Parts of a `CompoundCollider` have no `Velocity` of their own, but move with it.
+fn in_compound(
+    entity: Entity,
+    parents: &Query<&ChildOf>,
+    compounds: &Query<(), With<CompoundCollider>>,
+) -> bool {
+    parents
+        .iter_ancestors(entity)
+        .any(|ancestor| compounds.contains(ancestor))
+}
+
+/// Only rectangles are baked, a circle's bounds would turn it into a box.
+pub fn bake_static_colliders_system(
+    mut baked: ResMut<BakedStaticColliders>,
+    colliders: Query<
+        (Entity, &Collider, &GlobalTransform),
+        (Without<Velocity>, Without<ForceField>),
+    >,
+    parents: Query<&ChildOf>,
+    compounds: Query<(), With<CompoundCollider>>,
+) {
+    let rects: Vec<(Entity, Rectangle)> = colliders
+        .iter()
+        .filter(|(_, collider, _)| matches!(collider.kind, ColliderKind::Rectangle(_)))
+        .filter(|(entity, ..)| !in_compound(*entity, &parents, &compounds))
+        .map(|(entity, collider, transform)| (entity, collider.aabb(transform)))
+        .collect();
+    let sources: HashSet<Entity> = rects.iter().map(|(entity, _)| *entity).collect();
+    let merged = merge_rects(rects);
+
+    let baked = &mut *baked;
+    baked.hash.rebuild(merged.iter().copied());
+    baked.rects = merged.into_iter().collect();
+    baked.sources = sources;
+    baked.dirty = false;
+}
+
+/// Flags the bake when a static collider shows up, moves, changes or starts moving. Removals
+/// are caught by `mark_bake_dirty_on_remove`.
+#[allow(clippy::type_complexity)]
+pub fn mark_bake_dirty_system(
+    changed: Query<
+        Entity,
+        (
+            Or<(Changed<Collider>, Changed<GlobalTransform>)>,
+            With<Collider>,
+            Without<Velocity>,
+            Without<ForceField>,
+        ),
+    >,
+    (parents, compounds): (Query<&ChildOf>, Query<(), With<CompoundCollider>>),
+    started_moving: Query<Entity, Added<Velocity>>,
+    mut baked: ResMut<BakedStaticColliders>,
+) {
+    if changed
+        .iter()
+        .any(|entity| !in_compound(entity, &parents, &compounds))
+        || started_moving.iter().any(|entity| baked.contains(entity))
+    {
+        baked.dirty = true;
+    }
+}
+
+/// Flags the bake when a baked collider goes away. An observer, so despawns outside the
+/// physics schedules count too.
+pub fn mark_bake_dirty_on_remove(
+    remove: On<Remove, Collider>,
+    mut baked: ResMut<BakedStaticColliders>,
+) {
+    if baked.contains(remove.entity) {
+        baked.dirty = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn tiles(rows: &[&str]) -> Vec<(usize, Rectangle)> {
+        rows.iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|(_, c)| *c == '#')
+                    .map(move |(x, _)| (x, y))
+            })
+            .enumerate()
+            .map(|(i, (x, y))| (i, rect(x as f32 * 10.0, y as f32 * 10.0, 10.0, 10.0)))
+            .collect()
+    }
+
+    fn merged(rows: &[&str]) -> Vec<(f32, f32, f32, f32)> {
+        let mut merged: Vec<_> = merge_rects(tiles(rows))
+            .into_iter()
+            .map(|(_, r)| (r.x, r.y, r.width, r.height))
+            .collect();
+        merged.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        merged
+    }
+
+    #[test]
+    fn test_merge_solid_block() {
+        assert_eq!(merged(&["###", "###", "###"]), vec![(0.0, 0.0, 30.0, 30.0)]);
+    }
+
+    #[test]
+    fn test_merge_grid_with_holes() {
+        // The hole splits the middle row, so only the full rows stack
+        assert_eq!(
+            merged(&["###", "#.#", "###"]),
+            vec![
+                (0.0, 0.0, 30.0, 10.0),
+                (0.0, 10.0, 10.0, 10.0),
+                (0.0, 20.0, 30.0, 10.0),
+                (20.0, 10.0, 10.0, 10.0),
+            ]
+        );
+        // Matching row spans stack around a gap column
+        assert_eq!(
+            merged(&["##.##", "##.##", "....."]),
+            vec![(0.0, 0.0, 20.0, 20.0), (30.0, 0.0, 20.0, 20.0)]
+        );
+    }
+
+    #[test]
+    fn test_merge_overlapping_and_keys() {
+        let merged = merge_rects([
+            ("a", rect(0.0, 0.0, 20.0, 10.0)),
+            ("b", rect(10.0, 0.0, 20.0, 10.0)),
+            ("c", rect(100.0, 0.0, 10.0, 10.0)),
+        ]);
+        let merged: Vec<_> = merged
+            .into_iter()
+            .map(|(key, r)| (key, r.x, r.width))
+            .collect();
+        assert_eq!(merged, vec![("a", 0.0, 30.0), ("c", 100.0, 10.0)]);
+    }
+
+    #[test]
+    fn test_bake_marks_dirty_on_changes() {
+        use crate::components::Transform;
+
+        let mut world = World::new();
+        world.init_resource::<BakedStaticColliders>();
+        world.add_observer(mark_bake_dirty_on_remove);
+        let spawn_tile = |world: &mut World, x: f32| {
+            let transform = Transform::default().with_position(Vector2::new(x, 0.0));
+            world
+                .spawn((
+                    Collider {
+                        kind: ColliderKind::Rectangle(Vector2::new(10.0, 10.0)),
+                        offset: Vector2::new(0.0, 0.0),
+                    },
+                    GlobalTransform::from_root(&transform),
+                    transform,
+                ))
+                .id()
+        };
+        let first = spawn_tile(&mut world, 0.0);
+        spawn_tile(&mut world, 10.0);
+
+        // The bake cleans the flag again, so keep what the marking decided
+        #[derive(Resource, Default)]
+        struct MarkedDirty(bool);
+        fn record_dirty(baked: Res<BakedStaticColliders>, mut marked: ResMut<MarkedDirty>) {
+            marked.0 = baked.dirty;
+        }
+        world.init_resource::<MarkedDirty>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                mark_bake_dirty_system,
+                record_dirty,
+                bake_static_colliders_system.run_if(bake_is_dirty),
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+        let baked = world.resource::<BakedStaticColliders>();
+        assert!(!baked.dirty);
+        assert_eq!(baked.rects.len(), 1);
+        assert!(baked.contains(first));
+        let hits: Vec<_> = baked.query(rect(15.0, 0.0, 1.0, 1.0)).collect();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].1.width, 20.0);
+
+        // Nothing changed, the bake stays clean
+        schedule.run(&mut world);
+        assert!(!world.resource::<BakedStaticColliders>().dirty);
+
+        // Something without a collider moving, like the cursor sprite, isn't a reason
+        let cursor = world
+            .spawn((Transform::default(), GlobalTransform::default()))
+            .id();
+        schedule.run(&mut world);
+        world.get_mut::<GlobalTransform>(cursor).unwrap().position.x = 5.0;
+        schedule.run(&mut world);
+        assert!(!world.resource::<MarkedDirty>().0);
+
+        // A new tile further away is baked into its own rect
+        spawn_tile(&mut world, 50.0);
+        schedule.run(&mut world);
+        assert!(world.resource::<MarkedDirty>().0);
+        assert_eq!(world.resource::<BakedStaticColliders>().rects.len(), 2);
+
+        world.despawn(first);
+        assert!(world.resource::<BakedStaticColliders>().dirty);
+        schedule.run(&mut world);
+        let baked = world.resource::<BakedStaticColliders>();
+        assert!(!baked.contains(first));
+        assert_eq!(baked.rects.len(), 2);
+    }
+}
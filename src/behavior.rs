@@ -0,0 +1,350 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+
+use bevy_ecs::prelude::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rustyray::prelude::*;
+
+use crate::components::{Transform, Velocity};
+use crate::resources::Time;
+
+/// How close `MoveTo` has to get before it counts as arrived.
+const ARRIVE_DISTANCE: f32 = 0.01;
+
+#[derive(Debug, Clone)]
+pub enum BehaviorStep {
+    /// Walks at `BehaviorSequence::speed` until the position is reached.
+    MoveTo(Vector2),
+    /// Seconds to idle for.
+    Wait(f32),
+    SetVelocity(Vector2),
+    /// Sends a `ScriptEvent` with this name.
+    FireEvent(String),
+}
+
+/// Runs its steps front to back, the component is removed once they're all done.
+#[derive(Component, Debug, Clone)]
+#[require(Velocity)]
+pub struct BehaviorSequence {
+    pub steps: VecDeque<BehaviorStep>,
+    /// Time spent in the current `Wait` step.
+    pub current_wait: f32,
+    /// Units per second for `MoveTo`.
+    pub speed: f32,
+}
+
+impl BehaviorSequence {
+    pub fn new(speed: f32, steps: impl IntoIterator<Item = BehaviorStep>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+            current_wait: 0.0,
+            speed,
+        }
+    }
+}
+
+#[derive(Message, Debug, Clone, PartialEq, Eq)]
+pub struct ScriptEvent {
+    pub name: String,
+    pub entity: Entity,
+}
+
+/// Advances every sequence by one physics tick. Instant steps run back to back,
+/// so a tick only stops on a `MoveTo` or `Wait` that isn't finished yet.
+pub fn behavior_sequence_system(
+    time: Res<Time>,
+    mut sequences: Query<(Entity, &mut BehaviorSequence, &Transform, &mut Velocity)>,
+    mut ev_script: MessageWriter<ScriptEvent>,
+    mut commands: Commands,
+) {
+    let dt = time.delta();
+    for (entity, mut sequence, transform, mut velocity) in sequences.iter_mut() {
+        let sequence = &mut *sequence;
+        while let Some(step) = sequence.steps.front() {
+            match step {
+                BehaviorStep::MoveTo(target) => {
+                    let offset = *target - transform.position;
+                    let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+                    if distance > ARRIVE_DISTANCE {
+                        // Slow down on the last tick instead of overshooting
+                        let speed = sequence.speed.min(distance / dt);
+                        velocity.0 = offset * (speed / distance);
+                        break;
+                    }
+                    velocity.0 = Vector2::new(0.0, 0.0);
+                }
+                BehaviorStep::Wait(duration) => {
+                    sequence.current_wait += dt;
+                    if sequence.current_wait < *duration {
+                        break;
+                    }
+                    sequence.current_wait = 0.0;
+                }
+                BehaviorStep::SetVelocity(new_velocity) => velocity.0 = *new_velocity,
+                BehaviorStep::FireEvent(name) => {
+                    ev_script.write(ScriptEvent {
+                        name: name.clone(),
+                        entity,
+                    });
+                }
+            }
+            sequence.steps.pop_front();
+        }
+
+        if sequence.steps.is_empty() {
+            commands.entity(entity).remove::<BehaviorSequence>();
+        }
+    }
+}
+
+/// Walks `points` in order at `speed`, back to the first one after the last if `looped`.
+#[derive(Component, Debug, Clone)]
+#[require(Velocity)]
+pub struct FollowPath {
+    pub points: Vec<Vector2>,
+    pub speed: f32,
+    pub looped: bool,
+    /// Waypoint being walked to, `points.len()` once a path that doesn't loop is done.
+    pub current: usize,
+}
+
+impl FollowPath {
+    pub fn new(points: impl IntoIterator<Item = Vector2>, speed: f32, looped: bool) -> Self {
+        Self {
+            points: points.into_iter().collect(),
+            speed,
+            looped,
+            current: 0,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.current >= self.points.len()
+    }
+}
+
+/// Heads in a random direction at `speed`, picking a new one every `change_interval`
+/// seconds. Directions come from `seed` alone, so replays wander the same way.
+#[derive(Component, Debug, Clone)]
+#[require(Velocity)]
+pub struct Wander {
+    pub speed: f32,
+    pub change_interval: f32,
+    pub seed: u64,
+    /// Seconds until the next direction change.
+    pub timer: f32,
+    rng: SmallRng,
+}
+
+impl Wander {
+    pub fn new(speed: f32, change_interval: f32, seed: u64) -> Self {
+        Self {
+            speed,
+            change_interval,
+            seed,
+            timer: 0.0,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// Points `Velocity` at the current waypoint, `apply_velocity_system` still does the moving
+/// so followers collide like everything else. Reaching a waypoint heads for the next one on
+/// the same tick.
+pub fn follow_path_system(
+    time: Res<Time>,
+    mut followers: Query<(&mut FollowPath, &Transform, &mut Velocity)>,
+) {
+    let dt = time.delta();
+    for (mut path, transform, mut velocity) in followers.iter_mut() {
+        velocity.0 = Vector2::new(0.0, 0.0);
+        // Bounded, so a path of identical points can't spin forever
+        for _ in 0..path.points.len() {
+            if path.is_done() {
+                break;
+            }
+            let offset = path.points[path.current] - transform.position;
+            let distance = (offset.x * offset.x + offset.y * offset.y).sqrt();
+            if distance > ARRIVE_DISTANCE {
+                let speed = path.speed.min(distance / dt);
+                velocity.0 = offset * (speed / distance);
+                break;
+            }
+            path.current += 1;
+            if path.looped && path.is_done() {
+                path.current = 0;
+            }
+        }
+    }
+}
+
+pub fn wander_system(time: Res<Time>, mut wanderers: Query<(&mut Wander, &mut Velocity)>) {
+    let dt = time.delta();
+    for (mut wander, mut velocity) in wanderers.iter_mut() {
+        if wander.timer <= 0.0 {
+            wander.timer += wander.change_interval.max(dt);
+            let angle = wander.rng.gen_range(0.0..std::f32::consts::TAU);
+            velocity.0 = Vector2::new(angle.cos(), angle.sin()) * wander.speed;
+        }
+        wander.timer -= dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Resource, Default)]
+    struct ScriptEvents(Vec<ScriptEvent>);
+
+    fn record_script_events(
+        mut ev_script: MessageReader<ScriptEvent>,
+        mut events: ResMut<ScriptEvents>,
+    ) {
+        events.0.extend(ev_script.read().cloned());
+    }
+
+    fn integrate(time: Res<Time>, mut movers: Query<(&mut Transform, &Velocity)>) {
+        for (mut transform, velocity) in movers.iter_mut() {
+            transform.position += velocity.0 * time.delta();
+        }
+    }
+
+    #[test]
+    fn test_sequence_runs_steps_in_order() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.insert_resource(Messages::<ScriptEvent>::default());
+        world.init_resource::<ScriptEvents>();
+        // One unit per tick
+        let npc = world
+            .spawn((
+                Transform::default(),
+                BehaviorSequence::new(
+                    64.0,
+                    [
+                        BehaviorStep::MoveTo(Vector2::new(10.0, 0.0)),
+                        BehaviorStep::Wait(0.5),
+                        BehaviorStep::FireEvent("arrived".to_string()),
+                        BehaviorStep::SetVelocity(Vector2::new(0.0, 64.0)),
+                    ],
+                ),
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((behavior_sequence_system, integrate, record_script_events).chain());
+
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(world.get::<Transform>(npc).unwrap().position.x, 10.0);
+
+        // Arriving and the first wait tick share a tick, so the wait ends 32 ticks in
+        for _ in 0..31 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(world.get::<Transform>(npc).unwrap().position.x, 10.0);
+        assert!(world.resource::<ScriptEvents>().0.is_empty());
+
+        schedule.run(&mut world);
+        assert_eq!(
+            world.resource::<ScriptEvents>().0,
+            vec![ScriptEvent {
+                name: "arrived".to_string(),
+                entity: npc,
+            }]
+        );
+        assert_eq!(world.get::<Velocity>(npc).unwrap().y, 64.0);
+        assert!(world.get::<BehaviorSequence>(npc).is_none());
+    }
+
+    #[test]
+    fn test_path_follower_reaches_waypoints() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        let points = [Vector2::new(10.0, 0.0), Vector2::new(10.0, 10.0)];
+        // One unit per tick
+        let once = world
+            .spawn((Transform::default(), FollowPath::new(points, 64.0, false)))
+            .id();
+        let looped = world
+            .spawn((Transform::default(), FollowPath::new(points, 64.0, true)))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((follow_path_system, integrate).chain());
+        let position = |world: &World, entity| {
+            let p = world.get::<Transform>(entity).unwrap().position;
+            (p.x, p.y)
+        };
+
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world, once), (10.0, 0.0));
+        assert_eq!(world.get::<FollowPath>(once).unwrap().current, 0);
+
+        // The arriving tick already moves towards the next waypoint
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world, once), (10.0, 10.0));
+        assert_eq!(position(&world, looped), (10.0, 10.0));
+
+        // The last waypoint stops one path and sends the other back to the first
+        schedule.run(&mut world);
+        assert!(world.get::<FollowPath>(once).unwrap().is_done());
+        assert_eq!(world.get::<Velocity>(once).unwrap().x, 0.0);
+        assert_eq!(world.get::<Velocity>(once).unwrap().y, 0.0);
+        assert_eq!(position(&world, looped), (10.0, 9.0));
+        for _ in 0..9 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world, once), (10.0, 10.0));
+        assert_eq!(position(&world, looped), (10.0, 0.0));
+    }
+
+    #[test]
+    fn test_wander_is_deterministic_per_seed() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        let a = world
+            .spawn((Transform::default(), Wander::new(64.0, 0.25, 7)))
+            .id();
+        let b = world
+            .spawn((Transform::default(), Wander::new(64.0, 0.25, 7)))
+            .id();
+        let other = world
+            .spawn((Transform::default(), Wander::new(64.0, 0.25, 8)))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((wander_system, integrate).chain());
+        let velocity = |world: &World, entity| {
+            let v = world.get::<Velocity>(entity).unwrap().0;
+            (v.x, v.y)
+        };
+
+        schedule.run(&mut world);
+        let first = velocity(&world, a);
+        assert!(((first.0 * first.0 + first.1 * first.1).sqrt() - 64.0).abs() < 1e-3);
+        assert_ne!(first, velocity(&world, other));
+
+        // The direction holds for the whole interval, 16 ticks
+        for _ in 0..15 {
+            schedule.run(&mut world);
+            assert_eq!(velocity(&world, a), first);
+        }
+        schedule.run(&mut world);
+        assert_ne!(velocity(&world, a), first);
+        assert_eq!(velocity(&world, a), velocity(&world, b));
+        let (pa, pb) = (
+            world.get::<Transform>(a).unwrap().position,
+            world.get::<Transform>(b).unwrap().position,
+        );
+        assert_eq!((pa.x, pa.y), (pb.x, pb.y));
+    }
+}
@@ -0,0 +1,160 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{GlobalTransform, Sprite};
+use crate::resources::{
+    FOG_LAYER, InactiveLayers, LIGHTING_LAYER, LayerSettings, LayerTextures, WindowResource,
+    composite_order,
+};
+use crate::utils::HashMap;
+
+pub const BLUR_SHADER: &str = "blur";
+
+/// Shaders the renderer knows about, by name, and where they're loaded from.
+const SHADERS: [(&str, &str); 1] = [(BLUR_SHADER, "assets/shaders/blur.fs")];
+
+/// Frosted glass behind a screen-space sprite: the world under its bounds is drawn blurred
+/// just below the screen-space layers. `strength` is the blur radius in pixels.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct BlurBackground {
+    pub strength: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct ShaderLibrary(pub HashMap<String, OwnedShader>);
+
+impl ShaderLibrary {
+    /// Needs the window's GL context. Shaders that fail to load are logged and left out,
+    /// whatever uses them is skipped.
+    pub fn load() -> Self {
+        let mut library = Self::default();
+        for (name, path) in SHADERS {
+            match OwnedShader::load(None, Some(path)) {
+                Ok(shader) => {
+                    library.0.insert(name.to_string(), shader);
+                }
+                Err(err) => println!("Failed to load {path}: {err:?}"),
+            }
+        }
+        library
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut OwnedShader> {
+        self.0.get_mut(name)
+    }
+}
+
+/// Blurred panels for this frame, composited by `render_system` under the screen layers.
+#[derive(Resource, Default)]
+pub struct BlurTexture {
+    pub target: Option<OwnedRenderTexture>,
+    /// Screen size `target` was created for.
+    pub size: (i32, i32),
+    /// Something was drawn into `target` this frame.
+    pub active: bool,
+}
+
+impl BlurTexture {
+    pub fn texture(&self) -> Option<&OwnedRenderTexture> {
+        self.target.as_ref().filter(|_| self.active)
+    }
+}
+
+/// Draws the active world layers, lit and fogged like the composite, under every
+/// `BlurBackground` through the blur shader. Each layer is blurred on its own, which matches
+/// blurring the composite closely enough for a backdrop.
+pub fn blur_pass_system(
+    mut window: ResMut<WindowResource>,
+    mut blur_texture: ResMut<BlurTexture>,
+    mut shaders: ResMut<ShaderLibrary>,
+    layer_rt: Res<LayerTextures>,
+    layer_settings: Res<LayerSettings>,
+    inactive_layers: Res<InactiveLayers>,
+    panels: Query<(&Sprite, &GlobalTransform, &BlurBackground)>,
+) {
+    let panels: Vec<(Rectangle, f32)> = panels
+        .iter()
+        .filter_map(|(sprite, transform, blur)| Some((sprite.bounds(transform)?, blur.strength)))
+        .collect();
+    let Some(shader) = shaders.get_mut(BLUR_SHADER) else {
+        blur_texture.active = false;
+        return;
+    };
+    if panels.is_empty() {
+        blur_texture.active = false;
+        return;
+    }
+
+    let screen_size = window.screen_size();
+    let size = (screen_size.x, screen_size.y);
+    if blur_texture.target.is_none() || blur_texture.size != size {
+        match OwnedRenderTexture::new(screen_size.x, screen_size.y) {
+            Ok(rt) => blur_texture.target = Some(rt),
+            Err(err) => {
+                println!("Failed to create the blur texture: {err:?}");
+                blur_texture.active = false;
+                return;
+            }
+        }
+        blur_texture.size = size;
+    }
+    let world_layers: Vec<u32> = composite_order(layer_rt.0.keys().copied())
+        .into_iter()
+        .filter(|&layer| !layer_settings.is_screen_space(layer) && inactive_layers.is_active(layer))
+        .collect();
+    let active_texture = |layer: u32| {
+        layer_rt
+            .0
+            .get(&layer)
+            .filter(|_| inactive_layers.is_active(layer))
+    };
+
+    let resolution = screen_size.to_vector2();
+    let blur_texture = &mut *blur_texture;
+    let target = blur_texture.target.as_mut().unwrap();
+    window.draw_texture_mode(target, |mut d| {
+        d.clear(Color::BLANK);
+        for &(rect, strength) in &panels {
+            shader.set_value("strength", strength);
+            shader.set_value("resolution", resolution);
+            let mut d = d.begin_shader_mode(shader);
+            let source = |layer: u32| {
+                layer_settings
+                    .get(layer)
+                    .composite_source(rect, screen_size)
+            };
+            for &layer in &world_layers {
+                d.draw_render_texture_pro(
+                    &layer_rt.0[&layer],
+                    source(layer),
+                    rect,
+                    Vector2::new(0.0, 0.0),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+            if let Some(rt) = active_texture(LIGHTING_LAYER) {
+                let mut d = d.begin_blend_mode(BlendMode::Multiplied);
+                d.draw_render_texture_pro(
+                    rt,
+                    source(LIGHTING_LAYER),
+                    rect,
+                    Vector2::new(0.0, 0.0),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+            if let Some(rt) = active_texture(FOG_LAYER) {
+                d.draw_render_texture_pro(
+                    rt,
+                    source(FOG_LAYER),
+                    rect,
+                    Vector2::new(0.0, 0.0),
+                    0.0,
+                    Color::WHITE,
+                );
+            }
+        }
+    });
+    blur_texture.active = true;
+}
@@ -0,0 +1,180 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{
+    Collider, GlobalTransform, Layer, OnScreen, Player, Sprite, SpriteKind, SpriteOrigin,
+    SyncColliderWithSprite, Transform, Velocity, Visibility,
+};
+
+/// What `EntityBuilder::with_sprite` adds.
+pub type SpriteParts = (Sprite, Visibility);
+/// What `EntityBuilder::with_collider_synced` adds.
+pub type SyncedCollider = (Collider, SyncColliderWithSprite);
+
+/// What `EntityBuilder::new().with_sprite(..).with_collider_synced().build()` makes, named so
+/// callers that store or return such batches don't spell out the builder's nesting.
+pub type SyncedSpriteBundle = SpawnBundle<(((), SpriteParts), SyncedCollider)>;
+
+/// Every entity gets a transform and a layer, the rest is whatever the builder added.
+#[derive(Bundle)]
+pub struct SpawnBundle<B: Bundle> {
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub layer: Layer,
+    pub parts: B,
+}
+
+/// `EntityBuilder::new().with_sprite(..).with_transform(pos).with_collider_synced().build()`.
+/// Each call adds to the bundle's type, so every entity built the same way shares one type and
+/// a whole batch can go through `spawn_batch`.
+pub struct EntityBuilder<B: Bundle = ()> {
+    parts: B,
+    transform: Transform,
+    layer: Layer,
+    has_sprite: bool,
+}
+
+impl Default for EntityBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntityBuilder {
+    pub fn new() -> Self {
+        Self {
+            parts: (),
+            transform: Transform::default(),
+            layer: Layer::default(),
+            has_sprite: false,
+        }
+    }
+}
+
+impl<B: Bundle> EntityBuilder<B> {
+    /// Adds any other components, for what the named methods don't cover.
+    pub fn with<C: Bundle>(self, components: C) -> EntityBuilder<(B, C)> {
+        EntityBuilder {
+            parts: (self.parts, components),
+            transform: self.transform,
+            layer: self.layer,
+            has_sprite: self.has_sprite,
+        }
+    }
+
+    pub fn with_sprite(
+        self,
+        kind: SpriteKind,
+        color: Color,
+        origin: SpriteOrigin,
+    ) -> EntityBuilder<(B, SpriteParts)> {
+        debug_assert!(!self.has_sprite, "sprite set twice");
+        let sprite = Sprite {
+            kind,
+            color,
+            origin,
+            ..Default::default()
+        };
+        let mut builder = self.with((sprite, Visibility::Visible));
+        builder.has_sprite = true;
+        builder
+    }
+
+    pub fn with_transform(mut self, position: Vector2) -> Self {
+        self.transform.position = position;
+        self
+    }
+
+    pub fn with_velocity(self, velocity: Vector2) -> EntityBuilder<(B, Velocity)> {
+        self.with(Velocity(velocity))
+    }
+
+    /// Collider that follows the sprite's size and origin, see `sync_collider_with_sprite_system`.
+    pub fn with_collider_synced(self) -> EntityBuilder<(B, SyncedCollider)> {
+        debug_assert!(self.has_sprite, "a synced collider needs a sprite first");
+        self.with((Collider::default(), SyncColliderWithSprite))
+    }
+
+    pub fn with_layer(mut self, layer: u32) -> Self {
+        debug_assert!(self.has_sprite, "layers only order sprites, set one first");
+        self.layer = Layer(layer);
+        self
+    }
+
+    pub fn as_player(self) -> EntityBuilder<(B, Player)> {
+        self.with(Player)
+    }
+
+    /// Visible from the first frame, before `update_on_screen_system` gets to it.
+    pub fn on_screen(self) -> EntityBuilder<(B, OnScreen)> {
+        debug_assert!(self.has_sprite, "only sprites are culled, set one first");
+        self.with(OnScreen)
+    }
+
+    /// The `GlobalTransform` starts out matching, like `SpriteBundle::new`.
+    pub fn build(self) -> SpawnBundle<B> {
+        SpawnBundle {
+            global_transform: GlobalTransform::from_root(&self.transform),
+            transform: self.transform,
+            layer: self.layer,
+            parts: self.parts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_adds_only_what_was_asked_for() {
+        let mut world = World::new();
+        let player = world
+            .spawn(
+                EntityBuilder::new()
+                    .with_sprite(SpriteKind::default(), Color::RED, SpriteOrigin::Center)
+                    .with_transform(Vector2::new(50.0, 60.0))
+                    .with_velocity(Vector2::new(1.0, 0.0))
+                    .with_collider_synced()
+                    .with_layer(3)
+                    .as_player()
+                    .on_screen()
+                    .build(),
+            )
+            .id();
+        let player = world.entity(player);
+        assert!(player.contains::<Player>() && player.contains::<OnScreen>());
+        assert!(player.contains::<SyncColliderWithSprite>());
+        assert_eq!(player.get::<Layer>(), Some(&Layer(3)));
+        assert_eq!(player.get::<Velocity>().unwrap().0, Vector2::new(1.0, 0.0));
+        assert_eq!(
+            player.get::<GlobalTransform>().unwrap().position,
+            Vector2::new(50.0, 60.0)
+        );
+
+        // A bare build still has a transform and nothing else from the list
+        let bare = world.spawn(EntityBuilder::new().build()).id();
+        let bare = world.entity(bare);
+        assert!(bare.contains::<Transform>() && bare.contains::<GlobalTransform>());
+        assert!(!bare.contains::<Sprite>() && !bare.contains::<Velocity>());
+    }
+
+    #[test]
+    fn test_synced_sprite_bundle_matches_the_builder() {
+        // Fails to compile if the builder's nesting drifts from the alias
+        let built: SyncedSpriteBundle = EntityBuilder::new()
+            .with_sprite(SpriteKind::default(), Color::GREEN, SpriteOrigin::Center)
+            .with_collider_synced()
+            .build();
+        let mut world = World::new();
+        let entity = world.spawn(built).id();
+        assert!(world.entity(entity).contains::<SyncColliderWithSprite>());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "needs a sprite first")]
+    fn test_synced_collider_without_sprite_panics() {
+        let _ = EntityBuilder::new().with_collider_synced();
+    }
+}
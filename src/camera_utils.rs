@@ -0,0 +1,160 @@
+use rustyray::prelude::*;
+
+/// How far past the view `CameraViewRect` reaches by default, as a fraction of its size on
+/// every side, so things are culled well after they've left.
+pub const CULLING_MARGIN: f32 = 0.5;
+
+/// World-space rect the camera shows in a `window_size` window. Raylib draws `target` at
+/// `offset` on screen and scales around it by `zoom`, so the top left corner of the window
+/// is `offset / zoom` before the target. Rotation is ignored.
+pub fn camera_viewport_rect(camera: &Camera2D, window_size: Vector2i) -> Rectangle {
+    Rectangle {
+        x: camera.target.x - camera.offset.x / camera.zoom,
+        y: camera.target.y - camera.offset.y / camera.zoom,
+        width: window_size.x as f32 / camera.zoom,
+        height: window_size.y as f32 / camera.zoom,
+    }
+}
+
+/// World position under `screen`, the inverse of how raylib draws with `camera`.
+pub fn screen_to_world(camera: &Camera2D, screen: Vector2) -> Vector2 {
+    Vector2::new(
+        camera.target.x + (screen.x - camera.offset.x) / camera.zoom,
+        camera.target.y + (screen.y - camera.offset.y) / camera.zoom,
+    )
+}
+
+/// `rect` grown by `fraction` of its size on every side.
+pub fn grow_rect(rect: Rectangle, fraction: f32) -> Rectangle {
+    let (dx, dy) = (rect.width * fraction, rect.height * fraction);
+    Rectangle {
+        x: rect.x - dx,
+        y: rect.y - dy,
+        width: rect.width + dx * 2.0,
+        height: rect.height + dy * 2.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(r: Rectangle) -> (f32, f32, f32, f32) {
+        (r.x, r.y, r.width, r.height)
+    }
+
+    #[test]
+    fn test_viewport_rect() {
+        let mut camera = Camera2D {
+            target: Vector2::new(100.0, -50.0),
+            offset: Vector2::new(400.0, 300.0),
+            zoom: 2.0,
+            ..Default::default()
+        };
+        let size = Vector2i { x: 800, y: 600 };
+        assert_eq!(
+            rect(camera_viewport_rect(&camera, size)),
+            (-100.0, -200.0, 400.0, 300.0)
+        );
+        assert_eq!(
+            rect(grow_rect(
+                camera_viewport_rect(&camera, size),
+                CULLING_MARGIN
+            )),
+            (-300.0, -350.0, 800.0, 600.0)
+        );
+        // Target drawn at the top left corner
+        camera.offset = Vector2::new(0.0, 0.0);
+        assert_eq!(
+            rect(camera_viewport_rect(&camera, size)),
+            (100.0, -50.0, 400.0, 300.0)
+        );
+    }
+
+    /// The rect's corners have to land on the window's corners through the same transform
+    /// raylib draws with, for any zoom, offset and size.
+    #[test]
+    fn test_viewport_corners_map_to_window_corners() {
+        let to_screen = |camera: &Camera2D, x: f32, y: f32| {
+            (
+                (x - camera.target.x) * camera.zoom + camera.offset.x,
+                (y - camera.target.y) * camera.zoom + camera.offset.y,
+            )
+        };
+        let close =
+            |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).abs() < 1e-2 && (a.1 - b.1).abs() < 1e-2;
+        let sizes = [(1024, 768), (1280, 720), (320, 1000), (1, 1)];
+        for (w, h) in sizes {
+            let offsets = [
+                (w as f32 / 2.0, h as f32 / 2.0),
+                (0.0, 0.0),
+                (w as f32, h as f32),
+                (100.0, 30.0),
+            ];
+            for zoom in [0.25, 0.5, 1.0, 1.5, 4.0] {
+                for (ox, oy) in offsets {
+                    let camera = Camera2D {
+                        target: Vector2::new(-350.0, 1200.0),
+                        offset: Vector2::new(ox, oy),
+                        zoom,
+                        ..Default::default()
+                    };
+                    let view = camera_viewport_rect(&camera, Vector2i { x: w, y: h });
+                    let case = format!("{w}x{h} zoom {zoom} offset ({ox}, {oy})");
+                    assert!(
+                        close(to_screen(&camera, view.x, view.y), (0.0, 0.0)),
+                        "{case}"
+                    );
+                    let bottom_right = (view.x + view.width, view.y + view.height);
+                    assert!(
+                        close(
+                            to_screen(&camera, bottom_right.0, bottom_right.1),
+                            (w as f32, h as f32)
+                        ),
+                        "{case}"
+                    );
+                    // Centered, the target is in the middle of the rect
+                    if (ox, oy) == (w as f32 / 2.0, h as f32 / 2.0) {
+                        let center = (view.x + view.width / 2.0, view.y + view.height / 2.0);
+                        assert!(close(center, (-350.0, 1200.0)), "{case}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_screen_to_world() {
+        let camera = Camera2D {
+            target: Vector2::new(100.0, -50.0),
+            offset: Vector2::new(400.0, 300.0),
+            zoom: 2.0,
+            ..Default::default()
+        };
+        let world = |x, y| {
+            let position = screen_to_world(&camera, Vector2::new(x, y));
+            (position.x, position.y)
+        };
+        assert_eq!(world(400.0, 300.0), (100.0, -50.0));
+        // The window corners are the viewport's corners
+        let view = camera_viewport_rect(&camera, Vector2i { x: 800, y: 600 });
+        assert_eq!(world(0.0, 0.0), (view.x, view.y));
+        assert_eq!(
+            world(800.0, 600.0),
+            (view.x + view.width, view.y + view.height)
+        );
+    }
+
+    #[test]
+    fn test_grow_rect() {
+        let base = Rectangle {
+            x: 10.0,
+            y: 20.0,
+            width: 100.0,
+            height: 40.0,
+        };
+        assert_eq!(rect(grow_rect(base, 0.0)), rect(base));
+        assert_eq!(rect(grow_rect(base, 0.5)), (-40.0, 0.0, 200.0, 80.0));
+        assert_eq!(rect(grow_rect(base, 0.1)), (0.0, 16.0, 120.0, 48.0));
+    }
+}
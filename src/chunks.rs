@@ -0,0 +1,362 @@
+#![allow(dead_code)]
+
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{
+    Collider, ColliderKind, FlashOnEnterView, Sprite, SpriteBundle, SpriteKind, SpriteOrigin,
+    SyncColliderWithSprite, Transform, Velocity,
+};
+use crate::resources::CameraViewRect;
+use crate::utils::HashMap;
+
+/// Owned by the chunk at that id, despawned with it when it unloads.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunked(pub (i32, i32));
+
+/// What survives an unload, enough to spawn the entity again. One line of text each, see
+/// `encode`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SavedEntity {
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+    pub size: (f32, f32),
+    pub color: [u8; 4],
+}
+
+impl SavedEntity {
+    /// `x y vx vy width height r g b a`, floats round trip exactly.
+    pub fn encode(&self) -> String {
+        let [r, g, b, a] = self.color;
+        format!(
+            "{} {} {} {} {} {} {r} {g} {b} {a}",
+            self.position.0,
+            self.position.1,
+            self.velocity.0,
+            self.velocity.1,
+            self.size.0,
+            self.size.1,
+        )
+    }
+
+    pub fn decode(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [x, y, vx, vy, width, height, r, g, b, a] = fields.as_slice() else {
+            return None;
+        };
+        let float = |field: &str| field.parse::<f32>().ok();
+        let byte = |field: &str| field.parse::<u8>().ok();
+        Some(Self {
+            position: (float(x)?, float(y)?),
+            velocity: (float(vx)?, float(vy)?),
+            size: (float(width)?, float(height)?),
+            color: [byte(r)?, byte(g)?, byte(b)?, byte(a)?],
+        })
+    }
+
+    fn bundle(&self, chunk: (i32, i32)) -> impl Bundle {
+        let [r, g, b, a] = self.color;
+        let position = Vector2::new(self.position.0, self.position.1);
+        let sprite = SpriteBundle::new(
+            Sprite {
+                kind: SpriteKind::Rectangle {
+                    size: self.size,
+                    lines: false,
+                },
+                origin: SpriteOrigin::TopLeft,
+                color: Color::new(r, g, b, a),
+                opacity: 1.0,
+            },
+            Transform::default().with_position(position),
+        );
+        let collider = Collider {
+            kind: ColliderKind::Rectangle(Vector2::new(self.size.0, self.size.1)),
+            ..Default::default()
+        };
+        let flash = FlashOnEnterView;
+        (
+            sprite,
+            collider,
+            SyncColliderWithSprite,
+            flash,
+            Chunked(chunk),
+        )
+    }
+
+    /// Spawns the entity, only movers get a `Velocity` so the rest stay static geometry.
+    fn spawn(&self, chunk: (i32, i32), commands: &mut Commands) -> Entity {
+        let mut entity = commands.spawn(self.bundle(chunk));
+        if self.velocity != (0.0, 0.0) {
+            entity.insert(Velocity(Vector2::new(self.velocity.0, self.velocity.1)));
+        }
+        entity.id()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WorldChunk {
+    pub id: (i32, i32),
+    /// Live entities while loaded.
+    pub entities: Vec<Entity>,
+    pub loaded: bool,
+    /// State of the entities while unloaded.
+    pub saved: Vec<SavedEntity>,
+}
+
+impl WorldChunk {
+    /// Every saved entity, one line each.
+    pub fn encode(&self) -> String {
+        self.saved
+            .iter()
+            .map(|saved| saved.encode() + "\n")
+            .collect()
+    }
+
+    pub fn decode(id: (i32, i32), text: &str) -> Option<Self> {
+        Some(Self {
+            id,
+            saved: text
+                .lines()
+                .map(SavedEntity::decode)
+                .collect::<Option<_>>()?,
+            ..Default::default()
+        })
+    }
+}
+
+/// Chunks within `loaded_radius` chunks of the ones the view touches are kept spawned,
+/// the rest only exist as saved state.
+#[derive(Resource, Debug)]
+pub struct ChunkManager {
+    pub chunk_size: f32,
+    pub loaded_radius: i32,
+    pub chunks: HashMap<(i32, i32), WorldChunk>,
+}
+
+impl Default for ChunkManager {
+    fn default() -> Self {
+        Self::new(1024.0, 1)
+    }
+}
+
+impl ChunkManager {
+    pub fn new(chunk_size: f32, loaded_radius: i32) -> Self {
+        Self {
+            chunk_size,
+            loaded_radius,
+            chunks: HashMap::default(),
+        }
+    }
+
+    pub fn chunk_id(&self, position: (f32, f32)) -> (i32, i32) {
+        (
+            (position.0 / self.chunk_size).floor() as i32,
+            (position.1 / self.chunk_size).floor() as i32,
+        )
+    }
+
+    /// Adds an entity to its chunk's saved state, it's spawned once the chunk loads.
+    pub fn add(&mut self, saved: SavedEntity) {
+        let id = self.chunk_id(saved.position);
+        let chunk = self.chunks.entry(id).or_insert_with(|| WorldChunk {
+            id,
+            ..Default::default()
+        });
+        chunk.saved.push(saved);
+    }
+
+    pub fn loaded_count(&self) -> usize {
+        self.chunks.values().filter(|chunk| chunk.loaded).count()
+    }
+
+    /// Whether chunk `id` should be loaded with the view at `view`.
+    pub fn in_range(&self, id: (i32, i32), view: Rectangle) -> bool {
+        let min = self.chunk_id((view.x, view.y));
+        let max = self.chunk_id((view.x + view.width, view.y + view.height));
+        let radius = self.loaded_radius;
+        (min.0 - radius..=max.0 + radius).contains(&id.0)
+            && (min.1 - radius..=max.1 + radius).contains(&id.1)
+    }
+}
+
+/// Spawns the saved entities of chunks coming into range and saves then despawns the ones of
+/// chunks leaving it. Entities keep moving while loaded, so they're saved where they ended up,
+/// even if that's past their chunk's edge.
+pub fn chunk_streaming_system(
+    mut manager: ResMut<ChunkManager>,
+    view: Res<CameraViewRect>,
+    chunked: Query<(&Transform, &Sprite, Option<&Velocity>), With<Chunked>>,
+    mut commands: Commands,
+) {
+    let view = view.0;
+    let changes: Vec<((i32, i32), bool)> = manager
+        .chunks
+        .iter()
+        .filter_map(|(&id, chunk)| {
+            let in_range = manager.in_range(id, view);
+            (in_range != chunk.loaded).then_some((id, in_range))
+        })
+        .collect();
+
+    for (id, load) in changes {
+        let chunk = manager.chunks.get_mut(&id).unwrap();
+        chunk.loaded = load;
+        if load {
+            chunk.entities = chunk
+                .saved
+                .drain(..)
+                .map(|saved| saved.spawn(id, &mut commands))
+                .collect();
+            continue;
+        }
+        for entity in chunk.entities.drain(..) {
+            let Ok((transform, sprite, velocity)) = chunked.get(entity) else {
+                // Despawned while loaded, nothing to save
+                continue;
+            };
+            // Only rectangles can be saved, anything else still leaves with its chunk
+            if let SpriteKind::Rectangle { size, .. } = sprite.kind {
+                let velocity = velocity.map_or(Vector2::new(0.0, 0.0), |v| v.0);
+                // Opacity is baked into the alpha, a faded sprite comes back as faded
+                let color = sprite.with_opacity(sprite.color);
+                chunk.saved.push(SavedEntity {
+                    position: (transform.position.x, transform.position.y),
+                    velocity: (velocity.x, velocity.y),
+                    size,
+                    color: [color.r, color.g, color.b, color.a],
+                });
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// A field of boxes past the left edge of the grid that only exists while near the camera.
+pub fn demo_chunks() -> ChunkManager {
+    let mut manager = ChunkManager::default();
+    for row in 0..200 {
+        for column in 0..100 {
+            manager.add(SavedEntity {
+                position: (-200.0 - 40.0 * column as f32, 100.0 + 40.0 * row as f32),
+                velocity: (0.0, 0.0),
+                size: (24.0, 24.0),
+                color: [90, 140, 220, 255],
+            });
+        }
+    }
+    manager
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::{ActiveCamera, Camera};
+    use crate::resources::WindowSize;
+    use crate::spatial_hash::{CollisionHash, RenderHash, SpatialHash};
+    use crate::sweep_prune::CollisionSweep;
+    use crate::systems::{add_spatial_hash_observers, compute_camera_view_system};
+
+    fn saved(x: f32, y: f32) -> SavedEntity {
+        SavedEntity {
+            position: (x, y),
+            velocity: (0.0, 0.0),
+            size: (10.0, 10.0),
+            color: [1, 2, 3, 255],
+        }
+    }
+
+    #[test]
+    fn test_saved_entities_round_trip_as_text() {
+        let mut chunk = WorldChunk {
+            id: (3, -1),
+            ..Default::default()
+        };
+        chunk.saved.push(saved(0.1, -2048.5));
+        chunk.saved.push(SavedEntity {
+            velocity: (1.0 / 3.0, -7.25),
+            ..saved(5.0, 6.0)
+        });
+        let decoded = WorldChunk::decode((3, -1), &chunk.encode()).unwrap();
+        assert_eq!(decoded.saved, chunk.saved);
+        assert!(!decoded.loaded);
+        assert!(SavedEntity::decode("1 2 3").is_none());
+        assert!(SavedEntity::decode("1 2 3 4 5 6 7 8 9 256").is_none());
+    }
+
+    #[test]
+    fn test_chunks_stream_with_the_camera() {
+        let mut world = World::new();
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<CameraViewRect>();
+        let camera = world
+            .spawn((
+                Camera(Camera2D {
+                    offset: Vector2::new(50.0, 50.0),
+                    zoom: 1.0,
+                    ..Default::default()
+                }),
+                ActiveCamera,
+            ))
+            .id();
+        // The view covers -100..100, so chunk 0 and its neighbors load with a radius of 1
+        let mut manager = ChunkManager::new(256.0, 1);
+        manager.add(saved(10.0, 10.0));
+        manager.add(saved(600.0, 10.0));
+        manager.add(saved(620.0, 10.0));
+        world.insert_resource(manager);
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<CollisionSweep>();
+        add_spatial_hash_observers(&mut world);
+        let mut schedule = Schedule::default();
+        schedule.add_systems((compute_camera_view_system, chunk_streaming_system).chain());
+        let live = |world: &mut World| world.query::<&Chunked>().iter(world).count();
+
+        schedule.run(&mut world);
+        assert_eq!(live(&mut world), 1);
+        assert_eq!(world.resource::<ChunkManager>().loaded_count(), 1);
+
+        // Moved while loaded, the move survives the unload
+        let near = world.resource::<ChunkManager>().chunks[&(0, 0)].entities[0];
+        world.get_mut::<Transform>(near).unwrap().position.x = 42.0;
+        let rect = Rectangle {
+            x: 42.0,
+            y: 10.0,
+            width: 10.0,
+            height: 10.0,
+        };
+        world.resource_mut::<CollisionHash>().insert(near, rect);
+        world.resource_mut::<RenderHash>().insert(near, rect);
+
+        world.get_mut::<Camera>(camera).unwrap().0.target = Vector2::new(700.0, 0.0);
+        schedule.run(&mut world);
+        assert_eq!(live(&mut world), 2);
+        assert!(world.get_entity(near).is_err());
+        // Gone from the hashes without waiting for a physics tick
+        assert!(!world.resource::<CollisionHash>().contains(near));
+        assert!(!world.resource::<RenderHash>().contains(near));
+        let manager = world.resource::<ChunkManager>();
+        assert_eq!(manager.chunks[&(0, 0)].saved[0].position, (42.0, 10.0));
+        assert!(manager.chunks[&(2, 0)].loaded);
+
+        // A faded box keeps its fade, a sprite that can't be saved is still despawned
+        let [faded, circle] = manager.chunks[&(2, 0)].entities[..] else {
+            panic!("chunk (2, 0) should hold two entities");
+        };
+        world.get_mut::<Sprite>(faded).unwrap().opacity = 0.5;
+        world.get_mut::<Sprite>(circle).unwrap().kind = SpriteKind::Circle { radius: 5.0 };
+
+        world.get_mut::<Camera>(camera).unwrap().0.target = Vector2::new(0.0, 0.0);
+        schedule.run(&mut world);
+        let positions: Vec<f32> = world
+            .query_filtered::<&Transform, With<Chunked>>()
+            .iter(&world)
+            .map(|t| t.position.x)
+            .collect();
+        assert_eq!(positions, vec![42.0]);
+        assert!(world.get_entity(circle).is_err());
+        let saved = &world.resource::<ChunkManager>().chunks[&(2, 0)].saved;
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].color, [1, 2, 3, 127]);
+    }
+}
@@ -3,6 +3,8 @@
 use bevy_ecs::prelude::*;
 use rustyray::prelude::*;
 
+use crate::assets::TextureId;
+
 #[derive(Component)]
 pub struct Camera(pub Camera2D);
 
@@ -26,11 +28,117 @@ pub struct ActiveCamera;
 #[derive(Component)]
 pub struct CameraTarget;
 
+/// Zoom the camera eases towards, the mouse wheel only moves `target`.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct CameraZoom {
+    pub target: f32,
+    /// Higher is snappier, roughly the inverse of the time to cover 63% of the gap.
+    pub speed: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Default for CameraZoom {
+    fn default() -> Self {
+        Self {
+            target: 1.0,
+            speed: 12.0,
+            min: 0.3,
+            max: 5.0,
+        }
+    }
+}
+
+impl CameraZoom {
+    /// Exponential ease, covers the same share of the gap per second at any frame rate.
+    pub fn ease(&self, current: f32, dt: f32) -> f32 {
+        let target = self.target.clamp(self.min, self.max);
+        let t = 1.0 - (-self.speed * dt).exp();
+        (current + (target - current) * t).clamp(self.min, self.max)
+    }
+}
+
+/// Spring pulling the camera towards its target. `velocity` carries over between frames.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct CameraSpring {
+    pub velocity: Vector2,
+    pub stiffness: f32,
+    pub damping: f32,
+}
+
+impl CameraSpring {
+    /// Longest step integrated at once, bigger frames are split so stiff springs stay stable.
+    const MAX_STEP: f32 = 1.0 / 120.0;
+
+    /// Fastest settle without overshooting.
+    pub fn critically_damped(stiffness: f32) -> Self {
+        Self {
+            velocity: Vector2::new(0.0, 0.0),
+            stiffness,
+            damping: 2.0 * stiffness.sqrt(),
+        }
+    }
+
+    /// Advances `position` towards `target` by `dt` seconds (semi-implicit Euler).
+    pub fn step(&mut self, mut position: Vector2, target: Vector2, dt: f32) -> Vector2 {
+        let steps = (dt / Self::MAX_STEP).ceil().max(1.0);
+        let h = dt / steps;
+        for _ in 0..steps as u32 {
+            let acceleration = (target - position) * self.stiffness - self.velocity * self.damping;
+            self.velocity += acceleration * h;
+            position += self.velocity * h;
+        }
+        position
+    }
+}
+
+/// Framed target position at the last two physics ticks. The camera follows the point
+/// between them matching the render frame, instead of jumping once per tick.
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct CameraInterpolation {
+    pub previous: Vector2,
+    pub current: Vector2,
+    primed: bool,
+}
+
+impl CameraInterpolation {
+    /// Records this tick's target, the first one fills both ends so nothing lerps in from zero.
+    pub fn push(&mut self, target: Vector2) {
+        self.previous = match self.primed {
+            true => self.current,
+            false => target,
+        };
+        self.current = target;
+        self.primed = true;
+    }
+
+    pub fn at(&self, alpha: f32) -> Vector2 {
+        self.previous + (self.current - self.previous) * alpha
+    }
+}
+
 #[allow(dead_code)]
 pub enum SpriteKind {
-    Rectangle { size: (f32, f32), lines: bool },
-    Circle { radius: f32 },
-    Texture { texture: OwnedTexture },
+    Rectangle {
+        size: (f32, f32),
+        lines: bool,
+    },
+    Circle {
+        radius: f32,
+    },
+    Texture {
+        texture: OwnedTexture,
+    },
+    /// Texture from `TextureAssets`, stretched to `size` so reloads can't change the bounds.
+    Asset {
+        id: TextureId,
+        size: (f32, f32),
+    },
+    /// Tile `id` of the `TilesetResource`, stretched to `size`.
+    Tile {
+        id: u32,
+        size: (f32, f32),
+    },
 }
 
 impl Default for SpriteKind {
@@ -63,6 +171,9 @@ pub struct Sprite {
     pub kind: SpriteKind,
     pub origin: SpriteOrigin,
     pub color: Color,
+    /// 0 to 1, scales `color`'s alpha. Fades only need to touch this, e.g. with a
+    /// `Tween<Sprite>` on `tween_field!(Sprite, opacity)`.
+    pub opacity: f32,
 }
 
 impl Default for Sprite {
@@ -71,13 +182,49 @@ impl Default for Sprite {
             kind: SpriteKind::default(),
             origin: SpriteOrigin::default(),
             color: Color::GREEN,
+            opacity: 1.0,
         }
     }
 }
 
 impl Sprite {
+    /// World-space bounds used to index the sprite, `None` for kinds that aren't indexed.
+    pub fn bounds(&self, transform: &GlobalTransform) -> Option<Rectangle> {
+        let origin = self.get_origin_vector();
+        match &self.kind {
+            SpriteKind::Rectangle { size: shape, .. }
+            | SpriteKind::Asset { size: shape, .. }
+            | SpriteKind::Tile { size: shape, .. } => Some(Rectangle {
+                x: transform.position.x - (shape.0 * transform.scale.x) * origin.x,
+                y: transform.position.y - (shape.1 * transform.scale.y) * origin.y,
+                width: shape.0 * transform.scale.x,
+                height: shape.1 * transform.scale.y,
+            }),
+            SpriteKind::Circle { radius, .. } => Some(Rectangle {
+                x: transform.position.x - (radius * transform.scale.x) * origin.x,
+                y: transform.position.y - (radius * transform.scale.y) * origin.y,
+                width: radius * transform.scale.x,
+                height: radius * transform.scale.y,
+            }),
+            _ => None,
+        }
+    }
+
     pub fn get_origin_vector(&self) -> Vector2 {
-        match self.origin {
+        self.origin.to_vector()
+    }
+
+    /// `color` with its alpha scaled by `opacity`.
+    pub fn with_opacity(&self, color: Color) -> Color {
+        let alpha = color.a as f32 * self.opacity.clamp(0.0, 1.0);
+        Color::new(color.r, color.g, color.b, alpha as u8)
+    }
+}
+
+impl SpriteOrigin {
+    /// Normalized point inside the bounds, (0, 0) is the top left corner.
+    pub fn to_vector(&self) -> Vector2 {
+        match *self {
             SpriteOrigin::TopLeft => Vector2::new(0.0, 0.0),
             SpriteOrigin::Top => Vector2::new(0.5, 0.0),
             SpriteOrigin::TopRight => Vector2::new(1.0, 0.0),
@@ -96,13 +243,58 @@ impl Sprite {
     }
 }
 
+/// Multiplied with `Sprite::color` at draw time so fading or flashing doesn't touch the sprite.
+#[derive(Component, Clone, Copy)]
+pub struct Tint(pub Color);
+
+impl Tint {
+    /// Componentwise multiply, alpha included.
+    pub fn apply(&self, color: Color) -> Color {
+        let mul = |a: u8, b: u8| ((a as u16 * b as u16 + 127) / 255) as u8;
+        Color::new(
+            mul(color.r, self.0.r),
+            mul(color.g, self.0.g),
+            mul(color.b, self.0.b),
+            mul(color.a, self.0.a),
+        )
+    }
+}
+
+/// Color a sprite should be drawn with, taking an optional `Tint` into account.
+pub fn tinted_color(sprite: &Sprite, tint: Option<&Tint>) -> Color {
+    sprite.with_opacity(tint.map_or(sprite.color, |tint| tint.apply(sprite.color)))
+}
+
 #[derive(Component)]
 #[component(storage = "SparseSet")]
 pub struct OnScreen;
 
+pub const VIEW_FLASH_SECONDS: f32 = 0.5;
+
+/// Opts an entity into the green flash from `flash_on_enter_view_system`.
+#[derive(Component, Clone, Copy)]
+pub struct FlashOnEnterView;
+
+/// Seconds left on the green flash from entering view, the `Tint` from before goes back on
+/// when it runs out.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct ViewFlash {
+    pub remaining: f32,
+    pub previous: Option<Tint>,
+}
+
 #[derive(Debug)]
 pub enum ColliderKind {
     Rectangle(Vector2),
+    /// Radius, scaled by the larger of the two scale axes.
+    Circle(f32),
+    /// Upright capsule, `half_height` is the straight part above and below the center.
+    /// Rounds off corners it walks into, so seams between tiles don't snag it.
+    Capsule {
+        radius: f32,
+        half_height: f32,
+    },
 }
 
 impl Default for ColliderKind {
@@ -111,18 +303,220 @@ impl Default for ColliderKind {
     }
 }
 
+impl ColliderKind {
+    /// Capsule whose bounds are `size` once scaled by `scale`, as wide as the box and with
+    /// the ends rounded off. A box wider than it is tall gets a circle as tall as the box.
+    pub fn capsule_fitting(size: Vector2, scale: Vector2) -> Self {
+        // The radius scales with x, so clamp it in scaled space against the shorter side
+        let scaled_radius = (size.x * scale.x).min(size.y * scale.y) / 2.0;
+        let radius = if scale.x != 0.0 {
+            scaled_radius / scale.x
+        } else {
+            size.x / 2.0
+        };
+        let half_height = if scale.y != 0.0 {
+            ((size.y * scale.y / 2.0 - radius * scale.x) / scale.y).max(0.0)
+        } else {
+            0.0
+        };
+        Self::Capsule {
+            radius,
+            half_height,
+        }
+    }
+}
+
+/// Movers with a higher priority are resolved first, so they win squeezes against lower ones.
+#[derive(Debug, Component, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CollisionPriority(pub i32);
+
+/// Resolves this mover as one rigid body made of every collider in its hierarchy, its own
+/// included. The children shouldn't have a `Velocity` of their own.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct CompoundCollider;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ForceFalloff {
+    /// Full force anywhere in the field.
+    #[default]
+    None,
+    /// Full in the center, fading to nothing at the edges.
+    Linear,
+}
+
+/// Pushes the movers overlapping the entity's `Collider`, see `apply_force_fields_system`.
+/// The collider only marks the volume, nothing collides with it.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct ForceField {
+    /// Acceleration, in pixels per second squared.
+    pub force: Vector2,
+    pub falloff: ForceFalloff,
+}
+
+impl ForceField {
+    /// Fraction of `force` at `point` for a field covering `area`. Linear falloff goes by
+    /// whichever axis is closer to its edge.
+    pub fn strength(&self, area: &Rectangle, point: Vector2) -> f32 {
+        match self.falloff {
+            ForceFalloff::None => 1.0,
+            ForceFalloff::Linear => {
+                let (half_w, half_h) = (area.width / 2.0, area.height / 2.0);
+                let dx = (point.x - (area.x + half_w)).abs() / half_w.max(f32::EPSILON);
+                let dy = (point.y - (area.y + half_h)).abs() / half_h.max(f32::EPSILON);
+                (1.0 - dx.max(dy)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Component, Default)]
 pub struct Collider {
     pub kind: ColliderKind,
     pub offset: Vector2,
 }
 
+impl Collider {
+    /// Size of the collider bounds once scaled.
+    pub fn size(&self, scale: Vector2) -> Vector2 {
+        match self.kind {
+            ColliderKind::Rectangle(size) => Vector2::new(size.x * scale.x, size.y * scale.y),
+            ColliderKind::Circle(radius) => {
+                let diameter = radius * 2.0 * scale.x.max(scale.y);
+                Vector2::new(diameter, diameter)
+            }
+            ColliderKind::Capsule {
+                radius,
+                half_height,
+            } => {
+                let radius = radius * scale.x;
+                Vector2::new(radius * 2.0, (half_height * scale.y + radius) * 2.0)
+            }
+        }
+    }
+
+    /// World-space bounds of the collider for the given transform.
+    pub fn aabb(&self, transform: &GlobalTransform) -> Rectangle {
+        let size = self.size(transform.scale);
+        Rectangle {
+            x: transform.position.x - self.offset.x,
+            y: transform.position.y - self.offset.y,
+            width: size.x,
+            height: size.y,
+        }
+    }
+}
+
+/// Every point within `radius` of the vertical segment from `top` to `bottom`.
+#[derive(Debug, Clone, Copy)]
+pub struct Capsule {
+    pub top: Vector2,
+    pub bottom: Vector2,
+    pub radius: f32,
+}
+
+impl Capsule {
+    /// The capsule filling `bounds`, the way `Collider::aabb` lays out a `ColliderKind::Capsule`.
+    pub fn from_bounds(bounds: &Rectangle) -> Self {
+        let radius = bounds.width / 2.0;
+        let x = bounds.x + radius;
+        let half_height = (bounds.height / 2.0 - radius).max(0.0);
+        let center_y = bounds.y + bounds.height / 2.0;
+        Self {
+            top: Vector2::new(x, center_y - half_height),
+            bottom: Vector2::new(x, center_y + half_height),
+            radius,
+        }
+    }
+
+    /// Closest points between the segment and `rect`, segment side first.
+    pub fn closest_points(&self, rect: &Rectangle) -> (Vector2, Vector2) {
+        let (low, high) = (
+            self.top.y.max(rect.y),
+            self.bottom.y.min(rect.y + rect.height),
+        );
+        let y = if low <= high {
+            (low + high) / 2.0
+        } else if self.bottom.y < rect.y {
+            self.bottom.y
+        } else {
+            self.top.y
+        };
+        let on_segment = Vector2::new(self.top.x, y);
+        let on_rect = Vector2::new(
+            on_segment.x.clamp(rect.x, rect.x + rect.width),
+            on_segment.y.clamp(rect.y, rect.y + rect.height),
+        );
+        (on_segment, on_rect)
+    }
+
+    pub fn collides_rect(&self, rect: &Rectangle) -> bool {
+        self.push_out_of(rect).is_some()
+    }
+
+    /// Smallest move that takes the capsule out of `rect`, `None` if they don't overlap.
+    pub fn push_out_of(&self, rect: &Rectangle) -> Option<Vector2> {
+        let (on_segment, on_rect) = self.closest_points(rect);
+        let delta = on_segment - on_rect;
+        let distance = (delta.x * delta.x + delta.y * delta.y).sqrt();
+        if distance >= self.radius {
+            return None;
+        }
+        if distance > 0.0 {
+            let push = self.radius - distance;
+            return Some(Vector2::new(
+                delta.x / distance * push,
+                delta.y / distance * push,
+            ));
+        }
+
+        // The segment reaches into the rect, the bounds are exact along either axis then
+        let bounds = Rectangle {
+            x: self.top.x - self.radius,
+            y: self.top.y - self.radius,
+            width: self.radius * 2.0,
+            height: self.bottom.y - self.top.y + self.radius * 2.0,
+        };
+        let left = bounds.x + bounds.width - rect.x;
+        let right = rect.x + rect.width - bounds.x;
+        let up = bounds.y + bounds.height - rect.y;
+        let down = rect.y + rect.height - bounds.y;
+        let smallest = left.min(right).min(up).min(down);
+        Some(if smallest == left {
+            Vector2::new(-left, 0.0)
+        } else if smallest == right {
+            Vector2::new(right, 0.0)
+        } else if smallest == up {
+            Vector2::new(0.0, -up)
+        } else {
+            Vector2::new(0.0, down)
+        })
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct ColliderBundle {
     pub collider: Collider,
     pub transform: Transform,
 }
 
+/// Invisible wall: indexed in the collision hash, never rendered or culled.
+#[derive(Bundle, Default)]
+pub struct StaticColliderBundle {
+    pub collider: Collider,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+}
+
+impl StaticColliderBundle {
+    pub fn new(collider: Collider, transform: Transform) -> Self {
+        Self {
+            collider,
+            transform,
+            global_transform: GlobalTransform::from_root(&transform),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Component)]
 pub struct Transform {
     pub position: Vector2,
@@ -147,7 +541,7 @@ impl Default for Transform {
     }
 }
 
-#[derive(Debug, Clone, Copy, Component)]
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
 pub struct GlobalTransform {
     pub position: Vector2,
     pub rotation: f32,
@@ -155,6 +549,15 @@ pub struct GlobalTransform {
 }
 
 impl GlobalTransform {
+    /// NaN or infinite parts would poison sorting and drawing.
+    pub fn is_finite(&self) -> bool {
+        self.position.x.is_finite()
+            && self.position.y.is_finite()
+            && self.rotation.is_finite()
+            && self.scale.x.is_finite()
+            && self.scale.y.is_finite()
+    }
+
     pub fn from_local(parent: &GlobalTransform, local: &Transform) -> Self {
         Self {
             position: parent.position + local.position,
@@ -170,6 +573,78 @@ impl GlobalTransform {
             scale: local.scale,
         }
     }
+
+    /// The local transform that puts a child of `parent` here, the inverse of `from_local`.
+    /// A zero parent scale can't be undone, so `fallback_scale` is kept on that axis.
+    pub fn relative_to(&self, parent: &GlobalTransform, fallback_scale: Vector2) -> Transform {
+        let unscale = |scale: f32, parent: f32, fallback: f32| {
+            if parent == 0.0 {
+                fallback
+            } else {
+                scale / parent
+            }
+        };
+        Transform {
+            position: self.position - parent.position,
+            rotation: (self.rotation - parent.rotation).rem_euclid(360.0),
+            scale: Vector2::new(
+                unscale(self.scale.x, parent.scale.x, fallback_scale.x),
+                unscale(self.scale.y, parent.scale.y, fallback_scale.y),
+            ),
+        }
+    }
+}
+
+/// What `reparent` keeps when an entity changes parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReparentMode {
+    /// `Transform` is rewritten so the entity stays where it is on screen.
+    KeepWorldPosition,
+    /// `Transform` is left alone, so the entity jumps along with its new parent.
+    KeepLocalPosition,
+}
+
+/// Moves `entity` under `new_parent`, or makes it a root with `None`. Works from the last
+/// propagated `GlobalTransform`s, so a parent moved this tick is taken where it was drawn.
+/// Returns false without changing anything if `new_parent` is `entity` or one of its
+/// descendants, or either entity is gone.
+pub fn reparent(
+    world: &mut World,
+    entity: Entity,
+    new_parent: Option<Entity>,
+    mode: ReparentMode,
+) -> bool {
+    if world.get_entity(entity).is_err() {
+        return false;
+    }
+    let mut ancestor = new_parent;
+    while let Some(current) = ancestor {
+        if current == entity || world.get_entity(current).is_err() {
+            return false;
+        }
+        ancestor = world
+            .get::<ChildOf>(current)
+            .map(|child_of| child_of.parent());
+    }
+
+    if mode == ReparentMode::KeepWorldPosition {
+        let global = world.get::<GlobalTransform>(entity).copied();
+        let local = world.get::<Transform>(entity).copied();
+        if let (Some(global), Some(local)) = (global, local) {
+            let parent_global = new_parent
+                .and_then(|parent| world.get::<GlobalTransform>(parent).copied())
+                .unwrap_or_default();
+            world
+                .entity_mut(entity)
+                .insert(global.relative_to(&parent_global, local.scale));
+        }
+    }
+    let mut entity = world.entity_mut(entity);
+    match new_parent {
+        Some(parent) => entity.insert(ChildOf(parent)),
+        None => entity.remove::<ChildOf>(),
+    };
+    true
 }
 
 impl Default for GlobalTransform {
@@ -187,6 +662,231 @@ pub struct Text {
     pub content: String,
     pub font_size: u32,
     pub color: Color,
+    /// Name in `FontLibrary`, `None` or an unknown name draws with the default font.
+    pub font: Option<String>,
+    /// Wraps at spaces to stay within this many pixels, `None` draws a single line.
+    pub max_width: Option<u32>,
+    pub alignment: TextAlign,
+    /// Distance between line tops, in multiples of `font_size`.
+    pub line_spacing: f32,
+    pub shadow: Option<TextShadow>,
+    pub outline: Option<TextOutline>,
+    /// Scratch space for `set_content_if_changed`, swapped with `content` on change.
+    buffer: String,
+}
+
+impl Text {
+    pub fn new(content: impl Into<String>, font_size: u32, color: Color) -> Self {
+        Self {
+            content: content.into(),
+            font_size,
+            color,
+            font: None,
+            max_width: None,
+            alignment: TextAlign::Left,
+            line_spacing: 1.2,
+            shadow: None,
+            outline: None,
+            buffer: String::new(),
+        }
+    }
+
+    pub fn with_font(mut self, font: impl Into<String>) -> Self {
+        self.font = Some(font.into());
+        self
+    }
+
+    pub fn with_max_width(mut self, max_width: u32) -> Self {
+        self.max_width = Some(max_width);
+        self
+    }
+
+    pub fn with_alignment(mut self, alignment: TextAlign) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn with_shadow(mut self, offset: Vector2, color: Color) -> Self {
+        self.shadow = Some(TextShadow { offset, color });
+        self
+    }
+
+    pub fn with_outline(mut self, width: u32, color: Color) -> Self {
+        self.outline = Some(TextOutline { width, color });
+        self
+    }
+
+    /// Offset and color of each time the text is drawn, back to front. The shadow goes
+    /// first, then the outline in all eight directions, then the text itself.
+    pub fn passes(&self) -> Vec<(Vector2, Color)> {
+        let mut passes = Vec::with_capacity(10);
+        if let Some(shadow) = self.shadow {
+            passes.push((shadow.offset, shadow.color));
+        }
+        if let Some(outline) = self.outline {
+            let w = outline.width as f32;
+            for (x, y) in [
+                (-1.0, -1.0),
+                (0.0, -1.0),
+                (1.0, -1.0),
+                (-1.0, 0.0),
+                (1.0, 0.0),
+                (-1.0, 1.0),
+                (0.0, 1.0),
+                (1.0, 1.0),
+            ] {
+                passes.push((Vector2::new(x * w, y * w), outline.color));
+            }
+        }
+        passes.push((Vector2::new(0.0, 0.0), self.color));
+        passes
+    }
+
+    /// Top left corner of every line to draw. The anchor shifts the whole block, then each
+    /// line is shifted by its own width according to `alignment`.
+    pub fn layout(
+        &self,
+        position: Vector2,
+        anchor: Option<&TextAnchor>,
+        measure: impl Fn(&str) -> f32,
+    ) -> Vec<(&str, Vector2)> {
+        let font_size = self.font_size as f32;
+        let line_height = font_size * self.line_spacing;
+        let lines = self.lines(&measure);
+        let widths: Vec<f32> = lines.iter().map(|line| measure(line)).collect();
+        let mut position = position;
+        if let Some(anchor) = anchor {
+            let origin = anchor.0.to_vector();
+            let width = widths.iter().copied().fold(0.0, f32::max);
+            position.x -= width * origin.x;
+            let height = line_height * (lines.len() - 1) as f32 + font_size;
+            position.y -= height * origin.y;
+        }
+        let fraction = self.alignment.fraction();
+        lines
+            .into_iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(index, (line, width))| {
+                let x = position.x - width * fraction;
+                (
+                    line,
+                    Vector2::new(x, position.y + line_height * index as f32),
+                )
+            })
+            .collect()
+    }
+
+    /// Splits `content` into the lines to draw, `measure` returns a string's width in pixels.
+    /// Every `\n` starts a new line. With `max_width` words also break at spaces, one wider
+    /// than `max_width` gets a line to itself.
+    pub fn lines(&self, measure: impl Fn(&str) -> f32) -> Vec<&str> {
+        let mut lines = Vec::new();
+        for paragraph in self.content.split('\n') {
+            match self.max_width {
+                Some(max_width) => wrap_line(paragraph, max_width as f32, &measure, &mut lines),
+                None => lines.push(paragraph),
+            }
+        }
+        lines
+    }
+
+    /// Formats into the reused buffer and only replaces `content` when the bytes differ.
+    /// Returns whether it changed. Call through `bypass_change_detection` and `set_changed`
+    /// on `true`, otherwise the `Mut` access alone flags the text.
+    pub fn set_content_if_changed(&mut self, args: std::fmt::Arguments) -> bool {
+        use std::fmt::Write;
+
+        self.buffer.clear();
+        // Writing to a String can't fail
+        let _ = self.buffer.write_fmt(args);
+        if self.buffer == self.content {
+            return false;
+        }
+        std::mem::swap(&mut self.buffer, &mut self.content);
+        true
+    }
+}
+
+fn wrap_line<'a>(
+    line: &'a str,
+    max_width: f32,
+    measure: &impl Fn(&str) -> f32,
+    lines: &mut Vec<&'a str>,
+) {
+    let mut start = 0;
+    let mut end = 0;
+    for (index, _) in line.match_indices(' ').chain([(line.len(), "")]) {
+        if end > start && measure(&line[start..index]) > max_width {
+            lines.push(&line[start..end]);
+            // Skip the space the line broke at
+            start = end + 1;
+        }
+        end = index;
+    }
+    lines.push(&line[start..]);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextShadow {
+    pub offset: Vector2,
+    pub color: Color,
+}
+
+/// Drawn by repeating the text `width` pixels away in every direction.
+#[derive(Debug, Clone, Copy)]
+pub struct TextOutline {
+    pub width: u32,
+    pub color: Color,
+}
+
+/// Horizontal alignment of each line around the text's position.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    /// Share of a line's width drawn left of the position.
+    pub fn fraction(&self) -> f32 {
+        match self {
+            TextAlign::Left => 0.0,
+            TextAlign::Center => 0.5,
+            TextAlign::Right => 1.0,
+        }
+    }
+}
+
+/// Which point of the text sits on its position, top left when missing.
+#[derive(Component)]
+pub struct TextAnchor(pub SpriteOrigin);
+
+impl Default for TextAnchor {
+    fn default() -> Self {
+        Self(SpriteOrigin::TopLeft)
+    }
+}
+
+#[derive(Bundle)]
+pub struct TextBundle {
+    pub text: Text,
+    pub transform: Transform,
+    pub global_transform: GlobalTransform,
+    pub anchor: TextAnchor,
+}
+
+impl TextBundle {
+    pub fn new(text: Text, transform: Transform) -> Self {
+        Self {
+            text,
+            transform,
+            global_transform: GlobalTransform::from_root(&transform),
+            anchor: TextAnchor::default(),
+        }
+    }
 }
 
 #[derive(Component)]
@@ -197,20 +897,236 @@ pub struct OnScreenText;
 #[derive(Component)]
 pub struct Player;
 
-#[derive(Component, Default)]
+/// Looked up through `NameRegistry`. Immutable so the registry can't go stale, insert a new
+/// one to rename.
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+#[component(immutable)]
+pub struct EntityName(pub String);
+
+/// `player (12v1)` for logs, just the id without an `EntityName`.
+pub fn entity_label(entity: Entity, name: Option<&EntityName>) -> String {
+    match name {
+        Some(name) => format!("{} ({entity})", name.0),
+        None => format!("{entity}"),
+    }
+}
+
+/// Brightens a circle around the entity on the lighting layer, fading out towards `radius`.
+#[derive(Component, Clone, Copy)]
+pub struct PointLight {
+    pub color: Color,
+    pub radius: f32,
+    pub intensity: f32,
+}
+
+impl PointLight {
+    /// Color in the middle of the splat, `intensity` scales it up to full brightness.
+    pub fn center_color(&self) -> Color {
+        let scale = |c: u8| (c as f32 * self.intensity).round().clamp(0.0, 255.0) as u8;
+        Color::new(
+            scale(self.color.r),
+            scale(self.color.g),
+            scale(self.color.b),
+            255,
+        )
+    }
+
+    pub fn bounds(&self, center: Vector2) -> Rectangle {
+        Rectangle {
+            x: center.x - self.radius,
+            y: center.y - self.radius,
+            width: self.radius * 2.0,
+            height: self.radius * 2.0,
+        }
+    }
+}
+
+/// Entities with `Vision` clear the fog of war around them. They're looked up through the
+/// render hash, so they need a `Sprite` too.
+#[derive(Component)]
+#[require(VisionRadius)]
+pub struct Vision;
+
+#[derive(Debug, Component, Clone, Copy, PartialEq)]
+pub struct VisionRadius(pub f32);
+
+impl Default for VisionRadius {
+    fn default() -> Self {
+        Self(200.0)
+    }
+}
+
+#[derive(Debug, Component, Clone, Copy)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    /// Share of `max` left, 0 to 1.
+    pub fn fraction(&self) -> f32 {
+        match self.max {
+            max if max > 0.0 => (self.current / max).clamp(0.0, 1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Damages whatever `Health` it collides with, at most once per `cooldown` seconds per target.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct DamageOnContact {
+    pub amount: f32,
+    pub cooldown: f32,
+}
+
+/// Child rectangle sprite drawn `width` wide at full health, shrunk with its parent's `Health`.
+#[derive(Debug, Component, Clone, Copy)]
+pub struct HealthBar {
+    pub width: f32,
+}
+
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Layer(pub u32);
 
+/// Transform hasn't changed for a while, so `update_global_transforms_system` leaves it alone
+/// until it does. Added and removed by `mark_static_transforms_system`, only on roots without
+/// children.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct StaticTransform;
+
+/// Union of the world-space sprite bounds of a hierarchy, kept on its root by
+/// `update_subtree_bounds_system` while `CullingSettings::inherit_from_root` is on.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SubtreeBounds(pub Rectangle);
+
+impl SubtreeBounds {
+    pub fn union(a: Rectangle, b: Rectangle) -> Rectangle {
+        let (x, y) = (a.x.min(b.x), a.y.min(b.y));
+        Rectangle {
+            x,
+            y,
+            width: (a.x + a.width).max(b.x + b.width) - x,
+            height: (a.y + a.height).max(b.y + b.height) - y,
+        }
+    }
+}
+
+/// Recomputes `Layer` from the position whenever the transform changes, for things that
+/// pass behind and in front of others, e.g. `|pos| (pos.y / 100.0) as u32` in a top-down map.
+#[derive(Component, Clone, Copy)]
+pub struct DynamicLayerAssignment {
+    pub layer_fn: fn(Vector2) -> u32,
+}
+
+/// Set by `quarantine_non_finite_system` while the `GlobalTransform` isn't finite,
+/// rendering skips these entities.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Quarantined;
+
+/// Draw order inside screen-space layers, higher is drawn later. Camera-space layers y-sort.
+#[derive(Debug, Component, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ZIndex(pub i32);
+
+/// Draw order inside camera-space layers, breaks y-sort ties or replaces the y-sort
+/// entirely with `LayerSortMode::Manual`. Higher is drawn later.
+#[derive(Debug, Component, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RenderOrder(pub i32);
+
+/// Set by `classify_screen_space_system` on sprites whose layer is screen-space.
+/// They're kept out of the render hash, so they never get culled.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct ScreenSpace;
+
+pub const HUD_STRIP_HEIGHT: f32 = 30.0;
+
+/// Sprite following the mouse in place of the OS cursor, see `cursor_system`. Belongs on
+/// `CURSOR_LAYER`.
+#[derive(Component)]
+pub struct Cursor;
+
+/// The translucent strip along the bottom of the window, resized with it.
+#[derive(Component)]
+pub struct HudStrip;
+
 #[derive(Component)]
 pub struct SyncColliderWithSprite;
 
+/// Hidden sprites stay indexed, but they're skipped when drawing and don't count as `OnScreen`.
+#[derive(Debug, Component, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    Hidden,
+}
+
+impl Visibility {
+    /// Entities without a `Visibility` are drawn.
+    pub fn is_shown(visibility: Option<&Visibility>) -> bool {
+        visibility.is_none_or(|v| *v == Visibility::Visible)
+    }
+}
+
+/// `commands.entity(e).hide()` keeps the sprite and collider, only drawing stops.
+pub trait VisibilityCommandsExt {
+    fn hide(&mut self) -> &mut Self;
+    fn show(&mut self) -> &mut Self;
+}
+
+impl VisibilityCommandsExt for EntityCommands<'_> {
+    fn hide(&mut self) -> &mut Self {
+        self.insert(Visibility::Hidden)
+    }
+
+    fn show(&mut self) -> &mut Self {
+        self.insert(Visibility::Visible)
+    }
+}
+
+/// `commands.entity(e).reparent(Some(hand), ReparentMode::KeepWorldPosition)`, see `reparent`.
+pub trait ReparentCommandsExt {
+    fn reparent(&mut self, new_parent: Option<Entity>, mode: ReparentMode) -> &mut Self;
+}
+
+impl ReparentCommandsExt for EntityCommands<'_> {
+    fn reparent(&mut self, new_parent: Option<Entity>, mode: ReparentMode) -> &mut Self {
+        self.queue(move |entity: EntityWorldMut| {
+            let id = entity.id();
+            reparent(entity.into_world_mut(), id, new_parent, mode);
+        })
+    }
+}
+
 #[derive(Bundle, Default)]
 pub struct SpriteBundle {
     pub sprite: Sprite,
     pub transform: Transform,
+    pub global_transform: GlobalTransform,
     pub layer: Layer,
+    pub visibility: Visibility,
 }
 
+impl SpriteBundle {
+    /// Starts with a matching `GlobalTransform`, so the sprite is in place before the first
+    /// physics tick.
+    pub fn new(sprite: Sprite, transform: Transform) -> Self {
+        Self {
+            sprite,
+            transform,
+            global_transform: GlobalTransform::from_root(&transform),
+            ..Default::default()
+        }
+    }
+}
+
+/// World units per second, integrated with the fixed physics step.
 #[derive(Debug, Component, Default, Clone, Copy)]
+#[require(SleepTimer)]
 pub struct Velocity(pub Vector2);
 
 impl std::ops::Deref for Velocity {
@@ -226,3 +1142,506 @@ impl std::ops::DerefMut for Velocity {
         &mut self.0
     }
 }
+
+/// Caps the length of the mover's `Velocity`, applied by `clamp_velocity_system`.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct MaxSpeed(pub f32);
+
+impl MaxSpeed {
+    /// `velocity` shortened to at most this long, direction kept. Zero stays zero, and a
+    /// velocity that's already non-finite has no direction left to keep, so it stops.
+    pub fn clamp(&self, velocity: Vector2) -> Vector2 {
+        let length = (velocity.x * velocity.x + velocity.y * velocity.y).sqrt();
+        if !length.is_finite() {
+            return Vector2::new(0.0, 0.0);
+        }
+        if length <= self.0 {
+            return velocity;
+        }
+        let scale = self.0 / length;
+        Vector2::new(velocity.x * scale, velocity.y * scale)
+    }
+}
+
+/// What happens to a mover whose position leaves `WorldBounds`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoundsBehavior {
+    /// Stops at the edge, losing the velocity that pointed out.
+    Clamp,
+    /// Comes back in from the opposite edge.
+    Wrap,
+    Despawn,
+}
+
+/// Marks a mover that has settled, it's treated as a static collider until woken.
+#[derive(Component)]
+#[component(storage = "SparseSet")]
+pub struct Sleeping;
+
+/// Consecutive physics ticks a mover has spent idle.
+#[derive(Component, Default)]
+pub struct SleepTimer(pub u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgba(color: Color) -> (u8, u8, u8, u8) {
+        (color.r, color.g, color.b, color.a)
+    }
+
+    #[test]
+    fn test_tint() {
+        let sprite = Sprite {
+            color: Color::new(200, 100, 50, 255),
+            ..Default::default()
+        };
+
+        // No tint leaves the sprite color alone
+        assert_eq!(rgba(tinted_color(&sprite, None)), (200, 100, 50, 255));
+
+        let white = Tint(Color::new(255, 255, 255, 255));
+        assert_eq!(
+            rgba(tinted_color(&sprite, Some(&white))),
+            (200, 100, 50, 255)
+        );
+
+        let half_faded = Tint(Color::new(255, 255, 255, 128));
+        assert_eq!(
+            rgba(tinted_color(&sprite, Some(&half_faded))),
+            (200, 100, 50, 128)
+        );
+
+        let tint = Tint(Color::new(128, 0, 255, 0));
+        assert_eq!(rgba(tint.apply(sprite.color)), (100, 0, 50, 0));
+    }
+
+    #[test]
+    fn test_opacity_scales_alpha() {
+        let mut sprite = Sprite {
+            color: Color::new(200, 100, 50, 200),
+            opacity: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(rgba(tinted_color(&sprite, None)), (200, 100, 50, 100));
+
+        // Stacks with a tint's alpha, and stays inside 0 to 1
+        let half_faded = Tint(Color::new(255, 255, 255, 128));
+        assert_eq!(rgba(tinted_color(&sprite, Some(&half_faded))).3, 50);
+        sprite.opacity = 2.0;
+        assert_eq!(rgba(tinted_color(&sprite, None)).3, 200);
+        sprite.opacity = -1.0;
+        assert_eq!(rgba(tinted_color(&sprite, None)).3, 0);
+    }
+
+    #[test]
+    fn test_camera_spring_settles_without_overshoot() {
+        let mut spring = CameraSpring::critically_damped(100.0);
+        assert_eq!(spring.damping, 20.0);
+
+        let target = Vector2::new(100.0, -50.0);
+        let mut position = Vector2::new(0.0, 0.0);
+        for _ in 0..120 {
+            position = spring.step(position, target, 1.0 / 60.0);
+            assert!(position.x <= target.x + 1e-3);
+            assert!(position.y >= target.y - 1e-3);
+        }
+        assert!((position.x - target.x).abs() < 0.01);
+        assert!((position.y - target.y).abs() < 0.01);
+        assert!(spring.velocity.x.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_camera_spring_keeps_velocity() {
+        let target = Vector2::new(100.0, 0.0);
+        let mut spring = CameraSpring::critically_damped(50.0);
+        let first = spring.step(Vector2::new(0.0, 0.0), target, 1.0 / 60.0);
+        assert!(spring.velocity.x > 0.0);
+
+        // Already moving, so the same frame covers more ground than from rest
+        let second = spring.step(first, target, 1.0 / 60.0);
+        assert!(second.x - first.x > first.x);
+
+        // One long frame ends up close to many short ones
+        let mut long = CameraSpring::critically_damped(50.0);
+        let mut short = CameraSpring::critically_damped(50.0);
+        let long_pos = long.step(Vector2::new(0.0, 0.0), target, 0.25);
+        let mut short_pos = Vector2::new(0.0, 0.0);
+        for _ in 0..15 {
+            short_pos = short.step(short_pos, target, 0.25 / 15.0);
+        }
+        assert!((long_pos.x - short_pos.x).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_camera_zoom_ease() {
+        let zoom = CameraZoom {
+            target: 2.0,
+            ..Default::default()
+        };
+
+        // Same result after one second whatever the frame rate
+        let mut at_60 = 1.0;
+        for _ in 0..60 {
+            at_60 = zoom.ease(at_60, 1.0 / 60.0);
+        }
+        let mut at_15 = 1.0;
+        for _ in 0..15 {
+            at_15 = zoom.ease(at_15, 1.0 / 15.0);
+        }
+        assert!((at_60 - at_15).abs() < 1e-4);
+        assert!((at_60 - 2.0).abs() < 1e-4);
+
+        // Both the target and the current zoom are kept in range
+        let out_of_range = CameraZoom {
+            target: 50.0,
+            ..Default::default()
+        };
+        let mut current = 4.9;
+        for _ in 0..100 {
+            current = out_of_range.ease(current, 1.0 / 60.0);
+            assert!(current <= out_of_range.max);
+        }
+        assert!((current - out_of_range.max).abs() < 1e-4);
+        assert_eq!(zoom.ease(0.01, 0.0), zoom.min);
+
+        let instant = CameraZoom {
+            target: 3.0,
+            speed: 1e9,
+            ..Default::default()
+        };
+        assert_eq!(instant.ease(1.0, 1.0 / 60.0), 3.0);
+    }
+
+    #[test]
+    fn test_point_light_center_color() {
+        let light = PointLight {
+            color: Color::new(200, 100, 0, 128),
+            radius: 10.0,
+            intensity: 0.5,
+        };
+        assert_eq!(rgba(light.center_color()), (100, 50, 0, 255));
+
+        // Overdriven lights saturate instead of wrapping
+        let bright = PointLight {
+            intensity: 2.0,
+            ..light
+        };
+        assert_eq!(rgba(bright.center_color()), (255, 200, 0, 255));
+
+        let bounds = light.bounds(Vector2::new(5.0, 5.0));
+        assert_eq!(
+            (bounds.x, bounds.y, bounds.width, bounds.height),
+            (-5.0, -5.0, 20.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn test_text_font() {
+        let text = Text::new("hi", 20, Color::WHITE);
+        assert_eq!(text.font, None);
+        let text = text.with_font("main");
+        assert_eq!(text.font.as_deref(), Some("main"));
+        assert_eq!(text.content, "hi");
+    }
+
+    #[test]
+    fn test_text_wraps_at_spaces() {
+        // Ten pixels per character
+        let measure = |line: &str| line.len() as f32 * 10.0;
+        let text = Text::new("aa bb cc", 20, Color::WHITE);
+        assert_eq!(text.lines(measure), vec!["aa bb cc"]);
+
+        let text = text.with_max_width(50);
+        assert_eq!(text.lines(measure), vec!["aa bb", "cc"]);
+
+        // A word that can't fit still gets drawn, on its own line
+        let text = Text::new("a verylongword b", 20, Color::WHITE).with_max_width(50);
+        assert_eq!(text.lines(measure), vec!["a", "verylongword", "b"]);
+    }
+
+    #[test]
+    fn test_text_splits_on_newlines() {
+        let measure = |line: &str| line.len() as f32 * 10.0;
+        let mut text = Text::new("Move: WASD\n\nZoom: wheel", 20, Color::WHITE);
+        text.line_spacing = 1.5;
+        assert_eq!(text.lines(measure), vec!["Move: WASD", "", "Zoom: wheel"]);
+
+        let tops: Vec<_> = text
+            .layout(Vector2::new(0.0, 10.0), None, measure)
+            .into_iter()
+            .map(|(_, p)| p.y)
+            .collect();
+        assert_eq!(tops, vec![10.0, 40.0, 70.0]);
+
+        // Wrapping happens within each line
+        let text = text.with_max_width(60);
+        assert_eq!(
+            text.lines(measure),
+            vec!["Move:", "WASD", "", "Zoom:", "wheel"]
+        );
+    }
+
+    #[test]
+    fn test_text_passes_draw_shadow_and_outline_first() {
+        let text = Text::new("hi", 20, Color::WHITE);
+        let passes: Vec<_> = text
+            .passes()
+            .iter()
+            .map(|(o, c)| (o.x, o.y, rgba(*c)))
+            .collect();
+        assert_eq!(passes, vec![(0.0, 0.0, rgba(Color::WHITE))]);
+
+        let text = text
+            .with_outline(2, Color::BLACK)
+            .with_shadow(Vector2::new(3.0, 3.0), Color::GRAY);
+        let passes = text.passes();
+        assert_eq!(passes.len(), 10);
+        let (shadow_offset, shadow_color) = passes[0];
+        assert_eq!((shadow_offset.x, shadow_offset.y), (3.0, 3.0));
+        assert_eq!(rgba(shadow_color), rgba(Color::GRAY));
+        for (offset, color) in &passes[1..9] {
+            assert_eq!(rgba(*color), rgba(Color::BLACK));
+            assert_eq!(offset.x.abs().max(offset.y.abs()), 2.0);
+        }
+        let (offset, color) = passes[9];
+        assert_eq!((offset.x, offset.y), (0.0, 0.0));
+        assert_eq!(rgba(color), rgba(Color::WHITE));
+    }
+
+    #[test]
+    fn test_centered_text_grows_both_ways() {
+        let measure = |line: &str| line.len() as f32 * 10.0;
+        let position = Vector2::new(100.0, 50.0);
+        let anchor = TextAnchor::default();
+        let mut text = Text::new("12", 20, Color::WHITE).with_alignment(TextAlign::Center);
+        let short = text.layout(position, Some(&anchor), measure)[0].1;
+        assert_eq!((short.x, short.y), (90.0, 50.0));
+
+        text.content = "1234".to_string();
+        let long = text.layout(position, Some(&anchor), measure)[0].1;
+        assert_eq!((long.x, long.y), (80.0, 50.0));
+
+        // Every wrapped line is centered on its own width
+        let text = Text::new("aa bb cc", 20, Color::WHITE)
+            .with_max_width(50)
+            .with_alignment(TextAlign::Right);
+        let lines: Vec<_> = text
+            .layout(position, None, measure)
+            .into_iter()
+            .map(|(line, p)| (line, p.x, p.y))
+            .collect();
+        assert_eq!(lines, vec![("aa bb", 50.0, 50.0), ("cc", 80.0, 74.0)]);
+    }
+
+    #[test]
+    fn test_capsule_fitting_fills_the_box() {
+        let fitted = |size: Vector2, scale: Vector2| {
+            let collider = Collider {
+                kind: ColliderKind::capsule_fitting(size, scale),
+                ..Default::default()
+            };
+            let size = collider.size(scale);
+            (size.x, size.y)
+        };
+        assert_eq!(
+            fitted(Vector2::new(20.0, 40.0), Vector2::new(1.0, 1.0)),
+            (20.0, 40.0)
+        );
+        // Non-uniform scale still lands on the scaled box
+        assert_eq!(
+            fitted(Vector2::new(20.0, 40.0), Vector2::new(2.0, 4.0)),
+            (40.0, 160.0)
+        );
+        // Too flat for a straight part, just the circle
+        let ColliderKind::Capsule {
+            radius,
+            half_height,
+        } = ColliderKind::capsule_fitting(Vector2::new(40.0, 20.0), Vector2::new(1.0, 1.0))
+        else {
+            unreachable!()
+        };
+        assert_eq!((radius, half_height), (10.0, 0.0));
+    }
+
+    #[test]
+    fn test_capsule_fitting_stays_within_the_box() {
+        let sizes = [(40.0, 20.0), (20.0, 40.0), (30.0, 30.0), (64.0, 8.0)];
+        let scales = [(1.0, 1.0), (2.0, 1.0), (1.0, 3.0), (0.5, 2.0)];
+        for (w, h) in sizes {
+            for (sx, sy) in scales {
+                let scale = Vector2::new(sx, sy);
+                let collider = Collider {
+                    kind: ColliderKind::capsule_fitting(Vector2::new(w, h), scale),
+                    ..Default::default()
+                };
+                let fitted = collider.size(scale);
+                assert!(
+                    fitted.x <= w * sx && fitted.y <= h * sy,
+                    "{w}x{h} at {sx}x{sy} fitted {}x{}",
+                    fitted.x,
+                    fitted.y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_capsule_overlaps_rects() {
+        let capsule = Capsule::from_bounds(&Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 20.0,
+            height: 40.0,
+        });
+        assert_eq!(
+            (capsule.top.y, capsule.bottom.y, capsule.radius),
+            (10.0, 30.0, 10.0)
+        );
+        let rect = |x, y, width, height| Rectangle {
+            x,
+            y,
+            width,
+            height,
+        };
+        let push = |rect: Rectangle| capsule.push_out_of(&rect).map(|p| (p.x, p.y));
+
+        // Edge: straight out the side
+        assert_eq!(push(rect(18.0, 15.0, 10.0, 10.0)), Some((-2.0, 0.0)));
+        // Corner: inside the bounds but past the rounded end
+        assert_eq!(push(rect(18.0, 38.0, 10.0, 10.0)), None);
+        let (x, y) = push(rect(17.0, 37.0, 10.0, 10.0)).unwrap();
+        assert!(x < 0.0 && (x - y).abs() < 1e-5);
+        // Containment: the floor swallowed the bottom, up is the short way out
+        assert_eq!(push(rect(-5.0, 25.0, 50.0, 75.0)), Some((0.0, -15.0)));
+        // A corner just below the foot lifts it rather than stopping it
+        let (x, y) = push(rect(14.0, 39.0, 10.0, 10.0)).unwrap();
+        assert!(y < 0.0 && y.abs() > x.abs());
+    }
+
+    #[test]
+    fn test_max_speed_clamp() {
+        let max = MaxSpeed(5.0);
+        let clamp = |x, y| {
+            let v = max.clamp(Vector2::new(x, y));
+            (v.x, v.y)
+        };
+        assert_eq!(clamp(0.0, 0.0), (0.0, 0.0));
+        assert_eq!(clamp(3.0, -4.0), (3.0, -4.0));
+        assert_eq!(clamp(30.0, -40.0), (3.0, -4.0));
+        assert_eq!(clamp(f32::INFINITY, 1.0), (0.0, 0.0));
+        assert_eq!(clamp(f32::NAN, 1.0), (0.0, 0.0));
+        assert_eq!(MaxSpeed(0.0).clamp(Vector2::new(1.0, 1.0)).x, 0.0);
+    }
+
+    #[test]
+    fn test_reparent_keeps_world_or_local_position() {
+        let mut world = World::new();
+        let global = |x, y, rotation, scale| GlobalTransform {
+            position: Vector2::new(x, y),
+            rotation,
+            scale: Vector2::new(scale, scale),
+        };
+        let body = world
+            .spawn((Transform::default(), global(100.0, 50.0, 0.0, 1.0)))
+            .id();
+        let hand = world
+            .spawn((Transform::default(), global(300.0, -20.0, 90.0, 2.0)))
+            .id();
+        let local = Transform {
+            position: Vector2::new(10.0, 5.0),
+            rotation: 30.0,
+            scale: Vector2::new(1.0, 1.0),
+        };
+        let weapon = world
+            .spawn((local, global(110.0, 55.0, 30.0, 1.0), ChildOf(body)))
+            .id();
+
+        assert!(reparent(
+            &mut world,
+            weapon,
+            Some(hand),
+            ReparentMode::KeepWorldPosition
+        ));
+        let moved = *world.get::<Transform>(weapon).unwrap();
+        let parent_global = *world.get::<GlobalTransform>(hand).unwrap();
+        // Propagating the new local transform lands exactly where the weapon was
+        assert_eq!(
+            GlobalTransform::from_local(&parent_global, &moved),
+            global(110.0, 55.0, 30.0, 1.0)
+        );
+        assert_eq!(moved.rotation, 300.0);
+        assert_eq!(world.get::<ChildOf>(weapon).unwrap().parent(), hand);
+        assert!(world.get::<Children>(body).is_none());
+
+        assert!(reparent(
+            &mut world,
+            weapon,
+            Some(body),
+            ReparentMode::KeepLocalPosition
+        ));
+        assert_eq!(
+            world.get::<Transform>(weapon).unwrap().position,
+            moved.position
+        );
+
+        // Back to the root, the local transform becomes the world one
+        assert!(reparent(
+            &mut world,
+            weapon,
+            None,
+            ReparentMode::KeepWorldPosition
+        ));
+        assert!(world.get::<ChildOf>(weapon).is_none());
+        assert_eq!(
+            world.get::<Transform>(weapon).unwrap().position,
+            Vector2::new(110.0, 55.0)
+        );
+
+        // No cycles
+        world.entity_mut(hand).insert(ChildOf(weapon));
+        assert!(!reparent(
+            &mut world,
+            weapon,
+            Some(hand),
+            ReparentMode::KeepLocalPosition
+        ));
+        assert!(!reparent(
+            &mut world,
+            weapon,
+            Some(weapon),
+            ReparentMode::KeepLocalPosition
+        ));
+        assert!(world.get::<ChildOf>(weapon).is_none());
+    }
+
+    #[test]
+    fn test_reparent_command() {
+        let mut world = World::new();
+        let parent = world
+            .spawn((
+                Transform::default(),
+                GlobalTransform {
+                    position: Vector2::new(40.0, 0.0),
+                    ..Default::default()
+                },
+            ))
+            .id();
+        let child = world
+            .spawn((
+                Transform::default().with_position(Vector2::new(50.0, 0.0)),
+                GlobalTransform {
+                    position: Vector2::new(50.0, 0.0),
+                    ..Default::default()
+                },
+            ))
+            .id();
+        world
+            .commands()
+            .entity(child)
+            .reparent(Some(parent), ReparentMode::KeepWorldPosition);
+        world.flush();
+        assert_eq!(world.get::<ChildOf>(child).unwrap().parent(), parent);
+        assert_eq!(world.get::<Transform>(child).unwrap().position.x, 10.0);
+    }
+}
@@ -0,0 +1,417 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::assets::TextureId;
+use crate::components::*;
+use crate::resources::{CameraViewRect, DebugSettings, LayerSortMode};
+use crate::spatial_hash::RenderHash;
+
+/// Shape part of a `Sprite`, copied out so drawing doesn't borrow the component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExtractedKind {
+    Rectangle {
+        size: (f32, f32),
+        lines: bool,
+    },
+    Circle {
+        radius: f32,
+    },
+    Asset {
+        id: TextureId,
+        size: (f32, f32),
+    },
+    Tile {
+        id: u32,
+        size: (f32, f32),
+    },
+    /// Owned textures can't be copied, the renderer reads them from the entity's `Sprite`.
+    Texture,
+}
+
+impl From<&SpriteKind> for ExtractedKind {
+    fn from(kind: &SpriteKind) -> Self {
+        match *kind {
+            SpriteKind::Rectangle { size, lines } => ExtractedKind::Rectangle { size, lines },
+            SpriteKind::Circle { radius } => ExtractedKind::Circle { radius },
+            SpriteKind::Asset { id, size } => ExtractedKind::Asset { id, size },
+            SpriteKind::Tile { id, size } => ExtractedKind::Tile { id, size },
+            SpriteKind::Texture { .. } => ExtractedKind::Texture,
+        }
+    }
+}
+
+/// Everything `render_layers` needs to draw one sprite.
+#[derive(Clone, Copy)]
+pub struct ExtractedSprite {
+    pub entity: Entity,
+    pub position: Vector2,
+    pub rotation: f32,
+    pub scale: Vector2,
+    /// `Sprite::get_origin_vector`.
+    pub origin: Vector2,
+    pub kind: ExtractedKind,
+    /// Tint already applied.
+    pub color: Color,
+    pub layer: u32,
+    pub z_index: ZIndex,
+    pub render_order: RenderOrder,
+}
+
+impl ExtractedSprite {
+    fn new(
+        entity: Entity,
+        sprite: &Sprite,
+        transform: &GlobalTransform,
+        layer: &Layer,
+        color: Color,
+        z_index: ZIndex,
+        render_order: RenderOrder,
+    ) -> Self {
+        Self {
+            entity,
+            position: transform.position,
+            rotation: transform.rotation,
+            scale: transform.scale,
+            origin: sprite.get_origin_vector(),
+            kind: (&sprite.kind).into(),
+            color,
+            layer: layer.0,
+            z_index,
+            render_order,
+        }
+    }
+}
+
+/// What places a sprite in the draw order of the camera-space layers.
+#[derive(Debug, Clone, Copy)]
+pub struct DrawKey {
+    pub layer: u32,
+    pub y: f32,
+    pub render_order: RenderOrder,
+    pub entity: Entity,
+}
+
+impl From<&ExtractedSprite> for DrawKey {
+    fn from(sprite: &ExtractedSprite) -> Self {
+        Self {
+            layer: sprite.layer,
+            y: sprite.position.y,
+            render_order: sprite.render_order,
+            entity: sprite.entity,
+        }
+    }
+}
+
+/// Draw order across camera-space layers, later is drawn on top: layer first, then
+/// `compare_world_sprites`. Picking goes by this so it lands on what's visible.
+pub fn compare_draw_keys(mode: LayerSortMode, a: &DrawKey, b: &DrawKey) -> std::cmp::Ordering {
+    let order = a.render_order.cmp(&b.render_order);
+    a.layer
+        .cmp(&b.layer)
+        .then(match mode {
+            // total_cmp so a stray NaN can't panic the sort
+            LayerSortMode::YSort => a.y.total_cmp(&b.y).then(order),
+            LayerSortMode::Manual => order,
+        })
+        .then(a.entity.cmp(&b.entity))
+}
+
+/// Draw order of two sprites in a camera-space layer. The entity settles whatever's left,
+/// so the unstable parallel sort still comes out the same every frame.
+pub fn compare_world_sprites(
+    mode: LayerSortMode,
+    a: &ExtractedSprite,
+    b: &ExtractedSprite,
+) -> std::cmp::Ordering {
+    compare_draw_keys(mode, &a.into(), &b.into())
+}
+
+/// Draw data for the frame, double buffered: extraction fills the back buffer and swaps,
+/// the renderer only ever reads the front one.
+#[derive(Resource, Default)]
+pub struct RenderWorld {
+    buffers: [Vec<ExtractedSprite>; 2],
+    front: usize,
+}
+
+impl RenderWorld {
+    pub fn sprites(&self) -> &[ExtractedSprite] {
+        &self.buffers[self.front]
+    }
+
+    /// Refills the back buffer, keeping its allocation, then makes it the front one.
+    pub fn swap_in(&mut self, sprites: impl Iterator<Item = ExtractedSprite>) {
+        let back = &mut self.buffers[1 - self.front];
+        back.clear();
+        back.extend(sprites);
+        self.front = 1 - self.front;
+    }
+}
+
+/// Copies what's visible this frame into `RenderWorld`. Runs last before the render
+/// schedule, which then never touches `Sprite` or `GlobalTransform` for layer drawing.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn extract_render_data_system(
+    mut render_world: ResMut<RenderWorld>,
+    debug_settings: Res<DebugSettings>,
+    spatial_hash: Res<RenderHash>,
+    view: Res<CameraViewRect>,
+    sprite_q: Query<
+        (
+            Entity,
+            &Sprite,
+            &GlobalTransform,
+            &Layer,
+            Option<&Tint>,
+            Option<&Visibility>,
+            Option<&RenderOrder>,
+        ),
+        (With<OnScreen>, Without<ScreenSpace>, Without<Quarantined>),
+    >,
+    screen_space_q: Query<
+        (
+            Entity,
+            &Sprite,
+            &GlobalTransform,
+            &Layer,
+            Option<&Tint>,
+            Option<&Visibility>,
+            Option<&ZIndex>,
+        ),
+        (With<ScreenSpace>, Without<Quarantined>),
+    >,
+    all_sprites_q: Query<
+        (
+            &Sprite,
+            &GlobalTransform,
+            &Layer,
+            Option<&Tint>,
+            Has<OnScreen>,
+            Option<&Visibility>,
+            Option<&RenderOrder>,
+        ),
+        (Without<ScreenSpace>, Without<Quarantined>),
+    >,
+) {
+    let mut sprites: Vec<ExtractedSprite> = if debug_settings.visualize_culling {
+        // Bypass the OnScreen filter and take everything inside the culling rect,
+        // tinting whatever the culling pass left out
+        let culled_color = Color::RED.fade(0.5);
+        spatial_hash
+            .query(view.0)
+            .into_iter()
+            .filter_map(|e| all_sprites_q.get(e).ok().map(|data| (e, data)))
+            .filter(|(_, (.., visibility, _))| Visibility::is_shown(*visibility))
+            .map(
+                |(e, (sprite, transform, layer, tint, on_screen, _, order))| {
+                    let color = match on_screen {
+                        true => tinted_color(sprite, tint),
+                        false => culled_color,
+                    };
+                    let order = order.copied().unwrap_or_default();
+                    ExtractedSprite::new(
+                        e,
+                        sprite,
+                        transform,
+                        layer,
+                        color,
+                        ZIndex::default(),
+                        order,
+                    )
+                },
+            )
+            .collect()
+    } else {
+        sprite_q
+            .iter()
+            .filter(|(.., visibility, _)| Visibility::is_shown(*visibility))
+            .map(|(e, sprite, transform, layer, tint, _, order)| {
+                let color = tinted_color(sprite, tint);
+                let order = order.copied().unwrap_or_default();
+                ExtractedSprite::new(e, sprite, transform, layer, color, ZIndex::default(), order)
+            })
+            .collect()
+    };
+    // Screen-space sprites aren't culled, they're always drawn
+    sprites.extend(
+        screen_space_q
+            .iter()
+            .filter(|(.., visibility, _)| Visibility::is_shown(*visibility))
+            .map(|(e, sprite, transform, layer, tint, _, z_index)| {
+                let color = tinted_color(sprite, tint);
+                let z_index = z_index.copied().unwrap_or_default();
+                let order = RenderOrder::default();
+                ExtractedSprite::new(e, sprite, transform, layer, color, z_index, order)
+            }),
+    );
+    render_world.swap_in(sprites.into_iter());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial_hash::SpatialHash;
+
+    type Fields = (
+        Entity,
+        (f32, f32),
+        f32,
+        (f32, f32),
+        ExtractedKind,
+        (u8, u8, u8, u8),
+        u32,
+        ZIndex,
+    );
+
+    fn fields(s: &ExtractedSprite) -> Fields {
+        let c = s.color;
+        (
+            s.entity,
+            (s.position.x, s.position.y),
+            s.rotation,
+            (s.origin.x, s.origin.y),
+            s.kind,
+            (c.r, c.g, c.b, c.a),
+            s.layer,
+            s.z_index,
+        )
+    }
+
+    #[test]
+    fn test_extracted_buffer_matches_components() {
+        let mut world = World::new();
+        world.init_resource::<RenderWorld>();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(DebugSettings {
+            origins: false,
+            colliders: false,
+            visualize_culling: false,
+            show_system_counts: false,
+            contacts: false,
+            show_fps_graph: false,
+            inspector: false,
+            hide_cursor: false,
+            drag: false,
+        });
+        let tinted = world
+            .spawn((
+                SpriteBundle {
+                    layer: Layer(2),
+                    ..SpriteBundle::new(
+                        Sprite {
+                            kind: SpriteKind::Circle { radius: 5.0 },
+                            origin: SpriteOrigin::Center,
+                            color: Color::WHITE,
+                            opacity: 1.0,
+                        },
+                        Transform::default().with_position(Vector2::new(10.0, 20.0)),
+                    )
+                },
+                Tint(Color::new(255, 0, 0, 255)),
+                OnScreen,
+            ))
+            .id();
+        let hud = world
+            .spawn((
+                SpriteBundle::new(Sprite::default(), Transform::default()),
+                ScreenSpace,
+                ZIndex(3),
+            ))
+            .id();
+        // Off screen and hidden sprites aren't extracted
+        world.spawn(SpriteBundle::new(Sprite::default(), Transform::default()));
+        world.spawn((
+            SpriteBundle {
+                visibility: Visibility::Hidden,
+                ..SpriteBundle::new(Sprite::default(), Transform::default())
+            },
+            OnScreen,
+        ));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(extract_render_data_system);
+        schedule.run(&mut world);
+
+        let expected = |world: &World, entity: Entity, z_index: ZIndex| {
+            let sprite = world.get::<Sprite>(entity).unwrap();
+            let transform = world.get::<GlobalTransform>(entity).unwrap();
+            let color = tinted_color(sprite, world.get::<Tint>(entity));
+            let layer = world.get::<Layer>(entity).unwrap();
+            fields(&ExtractedSprite::new(
+                entity,
+                sprite,
+                transform,
+                layer,
+                color,
+                z_index,
+                RenderOrder::default(),
+            ))
+        };
+        let extracted: Vec<_> = world
+            .resource::<RenderWorld>()
+            .sprites()
+            .iter()
+            .map(fields)
+            .collect();
+        assert_eq!(
+            extracted,
+            vec![
+                expected(&world, tinted, ZIndex::default()),
+                expected(&world, hud, ZIndex(3)),
+            ]
+        );
+        assert_eq!(extracted[0].6, 2);
+        assert_eq!(extracted[0].4, ExtractedKind::Circle { radius: 5.0 });
+        assert_eq!(extracted[0].5, (255, 0, 0, 255));
+
+        // The next frame lands in the other buffer and reflects the move
+        world.get_mut::<GlobalTransform>(tinted).unwrap().position = Vector2::new(30.0, 40.0);
+        schedule.run(&mut world);
+        let extracted = world.resource::<RenderWorld>().sprites();
+        assert_eq!(extracted.len(), 2);
+        assert_eq!(
+            fields(&extracted[0]),
+            expected(&world, tinted, ZIndex::default())
+        );
+    }
+
+    #[test]
+    fn test_world_sprite_sort_modes() {
+        let mut world = World::new();
+        let sprite = |world: &mut World, y: f32, order: i32| ExtractedSprite {
+            entity: world.spawn_empty().id(),
+            position: Vector2::new(0.0, y),
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+            origin: Vector2::new(0.0, 0.0),
+            kind: ExtractedKind::Circle { radius: 1.0 },
+            color: Color::WHITE,
+            layer: 0,
+            z_index: ZIndex::default(),
+            render_order: RenderOrder(order),
+        };
+        // Spawned in an order that matches neither sort
+        let sprites = [
+            sprite(&mut world, 10.0, 0),
+            sprite(&mut world, 5.0, 2),
+            sprite(&mut world, 10.0, -1),
+            sprite(&mut world, 5.0, 2),
+        ];
+        let sorted = |mode| {
+            let mut sorted = sprites.to_vec();
+            sorted.sort_unstable_by(|a, b| compare_world_sprites(mode, a, b));
+            sorted.iter().map(|s| s.entity).collect::<Vec<_>>()
+        };
+        let ids = sprites.map(|s| s.entity);
+        // Same y goes by RenderOrder, and a full tie by entity
+        assert_eq!(
+            sorted(LayerSortMode::YSort),
+            [ids[1], ids[3], ids[2], ids[0]]
+        );
+        assert_eq!(
+            sorted(LayerSortMode::Manual),
+            [ids[2], ids[0], ids[1], ids[3]]
+        );
+    }
+}
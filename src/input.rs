@@ -0,0 +1,228 @@
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::resources::WindowResource;
+use crate::utils::{HashMap, HashSet};
+
+/// Which gamepad the input layer reads from.
+const GAMEPAD: i32 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Sprint,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Binding {
+    Key(KeyboardKey),
+    GamepadButton(GamepadButton),
+}
+
+/// Every binding that triggers an action, any of them being held is enough.
+#[derive(Resource)]
+pub struct ActionMap(pub HashMap<Action, Vec<Binding>>);
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        Self(
+            [
+                (Action::MoveLeft, vec![Binding::Key(KeyboardKey::A)]),
+                (Action::MoveRight, vec![Binding::Key(KeyboardKey::D)]),
+                (Action::MoveUp, vec![Binding::Key(KeyboardKey::W)]),
+                (Action::MoveDown, vec![Binding::Key(KeyboardKey::S)]),
+                (
+                    Action::Sprint,
+                    vec![
+                        Binding::Key(KeyboardKey::LeftShift),
+                        Binding::GamepadButton(GamepadButton::RightTrigger1),
+                    ],
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        )
+    }
+}
+
+#[derive(Resource)]
+pub struct InputSettings {
+    /// Radial deadzone of the analog sticks, in stick units (0..1).
+    pub deadzone: f32,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        Self { deadzone: 0.2 }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct GamepadState {
+    pub available: bool,
+    /// Already remapped through the deadzone.
+    pub left_stick: Vector2,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    /// Down, right, left, up.
+    pub face_buttons: [bool; 4],
+}
+
+/// Input snapshot for the frame, filled in by `update_input_system`.
+#[derive(Resource, Default)]
+pub struct InputState {
+    pub held: HashSet<Action>,
+    pub gamepad: GamepadState,
+}
+
+impl InputState {
+    pub fn is_held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+
+    /// Analog stick when it's pushed past the deadzone,
+    /// otherwise the normalized keyboard direction.
+    pub fn movement(&self) -> Vector2 {
+        let stick = self.gamepad.left_stick;
+        if stick.x != 0.0 || stick.y != 0.0 {
+            return stick;
+        }
+
+        let mut dir = Vector2::ZERO;
+        if self.is_held(Action::MoveLeft) {
+            dir.x -= 1.0;
+        }
+        if self.is_held(Action::MoveRight) {
+            dir.x += 1.0;
+        }
+        if self.is_held(Action::MoveUp) {
+            dir.y -= 1.0;
+        }
+        if self.is_held(Action::MoveDown) {
+            dir.y += 1.0;
+        }
+        // Nothing held, or opposite keys cancelling out, has no direction to normalize
+        if dir.x == 0.0 && dir.y == 0.0 {
+            return dir;
+        }
+        dir.normalized()
+    }
+}
+
+/// Zeroes a stick inside the deadzone and rescales the rest so movement starts from 0 at its edge.
+pub fn apply_radial_deadzone(stick: Vector2, deadzone: f32) -> Vector2 {
+    let length = (stick.x * stick.x + stick.y * stick.y).sqrt();
+    if length <= deadzone {
+        return Vector2::ZERO;
+    }
+
+    let scaled = ((length - deadzone) / (1.0 - deadzone)).min(1.0);
+    Vector2::new(stick.x / length * scaled, stick.y / length * scaled)
+}
+
+pub fn update_input_system(
+    window: Res<WindowResource>,
+    action_map: Res<ActionMap>,
+    settings: Res<InputSettings>,
+    mut input: ResMut<InputState>,
+) {
+    let gamepad = if window.is_gamepad_available(GAMEPAD) {
+        let stick = Vector2::new(
+            window.gamepad_axis_movement(GAMEPAD, GamepadAxis::LeftX),
+            window.gamepad_axis_movement(GAMEPAD, GamepadAxis::LeftY),
+        );
+        GamepadState {
+            available: true,
+            left_stick: apply_radial_deadzone(stick, settings.deadzone),
+            left_trigger: window.gamepad_axis_movement(GAMEPAD, GamepadAxis::LeftTrigger),
+            right_trigger: window.gamepad_axis_movement(GAMEPAD, GamepadAxis::RightTrigger),
+            face_buttons: [
+                GamepadButton::RightFaceDown,
+                GamepadButton::RightFaceRight,
+                GamepadButton::RightFaceLeft,
+                GamepadButton::RightFaceUp,
+            ]
+            .map(|button| window.is_gamepad_button_down(GAMEPAD, button)),
+        }
+    } else {
+        GamepadState::default()
+    };
+
+    let is_down = |binding: &Binding| match *binding {
+        Binding::Key(key) => window.is_key_down(key),
+        Binding::GamepadButton(button) => {
+            gamepad.available && window.is_gamepad_button_down(GAMEPAD, button)
+        }
+    };
+
+    input.held.clear();
+    for (&action, bindings) in action_map.0.iter() {
+        if bindings.iter().any(is_down) {
+            input.held.insert(action);
+        }
+    }
+    input.gamepad = gamepad;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn length(v: Vector2) -> f32 {
+        (v.x * v.x + v.y * v.y).sqrt()
+    }
+
+    #[test]
+    fn test_radial_deadzone() {
+        let deadzone = 0.2;
+
+        // Just inside
+        let v = apply_radial_deadzone(Vector2::new(0.19, 0.0), deadzone);
+        assert_eq!((v.x, v.y), (0.0, 0.0));
+        let v = apply_radial_deadzone(Vector2::new(0.14, 0.14), deadzone);
+        assert_eq!((v.x, v.y), (0.0, 0.0));
+
+        // Just outside starts near zero instead of jumping to the deadzone edge
+        let v = apply_radial_deadzone(Vector2::new(0.21, 0.0), deadzone);
+        assert!(v.x > 0.0 && v.x < 0.05);
+        assert_eq!(v.y, 0.0);
+
+        // Full tilt stays full, direction is preserved
+        let v = apply_radial_deadzone(Vector2::new(0.0, -1.0), deadzone);
+        assert!((v.y + 1.0).abs() < 1e-5);
+        let v = apply_radial_deadzone(Vector2::new(1.0, 1.0), deadzone);
+        assert!((length(v) - 1.0).abs() < 1e-5);
+        assert!((v.x - v.y).abs() < 1e-5);
+
+        // Half tilt maps to a sub-maximal speed
+        let v = apply_radial_deadzone(Vector2::new(0.6, 0.0), deadzone);
+        assert!((v.x - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_movement_prefers_stick_and_normalizes_keyboard() {
+        let mut input = InputState::default();
+        input.held.insert(Action::MoveRight);
+        input.held.insert(Action::MoveDown);
+
+        let dir = input.movement();
+        assert!((length(dir) - 1.0).abs() < 1e-5);
+        assert!((dir.x - dir.y).abs() < 1e-5);
+
+        input.gamepad.left_stick = Vector2::new(-0.5, 0.0);
+        let dir = input.movement();
+        assert_eq!((dir.x, dir.y), (-0.5, 0.0));
+
+        let idle = InputState::default().movement();
+        assert_eq!((idle.x, idle.y), (0.0, 0.0));
+
+        let mut cancelled = InputState::default();
+        cancelled.held.insert(Action::MoveLeft);
+        cancelled.held.insert(Action::MoveRight);
+        let dir = cancelled.movement();
+        assert_eq!((dir.x, dir.y), (0.0, 0.0));
+    }
+}
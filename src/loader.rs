@@ -0,0 +1,239 @@
+use std::ops::Range;
+
+use bevy_ecs::prelude::*;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use rustyray::prelude::*;
+
+use crate::builder::{EntityBuilder, SyncedSpriteBundle};
+use crate::components::{Collider, GlobalTransform, Sprite, SpriteKind, SpriteOrigin};
+use crate::resources::SeededRng;
+use crate::spatial_hash::{CollisionHash, RenderHash};
+
+/// Boxes per row of the startup grid.
+pub const GRID_COLUMNS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Done,
+    Cancelled,
+}
+
+/// Spawns the startup grid a batch per frame, so the window keeps drawing progress
+/// instead of freezing until everything exists.
+#[derive(Resource, Debug)]
+pub struct WorldLoader {
+    pub state: LoadState,
+    pub total: usize,
+    pub spawned: usize,
+    /// Boxes spawned per frame while loading.
+    pub per_frame: usize,
+}
+
+impl WorldLoader {
+    pub fn new(total: usize, per_frame: usize) -> Self {
+        Self {
+            state: match total {
+                0 => LoadState::Done,
+                _ => LoadState::Loading,
+            },
+            total,
+            spawned: 0,
+            per_frame,
+        }
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.state == LoadState::Loading
+    }
+
+    /// Share spawned so far, 0 to 1.
+    pub fn progress(&self) -> f32 {
+        match self.total {
+            0 => 1.0,
+            total => self.spawned as f32 / total as f32,
+        }
+    }
+
+    /// Grid indices to spawn this frame. The last batch switches to `Done`, nothing is
+    /// handed out once loading stopped.
+    pub fn next_batch(&mut self) -> Range<usize> {
+        if !self.is_loading() {
+            return self.spawned..self.spawned;
+        }
+        let start = self.spawned;
+        self.spawned = (start + self.per_frame.max(1)).min(self.total);
+        if self.spawned == self.total {
+            self.state = LoadState::Done;
+        }
+        start..self.spawned
+    }
+
+    pub fn cancel(&mut self) {
+        if self.is_loading() {
+            self.state = LoadState::Cancelled;
+        }
+    }
+}
+
+/// Box `index` of the grid, rows of `GRID_COLUMNS` 35 units apart.
+fn grid_box(index: usize, rng: &mut impl Rng) -> SyncedSpriteBundle {
+    let (row, column) = (index / GRID_COLUMNS, index % GRID_COLUMNS);
+    // Jitter inside the 3px gap so the boxes never overlap
+    let position = Vector2 {
+        x: 200. + (35 * column) as f32 + rng.gen_range(0.0..3.0),
+        y: 100. + (35 * row) as f32 + rng.gen_range(0.0..3.0),
+    };
+    EntityBuilder::new()
+        .with_sprite(
+            SpriteKind::default(),
+            Color::GREEN,
+            SpriteOrigin::Custom((0.0, 0.0).into()),
+        )
+        .with_transform(position)
+        .with_collider_synced()
+        .build()
+}
+
+/// Builds the boxes for `indices` across threads. Each box gets its own RNG seeded from
+/// `seed` and its index, so the result doesn't depend on how the work was split.
+pub fn grid_boxes_parallel(indices: Range<usize>, seed: u64) -> Vec<SyncedSpriteBundle> {
+    indices
+        .into_par_iter()
+        .map(|index| {
+            let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(index as u64));
+            grid_box(index, &mut rng)
+        })
+        .collect()
+}
+
+/// Spawns the next batch and indexes it in both hashes in one go, rather than leaving a
+/// frame's worth of single inserts to the update systems.
+pub fn populate_world_system(world: &mut World) {
+    let batch = world.resource_mut::<WorldLoader>().next_batch();
+    if batch.is_empty() {
+        return;
+    }
+    // One draw from the shared RNG per batch keeps runs with the same seed identical
+    let seed = world.resource_mut::<SeededRng>().0.r#gen::<u64>();
+    let boxes = grid_boxes_parallel(batch, seed);
+    let entities: Vec<Entity> = world.spawn_batch(boxes).collect();
+
+    let mut collider_rects = Vec::with_capacity(entities.len());
+    let mut sprite_rects = Vec::with_capacity(entities.len());
+    for &entity in &entities {
+        let entity_ref = world.entity(entity);
+        let transform = entity_ref.get::<GlobalTransform>().unwrap();
+        if let Some(collider) = entity_ref.get::<Collider>() {
+            collider_rects.push((entity, collider.aabb(transform)));
+        }
+        if let Some(rect) = entity_ref.get::<Sprite>().and_then(|s| s.bounds(transform)) {
+            sprite_rects.push((entity, rect));
+        }
+    }
+    world
+        .resource_mut::<CollisionHash>()
+        .insert_batch(collider_rects);
+    world
+        .resource_mut::<RenderHash>()
+        .insert_batch(sprite_rects);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::WorldSeed;
+    use crate::spatial_hash::SpatialHash;
+
+    #[test]
+    fn test_loader_batches_until_done() {
+        let mut loader = WorldLoader::new(10, 4);
+        assert_eq!(loader.next_batch(), 0..4);
+        assert_eq!(loader.progress(), 0.4);
+        assert_eq!(loader.next_batch(), 4..8);
+        assert!(loader.is_loading());
+        // The short last batch finishes loading
+        assert_eq!(loader.next_batch(), 8..10);
+        assert_eq!(loader.state, LoadState::Done);
+        assert!(loader.next_batch().is_empty());
+
+        // Cancelling stops handing out batches, and can't undo a finished load
+        let mut loader = WorldLoader::new(10, 4);
+        loader.next_batch();
+        loader.cancel();
+        assert_eq!(loader.state, LoadState::Cancelled);
+        assert!(loader.next_batch().is_empty());
+        loader.state = LoadState::Done;
+        loader.cancel();
+        assert_eq!(loader.state, LoadState::Done);
+
+        assert_eq!(WorldLoader::new(0, 4).state, LoadState::Done);
+    }
+
+    #[test]
+    fn test_populate_spawns_and_indexes_each_batch() {
+        let mut world = World::new();
+        world.insert_resource(WorldSeed(7));
+        world.init_resource::<SeededRng>();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.insert_resource(WorldLoader::new(250, 100));
+
+        let count = |world: &mut World| world.query::<&Collider>().iter(world).count();
+        for expected in [100, 200, 250, 250] {
+            populate_world_system(&mut world);
+            assert_eq!(count(&mut world), expected);
+            assert_eq!(world.resource::<CollisionHash>().len(), expected);
+            assert_eq!(world.resource::<RenderHash>().len(), expected);
+        }
+        assert_eq!(world.resource::<WorldLoader>().state, LoadState::Done);
+    }
+
+    fn positions(boxes: &[SyncedSpriteBundle]) -> Vec<(f32, f32)> {
+        boxes
+            .iter()
+            .map(|(bundle, ..)| (bundle.transform.position.x, bundle.transform.position.y))
+            .collect()
+    }
+
+    #[test]
+    fn test_parallel_boxes_are_reproducible() {
+        let first = positions(&grid_boxes_parallel(0..1000, 7));
+        assert_eq!(first, positions(&grid_boxes_parallel(0..1000, 7)));
+        assert_ne!(first, positions(&grid_boxes_parallel(0..1000, 8)));
+        // A box doesn't depend on which batch it came in
+        assert_eq!(first[500..], positions(&grid_boxes_parallel(500..1000, 7)));
+        // Still inside the 3px jitter of its grid slot
+        let (x, y) = first[GRID_COLUMNS + 1];
+        assert!((235.0..238.0).contains(&x) && (135.0..138.0).contains(&y));
+    }
+
+    /// `cargo test --release bench_grid_boxes -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_grid_boxes_sequential_vs_parallel() {
+        const COUNT: usize = 500_000;
+        let mut world = World::new();
+        let start = std::time::Instant::now();
+        let mut rng = SmallRng::seed_from_u64(7);
+        let boxes: Vec<_> = (0..COUNT).map(|index| grid_box(index, &mut rng)).collect();
+        let generated = start.elapsed();
+        world.spawn_batch(boxes);
+        println!(
+            "sequential: {generated:?} generating, {:?} total",
+            start.elapsed()
+        );
+
+        let mut world = World::new();
+        let start = std::time::Instant::now();
+        let boxes = grid_boxes_parallel(0..COUNT, 7);
+        let generated = start.elapsed();
+        world.spawn_batch(boxes);
+        println!(
+            "parallel: {generated:?} generating, {:?} total",
+            start.elapsed()
+        );
+    }
+}
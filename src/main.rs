@@ -1,35 +1,47 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
 
-use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
+use bevy_ecs::prelude::*;
+use builder::EntityBuilder;
 use components::*;
 use rayon::prelude::*;
 use resources::*;
+use runner::*;
 use rustyray::prelude::*;
+use spatial_hash::{CollisionHash, RenderHash, SpatialHash};
+use sweep_prune::{BroadphaseMethod, CollisionSweep};
+use system_order::{expect_ran, mark_ran};
 use systems::*;
+use timings::metric_scope;
 #[cfg(feature = "trace")]
 use tracing::{info, info_span};
 
+mod animation;
+mod assets;
+mod audio;
+mod bake;
+mod behavior;
+#[cfg(feature = "shaders")]
+mod blur;
+mod builder;
+mod camera_utils;
+mod chunks;
 mod components;
+mod extract;
+mod input;
+mod loader;
+mod particles;
+mod physics;
 mod resources;
+mod runner;
+mod snapshot;
 mod spatial_hash;
+mod sweep_prune;
+mod system_order;
 mod systems;
-
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct Update;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct FirstPhysicsUpdate;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct PrePhysicsUpdate;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct PhysicsUpdate;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct PostPhysicsUpdate;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct LastPhysicsUpdate;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct PreRender;
-#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
-struct Render;
+mod tileset;
+mod timings;
+mod tween;
+mod utils;
 
 fn main() {
     #[cfg(feature = "trace")]
@@ -49,18 +61,106 @@ fn main() {
     let mut pre_render_schedule = bevy_ecs::schedule::Schedule::new(PreRender);
     let mut render_schedule = bevy_ecs::schedule::Schedule::new(Render);
 
-    // update_schedule.add_systems(());
+    update_schedule.add_systems((
+        (
+            update_window_focus_system,
+            update_messages_system::<FocusChanged>,
+        )
+            .chain(),
+        input::update_input_system,
+        compute_camera_view_system,
+        tween::tween_system::<Transform>,
+        tween::tween_system::<Sprite>,
+        clear_contact_manifold_system,
+        record_frame_time_system,
+        update_messages_system::<audio::PlaySound>,
+        (
+            assets::reload_changed_assets_system,
+            assets::process_asset_queue_system,
+            update_messages_system::<assets::AssetReloaded>,
+        )
+            .chain(),
+        compact_spatial_hashes_system.run_if(spatial_hash_compact_due),
+    ));
 
-    first_physics_update_schedule.add_systems(ensure_global_transform_system);
+    first_physics_update_schedule.add_systems((
+        ensure_global_transform_system,
+        debug_contacts_tick_system,
+        system_order::reset_system_order_system,
+    ));
     pre_physics_update_schedule.add_systems((sync_collider_with_sprite_system,).chain());
-    physics_update_schedule.add_systems(move_player_system);
-    post_physics_update_schedule
-        .add_systems((apply_velocity_system, update_global_transforms_system).chain());
+    physics_update_schedule.add_systems((
+        move_player_system,
+        behavior::behavior_sequence_system,
+        behavior::follow_path_system,
+        behavior::wander_system,
+        (
+            particles::expire_particles_system,
+            particles::emit_particles_system,
+        )
+            .chain(),
+    ));
+    post_physics_update_schedule.add_systems(
+        (
+            apply_force_fields_system,
+            wake_system,
+            clamp_velocity_system,
+            apply_velocity_system,
+            enforce_world_bounds_system,
+            update_global_transforms_system.run_if(mark_ran(update_global_transforms_system)),
+            mark_static_transforms_system.run_if(static_transform_check_due),
+        )
+            .chain(),
+    );
     last_physics_update_schedule.add_systems(
         (
+            contact_damage_system,
+            (apply_damage_system, animation::player_animation_system),
+            update_health_bars_system,
+            kill_dead_entities_system,
             sync_collider_with_sprite_system,
-            update_spatial_hash_system,
-            update_on_screen_system,
+            (
+                bake::mark_bake_dirty_system,
+                bake::bake_static_colliders_system.run_if(bake::bake_is_dirty),
+            )
+                .chain(),
+            (dynamic_layer_system, classify_screen_space_system).chain(),
+            quarantine_non_finite_system,
+            // Hashes need this tick's transforms, culling needs the render hash. Conditions
+            // are all evaluated, so the order checks run even when nothing moved.
+            (
+                update_collision_hash_system
+                    .run_if(any_transform_changed)
+                    .run_if(expect_ran(
+                        update_global_transforms_system,
+                        update_collision_hash_system,
+                    ))
+                    .run_if(mark_ran(update_collision_hash_system)),
+                update_render_hash_system
+                    .run_if(any_transform_changed)
+                    .run_if(expect_ran(
+                        update_global_transforms_system,
+                        update_render_hash_system,
+                    ))
+                    .run_if(mark_ran(update_render_hash_system)),
+            ),
+            update_collision_sweep_system.run_if(uses_sweep_prune),
+            update_subtree_bounds_system,
+            update_on_screen_system.run_if(expect_ran(
+                update_render_hash_system,
+                update_on_screen_system,
+            )),
+            record_camera_target_system,
+            (flash_on_enter_view_system, tick_view_flash_system).chain(),
+            sleep_system,
+            (
+                update_messages_system::<CollisionEvent>,
+                update_messages_system::<DamageEvent>,
+                update_messages_system::<DeathEvent>,
+                update_messages_system::<EnteredView>,
+                update_messages_system::<LeftView>,
+                update_messages_system::<behavior::ScriptEvent>,
+            ),
         )
             .chain(),
     );
@@ -70,58 +170,136 @@ fn main() {
     render_schedule.add_systems((
         check_for_resize_system,
         update_render_textures_size_system,
-        render_layers,
+        (camera_interpolation_system, compute_camera_view_system)
+            .chain()
+            .before(render_layers)
+            .before(render_lighting_system)
+            .before(render_fog_system),
+        render_layers
+            .run_if(layers_need_redraw)
+            .after(update_render_textures_size_system),
+        render_lighting_system.run_if(lights_need_redraw),
+        render_fog_system.run_if(fog_needs_redraw),
         render_system,
+        audio::audio_system,
+        frame_limiter_system
+            .after(render_system)
+            .after(audio::audio_system),
     ));
-    pre_render_schedule.add_systems((
-        update_camera_offset,
-        move_camera_to_target_system,
-        update_count_text_system,
-        update_on_screen_text_system,
-        debug_toggle_system,
-    ));
+    #[cfg(feature = "shaders")]
+    render_schedule.add_systems(
+        blur::blur_pass_system
+            .after(render_layers)
+            .after(update_render_textures_size_system)
+            .before(render_system),
+    );
+    pre_render_schedule.add_systems(
+        (
+            (
+                update_camera_offset,
+                layout_hud_strip_system,
+                cursor_system,
+                (move_camera_to_target_system, ease_camera_zoom_system).chain(),
+                update_count_text_system,
+                update_on_screen_text_system,
+                (
+                    debug_toggle_system,
+                    apply_window_settings_system.run_if(resource_changed::<WindowSettings>),
+                )
+                    .chain(),
+                debug_cell_size_system,
+                toggle_player_capsule_system,
+                debug_system_counts_system,
+                (select_entity_system, inspect_selected_entity_system).chain(),
+                drag_entity_system,
+                (archetype_stats_system, debug_archetype_report_system).chain(),
+                update_fog_system,
+                chunks::chunk_streaming_system,
+                audio::bump_sound_system,
+            ),
+            // Last, so it sees every change made before rendering
+            extract::extract_render_data_system,
+        )
+            .chain(),
+    );
+    for schedule in [
+        update_schedule,
+        first_physics_update_schedule,
+        pre_physics_update_schedule,
+        physics_update_schedule,
+        post_physics_update_schedule,
+        last_physics_update_schedule,
+        pre_render_schedule,
+        render_schedule,
+    ] {
+        world.add_schedule(schedule);
+    }
 
     world.spawn((
-        SpriteBundle {
-            sprite: Sprite {
+        SpriteBundle::new(
+            Sprite {
                 kind: SpriteKind::Circle { radius: 40.0 },
                 color: Color::RED,
                 origin: SpriteOrigin::Bottom,
+                opacity: 1.0,
             },
-            transform: Transform {
-                position: Vector2 { x: 50.0, y: 1000.0 },
-                ..Default::default()
-            },
-            ..Default::default()
-        },
+            Transform::default().with_position(Vector2 { x: 50.0, y: 1000.0 }),
+        ),
         Velocity::default(),
         OnScreen,
         Collider::default(),
         SyncColliderWithSprite,
     ));
 
+    let player = world
+        .spawn(
+            EntityBuilder::new()
+                .with_sprite(
+                    SpriteKind::Rectangle {
+                        size: (32.0, 32.0),
+                        lines: false,
+                    },
+                    Color::RED,
+                    SpriteOrigin::Custom(Vector2::new(0.5, 0.75)),
+                )
+                .with_transform(Vector2 { x: 50.0, y: 50.0 })
+                .with_velocity(Vector2::new(0.0, 0.0))
+                .with_collider_synced()
+                .as_player()
+                .on_screen()
+                .with((
+                    EntityName("player".to_owned()),
+                    MaxSpeed(1200.0),
+                    Vision,
+                    PointLight {
+                        color: Color::new(255, 220, 170, 255),
+                        radius: 300.0,
+                        intensity: 1.0,
+                    },
+                    CameraTarget,
+                    CollisionPriority(10),
+                    Health::new(100.0),
+                    animation::AnimationState::player(),
+                ))
+                .build(),
+        )
+        .id();
+    // Health bar just above the player's sprite
     world.spawn((
-        SpriteBundle {
-            sprite: Sprite {
+        SpriteBundle::new(
+            Sprite {
                 kind: SpriteKind::Rectangle {
-                    size: (32.0, 32.0),
+                    size: (32.0, 4.0),
                     lines: false,
                 },
-                color: Color::RED,
-                origin: SpriteOrigin::Custom(Vector2::new(0.5, 0.75)),
-            },
-            transform: Transform {
-                position: Vector2 { x: 50.0, y: 50.0 },
-                ..Default::default()
+                color: Color::GREEN,
+                origin: SpriteOrigin::Left,
+                opacity: 1.0,
             },
-            ..Default::default()
-        },
-        Velocity::default(),
-        Player,
-        OnScreen,
-        CameraTarget,
-        Collider::default(),
-        SyncColliderWithSprite,
+            Transform::default().with_position(Vector2::new(-16.0, -30.0)),
+        ),
+        HealthBar { width: 32.0 },
+        ChildOf(player),
     ));
 
     // world.spawn((
@@ -133,95 +311,156 @@ fn main() {
     //         },
     //         ..Default::default()
     //     },
-    //     Velocity(Vector2::new(2.0, -2.0)),
+    //     Velocity(Vector2::new(120.0, -120.0)),
     //     OnScreen,
     //     Collider::default(),
     //     SyncColliderWithSprite,
     // ));
 
-    const TO_SPAWN: usize = 100_000 / 100;
+    // Patrols in the open strip left of the grid, resolved against colliders like the player
+    let patrol_sprite = |color| Sprite {
+        kind: SpriteKind::Rectangle {
+            size: (20.0, 20.0),
+            lines: false,
+        },
+        color,
+        origin: SpriteOrigin::Center,
+        opacity: 1.0,
+    };
+    for (i, x) in [40.0, 100.0, 150.0].into_iter().enumerate() {
+        let top = 200.0 + 150.0 * i as f32;
+        world.spawn((
+            SpriteBundle::new(
+                patrol_sprite(Color::SKYBLUE),
+                Transform::default().with_position(Vector2::new(x, top)),
+            ),
+            behavior::FollowPath::new(
+                [Vector2::new(x, top), Vector2::new(x, top + 400.0)],
+                80.0,
+                true,
+            ),
+            Collider::default(),
+            SyncColliderWithSprite,
+        ));
+    }
+    for seed in 0..2 {
+        world.spawn((
+            SpriteBundle::new(
+                patrol_sprite(Color::PURPLE),
+                Transform::default().with_position(Vector2::new(90.0, 800.0 + 100.0 * seed as f32)),
+            ),
+            behavior::Wander::new(60.0, 1.5, seed),
+            DamageOnContact {
+                amount: 10.0,
+                cooldown: 1.0,
+            },
+            Collider::default(),
+            SyncColliderWithSprite,
+        ));
+    }
+
+    // Streamed in and out around the camera instead
+    world.insert_resource(chunks::demo_chunks());
+    // The grid itself is spawned a batch per frame by the loader
+    world.insert_resource(loader::WorldLoader::new(100_000, 5_000));
 
-    (0..TO_SPAWN).for_each(|i| {
-        (0..100).for_each(|j| {
-            world.spawn((
-                SpriteBundle {
-                    sprite: Sprite {
-                        origin: SpriteOrigin::Custom((0.0, 0.0).into()),
-                        ..Default::default()
-                    },
-                    transform: Transform {
-                        position: Vector2 {
-                            x: 200. + (35 * j) as f32,
-                            y: 100. + (35 * i) as f32,
-                        },
-                        ..Default::default()
-                    },
-                    ..Default::default()
-                },
-                Collider::default(),
-                SyncColliderWithSprite,
-            ));
-        });
-    });
+    // Invisible wall along the left edge of the grid
+    world.spawn(StaticColliderBundle::new(
+        Collider {
+            kind: ColliderKind::Rectangle(Vector2::new(10.0, 35100.0)),
+            ..Default::default()
+        },
+        Transform::default().with_position(Vector2::new(180.0, 0.0)),
+    ));
 
+    // Fan blowing left across the open strip, catches the player and the red ball
     world.spawn((
-        Transform::default(),
-        Text {
-            content: String::new(),
-            font_size: 24,
-            color: Color::WHITE,
+        StaticColliderBundle::new(
+            Collider {
+                kind: ColliderKind::Rectangle(Vector2::new(300.0, 1200.0)),
+                ..Default::default()
+            },
+            Transform::default().with_position(Vector2::new(-130.0, 0.0)),
+        ),
+        ForceField {
+            force: Vector2::new(-4000.0, 0.0),
+            falloff: ForceFalloff::Linear,
         },
+    ));
+
+    world.spawn((
+        TextBundle::new(Text::new("", 24, Color::WHITE), Transform::default()),
         CountText,
     ));
     world.spawn((
-        Transform::default().with_position(Vector2::new(0.0, 20.0)),
-        Text {
-            content: String::new(),
-            font_size: 24,
-            color: Color::WHITE,
-        },
+        TextBundle::new(
+            Text::new("", 24, Color::WHITE).with_font("main"),
+            Transform::default().with_position(Vector2::new(0.0, 20.0)),
+        ),
         OnScreenText,
     ));
+    // Sized and placed by `layout_hud_strip_system`
+    world.spawn((
+        SpriteBundle {
+            layer: Layer(HUD_LAYER),
+            ..SpriteBundle::new(
+                Sprite {
+                    color: Color::new(0, 0, 0, 255).fade(0.5),
+                    origin: SpriteOrigin::TopLeft,
+                    ..Default::default()
+                },
+                Transform::default(),
+            )
+        },
+        HudStrip,
+        hud_backdrop(),
+    ));
+    // Placed on the mouse by `cursor_system`
+    world.spawn((
+        SpriteBundle {
+            layer: Layer(CURSOR_LAYER),
+            ..SpriteBundle::new(
+                Sprite {
+                    kind: SpriteKind::Circle { radius: 6.0 },
+                    color: Color::WHITE,
+                    ..Default::default()
+                },
+                Transform::default(),
+            )
+        },
+        ZIndex(i32::MAX),
+        Cursor,
+    ));
+
+    // Nothing else runs until the grid exists, ESC (or closing the window) gives up
+    while world.resource::<loader::WorldLoader>().is_loading() {
+        loader::populate_world_system(&mut world);
+        let window = world.resource::<WindowResource>();
+        if window.is_key_pressed(KeyboardKey::Escape) || window.should_close() {
+            world.resource_mut::<loader::WorldLoader>().cancel();
+        }
+        let progress = world.resource::<loader::WorldLoader>().progress();
+        draw_loading_screen(&mut world.resource_mut::<WindowResource>(), progress);
+    }
+    if world.resource::<loader::WorldLoader>().state == loader::LoadState::Cancelled {
+        cleanup_world(&mut world);
+        return;
+    }
 
     let mut physics_time = Time::new(60.0);
     world.insert_resource(Metrics::default());
-    let mut window = world.resource::<WindowResource>();
     loop {
-        let frame_time = window.frame_time();
-        world.insert_resource(Time {
-            delta: frame_time,
-            accumulator: 0.0,
-        });
-        {
-            #[cfg(feature = "trace")]
-            let _span = info_span!("update").entered();
-            update_schedule.run(&mut world);
-        }
-        physics_time.accumulator += frame_time;
-        while physics_time.accumulator >= physics_time.delta {
-            #[cfg(feature = "trace")]
-            let _span = info_span!("physics loop").entered();
-            world.insert_resource(physics_time);
-            first_physics_update_schedule.run(&mut world);
-            pre_physics_update_schedule.run(&mut world);
-            physics_update_schedule.run(&mut world);
-            post_physics_update_schedule.run(&mut world);
-            last_physics_update_schedule.run(&mut world);
-            physics_time.accumulator -= physics_time.delta
-        }
-        {
-            #[cfg(feature = "trace")]
-            let _span = info_span!("render").entered();
-            pre_render_schedule.run(&mut world);
-            render_schedule.run(&mut world);
-        }
+        let frame_time = world.resource::<WindowResource>().frame_time();
+        run_frame(&mut world, &mut physics_time, frame_time);
 
-        window = world.resource::<WindowResource>();
+        if let Some(duration) = frame_throttle(&world) {
+            std::thread::sleep(duration);
+        }
 
         #[cfg(feature = "once")]
         break;
 
-        if window.should_close() {
+        if world.resource::<WindowResource>().should_close() {
             break;
         }
     }
@@ -229,25 +468,180 @@ fn main() {
     cleanup_world(&mut world);
 }
 
+fn draw_loading_screen(window: &mut WindowResource, progress: f32) {
+    let screen_size = window.screen_size().to_vector2();
+    let bar = Rectangle {
+        x: screen_size.x * 0.25,
+        y: screen_size.y / 2.0 - 10.0,
+        width: screen_size.x * 0.5,
+        height: 20.0,
+    };
+    window.draw(|d| {
+        d.clear(Color::BLACK);
+        d.draw_rect(bar, Color::DARKGRAY);
+        d.draw_rect(
+            Rectangle {
+                width: bar.width * progress,
+                ..bar
+            },
+            Color::GREEN,
+        );
+        d.draw_text(
+            &format!("Loading {:.0}%", progress * 100.0),
+            bar.x as i32,
+            bar.y as i32 - 30,
+            20,
+            Color::WHITE,
+        );
+    });
+}
+
+/// Blurs what's behind the HUD strip, nothing without the `shaders` feature.
+#[cfg(feature = "shaders")]
+fn hud_backdrop() -> impl Bundle {
+    blur::BlurBackground { strength: 6.0 }
+}
+
+#[cfg(not(feature = "shaders"))]
+fn hud_backdrop() -> impl Bundle {}
+
 fn init_world(world: &mut World) {
-    world.insert_resource(spatial_hash::SpatialHash::new(96.0));
+    let seed = WorldSeed(42);
+    println!("World seed: {}", seed.0);
+    world.insert_resource(seed);
+    world.init_resource::<SeededRng>();
+    world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+    // Culling queries cover the whole view, so skip empty regions a coarse cell at a time
+    world.insert_resource(RenderHash(SpatialHash::new(96.0 * 8.0, 96.0)));
+    world.init_resource::<CollisionSweep>();
+    world.init_resource::<ContactManifold>();
+    world.init_resource::<BroadphaseMethod>();
+    world.init_resource::<bake::BakedStaticColliders>();
+    world.add_observer(bake::mark_bake_dirty_on_remove);
     world.insert_resource(Messages::<ResizeEvent>::default());
+    world.insert_resource(Messages::<CollisionEvent>::default());
+    world.insert_resource(Messages::<FocusChanged>::default());
+    world.insert_resource(Messages::<DamageEvent>::default());
+    world.insert_resource(Messages::<DeathEvent>::default());
+    world.init_resource::<DamageCooldowns>();
+    world.init_resource::<snapshot::SnapshotHistory>();
+    world.init_resource::<StepMode>();
+    world.init_resource::<StaticTransformSettings>();
+    world.init_resource::<CameraViewRect>();
+    world.init_resource::<InactiveLayers>();
+    world.insert_resource(Messages::<EnteredView>::default());
+    world.insert_resource(Messages::<LeftView>::default());
+    world.insert_resource(Messages::<audio::PlaySound>::default());
+    world.insert_resource(Messages::<behavior::ScriptEvent>::default());
+    world.insert_resource(Messages::<assets::AssetReloaded>::default());
+    world.init_resource::<assets::TextureAssets>();
+    world.init_resource::<audio::AudioSettings>();
+    world.init_resource::<audio::BumpSound>();
+    world.insert_resource(WindowFocus {
+        focused: true,
+        minimized: false,
+    });
+    world.init_resource::<RenderSettings>();
+    world.init_resource::<CullingSettings>();
+    world.init_resource::<WorldStats>();
+    world.init_resource::<input::ActionMap>();
+    world.init_resource::<input::InputSettings>();
+    world.init_resource::<input::InputState>();
+    add_world_stats_observers(world);
+    world.init_resource::<NameRegistry>();
+    add_name_registry_observers(world);
+    add_spatial_hash_observers(world);
+    world.add_observer(particles::free_particle_slot);
+    world.init_resource::<PhysicsSettings>();
+    // Everything spawned so far, including the streamed boxes, with room to spare
+    world.insert_resource(WorldBounds(Rectangle {
+        x: -6000.0,
+        y: -2000.0,
+        width: 12000.0,
+        height: 40000.0,
+    }));
     world.insert_resource(DebugSettings {
         origins: false,
         colliders: false,
+        visualize_culling: false,
+        show_system_counts: false,
+        contacts: false,
+        show_fps_graph: false,
+        inspector: false,
+        hide_cursor: true,
+        drag: false,
     });
+    world.init_resource::<SystemCountsTable>();
+    world.init_resource::<SelectedEntity>();
+    world.init_resource::<DragState>();
+    world.init_resource::<system_order::SystemOrderAssertion>();
+    world.init_resource::<InspectorPanel>();
     world.init_resource::<LayerTextures>();
 
     world.insert_resource(WindowSize(Vector2i { x: 1024, y: 768 }));
-    world.insert_resource(WindowResource(
-        WindowBuilder::new(1024, 768, "RustyRay")
-            .set_fps(60)
+    world.insert_resource(ReferenceResolution(Vector2i { x: 1024, y: 768 }));
+    // Resizing shows the same stretch of world, only bigger or smaller
+    world.insert_resource(CameraScalingMode::KeepWidth);
+    world.init_resource::<CameraFraming>();
+    world.init_resource::<FogOfWar>();
+    world.init_resource::<FogVisibility>();
+    world.init_resource::<LayerSettings>();
+    world.init_resource::<LayerSortMode>();
+    world.init_resource::<RenderRecovery>();
+    world.init_resource::<extract::RenderWorld>();
+    world.insert_resource(Lighting {
+        ambient: Color::new(90, 90, 120, 255),
+    });
+    // Covers the spawned grid, drawn in the top right corner
+    world.insert_resource(MinimapSettings {
+        screen_rect: Rectangle {
+            x: 1024.0 - 110.0,
+            y: 10.0,
+            width: 100.0,
+            height: 300.0,
+        },
+        world_rect: Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 3900.0,
+            height: 35300.0,
+        },
+        background_color: Color::new(0, 0, 0, 255).fade(0.6),
+    });
+    let window_settings = WindowSettings::default();
+    let mut window = WindowResource(
+        WindowBuilder::new(1024, 768, &window_settings.title)
+            .set_fps(window_settings.fps_cap.target_fps())
             .set_config_flags(
                 ConfigFlag::WindowHighdpi | ConfigFlag::WindowResizable | ConfigFlag::VsyncHint,
             )
             .build()
             .unwrap(),
-    ));
+    );
+    if let Some(icon) = &window_settings.icon {
+        if let Err(err) = window.set_icon(icon) {
+            println!("Failed to load {icon}: {err}");
+        }
+    }
+    world.insert_resource(window);
+    world.insert_resource(window_settings);
+    world.init_resource::<FramerateLimiter>();
+    #[cfg(feature = "audio")]
+    world.insert_non_send_resource(audio::AudioAssets::load());
+    // Fonts live on the GPU, so they need the window's context
+    let mut fonts = FontLibrary::default();
+    match OwnedFont::load("assets/fonts/main.ttf") {
+        Ok(font) => {
+            fonts.0.insert("main".to_string(), font);
+        }
+        Err(err) => println!("Failed to load assets/fonts/main.ttf: {err:?}"),
+    }
+    world.insert_resource(fonts);
+    #[cfg(feature = "shaders")]
+    {
+        world.insert_resource(blur::ShaderLibrary::load());
+        world.init_resource::<blur::BlurTexture>();
+    }
     world.spawn((
         Camera(Camera2D {
             offset: Vector2 {
@@ -256,6 +650,9 @@ fn init_world(world: &mut World) {
             },
             ..Default::default()
         }),
+        CameraSpring::critically_damped(60.0),
+        CameraInterpolation::default(),
+        CameraZoom::default(),
         ActiveCamera,
     ));
 }
@@ -263,13 +660,29 @@ fn init_world(world: &mut World) {
 fn cleanup_world(world: &mut World) {
     // Make sure we remove this now, because we can't be sure when the WindowResource is removed and that will close out the window so this will fail
     world.remove_resource::<LayerTextures>();
+    world.remove_resource::<FontLibrary>();
+    if let Some(metrics) = world.get_resource::<Metrics>() {
+        println!("{}", metrics.report());
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_system(
     mut window: ResMut<WindowResource>,
     layer_rt: Res<LayerTextures>,
-    text: Query<(&Text, &GlobalTransform)>,
+    layer_settings: Res<LayerSettings>,
+    debug_settings: Res<DebugSettings>,
+    text: Query<(&Text, &GlobalTransform, Option<&TextAnchor>)>,
+    fonts: Res<FontLibrary>,
     metrics: Res<Metrics>,
+    minimap: Res<MinimapSettings>,
+    minimap_q: Query<(&GlobalTransform, Has<Player>)>,
+    (system_counts, inspector): (Res<SystemCountsTable>, Res<InspectorPanel>),
+    window_settings: Res<WindowSettings>,
+    step_mode: Res<StepMode>,
+    snapshots: Res<snapshot::SnapshotHistory>,
+    inactive_layers: Res<InactiveLayers>,
+    #[cfg(feature = "shaders")] blur_texture: Res<blur::BlurTexture>,
 ) {
     let screen_size = window.screen_size();
     window.draw(|d| {
@@ -277,33 +690,146 @@ fn render_system(
 
         // Draw GAME entities and other stuff on the layers
         let _draw_layers_span = tracing::span!(tracing::Level::DEBUG, "draw_layers").entered();
-        for (_, rt) in layer_rt.0.iter() {
+        let (screen_layers, world_layers): (Vec<u32>, Vec<u32>) =
+            composite_order(layer_rt.0.keys().copied())
+                .into_iter()
+                .filter(|&layer| inactive_layers.is_active(layer))
+                .partition(|&layer| layer_settings.is_screen_space(layer));
+        let active_texture = |layer: u32| {
+            layer_rt
+                .0
+                .get(&layer)
+                .filter(|_| inactive_layers.is_active(layer))
+        };
+        // Scaled layers are stretched back over the window, render textures sample with point
+        // filtering so their pixels stay sharp
+        let screen_rect = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: screen_size.x as f32,
+            height: screen_size.y as f32,
+        };
+        let layer_source = |layer: u32| {
+            layer_settings
+                .get(layer)
+                .composite_source(screen_rect, screen_size)
+        };
+        for layer in world_layers {
             let _draw_layer = tracing::span!(tracing::Level::DEBUG, "draw_layer").entered();
+            d.draw_render_texture_pro(
+                &layer_rt.0[&layer],
+                layer_source(layer),
+                screen_rect,
+                Vector2::new(0.0, 0.0),
+                0.0,
+                Color::WHITE,
+            );
+        }
+        if let Some(rt) = active_texture(LIGHTING_LAYER) {
+            let mut d = d.begin_blend_mode(BlendMode::Multiplied);
+            d.draw_render_texture(rt);
+        }
+        if let Some(rt) = active_texture(FOG_LAYER) {
             d.draw_render_texture(rt);
         }
+        // Backdrops for the panels drawn next
+        #[cfg(feature = "shaders")]
+        if let Some(rt) = blur_texture.texture() {
+            d.draw_render_texture(rt);
+        }
+        // HUD and friends stay clear of the lighting and fog
+        for layer in screen_layers {
+            let _draw_layer = tracing::span!(tracing::Level::DEBUG, "draw_layer").entered();
+            d.draw_render_texture_pro(
+                &layer_rt.0[&layer],
+                layer_source(layer),
+                screen_rect,
+                Vector2::new(0.0, 0.0),
+                0.0,
+                Color::WHITE,
+            );
+        }
+        if debug_settings.origins || debug_settings.colliders || debug_settings.contacts {
+            if let Some(rt) = active_texture(DEBUG_LAYER) {
+                d.draw_render_texture(rt);
+            }
+        }
         drop(_draw_layers_span);
 
-        // START OF UI RENDERING
-        d.draw_rect(
-            Rectangle {
-                height: 30.0,
-                width: screen_size.x as f32,
-                x: 0.0,
-                y: screen_size.y as f32 - 30.0,
-            },
-            Color::new(0, 0, 0, 255).fade(0.5),
-        );
+        // Minimap is in screen space, so it's drawn straight to the window
+        let _draw_minimap_span = tracing::span!(tracing::Level::DEBUG, "draw_minimap").entered();
+        d.draw_rect(minimap.screen_rect, minimap.background_color);
+        let mut player_dots = Vec::new();
+        for (transform, is_player) in minimap_q.iter() {
+            let Some(dot) = minimap.world_to_minimap(transform.position) else {
+                continue;
+            };
+            // Players go on top of everything else
+            if is_player {
+                player_dots.push(dot);
+                continue;
+            }
+            d.draw_rect(
+                Rectangle {
+                    x: dot.x,
+                    y: dot.y,
+                    width: 1.0,
+                    height: 1.0,
+                },
+                Color::WHITE,
+            );
+        }
+        for dot in player_dots {
+            d.draw_rect(
+                Rectangle {
+                    x: dot.x - 2.0,
+                    y: dot.y - 2.0,
+                    width: 4.0,
+                    height: 4.0,
+                },
+                Color::RED,
+            );
+        }
+        drop(_draw_minimap_span);
+
+        // START OF UI RENDERING, the strip behind it is a `HudStrip` sprite
         d.draw_fps(10, screen_size.y - 25);
+        d.draw_text(
+            &format!("Cap: {}", window_settings.fps_cap),
+            110,
+            screen_size.y - 25,
+            20,
+            Color::WHITE,
+        );
         let _draw_texts_span = tracing::span!(tracing::Level::DEBUG, "draw_texts").entered();
-        for (text, transform) in text.iter() {
+        for (text, transform, anchor) in text.iter() {
             let _draw_text_span = tracing::span!(tracing::Level::DEBUG, "draw_text").entered();
-            d.draw_text(
-                &text.content,
-                transform.position.x as i32,
-                transform.position.y as i32,
-                text.font_size as i32,
-                text.color,
-            );
+            let font = fonts.get(text.font.as_deref());
+            let font_size = text.font_size as f32;
+            // Same spacing raylib uses for the default font
+            let spacing = font_size / 10.0;
+            let measure = |line: &str| match font {
+                Some(font) => d.measure_text_ex(font, line, font_size, spacing).x,
+                None => d.measure_text(line, text.font_size as i32) as f32,
+            };
+            let lines = text.layout(transform.position, anchor, measure);
+            for (offset, color) in text.passes() {
+                for &(line, line_position) in &lines {
+                    let line_position = line_position + offset;
+                    match font {
+                        Some(font) => {
+                            d.draw_text_ex(font, line, line_position, font_size, spacing, color)
+                        }
+                        None => d.draw_text(
+                            line,
+                            line_position.x as i32,
+                            line_position.y as i32,
+                            text.font_size as i32,
+                            color,
+                        ),
+                    }
+                }
+            }
         }
         drop(_draw_texts_span);
         d.draw_text(
@@ -326,98 +852,373 @@ fn render_system(
             20,
             Color::WHITE,
         );
+
+        if *step_mode != StepMode::Running {
+            d.draw_text(
+                &format!(
+                    "{:?} at tick {} (F10 resume, F11 step)",
+                    *step_mode, snapshots.tick
+                ),
+                10,
+                50,
+                20,
+                Color::YELLOW,
+            );
+        }
+
+        if debug_settings.show_fps_graph {
+            for (rect, color) in fps_graph_rects(&metrics.frame_time_history, screen_size) {
+                d.draw_rect(rect, color);
+            }
+        }
+
+        if debug_settings.show_system_counts {
+            let rows = &system_counts.0;
+            let height = rows.len() as f32 * 22.0 + 10.0;
+            let top = screen_size.y as f32 - 40.0 - height;
+            d.draw_rect(
+                Rectangle {
+                    x: 5.0,
+                    y: top,
+                    width: 480.0,
+                    height,
+                },
+                Color::new(0, 0, 0, 255).fade(0.6),
+            );
+            for (i, row) in rows.iter().enumerate() {
+                d.draw_text(row, 12, top as i32 + 6 + i as i32 * 22, 20, Color::WHITE);
+            }
+        }
+
+        if debug_settings.inspector && !inspector.0.is_empty() {
+            let rows = &inspector.0;
+            let width = 520.0;
+            let left = screen_size.x as f32 - width - 5.0;
+            d.draw_rect(
+                Rectangle {
+                    x: left,
+                    y: 5.0,
+                    width,
+                    height: rows.len() as f32 * 22.0 + 10.0,
+                },
+                Color::new(0, 0, 0, 255).fade(0.6),
+            );
+            for (i, row) in rows.iter().enumerate() {
+                d.draw_text(row, left as i32 + 7, 11 + i as i32 * 22, 20, Color::WHITE);
+            }
+        }
+    });
+}
+
+/// Background and one bar per frame for the FPS graph, newest on the right, 3px per
+/// millisecond up to the graph's height.
+fn fps_graph_rects(history: &VecDeque<f32>, screen_size: Vector2i) -> Vec<(Rectangle, Color)> {
+    let max_height = 100.0;
+    let right = screen_size.x as f32 - 10.0;
+    let bottom = screen_size.y as f32 - 40.0;
+    let left = right - FRAME_TIME_HISTORY as f32;
+    let background = Rectangle {
+        x: left,
+        y: bottom - max_height,
+        width: FRAME_TIME_HISTORY as f32,
+        height: max_height,
+    };
+    let start = right - history.len() as f32;
+    let bars = history.iter().enumerate().map(|(i, &frame_time)| {
+        let height = (frame_time * 1000.0 * 3.0).min(max_height);
+        let bar = Rectangle {
+            x: start + i as f32,
+            y: bottom - height,
+            width: 1.0,
+            height,
+        };
+        (bar, frame_time_color(frame_time))
+    });
+    std::iter::once((background, Color::new(0, 0, 0, 255).fade(0.6)))
+        .chain(bars)
+        .collect()
+}
+
+/// Layer texture, created on first use. A failed allocation is logged and the layer is
+/// retried next frame instead of taking the game down.
+fn layer_texture<'a>(
+    layer_rt: &'a mut LayerTextures,
+    recovery: &mut RenderRecovery,
+    layer: u32,
+    size: Vector2i,
+) -> Option<&'a mut OwnedRenderTexture> {
+    if !layer_rt.0.contains_key(&layer) {
+        match OwnedRenderTexture::new(size.x, size.y) {
+            Ok(rt) => {
+                layer_rt.0.insert(layer, rt);
+            }
+            Err(err) => {
+                let error = RenderError::TextureAllocation {
+                    layer,
+                    size,
+                    reason: format!("{err:?}"),
+                };
+                println!("{error}");
+                recovery.pending_layers.insert(layer);
+                return None;
+            }
+        }
+    }
+    recovery.pending_layers.remove(&layer);
+    layer_rt.0.get_mut(&layer)
+}
+
+/// Fills the fog layer with darkness and cuts out the visible cells. Subtracting a fully
+/// transparent black clamps every channel to 0, leaving a hole.
+fn render_fog_system(
+    mut window: ResMut<WindowResource>,
+    mut layer_rt: ResMut<LayerTextures>,
+    mut recovery: ResMut<RenderRecovery>,
+    fog: Res<FogOfWar>,
+    visibility: Res<FogVisibility>,
+    camera: Single<&Camera, With<ActiveCamera>>,
+    view: Res<CameraViewRect>,
+) {
+    let screen_size = window.screen_size();
+    let view_rect = view.0;
+    let Some(render_texture) = layer_texture(&mut layer_rt, &mut recovery, FOG_LAYER, screen_size)
+    else {
+        return;
+    };
+    window.draw_texture_mode(render_texture, |mut d| {
+        d.clear(fog.darkness);
+        let d = d.begin_mode_2d(&camera);
+        let d = d.begin_blend_mode(BlendMode::SubtractColors);
+        for &cell in visibility.0.iter() {
+            let rect = fog.cell_rect(cell);
+            if rect.collides_rect(&view_rect) {
+                d.draw_rect(rect, Color::BLANK);
+            }
+        }
+    });
+}
+
+fn render_lighting_system(
+    mut window: ResMut<WindowResource>,
+    mut layer_rt: ResMut<LayerTextures>,
+    mut recovery: ResMut<RenderRecovery>,
+    lighting: Res<Lighting>,
+    lights: Query<(&PointLight, &GlobalTransform)>,
+    camera: Single<&Camera, With<ActiveCamera>>,
+    view: Res<CameraViewRect>,
+) {
+    let screen_size = window.screen_size();
+    let view_rect = view.0;
+    let Some(render_texture) =
+        layer_texture(&mut layer_rt, &mut recovery, LIGHTING_LAYER, screen_size)
+    else {
+        return;
+    };
+    window.draw_texture_mode(render_texture, |mut d| {
+        d.clear(lighting.ambient);
+        let d = d.begin_mode_2d(&camera);
+        let d = d.begin_blend_mode(BlendMode::Additive);
+        for (light, transform) in lights.iter() {
+            if light.bounds(transform.position).collides_rect(&view_rect) {
+                d.draw_circle_gradient(
+                    transform.position,
+                    light.radius,
+                    light.center_color(),
+                    Color::BLANK,
+                );
+            }
+        }
     });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_layers(
     mut window: ResMut<WindowResource>,
     mut layer_rt: ResMut<LayerTextures>,
+    mut recovery: ResMut<RenderRecovery>,
     debug_settings: Res<DebugSettings>,
-    sprite_q: Query<(&Sprite, &GlobalTransform, &Layer), With<OnScreen>>,
+    (textures, tileset): (
+        Res<assets::TextureAssets>,
+        Option<Res<tileset::TilesetResource>>,
+    ),
+    layer_settings: Res<LayerSettings>,
+    sort_mode: Res<LayerSortMode>,
+    render_world: Res<extract::RenderWorld>,
+    // Only for `SpriteKind::Texture`, which can't be extracted
+    owned_textures: Query<&Sprite>,
+    // Grouped, the hidden collider overlay is the only user of either
+    (spatial_hash, view): (Res<RenderHash>, Res<CameraViewRect>),
     camera: Single<&Camera, With<ActiveCamera>>,
     colliders: Query<(&Collider, &GlobalTransform), With<OnScreen>>,
+    hidden_colliders: Query<(&Collider, &GlobalTransform, &Visibility)>,
+    debug_contacts: Option<Res<DebugContacts>>,
+    inactive_layers: Res<InactiveLayers>,
+    mut metrics: ResMut<Metrics>,
 ) {
-    let sprites: Vec<_> = sprite_q.iter().collect();
+    metric_scope!(metrics, "render_layers");
+    let screen_size = window.screen_size();
+    // Sprite layers that still have nothing to draw don't need another try
+    recovery
+        .pending_layers
+        .retain(|&layer| layer == FOG_LAYER || layer == LIGHTING_LAYER);
+    let sprites = render_world.sprites();
+    metrics.render_layers_sprites = sprites.len();
     // This created a map for each thread with all the sprites in that thread
     // then we merge all the small maps into a big one with all the sprites
-    let mut sprites_map: HashMap<u32, Vec<(&Sprite, &GlobalTransform)>> =
-        HashMap::with_capacity(layer_rt.0.len());
-    {
+    let mut sprites_map = {
         let _collection_sprites_spawn =
             tracing::span!(tracing::Level::DEBUG, "sort_sprites").entered();
-        for (sprite, transform, layer) in &sprites {
-            sprites_map
-                .entry(layer.0)
-                .or_default()
-                .push((*sprite, *transform));
-        }
-    }
+        group_by_layer(
+            sprites.iter().map(|sprite| (sprite.layer, sprite)),
+            layer_rt.0.len(),
+        )
+    };
 
     // parallel sort per-layer if needed
 
     {
         let _sorting_sprites_span = tracing::span!(tracing::Level::DEBUG, "sort_sprites").entered();
-        sprites_map.par_iter_mut().for_each(|(_, sprites)| {
-            sprites.par_sort_unstable_by(|a, b| {
-                (a.1.position.y).partial_cmp(&b.1.position.y).unwrap()
-            });
+        sprites_map.par_iter_mut().for_each(|(layer, sprites)| {
+            // Pixel positions say nothing about depth, so screen space only goes by ZIndex
+            if layer_settings.is_screen_space(*layer) {
+                sprites.par_sort_by_key(|sprite| sprite.z_index);
+            } else {
+                sprites
+                    .par_sort_unstable_by(|a, b| extract::compare_world_sprites(*sort_mode, a, b));
+            }
         });
     }
 
     #[cfg(feature = "trace")]
     let _span = info_span!("draw layers").entered();
+    // Identity transform, screen-space layers are positioned in pixels
+    let screen_camera = Camera2D {
+        zoom: 1.0,
+        ..Default::default()
+    };
     for (layer, sprites) in sprites_map.iter() {
+        if !inactive_layers.is_active(*layer) {
+            continue;
+        }
         #[cfg(feature = "trace")]
         let _span_layer = info_span!("draw layer").entered();
-        let render_texture = layer_rt
-            .0
-            .entry(*layer)
-            .or_insert_with(|| OwnedRenderTexture::new(1024, 768).unwrap());
+        let config = layer_settings.get(*layer);
+        let texture_size = config.texture_size(screen_size);
+        let Some(render_texture) =
+            layer_texture(&mut layer_rt, &mut recovery, *layer, texture_size)
+        else {
+            continue;
+        };
+        let layer_camera = config.scaled_camera(match config.screen_space {
+            true => &screen_camera,
+            false => &camera.0,
+        });
         window.draw_texture_mode(render_texture, |mut d| {
             #[cfg(feature = "trace")]
             let _span_in = info_span!("draw layer sprites").entered();
             d.clear(Color::BLANK);
-            let d = d.begin_mode_2d(&camera);
-            for &(sprite, transform) in sprites {
-                let origin = sprite.get_origin_vector();
-                match &sprite.kind {
-                    SpriteKind::Rectangle { size, lines } => {
+            let d = d.begin_mode_2d(&layer_camera);
+            let draw_sprite = |sprite: &extract::ExtractedSprite| {
+                let (origin, color) = (sprite.origin, sprite.color);
+                match sprite.kind {
+                    extract::ExtractedKind::Rectangle { size, lines } => {
                         let mut dest = Rectangle {
-                            x: transform.position.x,
-                            y: transform.position.y,
-                            width: size.0 * transform.scale.x,
-                            height: size.1 * transform.scale.y,
+                            x: sprite.position.x,
+                            y: sprite.position.y,
+                            width: size.0 * sprite.scale.x,
+                            height: size.1 * sprite.scale.y,
                         };
-                        if *lines {
+                        if lines {
                             dest.x -= dest.width * origin.x;
                             dest.y -= dest.height * origin.y;
-                            d.draw_rect_lines(dest, sprite.color);
+                            d.draw_rect_lines(dest, color);
                         } else {
-                            d.draw_rect_pro(
-                                dest,
-                                origin * dest.size(),
-                                transform.rotation,
-                                sprite.color,
-                            );
+                            d.draw_rect_pro(dest, origin * dest.size(), sprite.rotation, color);
                         }
                     }
-                    SpriteKind::Circle { radius } => {
-                        let radius = *radius * transform.scale;
+                    extract::ExtractedKind::Circle { radius } => {
+                        let radius = radius * sprite.scale;
                         let diameter = radius * 2.0;
-                        let center = transform.position + (radius - diameter * origin);
+                        let center = sprite.position + (radius - diameter * origin);
 
-                        match transform.scale.x == transform.scale.y {
-                            true => d.draw_circle(center, radius.x, sprite.color),
-                            false => d.draw_ellipse(center.to_vector2i(), radius, sprite.color),
+                        match sprite.scale.x == sprite.scale.y {
+                            true => d.draw_circle(center, radius.x, color),
+                            false => d.draw_ellipse(center.to_vector2i(), radius, color),
                         }
                     }
-                    SpriteKind::Texture { texture } => {
+                    extract::ExtractedKind::Asset { id, size } => {
+                        let dest = Rectangle {
+                            x: sprite.position.x,
+                            y: sprite.position.y,
+                            width: size.0 * sprite.scale.x,
+                            height: size.1 * sprite.scale.y,
+                        };
+                        // Still queued or failed, a placeholder of the same size
+                        let Some(texture) = textures.get(id) else {
+                            let placeholder = assets::PLACEHOLDER_COLOR;
+                            d.draw_rect_pro(
+                                dest,
+                                origin * dest.size(),
+                                sprite.rotation,
+                                placeholder,
+                            );
+                            return;
+                        };
+                        let source_size = texture.size();
+                        d.draw_texture_pro(
+                            texture,
+                            Rectangle {
+                                x: 0.0,
+                                y: 0.0,
+                                width: source_size.x as f32,
+                                height: source_size.y as f32,
+                            },
+                            dest,
+                            origin * dest.size(),
+                            sprite.rotation,
+                            color,
+                        );
+                    }
+                    extract::ExtractedKind::Tile { id, size } => {
+                        let dest = Rectangle {
+                            x: sprite.position.x,
+                            y: sprite.position.y,
+                            width: size.0 * sprite.scale.x,
+                            height: size.1 * sprite.scale.y,
+                        };
+                        let Some(tileset) = &tileset else {
+                            let placeholder = assets::PLACEHOLDER_COLOR;
+                            d.draw_rect_pro(
+                                dest,
+                                origin * dest.size(),
+                                sprite.rotation,
+                                placeholder,
+                            );
+                            return;
+                        };
+                        d.draw_texture_pro(
+                            &*tileset.texture,
+                            tileset.source_rect_for_tile(id),
+                            dest,
+                            origin * dest.size(),
+                            sprite.rotation,
+                            color,
+                        );
+                    }
+                    extract::ExtractedKind::Texture => {
+                        let Ok(Sprite {
+                            kind: SpriteKind::Texture { texture },
+                            ..
+                        }) = owned_textures.get(sprite.entity)
+                        else {
+                            return;
+                        };
                         let size = texture.size();
                         let dest = Rectangle {
-                            x: transform.position.x,
-                            y: transform.position.y,
-                            width: size.x as f32 * transform.scale.x,
-                            height: size.y as f32 * transform.scale.y,
+                            x: sprite.position.x,
+                            y: sprite.position.y,
+                            width: size.x as f32 * sprite.scale.x,
+                            height: size.y as f32 * sprite.scale.y,
                         };
                         d.draw_texture_pro(
                             texture,
@@ -429,43 +1230,120 @@ fn render_layers(
                             },
                             dest,
                             origin,
-                            transform.rotation,
-                            sprite.color,
+                            sprite.rotation,
+                            color,
                         );
                     }
                 }
-                if debug_settings.origins {
-                    d.draw_rect(
-                        Rectangle {
-                            x: transform.position.x,
-                            y: transform.position.y,
-                            width: 4.0,
-                            height: 4.0,
-                        },
-                        Color::BLUE,
-                    );
-                }
+            };
+            for sprite in sprites {
+                draw_sprite(sprite);
             }
+        });
+    }
 
-            if debug_settings.colliders {
-                for (collider, transform) in colliders.iter() {
-                    let pos = transform.position - collider.offset;
-                    match collider.kind {
-                        ColliderKind::Rectangle(size) => {
-                            d.draw_rect_lines_ex(
-                                Rectangle {
-                                    x: pos.x,
-                                    y: pos.y,
-                                    width: size.x,
-                                    height: size.y,
-                                },
-                                1.0,
-                                Color::ORANGE,
-                            );
-                        }
+    // Debug overlays go on their own layer so they stay above every sprite
+    if !debug_settings.origins && !debug_settings.colliders && !debug_settings.contacts {
+        layer_rt.0.remove(&DEBUG_LAYER);
+        return;
+    }
+
+    let Some(render_texture) =
+        layer_texture(&mut layer_rt, &mut recovery, DEBUG_LAYER, screen_size)
+    else {
+        return;
+    };
+    window.draw_texture_mode(render_texture, |mut d| {
+        d.clear(Color::BLANK);
+        let d = d.begin_mode_2d(&camera);
+        if debug_settings.origins {
+            for sprite in sprites {
+                d.draw_rect(
+                    Rectangle {
+                        x: sprite.position.x,
+                        y: sprite.position.y,
+                        width: 4.0,
+                        height: 4.0,
+                    },
+                    Color::BLUE,
+                );
+            }
+        }
+
+        if debug_settings.colliders {
+            let draw_collider = |collider: &Collider, transform: &GlobalTransform, color: Color| {
+                let pos = transform.position - collider.offset;
+                match collider.kind {
+                    ColliderKind::Rectangle(size) => {
+                        d.draw_rect_lines_ex(
+                            Rectangle {
+                                x: pos.x,
+                                y: pos.y,
+                                width: size.x,
+                                height: size.y,
+                            },
+                            1.0,
+                            color,
+                        );
+                    }
+                    ColliderKind::Circle(_) => {
+                        let radius = collider.size(transform.scale).x / 2.0;
+                        d.draw_circle_lines(pos + Vector2::new(radius, radius), radius, color);
+                    }
+                    ColliderKind::Capsule { .. } => {
+                        let capsule = Capsule::from_bounds(&collider.aabb(transform));
+                        let radius = capsule.radius;
+                        d.draw_circle_lines(capsule.top, radius, color);
+                        d.draw_circle_lines(capsule.bottom, radius, color);
+                        d.draw_rect_lines_ex(
+                            Rectangle {
+                                x: capsule.top.x - radius,
+                                y: capsule.top.y,
+                                width: radius * 2.0,
+                                height: capsule.bottom.y - capsule.top.y,
+                            },
+                            1.0,
+                            color,
+                        );
                     }
                 }
+            };
+            for (collider, transform) in colliders.iter() {
+                draw_collider(collider, transform, Color::ORANGE);
             }
-        });
-    }
+            // Hidden entities never get `OnScreen`, so they're looked up in view directly.
+            // Darker, so invisible walls stand out from the visible ones
+            let hidden_color = Color::new(140, 70, 0, 255);
+            for entity in spatial_hash.query(view.0) {
+                if let Ok((collider, transform, Visibility::Hidden)) = hidden_colliders.get(entity)
+                {
+                    draw_collider(collider, transform, hidden_color);
+                }
+            }
+        }
+
+        if let Some(debug_contacts) = debug_contacts.as_ref() {
+            for (contact, alpha) in debug_contacts.faded() {
+                let (p, n) = (contact.point, contact.normal);
+                let cross = Color::YELLOW.fade(alpha);
+                d.draw_line(
+                    p - Vector2::new(3.0, 3.0),
+                    p + Vector2::new(3.0, 3.0),
+                    cross,
+                );
+                d.draw_line(
+                    p - Vector2::new(3.0, -3.0),
+                    p + Vector2::new(3.0, -3.0),
+                    cross,
+                );
+                // Arrow along the normal, its head made of two short strokes
+                let tip = p + n * 12.0;
+                let side = Vector2::new(-n.y, n.x) * 3.0;
+                let arrow = Color::SKYBLUE.fade(alpha);
+                d.draw_line(p, tip, arrow);
+                d.draw_line(tip, tip - n * 4.0 + side, arrow);
+                d.draw_line(tip, tip - n * 4.0 - side, arrow);
+            }
+        }
+    });
 }
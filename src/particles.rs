@@ -0,0 +1,202 @@
+#![allow(dead_code)]
+
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+use crate::components::{
+    GlobalTransform, Sprite, SpriteBundle, SpriteKind, SpriteOrigin, Transform, Velocity,
+};
+use crate::resources::Time;
+
+/// Spawns small circles at its position, `rate` per second, until `max_live_particles`
+/// of its own are alive at once.
+#[derive(Component, Clone)]
+pub struct ParticleEmitter {
+    /// Particles per second.
+    pub rate: f32,
+    /// Seconds each particle lives.
+    pub lifetime: f32,
+    pub velocity: Vector2,
+    pub color: Color,
+    pub radius: f32,
+    pub max_live_particles: u32,
+    /// Particles spawned by this emitter that haven't expired yet.
+    pub live_count: u32,
+    /// Fractional particles carried over between ticks.
+    pub pending: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(rate: f32, lifetime: f32, max_live_particles: u32) -> Self {
+        Self {
+            rate,
+            lifetime,
+            velocity: Vector2::new(0.0, -40.0),
+            color: Color::WHITE,
+            radius: 2.0,
+            max_live_particles,
+            live_count: 0,
+            pending: 0.0,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Particle {
+    pub emitter: Entity,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+/// Spawns whatever the rate owes this tick, capped to the emitter's free slots. The count
+/// goes up here rather than once the spawn is applied, so a deferred batch can't overshoot.
+pub fn emit_particles_system(
+    time: Res<Time>,
+    mut emitters: Query<(Entity, &mut ParticleEmitter, &GlobalTransform)>,
+    mut commands: Commands,
+) {
+    for (entity, mut emitter, transform) in emitters.iter_mut() {
+        emitter.pending += emitter.rate * time.delta();
+        let due = emitter.pending.floor();
+        emitter.pending -= due;
+
+        let free = emitter
+            .max_live_particles
+            .saturating_sub(emitter.live_count);
+        let count = (due as u32).min(free);
+        if count == 0 {
+            continue;
+        }
+        emitter.live_count += count;
+
+        let (radius, color) = (emitter.radius, emitter.color);
+        let particle = Particle {
+            emitter: entity,
+            age: 0.0,
+            lifetime: emitter.lifetime,
+        };
+        let transform = Transform::default().with_position(transform.position);
+        let velocity = Velocity(emitter.velocity);
+        commands.spawn_batch((0..count).map(move |_| {
+            (
+                SpriteBundle::new(
+                    Sprite {
+                        kind: SpriteKind::Circle { radius },
+                        origin: SpriteOrigin::Center,
+                        color,
+                        opacity: 1.0,
+                    },
+                    transform,
+                ),
+                particle,
+                velocity,
+            )
+        }));
+    }
+}
+
+/// Ages particles and despawns the expired ones.
+pub fn expire_particles_system(
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Particle)>,
+    mut commands: Commands,
+) {
+    for (entity, mut particle) in particles.iter_mut() {
+        particle.age += time.delta();
+        if particle.age < particle.lifetime {
+            continue;
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Hands a particle's slot back to its emitter however it went away, expired or despawned
+/// by anything else.
+pub fn free_particle_slot(
+    remove: On<Remove, Particle>,
+    particles: Query<&Particle>,
+    mut emitters: Query<&mut ParticleEmitter>,
+) {
+    let Ok(particle) = particles.get(remove.entity) else {
+        return;
+    };
+    // The emitter may already be gone, its particles still run out their lifetime
+    if let Ok(mut emitter) = emitters.get_mut(particle.emitter) {
+        emitter.live_count = emitter.live_count.saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup(emitter: ParticleEmitter) -> (World, Schedule, Entity) {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.add_observer(free_particle_slot);
+        let transform = Transform::default().with_position(Vector2::new(10.0, 20.0));
+        let emitter = world
+            .spawn((emitter, GlobalTransform::from_root(&transform), transform))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems((expire_particles_system, emit_particles_system).chain());
+        (world, schedule, emitter)
+    }
+
+    fn live_particles(world: &mut World) -> usize {
+        world.query::<&Particle>().iter(world).count()
+    }
+
+    #[test]
+    fn test_emitter_caps_live_particles() {
+        let (mut world, mut schedule, emitter) = setup(ParticleEmitter::new(10_000.0, 100.0, 5));
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(live_particles(&mut world), 5);
+        assert_eq!(world.get::<ParticleEmitter>(emitter).unwrap().live_count, 5);
+
+        let position = world
+            .query::<(&Particle, &Transform)>()
+            .iter(&world)
+            .map(|(_, t)| (t.position.x, t.position.y))
+            .next();
+        assert_eq!(position, Some((10.0, 20.0)));
+    }
+
+    #[test]
+    fn test_expired_particles_free_their_slots() {
+        // 64 per second is one a tick, each living exactly two ticks
+        let (mut world, mut schedule, emitter) = setup(ParticleEmitter::new(64.0, 2.0 / 64.0, 1));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(live_particles(&mut world), 1);
+
+        // The first particle expires, the replacement spawns on the same tick
+        schedule.run(&mut world);
+        assert_eq!(live_particles(&mut world), 1);
+        let ages: Vec<f32> = world
+            .query::<&Particle>()
+            .iter(&world)
+            .map(|p| p.age)
+            .collect();
+        assert_eq!(ages, vec![0.0]);
+
+        // Despawned early, the slot is free again right away
+        let particle = world
+            .query_filtered::<Entity, With<Particle>>()
+            .single(&world)
+            .unwrap();
+        world.despawn(particle);
+        assert_eq!(world.get::<ParticleEmitter>(emitter).unwrap().live_count, 0);
+        schedule.run(&mut world);
+        assert_eq!(live_particles(&mut world), 1);
+
+        // Particles outlive their emitter
+        world.despawn(emitter);
+        schedule.run(&mut world);
+        assert_eq!(live_particles(&mut world), 1);
+        schedule.run(&mut world);
+        assert_eq!(live_particles(&mut world), 0);
+    }
+}
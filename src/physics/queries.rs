@@ -0,0 +1,242 @@
+#![allow(dead_code)]
+
+use bevy_ecs::{prelude::*, system::SystemParam};
+use rustyray::prelude::*;
+
+use crate::components::{Collider, ColliderKind, GlobalTransform};
+use crate::spatial_hash::CollisionHash;
+
+/// Exact world-space shape of a collider, used for narrow phase checks.
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Rect(Rectangle),
+    Circle { center: Vector2, radius: f32 },
+}
+
+impl Shape {
+    pub fn from_collider(collider: &Collider, transform: &GlobalTransform) -> Self {
+        let aabb = collider.aabb(transform);
+        match collider.kind {
+            ColliderKind::Rectangle(_) => Self::Rect(aabb),
+            ColliderKind::Circle(_) => Self::Circle {
+                center: Vector2::new(aabb.x + aabb.width / 2.0, aabb.y + aabb.height / 2.0),
+                radius: aabb.width / 2.0,
+            },
+        }
+    }
+
+    pub fn overlaps_rect(&self, rect: &Rectangle) -> bool {
+        match self {
+            Self::Rect(own) => own.collides_rect(rect),
+            Self::Circle { center, radius } => circle_overlaps_rect(*center, *radius, rect),
+        }
+    }
+
+    pub fn overlaps_circle(&self, center: Vector2, radius: f32) -> bool {
+        match self {
+            Self::Rect(own) => circle_overlaps_rect(center, radius, own),
+            Self::Circle {
+                center: own_center,
+                radius: own_radius,
+            } => {
+                let (dx, dy) = (center.x - own_center.x, center.y - own_center.y);
+                let reach = radius + own_radius;
+                dx * dx + dy * dy < reach * reach
+            }
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector2) -> bool {
+        match self {
+            Self::Rect(own) => {
+                point.x >= own.x
+                    && point.x < own.x + own.width
+                    && point.y >= own.y
+                    && point.y < own.y + own.height
+            }
+            Self::Circle { center, radius } => {
+                let (dx, dy) = (point.x - center.x, point.y - center.y);
+                dx * dx + dy * dy <= radius * radius
+            }
+        }
+    }
+}
+
+fn circle_overlaps_rect(center: Vector2, radius: f32, rect: &Rectangle) -> bool {
+    let closest_x = center.x.clamp(rect.x, rect.x + rect.width);
+    let closest_y = center.y.clamp(rect.y, rect.y + rect.height);
+    let (dx, dy) = (center.x - closest_x, center.y - closest_y);
+    dx * dx + dy * dy < radius * radius
+}
+
+/// Overlap queries against colliders: broad phase through the `CollisionHash`,
+/// narrow phase against the actual collider shapes.
+#[derive(SystemParam)]
+pub struct CollisionQueries<'w, 's> {
+    hash: Res<'w, CollisionHash>,
+    colliders: Query<'w, 's, (&'static Collider, &'static GlobalTransform)>,
+}
+
+impl CollisionQueries<'_, '_> {
+    /// Every collider overlapping `rect`.
+    pub fn overlap_rect(&self, rect: Rectangle) -> Vec<Entity> {
+        self.overlapping(rect, |shape| shape.overlaps_rect(&rect))
+    }
+
+    /// Every collider overlapping the circle.
+    pub fn overlap_circle(&self, center: Vector2, radius: f32) -> Vec<Entity> {
+        let bounds = Rectangle {
+            x: center.x - radius,
+            y: center.y - radius,
+            width: radius * 2.0,
+            height: radius * 2.0,
+        };
+        self.overlapping(bounds, |shape| shape.overlaps_circle(center, radius))
+    }
+
+    /// First collider containing `point`, if any.
+    pub fn point_in_collider(&self, point: Vector2) -> Option<Entity> {
+        let bounds = Rectangle {
+            x: point.x,
+            y: point.y,
+            width: 0.0,
+            height: 0.0,
+        };
+        self.overlapping(bounds, |shape| shape.contains_point(point))
+            .into_iter()
+            .next()
+    }
+
+    fn overlapping(&self, bounds: Rectangle, narrow: impl Fn(&Shape) -> bool) -> Vec<Entity> {
+        self.hash
+            .query(bounds)
+            .into_iter()
+            .filter(|&entity| {
+                self.colliders
+                    .get(entity)
+                    .is_ok_and(|(collider, transform)| {
+                        narrow(&Shape::from_collider(collider, transform))
+                    })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_ecs::system::SystemState;
+
+    use super::*;
+    use crate::spatial_hash::SpatialHash;
+    use crate::systems::update_collision_hash_system;
+
+    fn spawn_collider(world: &mut World, kind: ColliderKind, position: Vector2) -> Entity {
+        world
+            .spawn((
+                Collider {
+                    kind,
+                    offset: Vector2::ZERO,
+                },
+                GlobalTransform {
+                    position,
+                    ..Default::default()
+                },
+            ))
+            .id()
+    }
+
+    fn setup() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.insert_resource(CollisionHash(SpatialHash::flat(16.0)));
+        // Box covering (0, 0) to (10, 10)
+        let rect = spawn_collider(
+            &mut world,
+            ColliderKind::Rectangle(Vector2::new(10.0, 10.0)),
+            Vector2::new(0.0, 0.0),
+        );
+        // Circle centered on (105, 105) with radius 5, its bounds span (100, 100) to (110, 110)
+        let circle = spawn_collider(
+            &mut world,
+            ColliderKind::Circle(5.0),
+            Vector2::new(100.0, 100.0),
+        );
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_collision_hash_system);
+        schedule.run(&mut world);
+
+        (world, rect, circle)
+    }
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_overlap_rect() {
+        let (mut world, rect_collider, circle_collider) = setup();
+        let mut state = SystemState::<CollisionQueries>::new(&mut world);
+        let queries = state.get(&world);
+
+        // Rect vs rect
+        assert_eq!(
+            queries.overlap_rect(rect(5.0, 5.0, 2.0, 2.0)),
+            vec![rect_collider]
+        );
+        // Inside the circle's hash bounds, but outside the circle itself
+        assert!(queries.overlap_rect(rect(99.0, 99.0, 2.0, 2.0)).is_empty());
+        // Rect vs circle
+        assert_eq!(
+            queries.overlap_rect(rect(103.0, 99.0, 4.0, 2.0)),
+            vec![circle_collider]
+        );
+    }
+
+    #[test]
+    fn test_overlap_circle() {
+        let (mut world, rect_collider, circle_collider) = setup();
+        let mut state = SystemState::<CollisionQueries>::new(&mut world);
+        let queries = state.get(&world);
+
+        // Circle vs rect
+        assert_eq!(
+            queries.overlap_circle(Vector2::new(12.0, 5.0), 3.0),
+            vec![rect_collider]
+        );
+        // Circle vs circle
+        assert_eq!(
+            queries.overlap_circle(Vector2::new(113.0, 105.0), 4.0),
+            vec![circle_collider]
+        );
+        // Bounds overlap, circles don't
+        assert!(
+            queries
+                .overlap_circle(Vector2::new(99.0, 99.0), 1.5)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_point_in_collider() {
+        let (mut world, rect_collider, circle_collider) = setup();
+        let mut state = SystemState::<CollisionQueries>::new(&mut world);
+        let queries = state.get(&world);
+
+        assert_eq!(
+            queries.point_in_collider(Vector2::new(5.0, 5.0)),
+            Some(rect_collider)
+        );
+        assert_eq!(
+            queries.point_in_collider(Vector2::new(105.0, 108.0)),
+            Some(circle_collider)
+        );
+        // Corner of the circle's bounds
+        assert_eq!(queries.point_in_collider(Vector2::new(101.0, 101.0)), None);
+        assert_eq!(queries.point_in_collider(Vector2::new(50.0, 50.0)), None);
+    }
+}
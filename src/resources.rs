@@ -1,21 +1,378 @@
-use std::collections::HashMap;
-
+use bevy_ecs::archetype::ArchetypeId;
+use bevy_ecs::component::ComponentId;
 use bevy_ecs::prelude::*;
+use rand::{SeedableRng, rngs::SmallRng};
 use rustyray::prelude::*;
 
+use std::collections::VecDeque;
+
+use crate::spatial_hash::HashUpdatePath;
+use crate::timings::TimingsHandle;
+use crate::utils::{HashMap, HashSet};
+
 #[derive(Resource, Default)]
 pub struct Metrics {
     pub apply_velocity_system_time: std::time::Duration,
     pub update_on_screen_system_time: std::time::Duration,
+    // Entities each system handled on its last run
+    pub apply_velocity_movers: usize,
+    pub apply_velocity_candidates: usize,
+    pub update_on_screen_visible: usize,
+    pub collision_hash_updates: usize,
+    pub render_hash_updates: usize,
+    /// How each hash took its last batch of moves, and how long that took.
+    pub collision_hash_path: HashUpdatePath,
+    pub collision_hash_time: std::time::Duration,
+    pub render_hash_path: HashUpdatePath,
+    pub render_hash_time: std::time::Duration,
+    pub render_layers_sprites: usize,
+    /// `OnScreen` changes `update_on_screen_system` applied in its batch, and how long that took.
+    pub on_screen_inserted: usize,
+    pub on_screen_removed: usize,
+    pub on_screen_apply_time: std::time::Duration,
+    pub global_transform_inserts: usize,
+    /// Indexed by `ArchetypeId`, refreshed by `archetype_stats_system`.
+    pub archetype_stats: Vec<ArchetypeStat>,
+    /// Last `FRAME_TIME_HISTORY` frame times in seconds, oldest first.
+    pub frame_time_history: VecDeque<f32>,
+    /// Rolling statistics of every `metric_scope!`, plus `frame`. Advanced once per frame.
+    pub timings: TimingsHandle,
+}
+
+/// Frames kept for the FPS graph.
+pub const FRAME_TIME_HISTORY: usize = 200;
+
+impl Metrics {
+    pub fn record_frame_time(&mut self, frame_time: f32) {
+        if self.frame_time_history.len() >= FRAME_TIME_HISTORY {
+            self.frame_time_history.pop_front();
+        }
+        self.frame_time_history.push_back(frame_time);
+        let mut timings = self.timings.lock();
+        timings.advance(frame_time);
+        timings.record(
+            "frame",
+            std::time::Duration::from_secs_f32(frame_time.max(0.0)),
+        );
+    }
+
+    /// Rolling min/avg/p95/max and the all-time max of every timing, one line each.
+    pub fn report(&self) -> String {
+        let timings = self.timings.lock();
+        timings
+            .iter()
+            .map(|(name, stats)| {
+                let rolling = match stats.summary() {
+                    Some(s) => format!(
+                        "min {:.2?}, avg {:.2?}, p95 {:.2?}, max {:.2?}",
+                        s.min, s.avg, s.p95, s.max
+                    ),
+                    None => "no recent samples".to_string(),
+                };
+                match stats.all_time_max {
+                    Some((max, tick)) => {
+                        format!("{name}: {rolling}, all-time max {max:.2?} at tick {tick}")
+                    }
+                    None => format!("{name}: {rolling}"),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// p95 of every timing with recent samples, for the debug overlay.
+    pub fn p95_rows(&self) -> Vec<String> {
+        let timings = self.timings.lock();
+        timings
+            .iter()
+            .filter_map(|(name, stats)| Some(format!("{name} p95: {:.2?}", stats.summary()?.p95)))
+            .collect()
+    }
+
+    /// One line per system for the debug overlay.
+    pub fn system_count_rows(&self) -> [String; 5] {
+        [
+            format!(
+                "apply_velocity: {} movers, {} candidates",
+                short_count(self.apply_velocity_movers),
+                short_count(self.apply_velocity_candidates)
+            ),
+            format!(
+                "update_on_screen: {} visible",
+                short_count(self.update_on_screen_visible)
+            ),
+            format!(
+                "update_collision_hash: {} moved, {} in {:.2?}",
+                short_count(self.collision_hash_updates),
+                self.collision_hash_path.label(),
+                self.collision_hash_time
+            ),
+            format!(
+                "update_render_hash: {} moved, {} in {:.2?}",
+                short_count(self.render_hash_updates),
+                self.render_hash_path.label(),
+                self.render_hash_time
+            ),
+            format!(
+                "render_layers: {} sprites",
+                short_count(self.render_layers_sprites)
+            ),
+        ]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ArchetypeStat {
+    pub id: ArchetypeId,
+    pub components: Vec<ComponentId>,
+    /// Summed size of one entity's components.
+    pub row_bytes: usize,
+    pub entity_count: usize,
+}
+
+impl ArchetypeStat {
+    /// Rough table size, ignores padding, sparse set overhead and spare capacity.
+    pub fn estimated_bytes(&self) -> usize {
+        self.entity_count * self.row_bytes
+    }
+}
+
+/// The `count` fullest non-empty archetypes, fullest first.
+pub fn top_archetypes(stats: &[ArchetypeStat], count: usize) -> Vec<&ArchetypeStat> {
+    let mut top: Vec<&ArchetypeStat> = stats.iter().filter(|s| s.entity_count > 0).collect();
+    top.sort_by(|a, b| {
+        b.entity_count
+            .cmp(&a.entity_count)
+            .then(a.id.index().cmp(&b.id.index()))
+    });
+    top.truncate(count);
+    top
+}
+
+/// Rows drawn by the system counts overlay, refreshed by `debug_system_counts_system`.
+#[derive(Resource, Default)]
+pub struct SystemCountsTable(pub Vec<String>);
+
+/// Entity the inspector shows, picked by `select_entity_system`.
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectedEntity(pub Option<Entity>);
+
+/// Entity held by `drag_entity_system`, and where it sits relative to the cursor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drag {
+    pub entity: Entity,
+    /// Entity's world position minus the cursor's, kept so it doesn't snap to the cursor.
+    pub offset: Vector2,
+}
+
+impl Drag {
+    pub fn grab(entity: Entity, entity_position: Vector2, cursor: Vector2) -> Self {
+        Self {
+            entity,
+            offset: entity_position - cursor,
+        }
+    }
+
+    /// World position the entity follows the cursor to.
+    pub fn target(&self, cursor: Vector2) -> Vector2 {
+        cursor + self.offset
+    }
+}
+
+#[derive(Resource, Default, Debug, Clone, Copy, PartialEq)]
+pub struct DragState(pub Option<Drag>);
+
+/// Entities by `EntityName`, kept up to date by `add_name_registry_observers`. Names can
+/// repeat, each keeps its entities in the order they were registered.
+#[derive(Resource, Default, Debug)]
+pub struct NameRegistry(HashMap<String, Vec<Entity>>);
+
+impl NameRegistry {
+    pub fn register(&mut self, name: &str, entity: Entity) {
+        let entities = self.0.entry(name.to_owned()).or_default();
+        if !entities.is_empty() {
+            println!(
+                "Warning: {} entities named {name:?}, `find` returns {entity}",
+                entities.len() + 1
+            );
+        }
+        entities.push(entity);
+    }
+
+    pub fn unregister(&mut self, name: &str, entity: Entity) {
+        let Some(entities) = self.0.get_mut(name) else {
+            return;
+        };
+        entities.retain(|&e| e != entity);
+        if entities.is_empty() {
+            self.0.remove(name);
+        }
+    }
+
+    /// The most recently registered entity with `name`.
+    pub fn find(&self, name: &str) -> Option<Entity> {
+        self.0.get(name)?.last().copied()
+    }
+}
+
+/// Rows drawn by the inspector panel, refreshed by `inspect_selected_entity_system`.
+#[derive(Resource, Default)]
+pub struct InspectorPanel(pub Vec<String>);
+
+/// `142000` as `142k`, `1500000` as `1.5M`.
+pub fn short_count(count: usize) -> String {
+    match count {
+        0..1_000 => count.to_string(),
+        1_000..1_000_000 => format!("{}k", trim_decimal(count as f32 / 1_000.0)),
+        _ => format!("{}M", trim_decimal(count as f32 / 1_000_000.0)),
+    }
+}
+
+fn trim_decimal(value: f32) -> String {
+    let rounded = format!("{value:.1}");
+    rounded.trim_end_matches(".0").to_string()
 }
 
 #[derive(Resource)]
 pub struct WindowResource(pub Window);
 
+impl WindowResource {
+    pub fn set_title(&mut self, title: &str) {
+        self.0.set_window_title(title);
+    }
+
+    pub fn set_icon(&mut self, image_path: &str) -> Result<(), String> {
+        let image = OwnedImage::load(image_path).map_err(|err| format!("{err:?}"))?;
+        self.0.set_window_icon(&image);
+        Ok(())
+    }
+}
+
+/// Frame rate caps `cycle` steps through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FpsCap {
+    #[default]
+    Fps60,
+    Fps120,
+    Fps144,
+    /// No target of our own. The window asks for vsync, so this runs at the display's refresh
+    /// rate rather than as fast as it can.
+    Vsync,
+}
+
+impl FpsCap {
+    pub fn cycle(self) -> Self {
+        match self {
+            FpsCap::Fps60 => FpsCap::Fps120,
+            FpsCap::Fps120 => FpsCap::Fps144,
+            FpsCap::Fps144 => FpsCap::Vsync,
+            FpsCap::Vsync => FpsCap::Fps60,
+        }
+    }
+
+    /// For `set_target_fps`, where 0 means no limit.
+    pub fn target_fps(self) -> u32 {
+        match self {
+            FpsCap::Fps60 => 60,
+            FpsCap::Fps120 => 120,
+            FpsCap::Fps144 => 144,
+            FpsCap::Vsync => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for FpsCap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.target_fps() {
+            0 => write!(f, "vsync"),
+            fps => write!(f, "{fps}"),
+        }
+    }
+}
+
+/// Applied to the window whenever it changes, the icon only at startup. Only caps the
+/// rendered frame rate, physics keeps its own fixed step.
+#[derive(Resource, Debug, Clone)]
+pub struct WindowSettings {
+    pub title: String,
+    pub icon: Option<String>,
+    pub fps_cap: FpsCap,
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            title: "RustyRay".to_string(),
+            icon: None,
+            fps_cap: FpsCap::default(),
+        }
+    }
+}
+
+/// Software frame cap on top of the window's, adjustable without touching the window. Whichever
+/// of the two is lower wins. A `target_fps` of 0 turns it off like `enabled = false` does.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct FramerateLimiter {
+    pub target_fps: u32,
+    pub enabled: bool,
+    /// When the current frame started, set by `frame_limiter_system` after it wakes up.
+    pub frame_start: Option<std::time::Instant>,
+}
+
+impl FramerateLimiter {
+    pub fn new(target_fps: u32) -> Self {
+        Self {
+            target_fps,
+            enabled: true,
+            frame_start: None,
+        }
+    }
+
+    /// How long to sleep after a frame that took `elapsed`, if at all.
+    pub fn remaining(&self, elapsed: std::time::Duration) -> Option<std::time::Duration> {
+        if !self.enabled || self.target_fps == 0 {
+            return None;
+        }
+        let budget = std::time::Duration::from_secs_f64(1.0 / self.target_fps as f64);
+        budget.checked_sub(elapsed).filter(|sleep| !sleep.is_zero())
+    }
+}
+
+/// Entity counters kept exact by the `add_world_stats_observers` observers. `sprites` counts
+/// every sprite, the player and HUD included.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WorldStats {
+    pub sprites: usize,
+    pub on_screen: usize,
+}
+
 #[derive(Resource)]
 pub struct DebugSettings {
     pub origins: bool,
     pub colliders: bool,
+    /// Draw everything in the culling rect and tint what isn't `OnScreen`.
+    pub visualize_culling: bool,
+    /// Table of how many entities the main systems touched last run.
+    pub show_system_counts: bool,
+    /// Crosses and normals where contacts were resolved, see `DebugContacts`.
+    pub contacts: bool,
+    /// Bar chart of `Metrics::frame_time_history` in the bottom right corner.
+    pub show_fps_graph: bool,
+    /// Left click selects the sprite under the cursor and a panel lists its components.
+    pub inspector: bool,
+    /// Hide the OS cursor and draw the `Cursor` sprite in its place.
+    pub hide_cursor: bool,
+    /// Left mouse drags the sprite under the cursor around, see `drag_entity_system`.
+    pub drag: bool,
+}
+
+/// Green under 60 FPS worth of frame time, yellow under 30, red from there on.
+pub fn frame_time_color(frame_time: f32) -> Color {
+    match frame_time * 1000.0 {
+        ms if ms < 16.0 => Color::GREEN,
+        ms if ms < 33.0 => Color::YELLOW,
+        _ => Color::RED,
+    }
 }
 
 impl std::ops::Deref for WindowResource {
@@ -35,6 +392,230 @@ impl std::ops::DerefMut for WindowResource {
 #[derive(Resource, Default)]
 pub struct LayerTextures(pub HashMap<u32, OwnedRenderTexture>);
 
+#[derive(Debug)]
+pub enum RenderError {
+    /// The GPU refused a layer texture, the layer is retried next frame.
+    TextureAllocation {
+        layer: u32,
+        size: Vector2i,
+        reason: String,
+    },
+    /// A NaN or infinite transform, the entity is quarantined until it's finite again.
+    /// Holds the `entity_label`.
+    NonFiniteTransform(String),
+}
+
+impl std::fmt::Display for RenderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderError::TextureAllocation {
+                layer,
+                size,
+                reason,
+            } => write!(
+                f,
+                "Failed to create a {}x{} texture for layer {layer}, retrying next frame: {reason}",
+                size.x, size.y
+            ),
+            RenderError::NonFiniteTransform(label) => {
+                write!(f, "{label} has a non-finite transform, not drawing it")
+            }
+        }
+    }
+}
+
+/// Layers whose texture couldn't be created, their render systems run again next frame.
+#[derive(Resource, Default)]
+pub struct RenderRecovery {
+    pub pending_layers: HashSet<u32>,
+}
+
+impl RenderRecovery {
+    /// Whether a layer drawn by `render_layers` is waiting on a retry.
+    pub fn sprite_layers_pending(&self) -> bool {
+        self.pending_layers
+            .iter()
+            .any(|&layer| layer != FOG_LAYER && layer != LIGHTING_LAYER)
+    }
+}
+
+/// Layer texture the debug overlays are drawn on, always composited last.
+/// `Layer(DEBUG_LAYER)` is reserved, sprites using it are skipped.
+pub const DEBUG_LAYER: u32 = u32::MAX;
+
+/// Fog of war overlay, composited above the sprite layers and below the debug layer.
+pub const FOG_LAYER: u32 = u32::MAX - 1;
+
+/// Light map, multiplied over the sprite layers before the fog is drawn.
+pub const LIGHTING_LAYER: u32 = u32::MAX - 2;
+
+/// Screen-space layer the HUD lives on.
+pub const HUD_LAYER: u32 = 1000;
+
+/// Screen-space layer above the HUD for the `Cursor` sprite.
+pub const CURSOR_LAYER: u32 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerConfig {
+    /// Positions are in pixels, the camera is ignored and sprites are never culled.
+    /// Composited above the lighting and fog.
+    pub screen_space: bool,
+    /// The layer's texture is this fraction of the window, and stretched back over it with
+    /// point filtering. 0.5 or 0.25 give chunky pixels.
+    pub render_scale: f32,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        Self {
+            screen_space: false,
+            render_scale: 1.0,
+        }
+    }
+}
+
+impl LayerConfig {
+    /// Size of the layer's texture for a `window` sized screen, never empty.
+    pub fn texture_size(&self, window: Vector2i) -> Vector2i {
+        let scale = |size: i32| ((size as f32 * self.render_scale).round() as i32).max(1);
+        Vector2i {
+            x: scale(window.x),
+            y: scale(window.y),
+        }
+    }
+
+    /// `camera` drawing the same view into the smaller texture.
+    pub fn scaled_camera(&self, camera: &Camera2D) -> Camera2D {
+        Camera2D {
+            offset: camera.offset * self.render_scale,
+            zoom: camera.zoom * self.render_scale,
+            ..*camera
+        }
+    }
+
+    /// Source rect in the layer's texture for drawing it into `dest` on a `window` sized
+    /// screen. Render textures are stored upside down.
+    pub fn composite_source(&self, dest: Rectangle, window: Vector2i) -> Rectangle {
+        let size = self.texture_size(window);
+        let (sx, sy) = (
+            size.x as f32 / window.x.max(1) as f32,
+            size.y as f32 / window.y.max(1) as f32,
+        );
+        Rectangle {
+            x: dest.x * sx,
+            y: size.y as f32 - (dest.y + dest.height) * sy,
+            width: dest.width * sx,
+            height: -dest.height * sy,
+        }
+    }
+}
+
+/// How sprites in camera-space layers are ordered, see `compare_world_sprites`.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LayerSortMode {
+    /// Lower on screen is drawn later, `RenderOrder` breaks ties.
+    #[default]
+    YSort,
+    /// Only `RenderOrder`, for games that want strict layer order, e.g. top-down tiles.
+    Manual,
+}
+
+/// Per-layer options, layers without an entry use `LayerConfig::default()`.
+#[derive(Resource, Debug, Clone)]
+pub struct LayerSettings(pub HashMap<u32, LayerConfig>);
+
+impl Default for LayerSettings {
+    fn default() -> Self {
+        let mut layers = HashMap::default();
+        layers.insert(
+            HUD_LAYER,
+            LayerConfig {
+                screen_space: true,
+                ..Default::default()
+            },
+        );
+        layers.insert(
+            CURSOR_LAYER,
+            LayerConfig {
+                screen_space: true,
+                ..Default::default()
+            },
+        );
+        Self(layers)
+    }
+}
+
+impl LayerSettings {
+    pub fn get(&self, layer: u32) -> LayerConfig {
+        self.0.get(&layer).copied().unwrap_or_default()
+    }
+
+    pub fn is_screen_space(&self, layer: u32) -> bool {
+        self.get(layer).screen_space
+    }
+}
+
+/// Layers left out of rendering. Their textures are kept, so showing one again is just a
+/// redraw, no reallocation.
+#[derive(Resource, Default)]
+pub struct InactiveLayers(pub HashSet<u32>);
+
+impl std::ops::Deref for InactiveLayers {
+    type Target = HashSet<u32>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for InactiveLayers {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl InactiveLayers {
+    pub fn is_active(&self, layer: u32) -> bool {
+        !self.0.contains(&layer)
+    }
+}
+
+/// Layers at or above this are drawn by dedicated systems, not from sprites.
+pub const FIRST_RESERVED_LAYER: u32 = LIGHTING_LAYER;
+
+/// Buckets items per layer for `render_layers`, leaving out the reserved layers.
+pub fn group_by_layer<T>(
+    items: impl Iterator<Item = (u32, T)>,
+    capacity: usize,
+) -> HashMap<u32, Vec<T>> {
+    let mut layers: HashMap<u32, Vec<T>> =
+        HashMap::with_capacity_and_hasher(capacity, Default::default());
+    for (layer, item) in items.filter(|(layer, _)| *layer < FIRST_RESERVED_LAYER) {
+        layers.entry(layer).or_default().push(item);
+    }
+    layers
+}
+
+/// Sprite layers in the order they're composited, lowest first so higher layers end on top.
+/// Reserved layers are left out, `render_system` draws those after.
+pub fn composite_order(layers: impl Iterator<Item = u32>) -> Vec<u32> {
+    let mut order: Vec<u32> = layers
+        .filter(|layer| *layer < FIRST_RESERVED_LAYER)
+        .collect();
+    order.sort_unstable();
+    order
+}
+
+/// Fonts `Text` can pick by name.
+#[derive(Resource, Default)]
+pub struct FontLibrary(pub HashMap<String, OwnedFont>);
+
+impl FontLibrary {
+    pub fn get(&self, name: Option<&str>) -> Option<&OwnedFont> {
+        name.and_then(|name| self.0.get(name))
+    }
+}
+
 #[derive(Resource)]
 pub struct WindowSize(pub Vector2i);
 
@@ -44,6 +625,481 @@ pub struct ResizeEvent {
     pub to: Vector2i,
 }
 
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WindowFocus {
+    pub focused: bool,
+    pub minimized: bool,
+}
+
+/// How long to sleep each frame while throttled.
+pub const UNFOCUSED_THROTTLE: std::time::Duration = std::time::Duration::from_millis(50);
+
+impl WindowFocus {
+    /// Nothing is visible while minimized, but update and physics still run.
+    pub fn should_render(&self) -> bool {
+        !self.minimized
+    }
+
+    pub fn throttle(&self, settings: &RenderSettings) -> Option<std::time::Duration> {
+        let idle = !self.focused || self.minimized;
+        (settings.throttle_unfocused && idle).then_some(UNFOCUSED_THROTTLE)
+    }
+}
+
+#[derive(Resource)]
+pub struct RenderSettings {
+    pub throttle_unfocused: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            throttle_unfocused: true,
+        }
+    }
+}
+
+#[derive(Resource, Default, Clone, Copy, Debug)]
+pub struct CullingSettings {
+    /// Cull hierarchies as a whole on the union of their sprites' bounds, so a child never
+    /// pops in or out on its own at the view edge.
+    pub inherit_from_root: bool,
+}
+
+/// World rect the active camera shows, grown by `CameraViewMargin`. Recomputed once a frame by
+/// `compute_camera_view_system`, everything that needs to know what's in view reads this.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraViewRect(pub Rectangle);
+
+impl Default for CameraViewRect {
+    fn default() -> Self {
+        Self(Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 0.0,
+            height: 0.0,
+        })
+    }
+}
+
+/// Fraction of the view's size `CameraViewRect` reaches past it on every side, defaults to
+/// `CULLING_MARGIN` when missing.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraViewMargin(pub f32);
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct StaticTransformSettings {
+    /// Frames between `mark_static_transforms_system` runs. An entity that didn't move over
+    /// one whole gap is promoted to `StaticTransform`.
+    pub after_frames: u32,
+}
+
+impl Default for StaticTransformSettings {
+    fn default() -> Self {
+        Self { after_frames: 120 }
+    }
+}
+
+/// How `camera.zoom` follows the window size relative to `ReferenceResolution`.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraScalingMode {
+    /// Zoom is left alone, a bigger window shows more of the world.
+    #[default]
+    Stretch,
+    KeepHeight,
+    KeepWidth,
+    /// Whole multiples of the reference size (or whole fractions below it) for crisp pixels.
+    IntegerScale,
+}
+
+impl CameraScalingMode {
+    pub fn zoom(&self, reference: Vector2i, size: Vector2i) -> f32 {
+        let scale_x = size.x as f32 / reference.x.max(1) as f32;
+        let scale_y = size.y as f32 / reference.y.max(1) as f32;
+        match self {
+            CameraScalingMode::Stretch => 1.0,
+            CameraScalingMode::KeepHeight => scale_y,
+            CameraScalingMode::KeepWidth => scale_x,
+            CameraScalingMode::IntegerScale => {
+                let scale = scale_x.min(scale_y);
+                if scale >= 1.0 {
+                    scale.floor()
+                } else if scale > 0.0 {
+                    1.0 / (1.0 / scale).ceil()
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct ReferenceResolution(pub Vector2i);
+
+/// How the camera frames several `CameraTarget`s at once.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct CameraFraming {
+    /// World units kept around the targets' bounding box on every side.
+    pub padding: f32,
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+}
+
+impl Default for CameraFraming {
+    fn default() -> Self {
+        Self {
+            padding: 100.0,
+            min_zoom: 0.3,
+            max_zoom: 2.0,
+        }
+    }
+}
+
+impl CameraFraming {
+    /// Center of the targets' bounding box and the zoom fitting it in `screen_size`.
+    /// A single target gives no zoom so it's followed like before, `None` without targets.
+    pub fn frame(
+        &self,
+        mut positions: impl Iterator<Item = Vector2>,
+        screen_size: Vector2,
+    ) -> Option<(Vector2, Option<f32>)> {
+        let first = positions.next()?;
+        let (mut min, mut max, mut count) = (first, first, 1);
+        for p in positions {
+            min = Vector2::new(min.x.min(p.x), min.y.min(p.y));
+            max = Vector2::new(max.x.max(p.x), max.y.max(p.y));
+            count += 1;
+        }
+
+        let center = Vector2::new((min.x + max.x) / 2.0, (min.y + max.y) / 2.0);
+        if count == 1 {
+            return Some((center, None));
+        }
+
+        let width = max.x - min.x + self.padding * 2.0;
+        let height = max.y - min.y + self.padding * 2.0;
+        let zoom = (screen_size.x / width.max(f32::EPSILON))
+            .min(screen_size.y / height.max(f32::EPSILON))
+            .clamp(self.min_zoom, self.max_zoom);
+        Some((center, Some(zoom)))
+    }
+}
+
+/// Glides the active camera from `from` to the `to` entity, overriding the usual follow
+/// until it's done. Set through `trigger_camera_transition`.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct CameraTransition {
+    pub from: Vector2,
+    pub to: Entity,
+    pub elapsed: f32,
+    pub duration: f32,
+}
+
+impl CameraTransition {
+    /// Advances by `dt` and returns the camera position on the way to `to_position`, and
+    /// whether the transition finished.
+    pub fn step(&mut self, dt: f32, to_position: Vector2) -> (Vector2, bool) {
+        self.elapsed += dt;
+        let t = match self.duration > 0.0 {
+            true => (self.elapsed / self.duration).min(1.0),
+            false => 1.0,
+        };
+        let eased = ease_in_out_cubic(t);
+        (self.from + (to_position - self.from) * eased, t >= 1.0)
+    }
+}
+
+pub fn ease_in_out_cubic(t: f32) -> f32 {
+    if t < 0.5 {
+        4.0 * t * t * t
+    } else {
+        1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+    }
+}
+
+/// Fixed screen-space region that shows `world_rect` scaled down.
+#[derive(Resource, Clone, Copy)]
+pub struct MinimapSettings {
+    pub screen_rect: Rectangle,
+    pub world_rect: Rectangle,
+    pub background_color: Color,
+}
+
+impl MinimapSettings {
+    /// Returns `None` for positions outside `world_rect`.
+    pub fn world_to_minimap(&self, position: Vector2) -> Option<Vector2> {
+        let world = self.world_rect;
+        if world.width <= 0.0 || world.height <= 0.0 {
+            return None;
+        }
+        let t = Vector2::new(
+            (position.x - world.x) / world.width,
+            (position.y - world.y) / world.height,
+        );
+        if !(0.0..=1.0).contains(&t.x) || !(0.0..=1.0).contains(&t.y) {
+            return None;
+        }
+        Some(Vector2::new(
+            self.screen_rect.x + t.x * self.screen_rect.width,
+            self.screen_rect.y + t.y * self.screen_rect.height,
+        ))
+    }
+}
+
+/// Brightness of everything no `PointLight` reaches, white leaves the scene unlit.
+#[derive(Resource, Clone, Copy)]
+pub struct Lighting {
+    pub ambient: Color,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: Color::WHITE,
+        }
+    }
+}
+
+/// Everything outside the sight of `Vision` entities is covered with `darkness`.
+/// The world is split in square cells of `resolution` units.
+#[derive(Resource, Clone, Copy)]
+pub struct FogOfWar {
+    pub resolution: u32,
+    pub darkness: Color,
+}
+
+impl Default for FogOfWar {
+    fn default() -> Self {
+        Self {
+            resolution: 32,
+            darkness: Color::new(0, 0, 0, 255).fade(0.85),
+        }
+    }
+}
+
+impl FogOfWar {
+    fn cell_size(&self) -> f32 {
+        self.resolution.max(1) as f32
+    }
+
+    /// Cells whose center is within `radius` of `center`.
+    pub fn cells_in_radius(
+        &self,
+        center: Vector2,
+        radius: f32,
+    ) -> impl Iterator<Item = (i32, i32)> + use<> {
+        let size = self.cell_size();
+        let min = ((center.x - radius) / size).floor() as i32;
+        let max = ((center.x + radius) / size).floor() as i32;
+        let min_y = ((center.y - radius) / size).floor() as i32;
+        let max_y = ((center.y + radius) / size).floor() as i32;
+        (min_y..=max_y)
+            .flat_map(move |cy| (min..=max).map(move |cx| (cx, cy)))
+            .filter(move |&(cx, cy)| {
+                let dx = (cx as f32 + 0.5) * size - center.x;
+                let dy = (cy as f32 + 0.5) * size - center.y;
+                dx * dx + dy * dy <= radius * radius
+            })
+    }
+
+    pub fn cell_rect(&self, (cx, cy): (i32, i32)) -> Rectangle {
+        let size = self.cell_size();
+        Rectangle {
+            x: cx as f32 * size,
+            y: cy as f32 * size,
+            width: size,
+            height: size,
+        }
+    }
+}
+
+/// Fog cells currently seen by at least one `Vision` entity.
+/// Only written when it actually changes, so the overlay can key off `is_changed`.
+#[derive(Resource, Default, PartialEq)]
+pub struct FogVisibility(pub HashSet<(i32, i32)>);
+
+#[derive(Message)]
+pub struct FocusChanged {
+    pub focused: bool,
+}
+
+#[derive(Message)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+/// Seconds left before a `DamageOnContact` source can hurt a target again, keyed by
+/// (source, target).
+#[derive(Resource, Default)]
+pub struct DamageCooldowns(pub HashMap<(Entity, Entity), f32>);
+
+impl std::ops::Deref for DamageCooldowns {
+    type Target = HashMap<(Entity, Entity), f32>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for DamageCooldowns {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Sent once when an entity's `Health` reaches zero.
+#[derive(Message)]
+pub struct DeathEvent {
+    pub entity: Entity,
+}
+
+/// Sent when an entity gains `OnScreen`, compared against the previous tick.
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnteredView(pub Entity);
+
+/// Sent when an entity loses `OnScreen`, either by leaving the view or being hidden.
+#[derive(Message, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeftView(pub Entity);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    pub entity_a: Entity,
+    pub entity_b: Entity,
+    /// Center of the overlap.
+    pub point: Vector2,
+    /// Points out of `entity_b`, the direction `entity_a` gets pushed.
+    pub normal: Vector2,
+    pub depth: f32,
+}
+
+impl Contact {
+    /// Contact for `a` overlapping `b`, along the axis of least penetration.
+    pub fn from_overlap(entity_a: Entity, entity_b: Entity, a: &Rectangle, b: &Rectangle) -> Self {
+        let (left, right) = (a.x.max(b.x), (a.x + a.width).min(b.x + b.width));
+        let (top, bottom) = (a.y.max(b.y), (a.y + a.height).min(b.y + b.height));
+        let delta_x = (a.x + a.width / 2.0) - (b.x + b.width / 2.0);
+        let delta_y = (a.y + a.height / 2.0) - (b.y + b.height / 2.0);
+        let sign = |delta: f32| if delta > 0.0 { 1.0 } else { -1.0 };
+
+        let (normal, depth) = if right - left < bottom - top {
+            (Vector2::new(sign(delta_x), 0.0), right - left)
+        } else {
+            (Vector2::new(0.0, sign(delta_y)), bottom - top)
+        };
+        Self {
+            entity_a,
+            entity_b,
+            point: Vector2::new((left + right) / 2.0, (top + bottom) / 2.0),
+            normal,
+            depth,
+        }
+    }
+}
+
+/// Every contact resolved by `apply_velocity_system` this frame, recorded before push-out.
+#[derive(Resource, Default)]
+pub struct ContactManifold {
+    pub contacts: Vec<Contact>,
+}
+
+/// Contacts from the last `max_ticks` physics ticks, newest last. Only exists while
+/// `DebugSettings::contacts` is on, so physics skips recording entirely otherwise.
+#[derive(Resource)]
+pub struct DebugContacts {
+    pub ticks: VecDeque<Vec<Contact>>,
+    pub max_ticks: usize,
+}
+
+impl Default for DebugContacts {
+    fn default() -> Self {
+        Self {
+            ticks: VecDeque::new(),
+            max_ticks: 30,
+        }
+    }
+}
+
+impl DebugContacts {
+    /// Starts recording a new tick, reusing the oldest one's buffer once full.
+    pub fn begin_tick(&mut self) {
+        let mut tick = match self.ticks.len() >= self.max_ticks.max(1) {
+            true => self.ticks.pop_front().unwrap_or_default(),
+            false => Vec::new(),
+        };
+        tick.clear();
+        self.ticks.push_back(tick);
+    }
+
+    pub fn record(&mut self, contact: Contact) {
+        if let Some(tick) = self.ticks.back_mut() {
+            tick.push(contact);
+        }
+    }
+
+    /// Every kept contact with an alpha, 1 for the current tick fading towards the oldest.
+    pub fn faded(&self) -> impl Iterator<Item = (&Contact, f32)> {
+        let count = self.max_ticks.max(1) as f32;
+        self.ticks
+            .iter()
+            .rev()
+            .enumerate()
+            .flat_map(move |(age, tick)| {
+                let alpha = 1.0 - age as f32 / count;
+                tick.iter().map(move |contact| (contact, alpha))
+            })
+    }
+}
+
+#[derive(Message)]
+pub struct CollisionEvent {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+#[derive(Resource)]
+pub struct PhysicsSettings {
+    /// How many times the physics and post physics schedules run per fixed tick
+    pub substeps: u8,
+    /// Units per second below which a mover counts as resting.
+    pub sleep_velocity_epsilon: f32,
+    pub sleep_ticks: u32,
+    pub resolution_order: ResolutionOrder,
+    /// What `enforce_world_bounds_system` does with movers that have no `BoundsBehavior`,
+    /// `None` leaves them alone.
+    pub bounds_behavior: Option<BoundsBehavior>,
+}
+
+/// Order `apply_velocity_system` resolves movers in, earlier movers win squeezes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionOrder {
+    /// Highest `CollisionPriority` first, ties broken by entity.
+    #[default]
+    Priority,
+    /// Largest collider first, area stands in for mass. Ties broken by entity.
+    Mass,
+    /// Query order, skips the sort but which mover wins is arbitrary.
+    Insertion,
+}
+
+impl Default for PhysicsSettings {
+    fn default() -> Self {
+        Self {
+            substeps: 1,
+            sleep_velocity_epsilon: 0.5,
+            sleep_ticks: 30,
+            resolution_order: ResolutionOrder::default(),
+            bounds_behavior: Some(BoundsBehavior::Clamp),
+        }
+    }
+}
+
+/// Area movers are kept in, see `BoundsBehavior`.
+#[derive(Resource, Debug, Clone, Copy)]
+pub struct WorldBounds(pub Rectangle);
+
 #[derive(Resource, Clone, Copy)]
 pub struct Time {
     pub delta: f32,
@@ -62,4 +1118,591 @@ impl Time {
     pub fn delta(&self) -> f32 {
         self.delta
     }
+
+    /// How far the leftover accumulator is into the next tick, 0 to 1.
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.delta).clamp(0.0, 1.0)
+    }
+}
+
+/// Lets the physics clock be frozen and advanced a tick at a time for debugging.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StepMode {
+    #[default]
+    Running,
+    Paused,
+    /// Runs a single tick on the next frame, then pauses again.
+    StepOnce,
+}
+
+impl StepMode {
+    /// Physics ticks to run this frame. Only `Running` feeds the accumulator, so nothing
+    /// piles up to be caught up in a burst after a pause.
+    pub fn ticks_due(&mut self, time: &mut Time, frame_time: f32) -> u32 {
+        match *self {
+            StepMode::Running => {
+                time.accumulator += frame_time;
+                let mut ticks = 0;
+                while time.accumulator >= time.delta {
+                    time.accumulator -= time.delta;
+                    ticks += 1;
+                }
+                ticks
+            }
+            StepMode::Paused => 0,
+            StepMode::StepOnce => {
+                *self = StepMode::Paused;
+                1
+            }
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        *self = match self {
+            StepMode::Running => StepMode::Paused,
+            StepMode::Paused | StepMode::StepOnce => StepMode::Running,
+        };
+    }
+}
+
+/// `Time::alpha` of the physics clock, set before each rendered frame.
+#[derive(Resource, Clone, Copy, Default)]
+pub struct PhysicsAlpha(pub f32);
+
+#[derive(Resource, Clone, Copy)]
+pub struct WorldSeed(pub u64);
+
+/// Shared deterministic RNG, seeded from `WorldSeed` so a run can be reproduced.
+#[derive(Resource)]
+pub struct SeededRng(pub SmallRng);
+
+impl FromWorld for SeededRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.get_resource::<WorldSeed>().map_or(0, |seed| seed.0);
+        Self(SmallRng::seed_from_u64(seed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_transition_eases_in_and_out() {
+        let mut transition = CameraTransition {
+            from: Vector2::new(0.0, 0.0),
+            to: Entity::PLACEHOLDER,
+            elapsed: 0.0,
+            duration: 1.0,
+        };
+        let to = Vector2::new(100.0, -100.0);
+        // Slow start, 4 * 0.25^3 of the way after a quarter
+        let (p, done) = transition.step(0.25, to);
+        assert_eq!((p.x, p.y, done), (6.25, -6.25, false));
+        let (p, _) = transition.step(0.25, to);
+        assert_eq!((p.x, p.y), (50.0, -50.0));
+        let (p, _) = transition.step(0.25, to);
+        assert_eq!((p.x, p.y), (93.75, -93.75));
+        // Overshooting the duration lands exactly on the target
+        let (p, done) = transition.step(0.5, to);
+        assert_eq!((p.x, p.y, done), (100.0, -100.0, true));
+    }
+
+    #[test]
+    fn test_debug_contacts_fade_and_drop_old_ticks() {
+        let contact = |depth| Contact {
+            entity_a: Entity::PLACEHOLDER,
+            entity_b: Entity::PLACEHOLDER,
+            point: Vector2::new(0.0, 0.0),
+            normal: Vector2::new(0.0, -1.0),
+            depth,
+        };
+        let mut contacts = DebugContacts {
+            max_ticks: 2,
+            ..Default::default()
+        };
+        // Nothing is kept before the first tick starts
+        contacts.record(contact(1.0));
+        assert_eq!(contacts.faded().count(), 0);
+
+        for depth in [1.0, 2.0, 3.0] {
+            contacts.begin_tick();
+            contacts.record(contact(depth));
+        }
+        // The oldest tick was recycled, the newest comes first at full alpha
+        let faded: Vec<_> = contacts
+            .faded()
+            .map(|(c, alpha)| (c.depth, alpha))
+            .collect();
+        assert_eq!(faded, vec![(3.0, 1.0), (2.0, 0.5)]);
+    }
+
+    #[test]
+    fn test_frame_time_history_rolls() {
+        let mut metrics = Metrics::default();
+        for i in 0..FRAME_TIME_HISTORY + 5 {
+            metrics.record_frame_time(i as f32);
+        }
+        assert_eq!(metrics.frame_time_history.len(), FRAME_TIME_HISTORY);
+        assert_eq!(metrics.frame_time_history.front(), Some(&5.0));
+
+        let rgba = |c: Color| (c.r, c.g, c.b, c.a);
+        let green = rgba(Color::GREEN);
+        let yellow = rgba(Color::YELLOW);
+        let red = rgba(Color::RED);
+        assert_eq!(rgba(frame_time_color(0.010)), green);
+        assert_eq!(rgba(frame_time_color(0.016)), yellow);
+        assert_eq!(rgba(frame_time_color(0.020)), yellow);
+        assert_eq!(rgba(frame_time_color(0.033)), red);
+    }
+
+    #[test]
+    fn test_step_mode_runs_single_ticks() {
+        let mut time = Time::new(64.0);
+        time.accumulator = 0.0;
+        let mut mode = StepMode::Running;
+        assert_eq!(mode.ticks_due(&mut time, 2.5 / 64.0), 2);
+        assert_eq!(time.accumulator, 0.5 / 64.0);
+
+        // Nothing runs and nothing accumulates while paused
+        mode.toggle_pause();
+        for _ in 0..10 {
+            assert_eq!(mode.ticks_due(&mut time, 1.0), 0);
+        }
+        assert_eq!(time.accumulator, 0.5 / 64.0);
+
+        // Each step is exactly one tick, however long the frame
+        for _ in 0..3 {
+            mode = StepMode::StepOnce;
+            assert_eq!(mode.ticks_due(&mut time, 1.0), 1);
+            assert_eq!(mode, StepMode::Paused);
+            assert_eq!(mode.ticks_due(&mut time, 1.0), 0);
+        }
+
+        // Resuming picks up from the leftover, not the paused time
+        mode.toggle_pause();
+        assert_eq!(mode, StepMode::Running);
+        assert_eq!(mode.ticks_due(&mut time, 0.5 / 64.0), 1);
+    }
+
+    #[test]
+    fn test_fps_cap_cycles() {
+        let mut cap = FpsCap::default();
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            seen.push((cap.target_fps(), cap.to_string()));
+            cap = cap.cycle();
+        }
+        let expected = [
+            (60, "60"),
+            (120, "120"),
+            (144, "144"),
+            (0, "vsync"),
+            (60, "60"),
+        ];
+        let expected: Vec<_> = expected.iter().map(|&(f, s)| (f, s.to_string())).collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn test_framerate_limiter_remaining_budget() {
+        use std::time::Duration;
+
+        let limiter = FramerateLimiter::new(100);
+        assert_eq!(
+            limiter.remaining(Duration::from_millis(4)),
+            Some(Duration::from_millis(6))
+        );
+        // Slow frames and exact ones don't sleep
+        assert_eq!(limiter.remaining(Duration::from_millis(10)), None);
+        assert_eq!(limiter.remaining(Duration::from_millis(25)), None);
+
+        let disabled = FramerateLimiter {
+            enabled: false,
+            ..limiter.clone()
+        };
+        assert_eq!(disabled.remaining(Duration::ZERO), None);
+        assert_eq!(FramerateLimiter::new(0).remaining(Duration::ZERO), None);
+    }
+
+    #[test]
+    fn test_fps_cap_leaves_physics_step_alone() {
+        // A second of frames at each cap still runs a second of fixed ticks
+        for frame_rate in [60.0, 120.0, 144.0, 1000.0] {
+            let mut time = Time::new(60.0);
+            time.accumulator = 0.0;
+            let mut mode = StepMode::Running;
+            let ticks: u32 = (0..frame_rate as u32)
+                .map(|_| mode.ticks_due(&mut time, 1.0 / frame_rate))
+                .sum();
+            assert!(
+                (59..=60).contains(&ticks),
+                "{ticks} ticks at {frame_rate} FPS"
+            );
+            assert_eq!(time.delta, 1.0 / 60.0);
+        }
+    }
+
+    #[test]
+    fn test_group_by_layer_skips_debug_layer() {
+        let items = [
+            (0, 'a'),
+            (DEBUG_LAYER, 'b'),
+            (2, 'c'),
+            (0, 'd'),
+            (FOG_LAYER, 'e'),
+        ];
+        let layers = group_by_layer(items.into_iter(), 4);
+
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[&0], vec!['a', 'd']);
+        assert_eq!(layers[&2], vec!['c']);
+        assert!(!layers.contains_key(&DEBUG_LAYER));
+        assert!(!layers.contains_key(&FOG_LAYER));
+    }
+
+    #[test]
+    fn test_layer_settings_classification() {
+        let mut settings = LayerSettings::default();
+        assert!(settings.is_screen_space(HUD_LAYER));
+        assert!(settings.is_screen_space(CURSOR_LAYER));
+        assert!(!settings.is_screen_space(0));
+        assert_eq!(settings.get(5), LayerConfig::default());
+
+        settings.0.insert(
+            5,
+            LayerConfig {
+                screen_space: true,
+                ..Default::default()
+            },
+        );
+        assert!(settings.is_screen_space(5));
+    }
+
+    #[test]
+    fn test_layer_render_scale_sizes_and_zoom() {
+        let at = |render_scale| LayerConfig {
+            render_scale,
+            ..Default::default()
+        };
+        let size = |config: LayerConfig, x, y| {
+            let size = config.texture_size(Vector2i { x, y });
+            (size.x, size.y)
+        };
+        assert_eq!(size(at(1.0), 1024, 768), (1024, 768));
+        assert_eq!(size(at(0.5), 1024, 768), (512, 384));
+        assert_eq!(size(at(0.25), 1024, 768), (256, 192));
+        // After a resize, odd sizes round and a tiny window still gets a texture
+        assert_eq!(size(at(0.25), 1000, 750), (250, 188));
+        assert_eq!(size(at(0.25), 2, 2), (1, 1));
+
+        // A world point lands at the same fraction of the texture at every scale
+        let camera = Camera2D {
+            target: Vector2::new(100.0, 50.0),
+            offset: Vector2::new(512.0, 384.0),
+            zoom: 2.0,
+            ..Default::default()
+        };
+        let to_screen = |camera: &Camera2D, point: Vector2| {
+            (point - camera.target) * camera.zoom + camera.offset
+        };
+        let point = Vector2::new(164.0, 10.0);
+        let full = to_screen(&camera, point);
+        assert_eq!(full, Vector2::new(640.0, 304.0));
+        for scale in [0.5, 0.25] {
+            let scaled = to_screen(&at(scale).scaled_camera(&camera), point);
+            assert_eq!(scaled, full * scale);
+        }
+        // The default leaves the camera alone
+        let same = at(1.0).scaled_camera(&camera);
+        assert_eq!((same.zoom, same.offset), (camera.zoom, camera.offset));
+
+        // Compositing samples the whole, smaller, texture flipped
+        let window = Vector2i { x: 1024, y: 768 };
+        let screen = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 1024.0,
+            height: 768.0,
+        };
+        let source = at(0.5).composite_source(screen, window);
+        assert_eq!(
+            (source.x, source.y, source.width, source.height),
+            (0.0, 384.0, 512.0, -384.0)
+        );
+    }
+
+    #[test]
+    fn test_composite_source_flips_a_sub_rect() {
+        let dest = Rectangle {
+            x: 10.0,
+            y: 20.0,
+            width: 100.0,
+            height: 30.0,
+        };
+        let source = LayerConfig::default().composite_source(dest, Vector2i { x: 800, y: 600 });
+        assert_eq!(
+            (source.x, source.y, source.width, source.height),
+            (10.0, 550.0, 100.0, -30.0)
+        );
+    }
+
+    #[test]
+    fn test_inactive_layers() {
+        let mut inactive = InactiveLayers::default();
+        assert!(inactive.is_active(0));
+        inactive.insert(0);
+        inactive.insert(DEBUG_LAYER);
+        assert!(!inactive.is_active(0));
+        assert!(!inactive.is_active(DEBUG_LAYER));
+        assert!(inactive.is_active(HUD_LAYER));
+        inactive.remove(&0);
+        assert!(inactive.is_active(0));
+    }
+
+    #[test]
+    fn test_composite_order_puts_higher_layers_last() {
+        // Whatever order the layer map hands the keys out in
+        for keys in [[0, 1], [1, 0]] {
+            assert_eq!(composite_order(keys.into_iter()), vec![0, 1]);
+        }
+        let mut textures: HashMap<u32, ()> = HashMap::default();
+        for layer in [7, DEBUG_LAYER, 0, FOG_LAYER, LIGHTING_LAYER, 3, 12] {
+            textures.insert(layer, ());
+        }
+        assert_eq!(composite_order(textures.keys().copied()), vec![0, 3, 7, 12]);
+    }
+
+    #[test]
+    fn test_focus_throttle() {
+        let settings = RenderSettings::default();
+        let focused = WindowFocus {
+            focused: true,
+            minimized: false,
+        };
+        let unfocused = WindowFocus {
+            focused: false,
+            minimized: false,
+        };
+        let minimized = WindowFocus {
+            focused: false,
+            minimized: true,
+        };
+
+        assert!(focused.should_render());
+        assert!(unfocused.should_render());
+        assert!(!minimized.should_render());
+
+        assert_eq!(focused.throttle(&settings), None);
+        assert_eq!(unfocused.throttle(&settings), Some(UNFOCUSED_THROTTLE));
+        assert_eq!(minimized.throttle(&settings), Some(UNFOCUSED_THROTTLE));
+
+        let settings = RenderSettings {
+            throttle_unfocused: false,
+        };
+        assert_eq!(unfocused.throttle(&settings), None);
+        assert_eq!(minimized.throttle(&settings), None);
+    }
+
+    #[test]
+    fn test_camera_scaling_zoom() {
+        let reference = Vector2i { x: 320, y: 180 };
+        let size = |x, y| Vector2i { x, y };
+
+        assert_eq!(
+            CameraScalingMode::Stretch.zoom(reference, size(1280, 720)),
+            1.0
+        );
+
+        assert_eq!(
+            CameraScalingMode::KeepHeight.zoom(reference, size(1000, 360)),
+            2.0
+        );
+        assert_eq!(
+            CameraScalingMode::KeepWidth.zoom(reference, size(960, 100)),
+            3.0
+        );
+        assert_eq!(
+            CameraScalingMode::KeepHeight.zoom(reference, size(320, 90)),
+            0.5
+        );
+
+        // Fits the smaller axis and snaps down
+        let integer = CameraScalingMode::IntegerScale;
+        assert_eq!(integer.zoom(reference, size(1280, 720)), 4.0);
+        assert_eq!(integer.zoom(reference, size(1000, 720)), 3.0);
+        assert_eq!(integer.zoom(reference, size(320, 180)), 1.0);
+
+        // Below the reference it snaps to whole fractions
+        assert_eq!(integer.zoom(reference, size(300, 180)), 0.5);
+        assert_eq!(integer.zoom(reference, size(100, 60)), 1.0 / 4.0);
+        assert_eq!(integer.zoom(reference, size(0, 0)), 1.0);
+
+        let square = Vector2i { x: 1024, y: 768 };
+        assert_eq!(integer.zoom(square, size(2048, 2000)), 2.0);
+    }
+
+    #[test]
+    fn test_minimap_projection() {
+        let minimap = MinimapSettings {
+            screen_rect: Rectangle {
+                x: 10.0,
+                y: 20.0,
+                width: 100.0,
+                height: 50.0,
+            },
+            world_rect: Rectangle {
+                x: -500.0,
+                y: 0.0,
+                width: 1000.0,
+                height: 2000.0,
+            },
+            background_color: Color::WHITE,
+        };
+
+        let project = |x, y| {
+            minimap
+                .world_to_minimap(Vector2::new(x, y))
+                .map(|dot| (dot.x, dot.y))
+        };
+
+        assert_eq!(project(-500.0, 0.0), Some((10.0, 20.0)));
+        assert_eq!(project(0.0, 1000.0), Some((60.0, 45.0)));
+        assert_eq!(project(500.0, 2000.0), Some((110.0, 70.0)));
+        assert_eq!(project(501.0, 10.0), None);
+        assert_eq!(project(0.0, -1.0), None);
+    }
+
+    #[test]
+    fn test_fog_cells_in_radius() {
+        let fog = FogOfWar {
+            resolution: 10,
+            ..Default::default()
+        };
+
+        let mut cells: Vec<_> = fog.cells_in_radius(Vector2::new(0.0, 0.0), 8.0).collect();
+        cells.sort();
+        assert_eq!(cells, vec![(-1, -1), (-1, 0), (0, -1), (0, 0)]);
+
+        // One cell of radius around a cell center reaches the 4 neighbours, not the diagonals
+        let cells: HashSet<_> = fog
+            .cells_in_radius(Vector2::new(25.0, 25.0), 10.0)
+            .collect();
+        assert_eq!(cells.len(), 5);
+        assert!(cells.contains(&(2, 2)));
+        assert!(cells.contains(&(1, 2)) && cells.contains(&(3, 2)));
+        assert!(cells.contains(&(2, 1)) && cells.contains(&(2, 3)));
+        assert!(!cells.contains(&(1, 1)));
+
+        let rect = fog.cell_rect((-2, 3));
+        assert_eq!(
+            (rect.x, rect.y, rect.width, rect.height),
+            (-20.0, 30.0, 10.0, 10.0)
+        );
+    }
+
+    #[test]
+    fn test_camera_framing() {
+        let framing = CameraFraming {
+            padding: 50.0,
+            min_zoom: 0.25,
+            max_zoom: 2.0,
+        };
+        let screen = Vector2::new(1000.0, 500.0);
+        let frame = |points: &[(f32, f32)], screen: Vector2| {
+            framing
+                .frame(points.iter().map(|&(x, y)| Vector2::new(x, y)), screen)
+                .map(|(center, zoom)| ((center.x, center.y), zoom))
+        };
+
+        assert_eq!(frame(&[], screen), None);
+        // One target is plain follow, zoom is left alone
+        assert_eq!(frame(&[(30.0, -40.0)], screen), Some(((30.0, -40.0), None)));
+
+        // 400x0 box plus padding is 500x100, width bound
+        assert_eq!(
+            frame(&[(0.0, 0.0), (400.0, 0.0)], screen),
+            Some(((200.0, 0.0), Some(2.0)))
+        );
+        assert_eq!(
+            frame(&[(0.0, 0.0), (1900.0, 0.0)], screen),
+            Some(((950.0, 0.0), Some(0.5)))
+        );
+        // Height bound on a tall spread, then the same spread on a tall window
+        assert_eq!(
+            frame(&[(0.0, 0.0), (100.0, 900.0), (-100.0, 400.0)], screen),
+            Some(((0.0, 450.0), Some(0.5)))
+        );
+        assert_eq!(
+            frame(
+                &[(0.0, 0.0), (100.0, 900.0), (-100.0, 400.0)],
+                Vector2::new(500.0, 2000.0)
+            ),
+            Some(((0.0, 450.0), Some(500.0 / 300.0)))
+        );
+
+        // Clamped at both ends
+        assert_eq!(
+            frame(&[(0.0, 0.0), (0.0, 0.0)], screen),
+            Some(((0.0, 0.0), Some(2.0)))
+        );
+        assert_eq!(
+            frame(&[(0.0, 0.0), (100_000.0, 0.0)], screen),
+            Some(((50_000.0, 0.0), Some(0.25)))
+        );
+    }
+
+    #[test]
+    fn test_contact_from_overlap() {
+        let mut world = World::new();
+        let (a, b) = (world.spawn(()).id(), world.spawn(()).id());
+        let rect = |x, y, width, height| Rectangle {
+            x,
+            y,
+            width,
+            height,
+        };
+
+        // Landing on a floor: shallow on y, pushed up
+        let contact = Contact::from_overlap(
+            a,
+            b,
+            &rect(10.0, 95.0, 20.0, 10.0),
+            &rect(0.0, 100.0, 100.0, 50.0),
+        );
+        assert_eq!((contact.normal.x, contact.normal.y), (0.0, -1.0));
+        assert_eq!(contact.depth, 5.0);
+        assert_eq!((contact.point.x, contact.point.y), (20.0, 102.5));
+
+        // Clipping the right side of a wall
+        let contact = Contact::from_overlap(
+            a,
+            b,
+            &rect(48.0, 0.0, 10.0, 10.0),
+            &rect(0.0, -20.0, 50.0, 50.0),
+        );
+        assert_eq!((contact.normal.x, contact.normal.y), (1.0, 0.0));
+        assert_eq!(contact.depth, 2.0);
+        assert_eq!((contact.entity_a, contact.entity_b), (a, b));
+    }
+
+    #[test]
+    fn test_short_count() {
+        assert_eq!(short_count(0), "0");
+        assert_eq!(short_count(999), "999");
+        assert_eq!(short_count(1_000), "1k");
+        assert_eq!(short_count(1_250), "1.2k");
+        assert_eq!(short_count(142_000), "142k");
+        assert_eq!(short_count(1_500_000), "1.5M");
+
+        let metrics = Metrics {
+            apply_velocity_movers: 3,
+            apply_velocity_candidates: 142_000,
+            ..Default::default()
+        };
+        assert_eq!(
+            metrics.system_count_rows()[0],
+            "apply_velocity: 3 movers, 142k candidates"
+        );
+    }
 }
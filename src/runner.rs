@@ -0,0 +1,207 @@
+use bevy_ecs::{prelude::*, schedule::ScheduleLabel};
+#[cfg(feature = "trace")]
+use tracing::info_span;
+
+use crate::resources::{
+    PhysicsAlpha, PhysicsSettings, RenderSettings, StepMode, Time, WindowFocus, WindowResource,
+};
+use crate::snapshot;
+
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct Update;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct FirstPhysicsUpdate;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct PrePhysicsUpdate;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct PhysicsUpdate;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct PostPhysicsUpdate;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct LastPhysicsUpdate;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct PreRender;
+#[derive(ScheduleLabel, Hash, PartialEq, Eq, Debug, Clone)]
+pub struct Render;
+
+/// Longest frame time fed into the physics accumulator.
+pub const MAX_FRAME_TIME: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameReport {
+    /// Fixed physics ticks run this frame, substeps not counted.
+    pub physics_steps: u32,
+    /// Wall time `run_frame` took.
+    pub total_time: std::time::Duration,
+}
+
+/// One frame: update with the frame's delta, as many fixed physics ticks as `physics_time`
+/// has accumulated, then pre-render and render with the frame's delta again. Schedules are
+/// looked up by label, so every one of them has to be added to `world` first, even if empty.
+///
+/// `physics_time` keeps the fixed step and the leftover accumulator between frames.
+pub fn run_frame(world: &mut World, physics_time: &mut Time, frame_time: f32) -> FrameReport {
+    let start = std::time::Instant::now();
+    // Clamp so a stall (or a long unfocused frame) doesn't trigger a burst of physics ticks
+    let frame_time = frame_time.min(MAX_FRAME_TIME);
+    let frame_clock = Time {
+        delta: frame_time,
+        accumulator: 0.0,
+    };
+    world.insert_resource(frame_clock);
+    {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("update").entered();
+        world.run_schedule(Update);
+    }
+
+    let ticks = world
+        .resource_mut::<StepMode>()
+        .ticks_due(physics_time, frame_time);
+    for _ in 0..ticks {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("physics loop").entered();
+        world.insert_resource(*physics_time);
+        world.run_schedule(FirstPhysicsUpdate);
+        world.run_schedule(PrePhysicsUpdate);
+        let substeps = world.resource::<PhysicsSettings>().substeps.max(1);
+        world.insert_resource(Time {
+            delta: physics_time.delta / substeps as f32,
+            ..*physics_time
+        });
+        for _ in 0..substeps {
+            world.run_schedule(PhysicsUpdate);
+            world.run_schedule(PostPhysicsUpdate);
+        }
+        world.insert_resource(*physics_time);
+        world.run_schedule(LastPhysicsUpdate);
+        snapshot::record_snapshot_system(world);
+    }
+
+    if world.resource::<WindowFocus>().should_render() {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("render").entered();
+        // Camera smoothing is per rendered frame, not per physics tick
+        world.insert_resource(frame_clock);
+        world.insert_resource(PhysicsAlpha(physics_time.alpha()));
+        world.run_schedule(PreRender);
+        world.run_schedule(Render);
+    } else {
+        // Still end a frame so the window keeps polling its events
+        world.resource_mut::<WindowResource>().draw(|_| {});
+    }
+
+    // Without an App nothing else flushes the removed component buffers
+    world.clear_trackers();
+
+    FrameReport {
+        physics_steps: ticks,
+        total_time: start.elapsed(),
+    }
+}
+
+/// How long to sleep after a frame, if the window is idle and throttling is on.
+pub fn frame_throttle(world: &World) -> Option<std::time::Duration> {
+    let focus = world.resource::<WindowFocus>();
+    focus.throttle(world.resource::<RenderSettings>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::SnapshotHistory;
+
+    #[derive(Resource, Default)]
+    struct Seen {
+        update: Vec<Time>,
+        physics: Vec<Time>,
+        render: Vec<Time>,
+    }
+
+    fn test_world() -> World {
+        let mut world = World::new();
+        world.add_schedule(Schedule::new(Update));
+        world.add_schedule(Schedule::new(FirstPhysicsUpdate));
+        world.add_schedule(Schedule::new(PrePhysicsUpdate));
+        world.add_schedule(Schedule::new(PhysicsUpdate));
+        world.add_schedule(Schedule::new(PostPhysicsUpdate));
+        world.add_schedule(Schedule::new(LastPhysicsUpdate));
+        world.add_schedule(Schedule::new(PreRender));
+        world.add_schedule(Schedule::new(Render));
+        world.init_resource::<Seen>();
+        world.init_resource::<StepMode>();
+        world.init_resource::<PhysicsSettings>();
+        world.init_resource::<SnapshotHistory>();
+        world.insert_resource(WindowFocus {
+            focused: true,
+            minimized: false,
+        });
+        world.schedule_scope(Update, |_, schedule| {
+            schedule.add_systems(|time: Res<Time>, mut seen: ResMut<Seen>| {
+                seen.update.push(*time);
+            });
+        });
+        world.schedule_scope(PhysicsUpdate, |_, schedule| {
+            schedule.add_systems(|time: Res<Time>, mut seen: ResMut<Seen>| {
+                seen.physics.push(*time);
+            });
+        });
+        world.schedule_scope(Render, |_, schedule| {
+            schedule.add_systems(|time: Res<Time>, mut seen: ResMut<Seen>| {
+                seen.render.push(*time);
+            });
+        });
+        world
+    }
+
+    #[test]
+    fn test_frame_runs_whole_fixed_steps_and_keeps_the_rest() {
+        let mut world = test_world();
+        let mut physics_time = Time::new(64.0);
+        physics_time.accumulator = 0.0;
+        let step = physics_time.delta;
+
+        // 3.5 steps worth
+        let report = run_frame(&mut world, &mut physics_time, step * 3.5);
+        assert_eq!(report.physics_steps, 3);
+        assert_eq!(physics_time.accumulator, step * 0.5);
+        // The half step left over completes a fourth on the next frame
+        let report = run_frame(&mut world, &mut physics_time, step * 0.5);
+        assert_eq!(report.physics_steps, 1);
+        assert_eq!(physics_time.accumulator, 0.0);
+        assert_eq!(world.resource::<SnapshotHistory>().snapshots.len(), 4);
+
+        let seen = world.resource::<Seen>();
+        let deltas = |times: &[Time]| times.iter().map(|t| t.delta).collect::<Vec<_>>();
+        // Update and render see the frame, physics the fixed step
+        assert_eq!(deltas(&seen.update), vec![step * 3.5, step * 0.5]);
+        assert_eq!(deltas(&seen.render), vec![step * 3.5, step * 0.5]);
+        assert_eq!(deltas(&seen.physics), vec![step; 4]);
+        assert!(seen.update.iter().all(|t| t.accumulator == 0.0));
+    }
+
+    #[test]
+    fn test_frame_time_is_clamped_and_substeps_split_the_step() {
+        let mut world = test_world();
+        world.resource_mut::<PhysicsSettings>().substeps = 2;
+        let mut physics_time = Time::new(64.0);
+        physics_time.accumulator = 0.0;
+        let step = physics_time.delta;
+
+        // A 2 second stall only feeds MAX_FRAME_TIME into the accumulator
+        let report = run_frame(&mut world, &mut physics_time, 2.0);
+        assert_eq!(report.physics_steps, (MAX_FRAME_TIME / step) as u32);
+        let seen = world.resource::<Seen>();
+        assert_eq!(seen.update[0].delta, MAX_FRAME_TIME);
+        assert_eq!(seen.physics.len(), 2 * report.physics_steps as usize);
+        assert!(seen.physics.iter().all(|t| t.delta == step / 2.0));
+
+        // Paused, nothing accumulates and no steps run, but the frame still renders
+        *world.resource_mut::<StepMode>() = StepMode::Paused;
+        let accumulator = physics_time.accumulator;
+        let report = run_frame(&mut world, &mut physics_time, step * 10.0);
+        assert_eq!(report.physics_steps, 0);
+        assert_eq!(physics_time.accumulator, accumulator);
+        assert_eq!(world.resource::<Seen>().render.len(), 2);
+    }
+}
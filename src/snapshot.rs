@@ -0,0 +1,220 @@
+#![allow(dead_code)]
+
+use std::collections::VecDeque;
+
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::RunSystemOnce;
+
+use crate::components::{Collider, GlobalTransform, ScreenSpace, Sprite, Transform, Velocity};
+use crate::spatial_hash::{CollisionHash, RenderHash};
+use crate::sweep_prune::CollisionSweep;
+use crate::systems::update_global_transforms_system;
+
+/// Movers' state after a physics tick. Anything without `Velocity` can't change during a
+/// tick, so it's left out to keep snapshots small next to the static grid.
+#[derive(Debug, Clone)]
+pub struct PhysicsSnapshot {
+    pub tick: u64,
+    pub transforms: Vec<(Entity, Transform)>,
+    pub velocities: Vec<(Entity, Velocity)>,
+}
+
+impl PhysicsSnapshot {
+    pub fn transform(&self, entity: Entity) -> Option<&Transform> {
+        self.transforms
+            .iter()
+            .find(|(e, _)| *e == entity)
+            .map(|(_, transform)| transform)
+    }
+}
+
+/// The last `capacity` snapshots, oldest first.
+#[derive(Resource)]
+pub struct SnapshotHistory {
+    pub snapshots: VecDeque<PhysicsSnapshot>,
+    pub capacity: usize,
+    /// Tick the next snapshot is taken for.
+    pub tick: u64,
+}
+
+impl Default for SnapshotHistory {
+    fn default() -> Self {
+        Self::new(60)
+    }
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    pub fn push(&mut self, snapshot: PhysicsSnapshot) {
+        if self.snapshots.len() >= self.capacity.max(1) {
+            self.snapshots.pop_front();
+        }
+        self.tick = snapshot.tick + 1;
+        self.snapshots.push_back(snapshot);
+    }
+
+    pub fn get(&self, tick: u64) -> Option<&PhysicsSnapshot> {
+        self.snapshots.iter().find(|snapshot| snapshot.tick == tick)
+    }
+
+    pub fn latest(&self) -> Option<&PhysicsSnapshot> {
+        self.snapshots.back()
+    }
+
+    /// Drops every snapshot after `tick`, they're about to be re-simulated.
+    pub fn truncate_after(&mut self, tick: u64) {
+        self.snapshots.retain(|snapshot| snapshot.tick <= tick);
+        self.tick = tick + 1;
+    }
+}
+
+pub fn take_snapshot(world: &World) -> PhysicsSnapshot {
+    let tick = world
+        .get_resource::<SnapshotHistory>()
+        .map_or(0, |h| h.tick);
+    let (transforms, velocities) = match world.try_query::<(Entity, &Transform, &Velocity)>() {
+        Some(mut movers) => movers
+            .iter(world)
+            .map(|(entity, transform, velocity)| ((entity, *transform), (entity, *velocity)))
+            .unzip(),
+        None => Default::default(),
+    };
+    PhysicsSnapshot {
+        tick,
+        transforms,
+        velocities,
+    }
+}
+
+/// Puts movers back where the snapshot has them, then brings `GlobalTransform` and the
+/// broad phase in line so re-simulation starts from a consistent state. Entities despawned
+/// since the snapshot stay gone.
+pub fn restore_snapshot(world: &mut World, snapshot: &PhysicsSnapshot) {
+    for &(entity, transform) in &snapshot.transforms {
+        if let Some(mut current) = world.get_mut::<Transform>(entity) {
+            *current = transform;
+        }
+    }
+    for &(entity, velocity) in &snapshot.velocities {
+        if let Some(mut current) = world.get_mut::<Velocity>(entity) {
+            *current = velocity;
+        }
+    }
+    world
+        .run_system_once(update_global_transforms_system)
+        .expect("transform propagation has no failing params");
+
+    let colliders: Vec<_> = world
+        .query::<(Entity, &Collider, &GlobalTransform)>()
+        .iter(world)
+        .map(|(entity, collider, transform)| (entity, collider.aabb(transform)))
+        .collect();
+    let sprites: Vec<_> = world
+        .query_filtered::<(Entity, &Sprite, &GlobalTransform), Without<ScreenSpace>>()
+        .iter(world)
+        .filter_map(|(entity, sprite, transform)| sprite.bounds(transform).map(|r| (entity, r)))
+        .collect();
+    if let Some(mut sweep) = world.get_resource_mut::<CollisionSweep>() {
+        sweep.rebuild(colliders.iter().copied());
+    }
+    world
+        .resource_mut::<CollisionHash>()
+        .rebuild(colliders.into_iter());
+    world
+        .resource_mut::<RenderHash>()
+        .rebuild(sprites.into_iter());
+}
+
+/// Records the state at the end of the physics tick.
+pub fn record_snapshot_system(world: &mut World) {
+    let snapshot = take_snapshot(world);
+    world.resource_mut::<SnapshotHistory>().push(snapshot);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::SpriteBundle;
+    use crate::spatial_hash::SpatialHash;
+    use rustyray::prelude::*;
+
+    #[test]
+    fn test_history_keeps_recent_ticks() {
+        let mut world = World::new();
+        world.insert_resource(SnapshotHistory::new(3));
+        world.spawn((Transform::default(), Velocity::default()));
+        for _ in 0..5 {
+            record_snapshot_system(&mut world);
+        }
+        let history = world.resource::<SnapshotHistory>();
+        let ticks: Vec<u64> = history.snapshots.iter().map(|s| s.tick).collect();
+        assert_eq!(ticks, vec![2, 3, 4]);
+        assert!(history.get(1).is_none());
+        assert_eq!(history.latest().unwrap().transforms.len(), 1);
+
+        // Rolling back to tick 3 makes it the last recorded one
+        let mut history = world.resource_mut::<SnapshotHistory>();
+        history.truncate_after(3);
+        assert_eq!(history.latest().unwrap().tick, 3);
+        assert_eq!(history.tick, 4);
+    }
+
+    #[test]
+    fn test_restore_rewinds_movers_and_hashes() {
+        let mut world = World::new();
+        world.init_resource::<SnapshotHistory>();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<CollisionSweep>();
+        let start = Transform::default().with_position(Vector2::new(10.0, 10.0));
+        let mover = world
+            .spawn((
+                SpriteBundle::new(Sprite::default(), start),
+                Collider::default(),
+                Velocity(Vector2::new(64.0, 0.0)),
+            ))
+            .id();
+        let wall = world
+            .spawn((
+                SpriteBundle::new(Sprite::default(), Transform::default()),
+                Collider::default(),
+            ))
+            .id();
+        let snapshot = take_snapshot(&world);
+        assert_eq!(snapshot.transforms.len(), 1);
+        assert!(snapshot.transform(wall).is_none());
+
+        // Simulate moving far away, into other hash cells
+        let far = Vector2::new(1000.0, 1000.0);
+        world.get_mut::<Transform>(mover).unwrap().position = far;
+        world.get_mut::<GlobalTransform>(mover).unwrap().position = far;
+        world.get_mut::<Velocity>(mover).unwrap().0 = Vector2::new(0.0, 0.0);
+
+        restore_snapshot(&mut world, &snapshot);
+        let position = world.get::<GlobalTransform>(mover).unwrap().position;
+        assert_eq!((position.x, position.y), (10.0, 10.0));
+        assert_eq!(world.get::<Velocity>(mover).unwrap().x, 64.0);
+
+        // The broad phase finds the mover back at its old place, and nowhere else
+        let rect = |x, y| Rectangle {
+            x,
+            y,
+            width: 1.0,
+            height: 1.0,
+        };
+        let collision_hash = world.resource::<CollisionHash>();
+        assert!(collision_hash.query(rect(20.0, 20.0)).contains(&mover));
+        assert!(!collision_hash.query(rect(1000.0, 1000.0)).contains(&mover));
+        assert!(collision_hash.query(rect(5.0, 5.0)).contains(&wall));
+        assert!(world.resource::<CollisionSweep>().contains(mover));
+        let render_hash = world.resource::<RenderHash>();
+        assert!(render_hash.query(rect(20.0, 20.0)).contains(&mover));
+    }
+}
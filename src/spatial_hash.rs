@@ -1,26 +1,210 @@
-use std::collections::{HashMap, HashSet};
-
 use bevy_ecs::{entity::Entity, resource::Resource};
+use rayon::prelude::*;
 use rustyray::prelude::Rectangle;
 #[cfg(feature = "trace")]
 use tracing::info_span;
 
-#[derive(Default, Resource)]
+use crate::utils::{HashMap, HashSet};
+
+/// `apply_moves` switches to `par_rebuild` once more than this share of the stored entities
+/// moved in one go...
+pub const PAR_REBUILD_FRACTION: f32 = 0.25;
+/// ...and at least this many, below it the threads cost more than the serial updates.
+pub const PAR_REBUILD_MIN: usize = 1024;
+
+/// Which way `apply_moves` went.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashUpdatePath {
+    #[default]
+    Incremental,
+    ParallelRebuild,
+}
+
+impl HashUpdatePath {
+    pub fn label(self) -> &'static str {
+        match self {
+            HashUpdatePath::Incremental => "incremental",
+            HashUpdatePath::ParallelRebuild => "parallel rebuild",
+        }
+    }
+}
+
+/// Walk the contents through `iter_entities` and `iter_cells`, the maps underneath iterate
+/// in a different order every run.
+#[derive(Default)]
 pub struct SpatialHash {
-    pub cell_size: f32,
-    pub cells: HashMap<(i32, i32), Vec<Entity>>,
-    pub entities: HashMap<Entity, Vec<(i32, i32)>>,
+    cell_size: f32,
+    /// Buckets are kept sorted by entity.
+    cells: HashMap<(i32, i32), Vec<Entity>>,
+    entities: HashMap<Entity, Vec<(i32, i32)>>,
+    /// How many fine cells fit along each side of a coarse cell, 1 when flat.
+    coarse_ratio: i32,
+    /// Number of occupied fine cells per coarse cell, only kept when `coarse_ratio > 1`.
+    coarse_cells: HashMap<(i32, i32), usize>,
+    /// Last rect seen for each entity, so the cells can be recomputed for a new `cell_size`.
+    rects: HashMap<Entity, Rectangle>,
+}
+
+/// Broad phase index over `Collider` bounds.
+#[derive(Default, Resource)]
+pub struct CollisionHash(pub SpatialHash);
+
+/// Index over `Sprite` bounds, used for culling.
+#[derive(Default, Resource)]
+pub struct RenderHash(pub SpatialHash);
+
+impl std::ops::Deref for CollisionHash {
+    type Target = SpatialHash;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for CollisionHash {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl std::ops::Deref for RenderHash {
+    type Target = SpatialHash;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for RenderHash {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 impl SpatialHash {
-    pub fn new(cell_size: f32) -> Self {
+    /// Two level hash, queries skip whole coarse cells that hold nothing.
+    /// `coarse_size` is rounded to a whole multiple of `fine_size`.
+    pub fn new(coarse_size: f32, fine_size: f32) -> Self {
+        Self {
+            coarse_ratio: ((coarse_size / fine_size).round() as i32).max(1),
+            ..Self::flat(fine_size)
+        }
+    }
+
+    pub fn flat(cell_size: f32) -> Self {
         Self {
             cell_size,
-            cells: HashMap::new(),
-            entities: HashMap::new(),
+            cells: HashMap::default(),
+            entities: HashMap::default(),
+            coarse_ratio: 1,
+            coarse_cells: HashMap::default(),
+            rects: HashMap::default(),
         }
     }
 
+    /// Re-buckets every stored rect with `cell_size`, the coarse level keeps its ratio.
+    /// The set of stored entities doesn't change, only the cells they map to.
+    ///
+    /// Call this between schedule runs (or between physics steps), never while a system
+    /// holds results from `query_iter`, since every bucket is rebuilt.
+    pub fn set_cell_size(&mut self, cell_size: f32) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("spatial_hash_set_cell_size").entered();
+        self.cell_size = cell_size;
+        self.cells.clear();
+        self.coarse_cells.clear();
+        let rects = std::mem::take(&mut self.rects);
+        for (&entity, &rect) in rects.iter() {
+            let cells = self.cell_coords_rect(rect);
+            for cell in &cells {
+                self.push_to_cell(*cell, entity);
+            }
+            self.entities.insert(entity, cells);
+        }
+        self.rects = rects;
+    }
+
+    fn coarse_coords(&self, (cx, cy): (i32, i32)) -> (i32, i32) {
+        let ratio = self.coarse_ratio.max(1);
+        (cx.div_euclid(ratio), cy.div_euclid(ratio))
+    }
+
+    fn push_to_cell(&mut self, cell: (i32, i32), entity: Entity) {
+        let coarse = self.coarse_coords(cell);
+        let bucket = self.cells.entry(cell).or_default();
+        if bucket.is_empty() && self.coarse_ratio > 1 {
+            *self.coarse_cells.entry(coarse).or_default() += 1;
+        }
+        let index = bucket.partition_point(|&e| e < entity);
+        bucket.insert(index, entity);
+    }
+
+    fn remove_from_cell(&mut self, cell: (i32, i32), entity: Entity) {
+        let Some(bucket) = self.cells.get_mut(&cell) else {
+            return;
+        };
+        bucket.retain(|&e| e != entity);
+        if !bucket.is_empty() {
+            return;
+        }
+
+        self.cells.remove(&cell);
+        if self.coarse_ratio > 1 {
+            let coarse = self.coarse_coords(cell);
+            if let Some(count) = self.coarse_cells.get_mut(&coarse) {
+                *count -= 1;
+                if *count == 0 {
+                    self.coarse_cells.remove(&coarse);
+                }
+            }
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    /// Number of entities stored.
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entities.contains_key(&entity)
+    }
+
+    /// Number of occupied cells.
+    pub fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Last rect `entity` was given.
+    pub fn rect(&self, entity: Entity) -> Option<&Rectangle> {
+        self.rects.get(&entity)
+    }
+
+    /// Every stored entity with the last rect it was given, sorted by entity.
+    pub fn iter_entities(&self) -> impl Iterator<Item = (Entity, &Rectangle)> + '_ {
+        let mut entities: Vec<_> = self.rects.iter().map(|(&e, rect)| (e, rect)).collect();
+        entities.sort_unstable_by_key(|&(e, _)| e);
+        entities.into_iter()
+    }
+
+    /// Every occupied cell and its entities, sorted by cell coordinate.
+    pub fn iter_cells(&self) -> impl Iterator<Item = ((i32, i32), &[Entity])> + '_ {
+        let mut cells: Vec<_> = self
+            .cells
+            .iter()
+            .map(|(&cell, bucket)| (cell, bucket.as_slice()))
+            .collect();
+        cells.sort_unstable_by_key(|&(cell, _)| cell);
+        cells.into_iter()
+    }
+
     pub fn cell_coords(&self, x: f32, y: f32) -> (i32, i32) {
         let cx = (x / self.cell_size).floor() as i32;
         let cy = (y / self.cell_size).floor() as i32;
@@ -44,9 +228,19 @@ impl SpatialHash {
     pub fn insert(&mut self, entity: Entity, rect: Rectangle) {
         let cells = self.cell_coords_rect(rect);
         for cell in &cells {
-            self.cells.entry(*cell).or_default().push(entity);
+            self.push_to_cell(*cell, entity);
         }
         self.entities.insert(entity, cells);
+        self.rects.insert(entity, rect);
+    }
+
+    /// Inserts a batch of new entities, reserving room for all of them up front.
+    pub fn insert_batch(&mut self, entries: Vec<(Entity, Rectangle)>) {
+        self.entities.reserve(entries.len());
+        self.rects.reserve(entries.len());
+        for (entity, rect) in entries {
+            self.insert(entity, rect);
+        }
     }
 
     pub fn update(&mut self, entity: Entity, new_rect: Rectangle) {
@@ -54,27 +248,181 @@ impl SpatialHash {
         let _span = info_span!("spatial_hash_update").entered();
         let cells = self.cell_coords_rect(new_rect);
 
+        // check if cells are the same
+        if self.entities.get(&entity) == Some(&cells) {
+            self.rects.insert(entity, new_rect);
+            return;
+        }
+
+        // remove from old cells
+        self.remove(entity);
+
+        self.insert(entity, new_rect);
+    }
+
+    pub fn remove(&mut self, entity: Entity) {
+        self.rects.remove(&entity);
+        if let Some(old_cells) = self.entities.remove(&entity) {
+            for cell in old_cells {
+                self.remove_from_cell(cell, entity);
+            }
+        }
+    }
+
+    /// Like `update`, but checks the covered cell range first so small moves that stay inside
+    /// the same cells don't allocate or touch any bucket.
+    pub fn move_entity(&mut self, entity: Entity, new_rect: Rectangle) {
         if let Some(old_cells) = self.entities.get(&entity) {
-            // check if cells are the same
-            if *old_cells == cells {
+            // Cells are stored row by row, so the first and last are the range corners
+            let min = self.cell_coords(new_rect.x, new_rect.y);
+            let max = self.cell_coords(new_rect.x + new_rect.width, new_rect.y + new_rect.height);
+            if old_cells.first() == Some(&min) && old_cells.last() == Some(&max) {
+                self.rects.insert(entity, new_rect);
                 return;
             }
+        }
 
-            // remove from old cells
-            for cell in old_cells {
-                if let Some(bucket) = self.cells.get_mut(cell) {
-                    bucket.retain(|&e| e != entity);
-                    if bucket.is_empty() {
-                        self.cells.remove(cell);
+        self.update(entity, new_rect);
+    }
+
+    /// Hands back memory the maps and buckets kept from when they held more. Buckets are
+    /// already dropped as their last entity leaves, so this mostly shrinks capacity. Only
+    /// what's at least half empty is shrunk, anything fuller would be regrown next tick.
+    pub fn compact(&mut self) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("spatial_hash_compact").entered();
+        self.cells.retain(|_, bucket| !bucket.is_empty());
+        for bucket in self.cells.values_mut() {
+            if mostly_unused(bucket.capacity(), bucket.len()) {
+                bucket.shrink_to_fit();
+            }
+        }
+        if mostly_unused(self.cells.capacity(), self.cells.len()) {
+            self.cells.shrink_to_fit();
+        }
+        if mostly_unused(self.entities.capacity(), self.entities.len()) {
+            self.entities.shrink_to_fit();
+        }
+        if mostly_unused(self.coarse_cells.capacity(), self.coarse_cells.len()) {
+            self.coarse_cells.shrink_to_fit();
+        }
+        if mostly_unused(self.rects.capacity(), self.rects.len()) {
+            self.rects.shrink_to_fit();
+        }
+    }
+
+    /// Drops every entry and re-inserts `entities`, cheaper than `update` when most things moved.
+    pub fn rebuild(&mut self, entities: impl Iterator<Item = (Entity, Rectangle)>) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("spatial_hash_rebuild").entered();
+        self.cells.clear();
+        self.entities.clear();
+        self.coarse_cells.clear();
+        self.rects.clear();
+        for (entity, rect) in entities {
+            self.insert(entity, rect);
+        }
+    }
+
+    /// Same result as `rebuild`, but the cells are worked out on every thread and grouped
+    /// into buckets per thread before being merged, then swapped in all at once.
+    /// `entries` must not repeat an entity.
+    pub fn par_rebuild(&mut self, entries: &[(Entity, Rectangle)]) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("spatial_hash_par_rebuild").entered();
+        let hash = &*self;
+        let cell_lists: Vec<Vec<(i32, i32)>> = entries
+            .par_iter()
+            .map(|&(_, rect)| hash.cell_coords_rect(rect))
+            .collect();
+        let mut cells = entries
+            .par_iter()
+            .zip(&cell_lists)
+            .fold(
+                HashMap::<(i32, i32), Vec<Entity>>::default,
+                |mut cells, (&(entity, _), list)| {
+                    for &cell in list {
+                        cells.entry(cell).or_default().push(entity);
                     }
+                    cells
+                },
+            )
+            .reduce(HashMap::default, |mut a, mut b| {
+                if a.len() < b.len() {
+                    std::mem::swap(&mut a, &mut b);
+                }
+                for (cell, mut bucket) in b {
+                    a.entry(cell).or_default().append(&mut bucket);
                 }
+                a
+            });
+        let mut buckets: Vec<&mut Vec<Entity>> = cells.values_mut().collect();
+        buckets
+            .par_iter_mut()
+            .for_each(|bucket| bucket.sort_unstable());
+
+        let mut coarse_cells = HashMap::default();
+        if self.coarse_ratio > 1 {
+            for &cell in cells.keys() {
+                *coarse_cells.entry(self.coarse_coords(cell)).or_default() += 1;
+            }
+        }
+        self.cells = cells;
+        self.coarse_cells = coarse_cells;
+        self.entities = entries
+            .iter()
+            .map(|&(entity, _)| entity)
+            .zip(cell_lists)
+            .collect();
+        self.rects = entries.iter().copied().collect();
+    }
+
+    /// Moves (or inserts) every entry, one `move_entity` at a time when few things moved and
+    /// through `par_rebuild` when a large share of the hash did.
+    pub fn apply_moves(&mut self, moves: Vec<(Entity, Rectangle)>) -> HashUpdatePath {
+        let threshold = self.len() as f32 * PAR_REBUILD_FRACTION;
+        if moves.len() < PAR_REBUILD_MIN || (moves.len() as f32) <= threshold {
+            for (entity, rect) in moves {
+                self.move_entity(entity, rect);
             }
+            return HashUpdatePath::Incremental;
         }
 
-        self.insert(entity, new_rect);
+        let mut rects = std::mem::take(&mut self.rects);
+        rects.extend(moves);
+        let entries: Vec<(Entity, Rectangle)> = rects.into_iter().collect();
+        self.par_rebuild(&entries);
+        HashUpdatePath::ParallelRebuild
     }
 
     pub fn query(&self, query_rect: Rectangle) -> HashSet<Entity> {
+        self.query_excluding(query_rect, &[])
+    }
+
+    /// Like `query`, minus the entities that only share a cell with `query_rect` without
+    /// their own rect overlapping it.
+    pub fn query_strict(&self, query_rect: Rectangle) -> HashSet<Entity> {
+        self.query_strict_excluding(query_rect, &[])
+    }
+
+    /// `query_excluding` with the same narrow filter as `query_strict`.
+    pub fn query_strict_excluding(
+        &self,
+        query_rect: Rectangle,
+        exclude: &[Entity],
+    ) -> HashSet<Entity> {
+        let mut found = self.query_excluding(query_rect, exclude);
+        found.retain(|entity| {
+            self.rects
+                .get(entity)
+                .is_some_and(|rect| rect.collides_rect(&query_rect))
+        });
+        found
+    }
+
+    /// Same as `query` but leaves out `exclude`, which is expected to be tiny
+    /// (usually the querying entity).
+    pub fn query_excluding(&self, query_rect: Rectangle, exclude: &[Entity]) -> HashSet<Entity> {
         #[cfg(feature = "trace")]
         let _span = info_span!("spatial_hash_query").entered();
         let (min_cx, min_cy) = self.cell_coords(query_rect.x, query_rect.y);
@@ -89,16 +437,42 @@ impl SpatialHash {
         let avg_per_cell = self.entities.len() / self.cells.len().max(1);
         let capacity = num_cells * avg_per_cell;
 
-        let mut found = HashSet::with_capacity(capacity);
-        for cy in min_cy..=max_cy {
-            for cx in min_cx..=max_cx {
-                if let Some(bucket) = self.cells.get(&(cx, cy)) {
-                    found.extend(bucket);
-                }
-            }
-        }
+        let mut found = HashSet::with_capacity_and_hasher(capacity, Default::default());
+        found.extend(self.query_iter(query_rect).filter(|e| !exclude.contains(e)));
         found
     }
+
+    /// Walks the buckets overlapping `query_rect` without allocating.
+    /// Entities spanning several cells are yielded once per cell.
+    pub fn query_iter(&self, query_rect: Rectangle) -> impl Iterator<Item = Entity> + '_ {
+        let (min_cx, min_cy) = self.cell_coords(query_rect.x, query_rect.y);
+        let (max_cx, max_cy) = self.cell_coords(
+            query_rect.x + query_rect.width,
+            query_rect.y + query_rect.height,
+        );
+        // With a flat hash every coarse cell is a single fine cell
+        let ratio = self.coarse_ratio.max(1);
+
+        (min_cy.div_euclid(ratio)..=max_cy.div_euclid(ratio))
+            .flat_map(move |ccy| {
+                (min_cx.div_euclid(ratio)..=max_cx.div_euclid(ratio)).map(move |ccx| (ccx, ccy))
+            })
+            .filter(move |coarse| ratio == 1 || self.coarse_cells.contains_key(coarse))
+            .flat_map(move |(ccx, ccy)| {
+                // Fine cells of this coarse cell that are inside the query
+                let (x0, x1) = (min_cx.max(ccx * ratio), max_cx.min(ccx * ratio + ratio - 1));
+                let (y0, y1) = (min_cy.max(ccy * ratio), max_cy.min(ccy * ratio + ratio - 1));
+                (y0..=y1).flat_map(move |cy| (x0..=x1).map(move |cx| (cx, cy)))
+            })
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+/// Whether more than half of `capacity` goes unused.
+fn mostly_unused(capacity: usize, len: usize) -> bool {
+    capacity > 2 * len
 }
 
 #[cfg(test)]
@@ -107,7 +481,7 @@ mod tests {
 
     #[test]
     fn test_spatial_hash() {
-        let mut spatial_hash = SpatialHash::new(10.0);
+        let mut spatial_hash = SpatialHash::flat(10.0);
         assert_eq!(spatial_hash.cell_coords(5.0, 5.0), (0, 0));
         assert_eq!(spatial_hash.cell_coords(15.0, 5.0), (1, 0));
         assert_eq!(spatial_hash.cell_coords(-5.0, -5.0), (-1, -1));
@@ -154,4 +528,615 @@ mod tests {
 
         assert_eq!(e.len(), 0);
     }
+
+    #[test]
+    fn test_two_level_matches_flat() {
+        let mut flat = SpatialHash::flat(10.0);
+        let mut two_level = SpatialHash::new(40.0, 10.0);
+        let mut world = bevy_ecs::world::World::new();
+
+        let rects = [
+            (0.0, 0.0, 5.0, 5.0),
+            (35.0, 35.0, 10.0, 10.0),
+            (-55.0, 12.0, 30.0, 2.0),
+            (400.0, -400.0, 1.0, 1.0),
+        ];
+        let entities: Vec<_> = rects
+            .iter()
+            .map(|&(x, y, width, height)| {
+                let entity = world.spawn(()).id();
+                let rect = Rectangle {
+                    x,
+                    y,
+                    width,
+                    height,
+                };
+                flat.insert(entity, rect);
+                two_level.insert(entity, rect);
+                entity
+            })
+            .collect();
+
+        let queries = [
+            (-100.0, -100.0, 200.0, 200.0),
+            (30.0, 30.0, 8.0, 8.0),
+            (-1000.0, -1000.0, 2000.0, 2000.0),
+            (100.0, 100.0, 50.0, 50.0),
+        ];
+        for (x, y, width, height) in queries {
+            let rect = Rectangle {
+                x,
+                y,
+                width,
+                height,
+            };
+            assert_eq!(flat.query(rect), two_level.query(rect));
+        }
+
+        // Moving everything out of a coarse cell forgets it
+        two_level.update(
+            entities[3],
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 1.0,
+                height: 1.0,
+            },
+        );
+        assert!(!two_level.coarse_cells.contains_key(&(10, -10)));
+        assert_eq!(two_level.coarse_cells[&(0, 0)], 2);
+    }
+
+    #[test]
+    fn test_move_entity() {
+        let mut spatial_hash = SpatialHash::flat(10.0);
+        let mut world = bevy_ecs::world::World::new();
+        let entity = world.spawn(()).id();
+
+        let mut rect = Rectangle {
+            x: 1.0,
+            y: 1.0,
+            width: 12.0,
+            height: 5.0,
+        };
+        spatial_hash.insert(entity, rect);
+        assert_eq!(spatial_hash.entities[&entity], vec![(0, 0), (1, 0)]);
+
+        // Same cells
+        rect.x += 1.0;
+        spatial_hash.move_entity(entity, rect);
+        assert_eq!(spatial_hash.entities[&entity], vec![(0, 0), (1, 0)]);
+
+        // Crosses into the next column
+        rect.x += 8.0;
+        spatial_hash.move_entity(entity, rect);
+        assert_eq!(spatial_hash.entities[&entity], vec![(1, 0), (2, 0)]);
+        assert!(!spatial_hash.cells.contains_key(&(0, 0)));
+        assert_eq!(spatial_hash.cells[&(2, 0)], vec![entity]);
+    }
+
+    /// `cargo test --release bench_move_entity -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_move_entity_vs_update() {
+        const COUNT: usize = 100_000;
+        const TICKS: usize = 60;
+        let mut world = bevy_ecs::world::World::new();
+        let entities: Vec<Entity> = (0..COUNT).map(|_| world.spawn(()).id()).collect();
+        let rect_at = |i: usize, tick: usize| Rectangle {
+            x: 35.0 * (i % 100) as f32 + tick as f32,
+            y: 35.0 * (i / 100) as f32,
+            width: 32.0,
+            height: 32.0,
+        };
+
+        // A pixel per tick, most moves stay inside the cells they already cover
+        for (name, fast) in [("update", false), ("move_entity", true)] {
+            let mut spatial_hash = SpatialHash::flat(96.0);
+            for (i, &entity) in entities.iter().enumerate() {
+                spatial_hash.insert(entity, rect_at(i, 0));
+            }
+            let start = std::time::Instant::now();
+            for tick in 1..=TICKS {
+                for (i, &entity) in entities.iter().enumerate() {
+                    match fast {
+                        true => spatial_hash.move_entity(entity, rect_at(i, tick)),
+                        false => spatial_hash.update(entity, rect_at(i, tick)),
+                    }
+                }
+            }
+            println!("{name}: {:?} for {TICKS} ticks", start.elapsed());
+        }
+    }
+
+    #[test]
+    fn test_rebuild() {
+        let mut spatial_hash = SpatialHash::flat(10.0);
+        let mut world = bevy_ecs::world::World::new();
+        let a = world.spawn(()).id();
+        let b = world.spawn(()).id();
+
+        let old_rect = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 5.0,
+            height: 5.0,
+        };
+        let new_rect = Rectangle {
+            x: 50.0,
+            y: 50.0,
+            width: 5.0,
+            height: 5.0,
+        };
+        spatial_hash.insert(a, old_rect);
+
+        spatial_hash.rebuild([(b, new_rect)].into_iter());
+
+        assert!(spatial_hash.query(old_rect).is_empty());
+        assert!(!spatial_hash.contains(a));
+        let e = spatial_hash.query(new_rect);
+        assert_eq!(e.len(), 1);
+        assert!(e.contains(&b));
+    }
+
+    /// `cargo test --release bench_rebuild -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_rebuild_vs_update() {
+        const COUNT: usize = 100_000;
+        let mut world = bevy_ecs::world::World::new();
+        let entities: Vec<Entity> = (0..COUNT).map(|_| world.spawn(()).id()).collect();
+        let rect_at = |i: usize, shift: f32| Rectangle {
+            x: 35.0 * (i % 100) as f32 + shift,
+            y: 35.0 * (i / 100) as f32,
+            width: 32.0,
+            height: 32.0,
+        };
+        let fresh = || {
+            let mut spatial_hash = SpatialHash::flat(96.0);
+            spatial_hash.insert_batch(
+                entities
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &entity)| (entity, rect_at(i, 0.0)))
+                    .collect(),
+            );
+            spatial_hash
+        };
+
+        // A cell or more for the movers, so every update changes buckets
+        for percent in [25, 50, 75, 100] {
+            let moved = COUNT * percent / 100;
+            let entries: Vec<(Entity, Rectangle)> = entities
+                .iter()
+                .enumerate()
+                .map(|(i, &e)| (e, rect_at(i, if i < moved { 100.0 } else { 0.0 })))
+                .collect();
+
+            let mut spatial_hash = fresh();
+            let start = std::time::Instant::now();
+            for &(entity, rect) in &entries[..moved] {
+                spatial_hash.update(entity, rect);
+            }
+            let updated = start.elapsed();
+
+            let mut spatial_hash = fresh();
+            let start = std::time::Instant::now();
+            spatial_hash.rebuild(entries.iter().copied());
+            println!(
+                "{percent}% moved: update {updated:?}, rebuild {:?}",
+                start.elapsed()
+            );
+        }
+    }
+
+    #[test]
+    fn test_query_iter() {
+        let mut spatial_hash = SpatialHash::flat(10.0);
+        let mut world = bevy_ecs::world::World::new();
+        let entity = world.spawn(()).id();
+
+        // Spans two cells
+        spatial_hash.insert(
+            entity,
+            Rectangle {
+                x: 5.0,
+                y: 0.0,
+                width: 10.0,
+                height: 5.0,
+            },
+        );
+
+        let query_rect = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 15.0,
+            height: 5.0,
+        };
+        assert_eq!(spatial_hash.query_iter(query_rect).count(), 2);
+        assert!(spatial_hash.query_iter(query_rect).all(|e| e == entity));
+        assert_eq!(spatial_hash.query(query_rect).len(), 1);
+
+        let empty = Rectangle {
+            x: 100.0,
+            y: 100.0,
+            width: 1.0,
+            height: 1.0,
+        };
+        assert!(spatial_hash.query_iter(empty).next().is_none());
+    }
+
+    #[test]
+    fn test_query_excluding() {
+        let mut spatial_hash = SpatialHash::flat(10.0);
+        let mut world = bevy_ecs::world::World::new();
+        let a = world.spawn(()).id();
+        let b = world.spawn(()).id();
+
+        let rect = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            width: 15.0,
+            height: 5.0,
+        };
+        spatial_hash.insert(a, rect);
+        spatial_hash.insert(b, rect);
+
+        let e = spatial_hash.query_excluding(rect, &[a]);
+        assert_eq!(e.len(), 1);
+        assert!(e.contains(&b));
+
+        assert!(spatial_hash.query_excluding(rect, &[a, b]).is_empty());
+        assert_eq!(spatial_hash.query(rect).len(), 2);
+    }
+
+    #[test]
+    fn test_query_strict() {
+        let mut spatial_hash = SpatialHash::flat(96.0);
+        let mut world = bevy_ecs::world::World::new();
+        let rect = |x, y, size| Rectangle {
+            x,
+            y,
+            width: size,
+            height: size,
+        };
+        let a = world.spawn(()).id();
+        let b = world.spawn(()).id();
+        spatial_hash.insert(a, rect(0.0, 0.0, 10.0));
+        spatial_hash.insert(b, rect(20.0, 0.0, 10.0));
+
+        // Same cell, but clear of both rects
+        let corner = rect(90.0, 90.0, 1.0);
+        assert_eq!(spatial_hash.query(corner).len(), 2);
+        assert!(spatial_hash.query_strict(corner).is_empty());
+
+        let over_b = rect(25.0, 5.0, 1.0);
+        assert_eq!(spatial_hash.query_strict(over_b), HashSet::from_iter([b]));
+        assert!(spatial_hash.query_strict_excluding(over_b, &[b]).is_empty());
+        // Touching edges don't count as overlapping
+        assert!(spatial_hash.query_strict(rect(10.0, 0.0, 10.0)).is_empty());
+    }
+
+    /// `cargo test --release bench_query -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_query_vs_query_strict() {
+        use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+        // Same layout as the demo grid: 32px boxes 35px apart, 100 per row
+        let mut spatial_hash = SpatialHash::flat(96.0);
+        let mut world = bevy_ecs::world::World::new();
+        let mut rng = SmallRng::seed_from_u64(42);
+        for i in 0..100_000 {
+            let (row, column) = ((i / 100) as f32, (i % 100) as f32);
+            let rect = Rectangle {
+                x: 200.0 + 35.0 * column + rng.gen_range(0.0..3.0),
+                y: 100.0 + 35.0 * row + rng.gen_range(0.0..3.0),
+                width: 32.0,
+                height: 32.0,
+            };
+            spatial_hash.insert(world.spawn(()).id(), rect);
+        }
+        // Mover sized queries spread over the grid
+        let queries: Vec<Rectangle> = (0..10_000)
+            .map(|_| Rectangle {
+                x: rng.gen_range(200.0..3700.0),
+                y: rng.gen_range(100.0..35000.0),
+                width: 37.0,
+                height: 37.0,
+            })
+            .collect();
+
+        for (name, strict) in [("query", false), ("query_strict", true)] {
+            let start = std::time::Instant::now();
+            let mut found = 0;
+            for query in &queries {
+                found += match strict {
+                    true => spatial_hash.query_strict(*query).len(),
+                    false => spatial_hash.query(*query).len(),
+                };
+            }
+            println!("{name}: {:?}, {found} entities", start.elapsed());
+        }
+    }
+
+    #[test]
+    fn test_set_cell_size() {
+        let mut world = bevy_ecs::world::World::new();
+        let rect = |x, y, size| Rectangle {
+            x,
+            y,
+            width: size,
+            height: size,
+        };
+
+        let mut spatial_hash = SpatialHash::new(40.0, 10.0);
+        let mut rects: Vec<_> = (0..20)
+            .map(|i| {
+                let entity = world.spawn(()).id();
+                let (x, y) = ((i % 5) as f32 * 23.0 - 40.0, (i / 5) as f32 * 31.0);
+                (entity, rect(x, y, 8.0 + i as f32))
+            })
+            .collect();
+        for &(entity, r) in &rects {
+            spatial_hash.insert(entity, r);
+        }
+        // Moves inside the same cells must still be remembered
+        rects[0].1 = rect(-39.0, 1.0, 8.0);
+        spatial_hash.move_entity(rects[0].0, rects[0].1);
+        let entities: Vec<_> = rects.iter().map(|&(entity, _)| entity).collect();
+
+        let queries = [
+            rect(-50.0, -10.0, 30.0),
+            rect(0.0, 0.0, 5.0),
+            rect(20.0, 40.0, 60.0),
+            rect(-100.0, -100.0, 500.0),
+        ];
+        let before: Vec<_> = queries.iter().map(|q| spatial_hash.query(*q)).collect();
+        let overlapping = |q: &Rectangle| -> HashSet<Entity> {
+            rects
+                .iter()
+                .filter(|(_, r)| {
+                    r.x <= q.x + q.width
+                        && q.x <= r.x + r.width
+                        && r.y <= q.y + q.height
+                        && q.y <= r.y + r.height
+                })
+                .map(|&(entity, _)| entity)
+                .collect()
+        };
+
+        for cell_size in [5.0, 64.0, 13.0] {
+            spatial_hash.set_cell_size(cell_size);
+            assert_eq!(spatial_hash.len(), entities.len());
+            for query in &queries {
+                // Broad phase may return extra candidates, but never misses a real overlap
+                let found = spatial_hash.query(*query);
+                assert!(
+                    overlapping(query).is_subset(&found),
+                    "cell size {cell_size}"
+                );
+            }
+        }
+
+        spatial_hash.set_cell_size(10.0);
+        for (query, expected) in queries.iter().zip(&before) {
+            assert_eq!(&spatial_hash.query(*query), expected);
+        }
+
+        // Removed entities don't come back
+        spatial_hash.remove(entities[1]);
+        spatial_hash.set_cell_size(20.0);
+        assert!(!spatial_hash.contains(entities[1]));
+        assert_eq!(spatial_hash.len(), entities.len() - 1);
+    }
+
+    #[test]
+    fn test_iteration_ignores_insertion_order() {
+        let mut world = bevy_ecs::world::World::new();
+        let entries: Vec<_> = (0..50)
+            .map(|i| {
+                let rect = Rectangle {
+                    x: (i % 7) as f32 * 13.0 - 40.0,
+                    y: (i / 7) as f32 * 11.0 - 20.0,
+                    width: 5.0 + (i % 4) as f32 * 6.0,
+                    height: 8.0,
+                };
+                (world.spawn(()).id(), rect)
+            })
+            .collect();
+        let mut forward = SpatialHash::new(40.0, 10.0);
+        let mut backward = SpatialHash::new(40.0, 10.0);
+        for &(entity, rect) in &entries {
+            forward.insert(entity, rect);
+        }
+        for &(entity, rect) in entries.iter().rev() {
+            backward.insert(entity, rect);
+        }
+        assert_eq!((forward.len(), forward.is_empty()), (50, false));
+
+        let entities = |hash: &SpatialHash| -> Vec<_> {
+            hash.iter_entities()
+                .map(|(e, r)| (e, r.x, r.y, r.width))
+                .collect()
+        };
+        let cells = |hash: &SpatialHash| -> Vec<_> {
+            hash.iter_cells()
+                .map(|(cell, bucket)| (cell, bucket.to_vec()))
+                .collect()
+        };
+        assert_eq!(entities(&forward), entities(&backward));
+        assert_eq!(cells(&forward), cells(&backward));
+
+        let ids: Vec<Entity> = forward.iter_entities().map(|(e, _)| e).collect();
+        assert!(ids.is_sorted());
+        assert_eq!(ids.len(), entries.len());
+        let coords: Vec<(i32, i32)> = forward.iter_cells().map(|(cell, _)| cell).collect();
+        assert!(coords.is_sorted());
+        assert!(forward.iter_cells().all(|(_, bucket)| bucket.is_sorted()));
+    }
+
+    #[test]
+    fn test_compact_releases_emptied_cells() {
+        use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+        let mut world = bevy_ecs::world::World::new();
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut hash = SpatialHash::new(80.0, 20.0);
+        let entities: Vec<Entity> = (0..1000).map(|_| world.spawn(()).id()).collect();
+        for &entity in &entities {
+            let rect = Rectangle {
+                x: rng.gen_range(-2000.0..2000.0),
+                y: rng.gen_range(-2000.0..2000.0),
+                width: rng.gen_range(0.0..40.0),
+                height: rng.gen_range(0.0..40.0),
+            };
+            hash.insert(entity, rect);
+        }
+        let full_capacity = hash.cells.capacity();
+        for &entity in &entities {
+            hash.remove(entity);
+        }
+        // Emptied, but still holding on to the room for a thousand entities
+        assert_eq!(hash.cells.capacity(), full_capacity);
+
+        hash.compact();
+        assert_eq!(hash.cells.len(), 0);
+        assert!(hash.coarse_cells.is_empty() && hash.is_empty());
+        assert!(hash.cells.capacity() < full_capacity);
+        assert!(hash.entities.capacity() < entities.len());
+
+        // Still works afterwards
+        hash.insert(
+            entities[0],
+            Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 10.0,
+                height: 10.0,
+            },
+        );
+        assert_eq!(hash.cell_count(), 1);
+
+        // A hash still mostly in use keeps its room
+        for &entity in &entities[1..] {
+            hash.insert(
+                entity,
+                Rectangle {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 10.0,
+                    height: 10.0,
+                },
+            );
+        }
+        for &entity in &entities[..100] {
+            hash.remove(entity);
+        }
+        let capacity = hash.entities.capacity();
+        hash.compact();
+        assert_eq!(hash.entities.capacity(), capacity);
+    }
+
+    #[test]
+    fn test_par_rebuild_matches_serial_updates() {
+        use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+        let mut world = bevy_ecs::world::World::new();
+        let mut rng = SmallRng::seed_from_u64(3);
+        let random_rect = |rng: &mut SmallRng| Rectangle {
+            x: rng.gen_range(-500.0..500.0),
+            y: rng.gen_range(-500.0..500.0),
+            width: rng.gen_range(0.0..60.0),
+            height: rng.gen_range(0.0..60.0),
+        };
+        let entities: Vec<Entity> = (0..3000).map(|_| world.spawn(()).id()).collect();
+
+        for (coarse, fine) in [(10.0, 10.0), (80.0, 20.0)] {
+            let mut serial = SpatialHash::new(coarse, fine);
+            let mut parallel = SpatialHash::new(coarse, fine);
+            for &entity in &entities[..2000] {
+                serial.insert(entity, random_rect(&mut rng));
+            }
+            parallel.par_rebuild(
+                &serial
+                    .iter_entities()
+                    .map(|(e, r)| (e, *r))
+                    .collect::<Vec<_>>(),
+            );
+
+            // Most of them move and a thousand more show up
+            let mut moves = Vec::new();
+            for &entity in &entities {
+                if rng.gen_bool(0.8) {
+                    moves.push((entity, random_rect(&mut rng)));
+                }
+            }
+            for &(entity, rect) in &moves {
+                serial.move_entity(entity, rect);
+            }
+            assert_eq!(parallel.apply_moves(moves), HashUpdatePath::ParallelRebuild);
+
+            let cells = |hash: &SpatialHash| -> Vec<_> {
+                hash.iter_cells()
+                    .map(|(cell, bucket)| (cell, bucket.to_vec()))
+                    .collect()
+            };
+            let stored = |hash: &SpatialHash| -> Vec<_> {
+                hash.iter_entities()
+                    .map(|(e, r)| (e, r.x, r.y, r.width, r.height, hash.entities[&e].clone()))
+                    .collect()
+            };
+            assert_eq!(cells(&parallel), cells(&serial));
+            assert_eq!(stored(&parallel), stored(&serial));
+            let coarse_cells = |hash: &SpatialHash| -> Vec<_> {
+                let mut counts: Vec<_> = hash.coarse_cells.iter().map(|(&c, &n)| (c, n)).collect();
+                counts.sort_unstable();
+                counts
+            };
+            assert_eq!(coarse_cells(&parallel), coarse_cells(&serial));
+        }
+    }
+
+    #[test]
+    fn test_apply_moves_stays_incremental_for_small_batches() {
+        let mut world = bevy_ecs::world::World::new();
+        let mut hash = SpatialHash::flat(10.0);
+        let rect = |x| Rectangle {
+            x,
+            y: 0.0,
+            width: 5.0,
+            height: 5.0,
+        };
+        let entities: Vec<Entity> = (0..PAR_REBUILD_MIN * 8)
+            .map(|i| {
+                let entity = world.spawn(()).id();
+                hash.insert(entity, rect(i as f32));
+                entity
+            })
+            .collect();
+
+        // Under the minimum, even though it's all of a tiny hash's worth
+        let few = entities[..PAR_REBUILD_MIN - 1]
+            .iter()
+            .map(|&e| (e, rect(-100.0)));
+        assert_eq!(hash.apply_moves(few.collect()), HashUpdatePath::Incremental);
+        // Over the minimum but under the fraction
+        let some = entities[..PAR_REBUILD_MIN * 2]
+            .iter()
+            .map(|&e| (e, rect(-200.0)));
+        assert_eq!(
+            hash.apply_moves(some.collect()),
+            HashUpdatePath::Incremental
+        );
+        let most = entities[..PAR_REBUILD_MIN * 4]
+            .iter()
+            .map(|&e| (e, rect(-300.0)));
+        assert_eq!(
+            hash.apply_moves(most.collect()),
+            HashUpdatePath::ParallelRebuild
+        );
+        assert_eq!(hash.len(), entities.len());
+        assert_eq!(hash.query(rect(-300.0)).len(), PAR_REBUILD_MIN * 4);
+    }
 }
@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+
+use bevy_ecs::{entity::Entity, resource::Resource};
+use rustyray::prelude::Rectangle;
+#[cfg(feature = "trace")]
+use tracing::info_span;
+
+use crate::utils::HashMap;
+
+/// Sort and sweep broad phase, entries are kept sorted by the left edge of their bounds.
+/// Handles dense clusters in empty space better than a grid since there are no cells.
+#[derive(Default)]
+pub struct SweepPrune {
+    entries: Vec<(Entity, Rectangle)>,
+    positions: HashMap<Entity, usize>,
+    /// Entries `remove` left behind as `Entity::PLACEHOLDER`, dropped by the next `rebuild`
+    /// or once they make up half the list.
+    removed: usize,
+    /// Widest rect stored, bounds how far left of a query an overlapping entry can start.
+    /// Only grows until the next `rebuild`.
+    max_width: f32,
+}
+
+/// Alternative to `CollisionHash`, kept up to date while `BroadphaseMethod::SweepPrune` is used.
+#[derive(Default, Resource)]
+pub struct CollisionSweep(pub SweepPrune);
+
+impl std::ops::Deref for CollisionSweep {
+    type Target = SweepPrune;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for CollisionSweep {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// Which broad phase `apply_velocity_system` queries.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BroadphaseMethod {
+    #[default]
+    SpatialHash,
+    SweepPrune,
+}
+
+impl SweepPrune {
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.removed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.positions.contains_key(&entity)
+    }
+
+    /// Inserts or moves `entity`. Moves only swap with neighbours, so small moves are cheap.
+    pub fn move_entity(&mut self, entity: Entity, rect: Rectangle) {
+        self.max_width = self.max_width.max(rect.width);
+        let index = match self.positions.get(&entity) {
+            Some(&index) => {
+                self.entries[index].1 = rect;
+                index
+            }
+            None => {
+                self.entries.push((entity, rect));
+                self.positions.insert(entity, self.entries.len() - 1);
+                self.entries.len() - 1
+            }
+        };
+
+        // Insertion sort step in whichever direction the entry moved
+        let mut i = index;
+        while i > 0 && self.entries[i - 1].1.x > self.entries[i].1.x {
+            self.swap(i - 1, i);
+            i -= 1;
+        }
+        while i + 1 < self.entries.len() && self.entries[i + 1].1.x < self.entries[i].1.x {
+            self.swap(i, i + 1);
+            i += 1;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        for i in [a, b] {
+            let entity = self.entries[i].0;
+            if entity != Entity::PLACEHOLDER {
+                self.positions.insert(entity, i);
+            }
+        }
+    }
+
+    /// Leaves a placeholder in the slot instead of shifting everything after it, the rect stays
+    /// so the order holds. Placeholders are dropped in one pass once they're half the list.
+    pub fn remove(&mut self, entity: Entity) {
+        let Some(index) = self.positions.remove(&entity) else {
+            return;
+        };
+        self.entries[index].0 = Entity::PLACEHOLDER;
+        self.removed += 1;
+        if self.removed * 2 > self.entries.len() {
+            self.compact();
+        }
+    }
+
+    fn compact(&mut self) {
+        self.entries.retain(|(e, _)| *e != Entity::PLACEHOLDER);
+        self.removed = 0;
+        self.positions.clear();
+        self.positions
+            .extend(self.entries.iter().enumerate().map(|(i, (e, _))| (*e, i)));
+    }
+
+    /// Replaces everything with `entities` and sorts once, much cheaper than many
+    /// `move_entity` calls when most things are new.
+    pub fn rebuild(&mut self, entities: impl Iterator<Item = (Entity, Rectangle)>) {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("sweep_prune_rebuild").entered();
+        self.entries.clear();
+        self.entries.extend(entities);
+        self.entries.sort_by(|a, b| a.1.x.total_cmp(&b.1.x));
+        self.removed = 0;
+        self.positions.clear();
+        self.positions
+            .extend(self.entries.iter().enumerate().map(|(i, (e, _))| (*e, i)));
+        self.max_width = self
+            .entries
+            .iter()
+            .map(|(_, r)| r.width)
+            .fold(0.0, f32::max);
+    }
+
+    pub fn query(&self, query_rect: Rectangle) -> Vec<Entity> {
+        self.query_excluding(query_rect, &[])
+    }
+
+    /// Entities whose bounds overlap or touch `query_rect`, same edge rules as `SpatialHash`.
+    pub fn query_excluding(&self, query_rect: Rectangle, exclude: &[Entity]) -> Vec<Entity> {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("sweep_prune_query").entered();
+        let min_x = query_rect.x;
+        let max_x = query_rect.x + query_rect.width;
+        // Anything starting further left than this is too narrow to reach the query
+        let start = self
+            .entries
+            .partition_point(|(_, r)| r.x < min_x - self.max_width);
+
+        self.entries[start..]
+            .iter()
+            .take_while(|(_, r)| r.x <= max_x)
+            .filter(|(entity, r)| {
+                *entity != Entity::PLACEHOLDER
+                    && r.x + r.width >= min_x
+                    && r.y <= query_rect.y + query_rect.height
+                    && r.y + r.height >= query_rect.y
+                    && !exclude.contains(entity)
+            })
+            .map(|(entity, _)| *entity)
+            .collect()
+    }
+
+    /// Every overlapping pair, each reported once. `O(n + k)` on top of the sort.
+    pub fn overlapping_pairs(&self) -> Vec<(Entity, Entity)> {
+        let mut pairs = Vec::new();
+        for (i, (a, ra)) in self.entries.iter().enumerate() {
+            if *a == Entity::PLACEHOLDER {
+                continue;
+            }
+            for (b, rb) in self.entries[i + 1..]
+                .iter()
+                .take_while(|(_, rb)| rb.x <= ra.x + ra.width)
+            {
+                if *b != Entity::PLACEHOLDER && rb.y <= ra.y + ra.height && rb.y + rb.height >= ra.y
+                {
+                    pairs.push((*a, *b));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial_hash::SpatialHash;
+    use crate::utils::HashSet;
+
+    fn rect(x: f32, y: f32, width: f32, height: f32) -> Rectangle {
+        Rectangle {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn overlaps(a: &Rectangle, b: &Rectangle) -> bool {
+        a.x <= b.x + b.width
+            && b.x <= a.x + a.width
+            && a.y <= b.y + b.height
+            && b.y <= a.y + a.height
+    }
+
+    #[test]
+    fn test_query_matches_brute_force() {
+        let mut world = bevy_ecs::world::World::new();
+        // A dense cluster plus a few far away and one very wide entry
+        let mut rects: Vec<(Entity, Rectangle)> = (0..40)
+            .map(|i| {
+                let (x, y) = ((i * 7 % 13) as f32 * 3.0, (i * 5 % 11) as f32 * 4.0);
+                (world.spawn(()).id(), rect(x, y, 6.0, 6.0))
+            })
+            .collect();
+        rects.push((world.spawn(()).id(), rect(5000.0, 0.0, 10.0, 10.0)));
+        rects.push((world.spawn(()).id(), rect(-300.0, 20.0, 400.0, 2.0)));
+
+        let mut sweep = SweepPrune::default();
+        for &(entity, r) in rects.iter().rev() {
+            sweep.move_entity(entity, r);
+        }
+        let mut rebuilt = SweepPrune::default();
+        rebuilt.rebuild(rects.iter().copied());
+
+        let queries = [
+            rect(0.0, 0.0, 10.0, 10.0),
+            rect(20.0, 15.0, 3.0, 3.0),
+            rect(-200.0, 21.0, 1.0, 1.0),
+            rect(4990.0, 5.0, 20.0, 1.0),
+            rect(1000.0, 1000.0, 5.0, 5.0),
+        ];
+        for query in queries {
+            let expected: HashSet<Entity> = rects
+                .iter()
+                .filter(|(_, r)| overlaps(r, &query))
+                .map(|(e, _)| *e)
+                .collect();
+            let found: HashSet<Entity> = sweep.query(query).into_iter().collect();
+            assert_eq!(found, expected);
+            let found: HashSet<Entity> = rebuilt.query(query).into_iter().collect();
+            assert_eq!(found, expected);
+        }
+
+        // Exact overlaps are a subset of what the grid hands out
+        let mut hash = SpatialHash::flat(16.0);
+        for &(entity, r) in &rects {
+            hash.insert(entity, r);
+        }
+        for query in queries {
+            let grid = hash.query(query);
+            assert!(sweep.query(query).iter().all(|e| grid.contains(e)));
+        }
+    }
+
+    #[test]
+    fn test_move_and_remove_keep_order() {
+        let mut world = bevy_ecs::world::World::new();
+        let entities: Vec<Entity> = (0..5).map(|_| world.spawn(()).id()).collect();
+        let mut sweep = SweepPrune::default();
+        for (i, &entity) in entities.iter().enumerate() {
+            sweep.move_entity(entity, rect(i as f32 * 10.0, 0.0, 5.0, 5.0));
+        }
+
+        // Jump the first one to the far end
+        sweep.move_entity(entities[0], rect(100.0, 0.0, 5.0, 5.0));
+        assert!(sweep.entries.windows(2).all(|w| w[0].1.x <= w[1].1.x));
+        assert_eq!(sweep.query(rect(0.0, 0.0, 2.0, 2.0)), Vec::<Entity>::new());
+        assert_eq!(sweep.query(rect(101.0, 1.0, 1.0, 1.0)), vec![entities[0]]);
+        assert_eq!(
+            sweep.query_excluding(rect(100.0, 0.0, 1.0, 1.0), &[entities[0]]),
+            Vec::<Entity>::new()
+        );
+
+        sweep.remove(entities[2]);
+        assert_eq!(sweep.len(), 4);
+        assert!(!sweep.contains(entities[2]));
+        for (entity, &index) in &sweep.positions {
+            assert_eq!(sweep.entries[index].0, *entity);
+        }
+        assert_eq!(sweep.query(rect(20.0, 0.0, 1.0, 1.0)), Vec::<Entity>::new());
+        // Still sorted with the placeholder in its slot, and moves can swap past it
+        sweep.move_entity(entities[1], rect(25.0, 0.0, 5.0, 5.0));
+        assert!(sweep.entries.windows(2).all(|w| w[0].1.x <= w[1].1.x));
+        assert!(!sweep.positions.contains_key(&Entity::PLACEHOLDER));
+        assert_eq!(sweep.query(rect(26.0, 0.0, 1.0, 1.0)), vec![entities[1]]);
+    }
+
+    #[test]
+    fn test_removals_compact_once_half_the_list() {
+        let mut world = bevy_ecs::world::World::new();
+        let entities: Vec<Entity> = (0..6).map(|_| world.spawn(()).id()).collect();
+        let mut sweep = SweepPrune::default();
+        for (i, &entity) in entities.iter().enumerate() {
+            sweep.move_entity(entity, rect(i as f32 * 10.0, 0.0, 15.0, 5.0));
+        }
+
+        sweep.remove(entities[1]);
+        sweep.remove(entities[3]);
+        sweep.remove(entities[3]);
+        assert_eq!((sweep.len(), sweep.entries.len()), (4, 6));
+        let pairs: HashSet<(Entity, Entity)> = sweep.overlapping_pairs().into_iter().collect();
+        assert!(pairs.iter().all(|&(a, b)| ![a, b].contains(&entities[1])));
+        assert_eq!(pairs.len(), 1);
+
+        // A fourth placeholder tips it over half, they're all dropped at once
+        sweep.remove(entities[0]);
+        sweep.remove(entities[5]);
+        assert_eq!((sweep.len(), sweep.entries.len()), (2, 2));
+        for (entity, &index) in &sweep.positions {
+            assert_eq!(sweep.entries[index].0, *entity);
+        }
+        assert_eq!(sweep.query(rect(0.0, 0.0, 100.0, 5.0)).len(), 2);
+    }
+
+    #[test]
+    fn test_overlapping_pairs() {
+        let mut world = bevy_ecs::world::World::new();
+        let a = world.spawn(()).id();
+        let b = world.spawn(()).id();
+        let c = world.spawn(()).id();
+        let d = world.spawn(()).id();
+
+        let mut sweep = SweepPrune::default();
+        sweep.rebuild(
+            [
+                (a, rect(0.0, 0.0, 10.0, 10.0)),
+                (b, rect(5.0, 5.0, 10.0, 10.0)),
+                // Overlaps a on x only
+                (c, rect(8.0, 50.0, 10.0, 10.0)),
+                (d, rect(14.0, 14.0, 2.0, 2.0)),
+            ]
+            .into_iter(),
+        );
+
+        let pairs: HashSet<(Entity, Entity)> = sweep.overlapping_pairs().into_iter().collect();
+        assert_eq!(pairs.len(), 2);
+        assert!(pairs.contains(&(a, b)));
+        assert!(pairs.contains(&(b, d)));
+    }
+}
@@ -0,0 +1,103 @@
+use std::any::{TypeId, type_name};
+use std::sync::Mutex;
+
+use bevy_ecs::prelude::*;
+
+use crate::utils::HashSet;
+
+/// Systems that had their turn this physics tick, by the type of the system function.
+/// Behind a lock because run conditions, which feed it, only get shared access. Only fed in
+/// debug builds, see `mark_ran` and `expect_ran`.
+#[derive(Resource, Default, Debug)]
+pub struct SystemOrderAssertion {
+    ran: Mutex<HashSet<TypeId>>,
+}
+
+impl SystemOrderAssertion {
+    fn record(&self, id: TypeId) {
+        self.ran.lock().unwrap().insert(id);
+    }
+
+    fn contains(&self, id: TypeId) -> bool {
+        self.ran.lock().unwrap().contains(&id)
+    }
+
+    pub fn has_run<S: 'static>(&self, _system: S) -> bool {
+        self.contains(TypeId::of::<S>())
+    }
+}
+
+/// Run condition that records `system` had its turn, `system.run_if(mark_ran(system))`.
+/// Always true, and bevy evaluates every condition, so a run skipped by another `run_if`
+/// still counts.
+pub fn mark_ran<S: 'static>(_system: S) -> impl Fn(Res<SystemOrderAssertion>) -> bool + Clone {
+    |order: Res<SystemOrderAssertion>| {
+        if cfg!(debug_assertions) {
+            order.record(TypeId::of::<S>());
+        }
+        true
+    }
+}
+
+/// Run condition on `dependent` asserting `dependency` had its turn earlier this tick,
+/// `dependent.run_if(expect_ran(dependency, dependent))`. Always true.
+pub fn expect_ran<D: 'static, S: 'static>(
+    _dependency: D,
+    _dependent: S,
+) -> impl Fn(Res<SystemOrderAssertion>) -> bool + Clone {
+    |order: Res<SystemOrderAssertion>| {
+        debug_assert!(
+            order.contains(TypeId::of::<D>()),
+            "{} is about to run before {}",
+            type_name::<S>(),
+            type_name::<D>()
+        );
+        true
+    }
+}
+
+/// First thing every physics tick.
+pub fn reset_system_order_system(order: Res<SystemOrderAssertion>) {
+    order.ran.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn produce() {}
+    fn consume() {}
+
+    fn never() -> bool {
+        false
+    }
+
+    fn run(produce_first: bool) -> World {
+        let mut world = World::new();
+        world.init_resource::<SystemOrderAssertion>();
+        let producer = produce.run_if(never).run_if(mark_ran(produce));
+        let consumer = consume.run_if(expect_ran(produce, consume));
+        let mut schedule = Schedule::default();
+        match produce_first {
+            true => schedule.add_systems((reset_system_order_system, producer, consumer).chain()),
+            false => schedule.add_systems((reset_system_order_system, consumer, producer).chain()),
+        };
+        schedule.run(&mut world);
+        world
+    }
+
+    #[test]
+    fn test_dependency_in_order_passes() {
+        let world = run(true);
+        // Skipped by its other condition, but it still had its turn
+        assert!(world.resource::<SystemOrderAssertion>().has_run(produce));
+        assert!(!world.resource::<SystemOrderAssertion>().has_run(consume));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "is about to run before")]
+    fn test_dependency_out_of_order_panics() {
+        run(false);
+    }
+}
@@ -1,22 +1,42 @@
+use bevy_ecs::archetype::Archetypes;
+use bevy_ecs::component::Components;
 use bevy_ecs::prelude::*;
 use rustyray::prelude::*;
 
+use crate::assets::TextureAssets;
+use crate::bake::BakedStaticColliders;
+use crate::camera_utils::{CULLING_MARGIN, camera_viewport_rect, grow_rect, screen_to_world};
 use crate::components::*;
+use crate::extract::{DrawKey, compare_draw_keys};
+use crate::input::{Action, InputState};
 use crate::resources::*;
-use crate::spatial_hash::SpatialHash;
+use crate::spatial_hash::{CollisionHash, RenderHash, SpatialHash};
+use crate::sweep_prune::{BroadphaseMethod, CollisionSweep};
+use crate::tileset::TilesetResource;
+use crate::timings::metric_scope;
+use crate::utils::HashSet;
 #[cfg(feature = "trace")]
 use tracing::info_span;
 
 // Iterative system for propagating GlobalTransforms
+/// Roots marked `StaticTransform` are skipped unless their `Transform` changed since the last
+/// run, so a static entity that gets moved is still picked up before it's demoted.
 #[allow(clippy::type_complexity)]
 pub fn update_global_transforms_system(
-    mut parents: Query<(&mut GlobalTransform, &Transform, Option<&Children>), Without<ChildOf>>,
+    mut parents: Query<
+        (&mut GlobalTransform, &Transform, Option<&Children>),
+        (
+            Without<ChildOf>,
+            Or<(Without<StaticTransform>, With<Children>, Changed<Transform>)>,
+        ),
+    >,
     mut children: Query<(&mut GlobalTransform, &Transform, Option<&Children>), With<ChildOf>>,
 ) {
     let mut stack: Vec<(GlobalTransform, Entity)> = Vec::new();
 
     for (mut global, local, maybe_children) in parents.iter_mut() {
-        *global = GlobalTransform::from_root(local);
+        // Only flag the change when something actually moved so change-driven systems can skip
+        global.set_if_neq(GlobalTransform::from_root(local));
 
         if let Some(children) = maybe_children {
             for &child in children {
@@ -30,7 +50,7 @@ pub fn update_global_transforms_system(
             continue;
         };
 
-        *global = GlobalTransform::from_local(&parent_gt, local);
+        global.set_if_neq(GlobalTransform::from_local(&parent_gt, local));
 
         if let Some(children) = maybe_children {
             for &child in children {
@@ -40,41 +60,161 @@ pub fn update_global_transforms_system(
     }
 }
 
-pub fn move_player_system(
-    window: Res<WindowResource>,
-    time: Res<Time>,
-    mut velocity: Single<&mut Velocity, With<Player>>,
+/// Run condition for `mark_static_transforms_system`, true every `after_frames` frames. The
+/// system only sees changes since it last ran, so skipped frames widen its window.
+pub fn static_transform_check_due(
+    settings: Option<Res<StaticTransformSettings>>,
+    mut frames: Local<u32>,
+) -> bool {
+    let after_frames = settings.map_or(StaticTransformSettings::default().after_frames, |s| {
+        s.after_frames
+    });
+    nth_frame(&mut *frames, after_frames)
+}
+
+/// Frames between `compact_spatial_hashes_system` runs.
+pub const SPATIAL_HASH_COMPACT_FRAMES: u32 = 300;
+
+/// Run condition for `compact_spatial_hashes_system`.
+pub fn spatial_hash_compact_due(mut frames: Local<u32>) -> bool {
+    nth_frame(&mut *frames, SPATIAL_HASH_COMPACT_FRAMES)
+}
+
+/// Counts a frame, true every `n`th one.
+fn nth_frame(frames: &mut u32, n: u32) -> bool {
+    *frames += 1;
+    if *frames < n.max(1) {
+        return false;
+    }
+    *frames = 0;
+    true
+}
+
+/// Hands back what the hashes grew to hold. Their contents don't change, so systems
+/// watching them aren't told.
+pub fn compact_spatial_hashes_system(
+    mut collision_hash: ResMut<CollisionHash>,
+    mut render_hash: ResMut<RenderHash>,
 ) {
-    let move_left = window.is_key_down(KeyboardKey::A);
-    let move_right = window.is_key_down(KeyboardKey::D);
-    let move_up = window.is_key_down(KeyboardKey::W);
-    let move_down = window.is_key_down(KeyboardKey::S);
+    collision_hash.bypass_change_detection().compact();
+    render_hash.bypass_change_detection().compact();
+}
 
-    const SPEED: f32 = 300.0;
-    const RUN_SPEED: f32 = SPEED * 3.0;
-    let mut dir = Vector2::ZERO;
-    if move_left {
-        dir.x -= 1.0;
+/// Promotes childless roots whose `Transform` didn't change since the last check to
+/// `StaticTransform`, and demotes the static ones that moved or joined a hierarchy.
+#[allow(clippy::type_complexity)]
+pub fn mark_static_transforms_system(
+    candidates: Query<
+        (Entity, Ref<Transform>),
+        (
+            Without<StaticTransform>,
+            Without<ChildOf>,
+            Without<Children>,
+        ),
+    >,
+    marked: Query<
+        Entity,
+        (
+            With<StaticTransform>,
+            Or<(Changed<Transform>, With<ChildOf>, With<Children>)>,
+        ),
+    >,
+    mut commands: Commands,
+) {
+    let promoted: Vec<(Entity, StaticTransform)> = candidates
+        .iter()
+        .filter(|(_, transform)| !transform.is_changed())
+        .map(|(entity, _)| (entity, StaticTransform))
+        .collect();
+    if !promoted.is_empty() {
+        commands.try_insert_batch(promoted);
     }
-    if move_right {
-        dir.x += 1.0;
+    for entity in marked.iter() {
+        commands.entity(entity).try_remove::<StaticTransform>();
     }
-    if move_up {
-        dir.y -= 1.0;
+}
+
+/// Runs after everything that sets velocities and before they're applied.
+pub fn clamp_velocity_system(mut movers: Query<(&mut Velocity, &MaxSpeed)>) {
+    for (mut velocity, max_speed) in movers.iter_mut() {
+        let clamped = max_speed.clamp(velocity.0);
+        if (clamped.x, clamped.y) != (velocity.x, velocity.y) {
+            velocity.0 = clamped;
+        }
     }
-    if move_down {
-        dir.y += 1.0;
+}
+
+/// Keeps root movers inside `WorldBounds`, by their `BoundsBehavior` or the default from
+/// `PhysicsSettings`. Runs before transform propagation, so the hashes pick up wraps like
+/// any other move.
+pub fn enforce_world_bounds_system(
+    bounds: Res<WorldBounds>,
+    settings: Res<PhysicsSettings>,
+    mut movers: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut Velocity,
+            Option<&BoundsBehavior>,
+        ),
+        Without<ChildOf>,
+    >,
+    mut commands: Commands,
+) {
+    let (min, max) = (
+        Vector2::new(bounds.0.x, bounds.0.y),
+        Vector2::new(bounds.0.x + bounds.0.width, bounds.0.y + bounds.0.height),
+    );
+    for (entity, mut transform, mut velocity, behavior) in movers.iter_mut() {
+        let Some(behavior) = behavior.copied().or(settings.bounds_behavior) else {
+            continue;
+        };
+        let position = transform.position;
+        let outside_x = position.x < min.x || position.x > max.x;
+        let outside_y = position.y < min.y || position.y > max.y;
+        if !outside_x && !outside_y {
+            continue;
+        }
+
+        match behavior {
+            BoundsBehavior::Clamp => {
+                transform.position = Vector2::new(
+                    position.x.clamp(min.x, max.x),
+                    position.y.clamp(min.y, max.y),
+                );
+                if outside_x {
+                    velocity.x = 0.0;
+                }
+                if outside_y {
+                    velocity.y = 0.0;
+                }
+            }
+            BoundsBehavior::Wrap => {
+                let wrap = |value: f32, min: f32, size: f32| min + (value - min).rem_euclid(size);
+                if outside_x {
+                    transform.position.x = wrap(position.x, min.x, bounds.0.width);
+                }
+                if outside_y {
+                    transform.position.y = wrap(position.y, min.y, bounds.0.height);
+                }
+            }
+            BoundsBehavior::Despawn => commands.entity(entity).despawn(),
+        }
     }
+}
+
+pub fn move_player_system(
+    input: Res<InputState>,
+    mut velocity: Single<&mut Velocity, With<Player>>,
+) {
+    const SPEED: f32 = 300.0;
+    const RUN_SPEED: f32 = SPEED * 3.0;
 
-    let m = if window
-        .0
-        .is_key_down(rustyray::prelude::KeyboardKey::LeftShift)
-    {
+    let m = if input.is_held(Action::Sprint) {
         RUN_SPEED
     } else {
         SPEED
-    } * dir.normalized()
-        * time.delta();
+    } * input.movement();
     velocity.0 = m;
 }
 
@@ -82,382 +222,4185 @@ enum CollisionShape {
     Rect(Rectangle),
 }
 
-#[allow(clippy::type_complexity)]
+/// World rects of a mover's parts, `parts` holding offsets from `rect`'s corner. Anything but a
+/// compound has no parts and is just `rect`.
+fn body_parts<'a>(
+    rect: &'a Rectangle,
+    parts: &'a Option<Vec<Rectangle>>,
+) -> impl Iterator<Item = Rectangle> + 'a {
+    let whole = parts.is_none().then_some(*rect);
+    parts
+        .iter()
+        .flatten()
+        .map(move |part| Rectangle {
+            x: rect.x + part.x,
+            y: rect.y + part.y,
+            ..*part
+        })
+        .chain(whole)
+}
+
+/// The first part of the mover overlapping a part of `other`, and that part.
+fn overlapping_parts(
+    rect: &Rectangle,
+    parts: &Option<Vec<Rectangle>>,
+    other: &Rectangle,
+    other_parts: &Option<Vec<Rectangle>>,
+) -> Option<(Rectangle, Rectangle)> {
+    // Bounds first, most movers aren't near anything
+    if !rect.collides_rect(other) {
+        return None;
+    }
+    body_parts(rect, parts).find_map(|mine| {
+        body_parts(other, other_parts)
+            .find(|theirs| mine.collides_rect(theirs))
+            .map(|theirs| (mine, theirs))
+    })
+}
+
+/// Moves `rect` along the axis of least penetration until `part` no longer overlaps `other`.
+fn push_out_least(rect: &mut Rectangle, part: &Rectangle, other: &Rectangle) {
+    // Compute overlap along X and Y
+    let delta_x = (part.x + part.width / 2.0) - (other.x + other.width / 2.0);
+    let delta_y = (part.y + part.height / 2.0) - (other.y + other.height / 2.0);
+    let intersect_x = (part.width + other.width) / 2.0 - delta_x.abs();
+    let intersect_y = (part.height + other.height) / 2.0 - delta_y.abs();
+
+    // Only push along the axis of least penetration
+    if intersect_x < intersect_y {
+        // X axis
+        if delta_x > 0.0 {
+            rect.x += intersect_x;
+        } else {
+            rect.x -= intersect_x;
+        }
+    } else {
+        // Y axis
+        if delta_y > 0.0 {
+            rect.y += intersect_y;
+        } else {
+            rect.y -= intersect_y;
+        }
+    }
+}
+
+/// A mover as `apply_velocity_system` resolves it.
+struct MovingRect<'w> {
+    /// Bounds, moved in place while collisions are resolved.
+    rect: Rectangle,
+    transform: Mut<'w, Transform>,
+    /// This step's displacement, not the velocity.
+    velocity: Vector2,
+    entity: Entity,
+    priority: CollisionPriority,
+    /// Rounds its corners off against static geometry.
+    capsule: bool,
+    /// Parts of a compound, relative to the corner of `rect`.
+    parts: Option<Vec<Rectangle>>,
+}
+
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn apply_velocity_system(
-    mut movers_q: Query<(
-        &mut Transform,
-        &GlobalTransform,
-        &Velocity,
-        Option<&Collider>,
-    )>,
-    static_colliders: Query<(&Collider, &GlobalTransform), Without<Velocity>>,
-    spatial_hash: Res<SpatialHash>,
+    mut movers_q: Query<
+        (
+            Entity,
+            &mut Transform,
+            &GlobalTransform,
+            &Velocity,
+            Option<&Collider>,
+            Option<&CollisionPriority>,
+            Has<CompoundCollider>,
+        ),
+        Without<Sleeping>,
+    >,
+    // Sleeping movers act as static geometry until something wakes them
+    static_colliders: Query<
+        (&Collider, &GlobalTransform),
+        (Or<(Without<Velocity>, With<Sleeping>)>, Without<ForceField>),
+    >,
+    (children_q, parents_q, compounds): (
+        Query<&Children>,
+        Query<&ChildOf>,
+        Query<(), With<CompoundCollider>>,
+    ),
+    spatial_hash: Res<CollisionHash>,
+    sweep: Res<CollisionSweep>,
+    broadphase: Res<BroadphaseMethod>,
+    baked: Res<BakedStaticColliders>,
+    mut collisions: MessageWriter<CollisionEvent>,
+    mut manifold: ResMut<ContactManifold>,
     mut metrics: ResMut<Metrics>,
+    time: Res<Time>,
+    settings: Res<PhysicsSettings>,
+    mut debug_contacts: Option<ResMut<DebugContacts>>,
 ) {
+    metric_scope!(metrics, "apply_velocity");
     let start = std::time::Instant::now();
 
+    // Parts of awake compounds, resolved with their root instead of as static geometry
+    let mut compound_parts = HashSet::default();
     let mut moving_rects = movers_q
         .iter_mut()
-        .filter_map(|(mut t, gt, v, collider)| {
+        .filter_map(|(e, mut t, gt, v, collider, priority, compound)| {
+            // Everything below works on this step's displacement, not the velocity
+            let v = v.0 * time.delta();
+            let priority = priority.copied().unwrap_or_default();
+            if compound {
+                let rects = collider
+                    .map(|collider| collider.aabb(gt))
+                    .into_iter()
+                    .chain(children_q.iter_descendants(e).filter_map(|child| {
+                        let (collider, child_gt) = static_colliders.get(child).ok()?;
+                        compound_parts.insert(child);
+                        Some(collider.aabb(child_gt))
+                    }))
+                    .collect::<Vec<_>>();
+                if let Some(first) = rects.first() {
+                    let bounds = rects.iter().fold(*first, |bounds, r| {
+                        let x = bounds.x.min(r.x);
+                        let y = bounds.y.min(r.y);
+                        Rectangle {
+                            x,
+                            y,
+                            width: (bounds.x + bounds.width).max(r.x + r.width) - x,
+                            height: (bounds.y + bounds.height).max(r.y + r.height) - y,
+                        }
+                    });
+                    let parts = rects
+                        .iter()
+                        .map(|r| Rectangle {
+                            x: r.x - bounds.x,
+                            y: r.y - bounds.y,
+                            ..*r
+                        })
+                        .collect();
+                    return Some(MovingRect {
+                        rect: bounds,
+                        transform: t,
+                        velocity: v,
+                        entity: e,
+                        priority,
+                        capsule: false,
+                        parts: Some(parts),
+                    });
+                }
+            }
             let Some(collider) = collider else {
-                t.position += v.0;
+                if v.x != 0.0 || v.y != 0.0 {
+                    t.position += v;
+                }
                 return None;
             };
 
-            let r = match collider.kind {
-                ColliderKind::Rectangle(size) => Rectangle {
-                    x: gt.position.x - collider.offset.x,
-                    y: gt.position.y - collider.offset.y,
-                    width: size.x * gt.scale.x,
-                    height: size.y * gt.scale.y,
-                },
-            };
-
-            Some((r, t, v))
+            // Movers are resolved against their bounds, capsules round theirs off against
+            // static geometry
+            Some(MovingRect {
+                rect: collider.aabb(gt),
+                transform: t,
+                velocity: v,
+                entity: e,
+                priority,
+                capsule: matches!(collider.kind, ColliderKind::Capsule { .. }),
+                parts: None,
+            })
         })
         .collect::<Vec<_>>();
 
+    // Earlier movers claim space first, later ones stop against their new positions
+    match settings.resolution_order {
+        ResolutionOrder::Priority => {
+            moving_rects.sort_by_key(|m| (std::cmp::Reverse(m.priority), m.entity))
+        }
+        ResolutionOrder::Mass => moving_rects.sort_by(|a, b| {
+            let area = |r: &Rectangle| r.width * r.height;
+            area(&b.rect)
+                .total_cmp(&area(&a.rect))
+                .then(a.entity.cmp(&b.entity))
+        }),
+        ResolutionOrder::Insertion => {}
+    }
+
+    let movers = moving_rects.len();
+    let mut candidates = 0;
     for i in 0..moving_rects.len() {
         let (left, right) = moving_rects.split_at_mut(i);
-        let ((player_rect, transform, velocity), rest) = right.split_first_mut().unwrap();
+        let (mover, rest) = right.split_first_mut().unwrap();
+        let MovingRect {
+            rect: player_rect,
+            transform,
+            velocity,
+            entity,
+            capsule,
+            parts,
+            ..
+        } = mover;
+        let entity = *entity;
+        let capsule = *capsule;
+        let parts = &*parts;
         let original_position = player_rect.position();
+        let mut record = |other: Entity, rect: &Rectangle, other_rect: &Rectangle| {
+            collisions.write(CollisionEvent {
+                a: entity,
+                b: other,
+            });
+            let contact = Contact::from_overlap(entity, other, rect, other_rect);
+            manifold.contacts.push(contact);
+            if let Some(debug_contacts) = debug_contacts.as_mut() {
+                debug_contacts.record(contact);
+            }
+        };
         // Precompute all static colliders
-        let static_rects = spatial_hash
-            .query(*player_rect)
-            .iter()
-            .filter_map(|&e| {
-                if let Ok((collider, collider_gt)) = static_colliders.get(e) {
-                    return match collider.kind {
-                        ColliderKind::Rectangle(size) => Some(CollisionShape::Rect(Rectangle {
-                            x: collider_gt.position.x - collider.offset.x,
-                            y: collider_gt.position.y - collider.offset.y,
-                            width: size.x * collider_gt.scale.x,
-                            height: size.y * collider_gt.scale.y,
-                        })),
-                    };
-                }
+        let static_shape = |&e: &Entity| {
+            // Covered by a merged rect from the bake, or moving with an awake compound
+            if baked.contains(e) || compound_parts.contains(&e) {
+                return None;
+            }
+            if let Ok((collider, collider_gt)) = static_colliders.get(e) {
+                // Parts of a sleeping compound report hits against the compound
+                let root = parents_q.root_ancestor(e);
+                let owner = if compounds.contains(root) { root } else { e };
+                return Some((owner, CollisionShape::Rect(collider.aabb(collider_gt))));
+            }
 
-                None
-            })
-            .collect::<Vec<_>>();
+            None
+        };
+        // Cover the whole move, an exact broad phase would miss walls we're about to reach
+        let swept_rect = Rectangle {
+            x: player_rect.x + velocity.x.min(0.0),
+            y: player_rect.y + velocity.y.min(0.0),
+            width: player_rect.width + velocity.x.abs(),
+            height: player_rect.height + velocity.y.abs(),
+        };
+        let mut static_rects = match *broadphase {
+            BroadphaseMethod::SpatialHash => spatial_hash
+                .query_strict_excluding(swept_rect, &[entity])
+                .iter()
+                .filter_map(static_shape)
+                .collect::<Vec<_>>(),
+            BroadphaseMethod::SweepPrune => sweep
+                .query_excluding(swept_rect, &[entity])
+                .iter()
+                .filter_map(static_shape)
+                .collect::<Vec<_>>(),
+        };
+        // Contacts with a merged rect report the first entity baked into it
+        static_rects.extend(
+            baked
+                .query(swept_rect)
+                .map(|(e, rect)| (e, CollisionShape::Rect(rect))),
+        );
+        candidates += static_rects.len() + rest.len() + left.len();
 
+        // Stopping a part against a wall moves the whole body, so every correction below is
+        // relative to the part that hit
         if velocity.x != 0.0 || velocity.y != 0.0 {
             player_rect.x += velocity.x;
-            for static_rect in static_rects.iter() {
+            for (other, static_rect) in static_rects.iter() {
                 match static_rect {
                     CollisionShape::Rect(static_rect) => {
-                        if player_rect.collides_rect(static_rect) {
+                        if capsule {
+                            if let Some(push) =
+                                Capsule::from_bounds(player_rect).push_out_of(static_rect)
+                            {
+                                record(*other, player_rect, static_rect);
+                                player_rect.x += push.x;
+                                player_rect.y += push.y;
+                            }
+                            continue;
+                        }
+                        if let Some((part, hit)) =
+                            overlapping_parts(player_rect, parts, static_rect, &None)
+                        {
+                            record(*other, &part, &hit);
+                            let inset = part.x - player_rect.x;
                             if velocity.x > 0.0 {
-                                player_rect.x = static_rect.x - player_rect.width; // stop right before left wall
+                                player_rect.x = hit.x - inset - part.width; // stop before left wall
                             } else if velocity.x < 0.0 {
-                                player_rect.x = static_rect.x + static_rect.width; // stop right before right wall
+                                player_rect.x = hit.x + hit.width - inset; // stop before right wall
                             }
                         }
                     }
                 }
             }
 
-            for (moving_rect, ..) in left.iter().chain(rest.iter()) {
-                if player_rect.collides_rect(moving_rect) {
+            for other in left.iter().chain(rest.iter()) {
+                if let Some((part, hit)) =
+                    overlapping_parts(player_rect, parts, &other.rect, &other.parts)
+                {
+                    record(other.entity, &part, &hit);
+                    let inset = part.x - player_rect.x;
                     if velocity.x > 0.0 {
-                        player_rect.x = moving_rect.x - player_rect.width; // stop right before left wall
+                        player_rect.x = hit.x - inset - part.width; // stop right before left wall
                     } else if velocity.x < 0.0 {
-                        player_rect.x = moving_rect.x + moving_rect.width; // stop right before right wall
+                        player_rect.x = hit.x + hit.width - inset; // stop right before right wall
                     }
                 }
             }
 
             player_rect.y += velocity.y;
-            for static_rect in static_rects.iter() {
+            for (other, static_rect) in static_rects.iter() {
                 match static_rect {
                     CollisionShape::Rect(static_rect) => {
-                        if player_rect.collides_rect(static_rect) {
+                        if capsule {
+                            if let Some(push) =
+                                Capsule::from_bounds(player_rect).push_out_of(static_rect)
+                            {
+                                record(*other, player_rect, static_rect);
+                                player_rect.x += push.x;
+                                player_rect.y += push.y;
+                            }
+                            continue;
+                        }
+                        if let Some((part, hit)) =
+                            overlapping_parts(player_rect, parts, static_rect, &None)
+                        {
+                            record(*other, &part, &hit);
+                            let inset = part.y - player_rect.y;
                             if velocity.y > 0.0 {
-                                player_rect.y = static_rect.y - player_rect.height; // stop above floor
+                                player_rect.y = hit.y - inset - part.height; // stop above floor
                             } else if velocity.y < 0.0 {
-                                player_rect.y = static_rect.y + static_rect.height; // stop below ceiling
+                                player_rect.y = hit.y + hit.height - inset; // stop below ceiling
                             }
                         }
                     }
                 }
             }
 
-            for (moving_rect, ..) in left.iter().chain(rest.iter()) {
-                if player_rect.collides_rect(moving_rect) {
+            for other in left.iter().chain(rest.iter()) {
+                if let Some((part, hit)) =
+                    overlapping_parts(player_rect, parts, &other.rect, &other.parts)
+                {
+                    record(other.entity, &part, &hit);
+                    let inset = part.y - player_rect.y;
                     if velocity.y > 0.0 {
-                        player_rect.y = moving_rect.y - player_rect.height; // stop above floor
+                        player_rect.y = hit.y - inset - part.height; // stop above floor
                     } else if velocity.y < 0.0 {
-                        player_rect.y = moving_rect.y + moving_rect.height; // stop below ceiling
+                        player_rect.y = hit.y + hit.height - inset; // stop below ceiling
                     }
                 }
             }
         } else {
             // No velocity position check
-            for static_rect in static_rects.iter() {
+            for (other, static_rect) in static_rects.iter() {
                 match static_rect {
                     CollisionShape::Rect(static_rect) => {
-                        if player_rect.collides_rect(static_rect) {
-                            // Compute overlap along X and Y
-                            let delta_x = (player_rect.x + player_rect.width / 2.0)
-                                - (static_rect.x + static_rect.width / 2.0);
-                            let delta_y = (player_rect.y + player_rect.height / 2.0)
-                                - (static_rect.y + static_rect.height / 2.0);
-                            let intersect_x =
-                                (player_rect.width + static_rect.width) / 2.0 - delta_x.abs();
-                            let intersect_y =
-                                (player_rect.height + static_rect.height) / 2.0 - delta_y.abs();
-
-                            // Only push along the axis of least penetration
-                            if intersect_x < intersect_y {
-                                // X axis
-                                if delta_x > 0.0 {
-                                    player_rect.x += intersect_x;
-                                } else {
-                                    player_rect.x -= intersect_x;
-                                }
-                            } else {
-                                // Y axis
-                                if delta_y > 0.0 {
-                                    player_rect.y += intersect_y;
-                                } else {
-                                    player_rect.y -= intersect_y;
-                                }
+                        if capsule {
+                            if let Some(push) =
+                                Capsule::from_bounds(player_rect).push_out_of(static_rect)
+                            {
+                                record(*other, player_rect, static_rect);
+                                player_rect.x += push.x;
+                                player_rect.y += push.y;
                             }
+                            continue;
+                        }
+                        if let Some((part, hit)) =
+                            overlapping_parts(player_rect, parts, static_rect, &None)
+                        {
+                            record(*other, &part, &hit);
+                            push_out_least(player_rect, &part, &hit);
                         }
                     }
                 }
             }
 
-            for (moving_rect, _, mover_velocity) in left.iter().chain(rest.iter()) {
+            for other in left.iter().chain(rest.iter()) {
                 // If the other entity has velocity, we will handle the collision then
-                if mover_velocity.x == 0.0
-                    && mover_velocity.y == 0.0
-                    && player_rect.collides_rect(moving_rect)
+                if other.velocity.x != 0.0 || other.velocity.y != 0.0 {
+                    continue;
+                }
+                if let Some((part, hit)) =
+                    overlapping_parts(player_rect, parts, &other.rect, &other.parts)
                 {
-                    // Compute overlap along X and Y
-                    let delta_x = (player_rect.x + player_rect.width / 2.0)
-                        - (moving_rect.x + moving_rect.width / 2.0);
-                    let delta_y = (player_rect.y + player_rect.height / 2.0)
-                        - (moving_rect.y + moving_rect.height / 2.0);
-                    let intersect_x = (player_rect.width + moving_rect.width) / 2.0 - delta_x.abs();
-                    let intersect_y =
-                        (player_rect.height + moving_rect.height) / 2.0 - delta_y.abs();
-
-                    // Only push along the axis of least penetration
-                    if intersect_x < intersect_y {
-                        // X axis
-                        if delta_x > 0.0 {
-                            player_rect.x += intersect_x;
-                        } else {
-                            player_rect.x -= intersect_x;
-                        }
-                    } else {
-                        // Y axis
-                        if delta_y > 0.0 {
-                            player_rect.y += intersect_y;
-                        } else {
-                            player_rect.y -= intersect_y;
-                        }
-                    }
+                    record(other.entity, &part, &hit);
+                    push_out_least(player_rect, &part, &hit);
                 }
             }
         }
 
         // Update the actual position based on resolved rectangle (world space)
         let delta = player_rect.position() - original_position;
-        transform.position.x += delta.x;
-        transform.position.y += delta.y;
+        // Leave resting movers untouched so `sleep_system` can see they didn't move
+        if delta.x != 0.0 || delta.y != 0.0 {
+            transform.position.x += delta.x;
+            transform.position.y += delta.y;
+        }
     }
 
     // let duration = start.elapsed();
     // println!("Collision detection took: {duration:?}");
     metrics.apply_velocity_system_time = start.elapsed();
+    metrics.apply_velocity_movers = movers;
+    metrics.apply_velocity_candidates = candidates;
 }
 
-pub fn move_camera_to_target_system(
-    mut camera: Single<&mut Camera, With<ActiveCamera>>,
-    target: Single<&Transform, With<CameraTarget>>,
-    window: Res<WindowResource>,
+/// Puts movers to sleep once they've been idle for `PhysicsSettings::sleep_ticks` in a row.
+pub fn sleep_system(
+    settings: Res<PhysicsSettings>,
+    mut movers: Query<(Entity, &Velocity, &mut SleepTimer, Ref<Transform>), Without<Sleeping>>,
+    mut commands: Commands,
 ) {
-    camera.target = target.position;
+    let epsilon = settings.sleep_velocity_epsilon;
+    for (entity, velocity, mut timer, transform) in movers.iter_mut() {
+        if velocity.x.abs() > epsilon || velocity.y.abs() > epsilon || transform.is_changed() {
+            timer.0 = 0;
+            continue;
+        }
 
-    let mouse_scroll = window.mouse_wheel_move() / 10.0;
-    if mouse_scroll != 0.0 {
-        camera.zoom = (camera.zoom + mouse_scroll).clamp(0.3, 5.0);
+        timer.0 += 1;
+        if timer.0 >= settings.sleep_ticks {
+            commands.entity(entity).insert(Sleeping);
+        }
     }
 }
 
-pub fn update_count_text_system(
-    mut text: Query<&mut Text, With<CountText>>,
-    entities: Query<&Sprite, Without<Player>>,
+/// Adds each `ForceField`'s force to the movers overlapping it. Runs after everything that
+/// sets velocities outright, which would throw it away, and wakes sleepers through
+/// `wake_system`.
+pub fn apply_force_fields_system(
+    fields: Query<(Entity, &ForceField, &Collider, &GlobalTransform)>,
+    mut movers: Query<(&mut Velocity, &Collider, &GlobalTransform), Without<ForceField>>,
+    spatial_hash: Res<CollisionHash>,
+    time: Res<Time>,
 ) {
-    let count = entities.iter().count();
-    for mut t in text.iter_mut() {
-        t.content = format!("Count: {count}"); // Replace 42 with the actual count
+    for (field_entity, field, collider, transform) in fields.iter() {
+        let area = collider.aabb(transform);
+        for entity in spatial_hash.query_strict_excluding(area, &[field_entity]) {
+            let Ok((mut velocity, collider, transform)) = movers.get_mut(entity) else {
+                continue;
+            };
+            let rect = collider.aabb(transform);
+            let center = Vector2::new(rect.x + rect.width / 2.0, rect.y + rect.height / 2.0);
+            let strength = field.strength(&area, center);
+            if strength > 0.0 {
+                velocity.0 += field.force * (strength * time.delta());
+            }
+        }
     }
 }
 
-pub fn update_on_screen_text_system(
-    mut text: Query<&mut Text, With<OnScreenText>>,
-    entities: Query<&Sprite, With<OnScreen>>,
-    camera: Single<&Camera, With<ActiveCamera>>,
+/// Wakes sleeping movers that had their velocity written or got hit by another mover.
+pub fn wake_system(
+    mut collisions: MessageReader<CollisionEvent>,
+    pushed: Query<Entity, (With<Sleeping>, Changed<Velocity>)>,
+    mut sleeping: Query<&mut SleepTimer, With<Sleeping>>,
+    mut commands: Commands,
 ) {
-    let count = entities.iter().count();
-    for mut t in text.iter_mut() {
-        t.content = format!("On Screen: {count} {:.2}", camera.0.zoom);
+    let hit = collisions.read().flat_map(|ev| [ev.a, ev.b]);
+    for entity in pushed.iter().chain(hit) {
+        if let Ok(mut timer) = sleeping.get_mut(entity) {
+            timer.0 = 0;
+            commands.entity(entity).remove::<Sleeping>();
+        }
     }
 }
 
-pub fn update_on_screen_system(
-    spatial_hash: Res<SpatialHash>,
+/// Swaps the message buffers so messages older than a frame get dropped.
+pub fn update_messages_system<M: Message>(mut messages: ResMut<Messages<M>>) {
+    messages.update();
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn move_camera_to_target_system(
+    camera: Single<
+        (
+            &mut Camera,
+            Option<&mut CameraSpring>,
+            Option<&mut CameraZoom>,
+            Has<CameraInterpolation>,
+        ),
+        With<ActiveCamera>,
+    >,
+    targets: Query<&Transform, With<CameraTarget>>,
+    positions: Query<&Transform>,
+    framing: Res<CameraFraming>,
     window: Res<WindowResource>,
-    camera: Single<&Camera, With<ActiveCamera>>,
-    on_screen_q: Query<Entity, With<OnScreen>>,
+    time: Res<Time>,
+    transition: Option<ResMut<CameraTransition>>,
     mut commands: Commands,
-    mut metrics: ResMut<Metrics>,
 ) {
-    let extra_offset = 0.0f32;
+    let (mut camera, spring, mut zoom, interpolated) = camera.into_inner();
     let screen_size = window.screen_size().to_vector2();
-    let (min_x, min_y, max_x, max_y) = (
-        camera.target.x - screen_size.x / camera.zoom,
-        camera.target.y - screen_size.y / camera.zoom,
-        camera.target.x + screen_size.x / camera.zoom,
-        camera.target.y + screen_size.y / camera.zoom,
-    );
-    let start = std::time::Instant::now();
-    let on_screen_entities = spatial_hash.query(Rectangle {
-        x: min_x - extra_offset,
-        y: min_y - extra_offset,
-        width: max_x - min_x + extra_offset * 2.0,
-        height: max_y - min_y + extra_offset * 2.0,
-    });
-    metrics.update_on_screen_system_time = start.elapsed();
+    let Some((center, fit_zoom)) = framing.frame(targets.iter().map(|t| t.position), screen_size)
+    else {
+        return;
+    };
 
-    {
-        #[cfg(feature = "trace")]
-        let _span = info_span!("add_onscreen_component").entered();
-        on_screen_entities.iter().for_each(|&entity| {
-            commands.entity(entity).insert(OnScreen);
-        });
+    if let Some(mut transition) = transition {
+        match positions.get(transition.to) {
+            Ok(to) => {
+                let (position, done) = transition.step(time.delta(), to.position);
+                camera.target = position;
+                if done {
+                    commands.remove_resource::<CameraTransition>();
+                }
+            }
+            // The target went away, let the normal follow take over
+            Err(_) => commands.remove_resource::<CameraTransition>(),
+        }
+        // Start the follow from rest once the transition hands over
+        if let Some(mut spring) = spring {
+            spring.velocity = Vector2::new(0.0, 0.0);
+        }
+    } else if !interpolated {
+        // `camera_interpolation_system` positions interpolated cameras, only the zoom is left here
+        let goal = match spring {
+            Some(mut spring) => spring.step(camera.target, center, time.delta()),
+            None => center,
+        };
+        if camera.target != goal {
+            camera.target = goal;
+        }
     }
 
-    // Remove OnScreen from entities that are no longer on screen
-    {
-        #[cfg(feature = "trace")]
-        let _span = info_span!("remove_onscreen_component").entered();
-        for entity in on_screen_q.iter() {
-            if !on_screen_entities.contains(&entity) {
-                commands.entity(entity).remove::<OnScreen>();
-            }
+    // Several targets drive the zoom, the wheel only matters when following one
+    if let Some(fit_zoom) = fit_zoom {
+        match zoom.as_mut() {
+            Some(zoom) => zoom.target = fit_zoom,
+            None if camera.zoom != fit_zoom => camera.zoom = fit_zoom,
+            None => {}
+        }
+        return;
+    }
+
+    let mouse_scroll = window.mouse_wheel_move() / 10.0;
+    if mouse_scroll != 0.0 {
+        match zoom {
+            Some(mut zoom) => zoom.target = (zoom.target + mouse_scroll).clamp(zoom.min, zoom.max),
+            None => camera.zoom = (camera.zoom + mouse_scroll).clamp(0.3, 5.0),
         }
     }
 }
 
-pub fn check_for_resize_system(
-    window: Res<WindowResource>,
-    mut current_size: ResMut<WindowSize>,
-    mut ev_resize: MessageWriter<ResizeEvent>,
-) {
-    let new_size = window.screen_size();
-    if new_size != current_size.0 {
-        println!("Window resized to {}x{}", new_size.x, new_size.y);
-        ev_resize.write(ResizeEvent {
-            from: current_size.0,
-            to: new_size,
-        });
-        current_size.0 = new_size;
+/// Hands `CameraTarget` over to `target` and glides the active camera there over `duration`
+/// seconds instead of snapping.
+pub fn trigger_camera_transition(world: &mut World, target: Entity, duration: f32) {
+    let mut cameras = world.query_filtered::<&Camera, With<ActiveCamera>>();
+    let Ok(camera) = cameras.single(world) else {
+        return;
+    };
+    let from = camera.target;
+    let mut targets = world.query_filtered::<Entity, With<CameraTarget>>();
+    let previous: Vec<Entity> = targets.iter(world).collect();
+    for entity in previous {
+        world.entity_mut(entity).remove::<CameraTarget>();
     }
+    world.entity_mut(target).insert(CameraTarget);
+    world.insert_resource(CameraTransition {
+        from,
+        to: target,
+        elapsed: 0.0,
+        duration,
+    });
 }
 
-pub fn debug_toggle_system(
-    mut debug_settings: ResMut<DebugSettings>,
-    mut window: ResMut<WindowResource>,
+/// Records where the camera targets are framed after each physics tick.
+pub fn record_camera_target_system(
+    mut cameras: Query<&mut CameraInterpolation, With<ActiveCamera>>,
+    targets: Query<&Transform, With<CameraTarget>>,
+    framing: Res<CameraFraming>,
+    window_size: Res<WindowSize>,
 ) {
-    if window.is_key_pressed(KeyboardKey::O) {
-        debug_settings.origins = !debug_settings.origins;
+    let screen_size = window_size.0.to_vector2();
+    let Some((center, _)) = framing.frame(targets.iter().map(|t| t.position), screen_size) else {
+        return;
+    };
+    for mut interpolation in cameras.iter_mut() {
+        interpolation.push(center);
     }
-    if window.is_key_pressed(KeyboardKey::C) {
-        debug_settings.colliders = !debug_settings.colliders;
+}
+
+/// Follows the framed target at the physics alpha of this frame, so the camera moves as
+/// smoothly as the refresh rate allows. Runs in the render schedule, after `update_camera_offset`
+/// and before anything draws through the camera.
+pub fn camera_interpolation_system(
+    alpha: Res<PhysicsAlpha>,
+    time: Res<Time>,
+    transition: Option<Res<CameraTransition>>,
+    mut cameras: Query<
+        (&mut Camera, &CameraInterpolation, Option<&mut CameraSpring>),
+        With<ActiveCamera>,
+    >,
+) {
+    // `move_camera_to_target_system` drives the camera until the transition is over
+    if transition.is_some() {
+        return;
     }
-    if window.is_key_pressed(KeyboardKey::F) {
-        window.set_target_fps(50000);
+    for (mut camera, interpolation, spring) in cameras.iter_mut() {
+        let center = interpolation.at(alpha.0);
+        let goal = match spring {
+            Some(mut spring) => spring.step(camera.target, center, time.delta()),
+            None => center,
+        };
+        if camera.target != goal {
+            camera.target = goal;
+        }
     }
 }
 
-pub fn update_render_textures_size_system(
-    mut ev_resize: MessageReader<ResizeEvent>,
-    mut render_textures: ResMut<LayerTextures>,
-) {
-    for ev in ev_resize.read() {
-        for (_, rt) in render_textures.0.iter_mut() {
-            *rt = OwnedRenderTexture::new(ev.to.x, ev.to.y).unwrap();
+/// Moves `camera.zoom` towards `CameraZoom::target`. Culling reads `camera.zoom`, so it
+/// always matches what is on screen mid-ease.
+pub fn ease_camera_zoom_system(time: Res<Time>, mut cameras: Query<(&mut Camera, &CameraZoom)>) {
+    for (mut camera, zoom) in cameras.iter_mut() {
+        let eased = zoom.ease(camera.zoom, time.delta());
+        if camera.zoom != eased {
+            camera.zoom = eased;
         }
     }
 }
 
-pub fn update_camera_offset(
-    mut ev_resize: MessageReader<ResizeEvent>,
-    mut camera: Single<&mut Camera>,
+// Observers keeping the `WorldStats` counters exact, despawns trigger `Remove` too
+pub fn count_sprite_added(_add: On<Add, Sprite>, mut stats: ResMut<WorldStats>) {
+    stats.sprites += 1;
+}
+
+pub fn count_sprite_removed(_remove: On<Remove, Sprite>, mut stats: ResMut<WorldStats>) {
+    stats.sprites = stats.sprites.saturating_sub(1);
+}
+
+pub fn count_on_screen_added(_add: On<Add, OnScreen>, mut stats: ResMut<WorldStats>) {
+    stats.on_screen += 1;
+}
+
+pub fn count_on_screen_removed(_remove: On<Remove, OnScreen>, mut stats: ResMut<WorldStats>) {
+    stats.on_screen = stats.on_screen.saturating_sub(1);
+}
+
+pub fn register_name(
+    insert: On<Insert, EntityName>,
+    names: Query<&EntityName>,
+    mut registry: ResMut<NameRegistry>,
 ) {
-    for ev in ev_resize.read() {
-        camera.offset = Vector2::new(ev.to.x as f32 / 2.0, ev.to.y as f32 / 2.0);
+    if let Ok(name) = names.get(insert.entity) {
+        registry.register(&name.0, insert.entity);
     }
 }
 
-pub fn ensure_global_transform_system(
-    q: Query<(Entity, &Transform), Without<GlobalTransform>>,
-    mut commands: Commands,
+/// Also runs on despawn, and before a new `EntityName` replaces the old one.
+pub fn unregister_name(
+    replace: On<Replace, EntityName>,
+    names: Query<&EntityName>,
+    mut registry: ResMut<NameRegistry>,
 ) {
-    for (e, t) in q.iter() {
-        commands
-            .entity(e)
-            .insert_if_new(GlobalTransform::from_root(t));
+    if let Ok(name) = names.get(replace.entity) {
+        registry.unregister(&name.0, replace.entity);
     }
 }
 
+pub fn add_name_registry_observers(world: &mut World) {
+    world.add_observer(register_name);
+    world.add_observer(unregister_name);
+}
+
+pub fn add_world_stats_observers(world: &mut World) {
+    world.add_observer(count_sprite_added);
+    world.add_observer(count_sprite_removed);
+    world.add_observer(count_on_screen_added);
+    world.add_observer(count_on_screen_removed);
+}
+
+/// Counts the world's sprites, leaving out the player, its health bar and screen-space
+/// sprites like the HUD and the cursor. Those are few, so they're subtracted rather than
+/// tracked.
 #[allow(clippy::type_complexity)]
-pub fn sync_collider_with_sprite_system(
-    mut q: Query<
-        (&mut Collider, &Sprite, &GlobalTransform),
+pub fn update_count_text_system(
+    mut text: Query<&mut Text, With<CountText>>,
+    stats: Res<WorldStats>,
+    not_counted: Query<
+        (),
         (
-            With<SyncColliderWithSprite>,
-            Or<(Changed<Sprite>, Changed<GlobalTransform>)>,
+            With<Sprite>,
+            Or<(With<Player>, With<HealthBar>, With<ScreenSpace>)>,
         ),
     >,
+    mut last_count: Local<Option<usize>>,
 ) {
-    for (mut collider, sprite, global_transform) in q.iter_mut() {
-        let origin = sprite.get_origin_vector();
-        collider.offset = match collider.kind {
-            ColliderKind::Rectangle(size) => Vector2::new(
-                (size.x * global_transform.scale.x) * origin.x,
-                (size.y * global_transform.scale.y) * origin.y,
-            ),
+    let count = stats.sprites.saturating_sub(not_counted.iter().count());
+    // `WorldStats` also changes with the on screen count, which this text doesn't show
+    if *last_count == Some(count) {
+        return;
+    }
+    *last_count = Some(count);
+
+    for mut t in text.iter_mut() {
+        if t.bypass_change_detection()
+            .set_content_if_changed(format_args!("Count: {count}"))
+        {
+            t.set_changed();
         }
     }
 }
 
-pub fn update_spatial_hash_system(
-    mut spatial_hash: ResMut<SpatialHash>,
-    query: Query<(Entity, &Sprite, &GlobalTransform), Changed<GlobalTransform>>,
+pub fn update_on_screen_text_system(
+    mut text: Query<&mut Text, With<OnScreenText>>,
+    stats: Res<WorldStats>,
+    camera: Single<Ref<Camera>, With<ActiveCamera>>,
 ) {
-    for (entity, sprite, transform) in query.iter() {
-        let origin = sprite.get_origin_vector();
-        let rect = match &sprite.kind {
-            SpriteKind::Rectangle { size: shape, .. } => Rectangle {
-                x: transform.position.x - (shape.0 * transform.scale.x) * origin.x,
-                y: transform.position.y - (shape.1 * transform.scale.y) * origin.y,
-                width: shape.0 * transform.scale.x,
-                height: shape.1 * transform.scale.y,
-            },
-            SpriteKind::Circle { radius, .. } => Rectangle {
-                x: transform.position.x - (radius * transform.scale.x) * origin.x,
-                y: transform.position.y - (radius * transform.scale.y) * origin.y,
-                width: radius * transform.scale.x,
-                height: radius * transform.scale.y,
-            },
-            _ => continue,
-        };
+    if !stats.is_changed() && !camera.is_changed() {
+        return;
+    }
+
+    for mut t in text.iter_mut() {
+        if t.bypass_change_detection()
+            .set_content_if_changed(format_args!(
+                "On Screen: {} {:.2}",
+                stats.on_screen, camera.zoom
+            ))
+        {
+            t.set_changed();
+        }
+    }
+}
+
+/// Runs in the update schedule, so physics culls against the rect of the camera as it was
+/// last drawn. Runs again in the render schedule once this frame's follow, zoom and
+/// interpolation moved the camera, so fog, lighting and the hidden collider overlay cull
+/// against the view actually drawn.
+pub fn compute_camera_view_system(
+    camera: Single<&Camera, With<ActiveCamera>>,
+    window_size: Res<WindowSize>,
+    margin: Option<Res<CameraViewMargin>>,
+    mut view: ResMut<CameraViewRect>,
+) {
+    let margin = margin.map_or(CULLING_MARGIN, |margin| margin.0);
+    let rect = grow_rect(camera_viewport_rect(&camera, window_size.0), margin);
+    let as_tuple = |r: Rectangle| (r.x, r.y, r.width, r.height);
+    if as_tuple(rect) != as_tuple(view.0) {
+        view.0 = rect;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn update_on_screen_system(
+    spatial_hash: Res<RenderHash>,
+    view: Res<CameraViewRect>,
+    on_screen_q: Query<Entity, With<OnScreen>>,
+    visibility_q: Query<&Visibility>,
+    mut commands: Commands,
+    mut metrics: ResMut<Metrics>,
+    mut ev_entered: MessageWriter<EnteredView>,
+    mut ev_left: MessageWriter<LeftView>,
+    culling: Option<Res<CullingSettings>>,
+    subtrees: Query<(Entity, &SubtreeBounds)>,
+    parents: Query<&ChildOf>,
+    children: Query<&Children>,
+) {
+    let view_rect = view.0;
+    metric_scope!(metrics, "update_on_screen");
+    let start = std::time::Instant::now();
+    // Cells stick out past the view, so check the rects themselves too
+    let mut on_screen_entities = spatial_hash.query_strict(view_rect);
+    if culling.is_some_and(|culling| culling.inherit_from_root) {
+        // Members of a hierarchy with known bounds follow the whole hierarchy instead
+        on_screen_entities.retain(|&entity| !subtrees.contains(parents.root_ancestor(entity)));
+        for (root, bounds) in subtrees.iter() {
+            if bounds.0.collides_rect(&view_rect) {
+                on_screen_entities.extend(
+                    std::iter::once(root)
+                        .chain(children.iter_descendants(root))
+                        .filter(|&entity| spatial_hash.contains(entity)),
+                );
+            }
+        }
+    }
+    metrics.update_on_screen_system_time = start.elapsed();
+    // Hidden entities count as out of view even inside the rect
+    on_screen_entities
+        .retain(|&entity| !matches!(visibility_q.get(entity), Ok(Visibility::Hidden)));
+    metrics.update_on_screen_visible = on_screen_entities.len();
+
+    // Only the state at the end of the frame is compared, so a quick out and back is no flip
+    let entered: Vec<Entity> = on_screen_entities
+        .iter()
+        .copied()
+        .filter(|&entity| !on_screen_q.contains(entity))
+        .collect();
+    let left: Vec<Entity> = on_screen_q
+        .iter()
+        .filter(|entity| !on_screen_entities.contains(entity))
+        .collect();
+    ev_entered.write_batch(entered.iter().map(|&entity| EnteredView(entity)));
+    ev_left.write_batch(left.iter().map(|&entity| LeftView(entity)));
+    metrics.on_screen_inserted = entered.len();
+    metrics.on_screen_removed = left.len();
+    commands.queue(move |world: &mut World| {
+        #[cfg(feature = "trace")]
+        let _span = info_span!("apply_onscreen_changes").entered();
+        let start = std::time::Instant::now();
+        apply_on_screen_changes(world, entered, left);
+        world.resource_mut::<Metrics>().on_screen_apply_time = start.elapsed();
+    });
+}
+
+/// Recomputes `SubtreeBounds` for the hierarchies where something moved, changed shape or
+/// gained or lost a child. Everything is recomputed when the settings change.
+#[allow(clippy::type_complexity)]
+pub fn update_subtree_bounds_system(
+    settings: Res<CullingSettings>,
+    changed: Query<
+        Entity,
+        (
+            Or<(Changed<GlobalTransform>, Changed<Sprite>, Changed<Children>)>,
+            Or<(With<ChildOf>, With<Children>)>,
+        ),
+    >,
+    all: Query<Entity, Or<(With<ChildOf>, With<Children>)>>,
+    childless: Query<Entity, (With<SubtreeBounds>, Without<Children>)>,
+    parents: Query<&ChildOf>,
+    children: Query<&Children>,
+    sprites: Query<(&Sprite, &GlobalTransform), Without<ScreenSpace>>,
+    mut commands: Commands,
+) {
+    if !settings.inherit_from_root {
+        return;
+    }
+    // Lone entities are culled on their own rect again
+    for entity in childless.iter() {
+        commands.entity(entity).remove::<SubtreeBounds>();
+    }
+
+    let members: Vec<Entity> = if settings.is_changed() {
+        all.iter().collect()
+    } else {
+        changed.iter().collect()
+    };
+    let roots: HashSet<Entity> = members
+        .into_iter()
+        .map(|entity| parents.root_ancestor(entity))
+        .collect();
+    for root in roots {
+        if !children.contains(root) {
+            continue;
+        }
+        let bounds = std::iter::once(root)
+            .chain(children.iter_descendants(root))
+            .filter_map(|entity| {
+                let (sprite, transform) = sprites.get(entity).ok()?;
+                sprite.bounds(transform)
+            })
+            .reduce(SubtreeBounds::union);
+        match bounds {
+            Some(bounds) => commands.entity(root).try_insert(SubtreeBounds(bounds)),
+            None => commands.entity(root).try_remove::<SubtreeBounds>(),
+        };
+    }
+}
+
+/// One command for the whole frame instead of one per entity, inserts go in as a batch.
+/// Removals are grouped by archetype and taken from the back of each, so they all follow
+/// the same archetype edge and nothing is swapped into the gaps they leave. Entities
+/// despawned since the query ran are skipped.
+pub fn apply_on_screen_changes(world: &mut World, entered: Vec<Entity>, left: Vec<Entity>) {
+    let _ = world.try_insert_batch(entered.into_iter().map(|entity| (entity, OnScreen)));
+    let mut left: Vec<_> = left
+        .into_iter()
+        .filter_map(|entity| {
+            let location = world.get_entity(entity).ok()?.location();
+            Some((
+                location.archetype_id,
+                std::cmp::Reverse(location.archetype_row),
+                entity,
+            ))
+        })
+        .collect();
+    left.sort_unstable();
+    for (.., entity) in left {
+        world.entity_mut(entity).remove::<OnScreen>();
+    }
+}
+
+/// Demo reaction to `EnteredView`, flashes `FlashOnEnterView` entities green for a moment.
+/// The entity may be gone by the time the commands apply.
+pub fn flash_on_enter_view_system(
+    mut ev_entered: MessageReader<EnteredView>,
+    flashable: Query<(Option<&Tint>, Option<&ViewFlash>), With<FlashOnEnterView>>,
+    mut commands: Commands,
+) {
+    for &EnteredView(entity) in ev_entered.read() {
+        let Ok((tint, flash)) = flashable.get(entity) else {
+            continue;
+        };
+        // Flashing again keeps the tint from before the first flash
+        let previous = flash.map_or(tint.copied(), |flash| flash.previous);
+        commands.entity(entity).try_insert((
+            ViewFlash {
+                remaining: VIEW_FLASH_SECONDS,
+                previous,
+            },
+            Tint(Color::GREEN),
+        ));
+    }
+}
+
+pub fn tick_view_flash_system(
+    time: Res<Time>,
+    mut flashes: Query<(Entity, &mut ViewFlash)>,
+    mut commands: Commands,
+) {
+    for (entity, mut flash) in flashes.iter_mut() {
+        flash.remaining -= time.delta();
+        if flash.remaining > 0.0 {
+            continue;
+        }
+        let mut entity = commands.entity(entity);
+        entity.try_remove::<ViewFlash>();
+        match flash.previous {
+            Some(tint) => entity.try_insert(tint),
+            None => entity.try_remove::<Tint>(),
+        };
+    }
+}
+
+pub fn check_for_resize_system(
+    window: Res<WindowResource>,
+    mut current_size: ResMut<WindowSize>,
+    mut ev_resize: MessageWriter<ResizeEvent>,
+) {
+    let new_size = window.screen_size();
+    if new_size != current_size.0 {
+        println!("Window resized to {}x{}", new_size.x, new_size.y);
+        ev_resize.write(ResizeEvent {
+            from: current_size.0,
+            to: new_size,
+        });
+        current_size.0 = new_size;
+    }
+}
+
+pub fn update_window_focus_system(
+    window: Res<WindowResource>,
+    mut focus: ResMut<WindowFocus>,
+    mut ev_focus: MessageWriter<FocusChanged>,
+) {
+    let new_focus = WindowFocus {
+        focused: window.is_focused(),
+        minimized: window.is_minimized(),
+    };
+    if new_focus.focused != focus.focused {
+        ev_focus.write(FocusChanged {
+            focused: new_focus.focused,
+        });
+    }
+    focus.set_if_neq(new_focus);
+}
+
+/// Pushes `WindowSettings` to the window, run when they change.
+pub fn apply_window_settings_system(
+    mut window: ResMut<WindowResource>,
+    settings: Res<WindowSettings>,
+) {
+    window.set_title(&settings.title);
+    window.set_target_fps(settings.fps_cap.target_fps());
+}
+
+/// Sleeps off what's left of the frame budget. Runs last in the render schedule and measures
+/// from its own previous wake up, so the budget covers the whole frame, physics included.
+pub fn frame_limiter_system(mut limiter: ResMut<FramerateLimiter>) {
+    let sleep = limiter
+        .frame_start
+        .and_then(|start| limiter.remaining(start.elapsed()));
+    if let Some(sleep) = sleep {
+        std::thread::sleep(sleep);
+    }
+    limiter.frame_start = Some(std::time::Instant::now());
+}
+
+/// Unclamped, so the graph shows the stalls the physics clock hides.
+pub fn record_frame_time_system(window: Res<WindowResource>, mut metrics: ResMut<Metrics>) {
+    metrics.record_frame_time(window.frame_time());
+}
+
+pub fn debug_toggle_system(
+    mut debug_settings: ResMut<DebugSettings>,
+    mut step_mode: ResMut<StepMode>,
+    mut window_settings: ResMut<WindowSettings>,
+    window: Res<WindowResource>,
+) {
+    if window.is_key_pressed(KeyboardKey::O) {
+        debug_settings.origins = !debug_settings.origins;
+    }
+    if window.is_key_pressed(KeyboardKey::C) {
+        debug_settings.colliders = !debug_settings.colliders;
+    }
+    if window.is_key_pressed(KeyboardKey::V) {
+        debug_settings.visualize_culling = !debug_settings.visualize_culling;
+    }
+    if window.is_key_pressed(KeyboardKey::X) {
+        debug_settings.contacts = !debug_settings.contacts;
+    }
+    if window.is_key_pressed(KeyboardKey::P) {
+        debug_settings.show_system_counts = !debug_settings.show_system_counts;
+    }
+    if window.is_key_pressed(KeyboardKey::G) {
+        debug_settings.show_fps_graph = !debug_settings.show_fps_graph;
+    }
+    if window.is_key_pressed(KeyboardKey::N) {
+        debug_settings.inspector = !debug_settings.inspector;
+    }
+    if window.is_key_pressed(KeyboardKey::M) {
+        debug_settings.hide_cursor = !debug_settings.hide_cursor;
+    }
+    if window.is_key_pressed(KeyboardKey::J) {
+        debug_settings.drag = !debug_settings.drag;
+    }
+    if window.is_key_pressed(KeyboardKey::F) {
+        window_settings.fps_cap = window_settings.fps_cap.cycle();
+    }
+    if window.is_key_pressed(KeyboardKey::F10) {
+        step_mode.toggle_pause();
+    }
+    // Stepping only makes sense from a paused clock
+    if window.is_key_pressed(KeyboardKey::F11) && *step_mode != StepMode::Running {
+        *step_mode = StepMode::StepOnce;
+    }
+}
+
+/// `K` switches the player between a box and a capsule collider of the same height.
+pub fn toggle_player_capsule_system(
+    window: Res<WindowResource>,
+    mut player: Single<(&mut Collider, &Sprite, &GlobalTransform), With<Player>>,
+) {
+    if !window.is_key_pressed(KeyboardKey::K) {
+        return;
+    }
+    let (collider, sprite, transform) = &mut *player;
+    collider.kind = match collider.kind {
+        ColliderKind::Capsule { .. } => ColliderKind::default(),
+        _ => ColliderKind::Capsule {
+            radius: 14.0,
+            half_height: 2.0,
+        },
+    };
+    // Sprite sync only runs on sprite or transform changes
+    collider.offset = collider.size(transform.scale) * sprite.get_origin_vector();
+}
+
+/// `=`/`-` double or halve the collision hash cell size. Runs outside the physics loop,
+/// so no query results are held while the hash is re-bucketed.
+pub fn debug_cell_size_system(
+    window: Res<WindowResource>,
+    mut collision_hash: ResMut<CollisionHash>,
+) {
+    let cell_size = if window.is_key_pressed(KeyboardKey::Equal) {
+        collision_hash.cell_size() * 2.0
+    } else if window.is_key_pressed(KeyboardKey::Minus) {
+        (collision_hash.cell_size() / 2.0).max(4.0)
+    } else {
+        return;
+    };
+
+    collision_hash.set_cell_size(cell_size);
+    println!(
+        "Collision hash: cell size {}, {} cells, {} entities, {:.1} entities/cell",
+        collision_hash.cell_size(),
+        collision_hash.cell_count(),
+        collision_hash.len(),
+        collision_hash
+            .iter_cells()
+            .map(|(_, bucket)| bucket.len())
+            .sum::<usize>() as f32
+            / collision_hash.cell_count().max(1) as f32,
+    );
+}
+
+/// Refreshes `Metrics::archetype_stats`. Archetypes are never removed, so only new ones
+/// allocate, known ones just get their entity count updated.
+pub fn archetype_stats_system(
+    archetypes: &Archetypes,
+    components: &Components,
+    mut metrics: ResMut<Metrics>,
+) {
+    for archetype in archetypes.iter() {
+        let index = archetype.id().index();
+        if index >= metrics.archetype_stats.len() {
+            let component_ids = archetype.components().to_vec();
+            let row_bytes = component_ids
+                .iter()
+                .filter_map(|&id| components.get_info(id))
+                .map(|info| info.layout().size())
+                .sum();
+            metrics.archetype_stats.push(ArchetypeStat {
+                id: archetype.id(),
+                components: component_ids,
+                row_bytes,
+                entity_count: 0,
+            });
+        }
+        metrics.archetype_stats[index].entity_count = archetype.entities().len();
+    }
+}
+
+/// `I` prints the ten fullest archetypes, to spot component combinations worth bundling.
+pub fn debug_archetype_report_system(
+    window: Res<WindowResource>,
+    metrics: Res<Metrics>,
+    components: &Components,
+) {
+    if !window.is_key_pressed(KeyboardKey::I) {
+        return;
+    }
+
+    let total: usize = metrics
+        .archetype_stats
+        .iter()
+        .map(ArchetypeStat::estimated_bytes)
+        .sum();
+    println!(
+        "{} archetypes, ~{}B of components",
+        metrics.archetype_stats.len(),
+        short_count(total)
+    );
+    for stat in top_archetypes(&metrics.archetype_stats, 10) {
+        let names = stat
+            .components
+            .iter()
+            .map(|&id| {
+                components
+                    .get_info(id)
+                    .map_or_else(|| format!("{id:?}"), |info| info.name().to_string())
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "  {:>6} entities  ~{:>5}B  [{}]",
+            short_count(stat.entity_count),
+            short_count(stat.estimated_bytes()),
+            names
+        );
+    }
+}
+
+/// Topmost indexed entity whose rect contains `point`, in the order `render_layers` stacks
+/// them, see `compare_draw_keys`.
+pub fn entity_at(
+    spatial_hash: &SpatialHash,
+    point: Vector2,
+    mode: LayerSortMode,
+    draw_key: impl Fn(Entity) -> DrawKey,
+) -> Option<Entity> {
+    let probe = Rectangle {
+        x: point.x,
+        y: point.y,
+        width: 0.0,
+        height: 0.0,
+    };
+    spatial_hash
+        .query_iter(probe)
+        .filter(|&entity| {
+            spatial_hash.rect(entity).is_some_and(|rect| {
+                (rect.x..=rect.x + rect.width).contains(&point.x)
+                    && (rect.y..=rect.y + rect.height).contains(&point.y)
+            })
+        })
+        .map(draw_key)
+        .max_by(|a, b| compare_draw_keys(mode, a, b))
+        .map(|key| key.entity)
+}
+
+/// What extraction would sort `entity` by. Indexed entities without a `Layer` draw on 0.
+fn draw_key(sprites: &Query<DrawKeyData>, entity: Entity) -> DrawKey {
+    let (layer, transform, render_order) = sprites.get(entity).unwrap_or_default();
+    DrawKey {
+        layer: layer.map_or(0, |layer| layer.0),
+        y: transform.map_or(0.0, |transform| transform.position.y),
+        render_order: render_order.copied().unwrap_or_default(),
+        entity,
+    }
+}
+
+type DrawKeyData = (
+    Option<&'static Layer>,
+    Option<&'static GlobalTransform>,
+    Option<&'static RenderOrder>,
+);
+
+/// Left click picks the sprite under the cursor while the inspector is on, clicking empty
+/// space clears the selection.
+pub fn select_entity_system(
+    debug_settings: Res<DebugSettings>,
+    window: Res<WindowResource>,
+    camera: Single<&Camera, With<ActiveCamera>>,
+    spatial_hash: Res<RenderHash>,
+    sort_mode: Res<LayerSortMode>,
+    sprites: Query<DrawKeyData>,
+    mut selected: ResMut<SelectedEntity>,
+) {
+    if !debug_settings.inspector || !window.is_mouse_button_pressed(MouseButton::Left) {
+        return;
+    }
+    let point = screen_to_world(&camera, window.mouse_position());
+    let key = |entity| draw_key(&sprites, entity);
+    selected.set_if_neq(SelectedEntity(entity_at(
+        &spatial_hash,
+        point,
+        *sort_mode,
+        key,
+    )));
+}
+
+/// Local position that puts a child of `parent` at `world`, or `world` itself for a root.
+pub fn world_to_local(world: Vector2, parent: Option<&GlobalTransform>) -> Vector2 {
+    parent.map_or(world, |parent| {
+        let target = GlobalTransform {
+            position: world,
+            ..*parent
+        };
+        target.relative_to(parent, Vector2::new(1.0, 1.0)).position
+    })
+}
+
+/// While `DebugSettings::drag` is on, left mouse grabs the topmost sprite under the cursor
+/// and carries it along, velocity zeroed so physics doesn't fight it. Both hashes get the
+/// new bounds on release, rather than waiting for the next physics tick.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn drag_entity_system(
+    debug_settings: Res<DebugSettings>,
+    window: Res<WindowResource>,
+    camera: Single<&Camera, With<ActiveCamera>>,
+    (mut render_hash, mut collision_hash): (ResMut<RenderHash>, ResMut<CollisionHash>),
+    sort_mode: Res<LayerSortMode>,
+    sprites: Query<DrawKeyData>,
+    shapes: Query<(Option<&Sprite>, Option<&Collider>)>,
+    mut movers: Query<(
+        &mut Transform,
+        &mut GlobalTransform,
+        Option<&mut Velocity>,
+        Option<&ChildOf>,
+    )>,
+    mut drag: ResMut<DragState>,
+) {
+    if !debug_settings.drag {
+        if drag.0.is_some() {
+            drag.0 = None;
+        }
+        return;
+    }
+    let cursor = screen_to_world(&camera, window.mouse_position());
+    if window.is_mouse_button_pressed(MouseButton::Left) {
+        let key = |entity| draw_key(&sprites, entity);
+        drag.0 = entity_at(&render_hash, cursor, *sort_mode, key).and_then(|entity| {
+            let (_, global, ..) = movers.get(entity).ok()?;
+            Some(Drag::grab(entity, global.position, cursor))
+        });
+    }
+    let Some(held) = drag.0 else {
+        return;
+    };
+
+    let parent = movers
+        .get(held.entity)
+        .ok()
+        .and_then(|(.., child_of)| child_of.map(ChildOf::parent))
+        .and_then(|parent| movers.get(parent).ok().map(|(_, global, ..)| *global));
+    // Despawned while held
+    let Ok((mut transform, mut global, velocity, _)) = movers.get_mut(held.entity) else {
+        drag.0 = None;
+        return;
+    };
+    let position = held.target(cursor);
+    transform.position = world_to_local(position, parent.as_ref());
+    // Rendering comes before the next propagation
+    global.position = position;
+    if let Some(mut velocity) = velocity {
+        velocity.0 = Vector2::new(0.0, 0.0);
+    }
+
+    if window.is_mouse_button_released(MouseButton::Left) {
+        if let Ok((sprite, collider)) = shapes.get(held.entity) {
+            if let Some(rect) = sprite.and_then(|sprite| sprite.bounds(&global)) {
+                render_hash.move_entity(held.entity, rect);
+            }
+            if let Some(collider) = collider {
+                collision_hash.move_entity(held.entity, collider.aabb(&global));
+            }
+        }
+        drag.0 = None;
+    }
+}
+
+/// Inspector rows for whatever `SelectedEntity` points at, a despawned one is deselected.
+#[allow(clippy::type_complexity)]
+pub fn inspect_selected_entity_system(
+    debug_settings: Res<DebugSettings>,
+    mut selected: ResMut<SelectedEntity>,
+    entities: Query<(
+        Option<&Transform>,
+        Option<&GlobalTransform>,
+        Option<&Velocity>,
+        Option<&Collider>,
+        Option<&Layer>,
+        Option<&EntityName>,
+    )>,
+    mut panel: ResMut<InspectorPanel>,
+) {
+    let Some(entity) = selected.0.filter(|_| debug_settings.inspector) else {
+        if !panel.0.is_empty() {
+            panel.0.clear();
+        }
+        return;
+    };
+    let Ok((transform, global, velocity, collider, layer, name)) = entities.get(entity) else {
+        selected.0 = None;
+        panel.0.clear();
+        return;
+    };
+    let vector = |v: Vector2| format!("({:.1}, {:.1})", v.x, v.y);
+    let mut rows = vec![format!("Entity {}", entity_label(entity, name))];
+    if let Some(t) = transform {
+        rows.push(format!(
+            "Transform: {} rot {:.1} scale {}",
+            vector(t.position),
+            t.rotation,
+            vector(t.scale)
+        ));
+    }
+    if let Some(g) = global {
+        rows.push(format!(
+            "GlobalTransform: {} rot {:.1} scale {}",
+            vector(g.position),
+            g.rotation,
+            vector(g.scale)
+        ));
+    }
+    if let Some(v) = velocity {
+        rows.push(format!("Velocity: {}", vector(v.0)));
+    }
+    if let Some(c) = collider {
+        let kind = match c.kind {
+            ColliderKind::Rectangle(size) => format!("rect {}", vector(size)),
+            ColliderKind::Circle(radius) => format!("circle r {radius:.1}"),
+            ColliderKind::Capsule {
+                radius,
+                half_height,
+            } => format!("capsule r {radius:.1} h {half_height:.1}"),
+        };
+        rows.push(format!("Collider: {kind} offset {}", vector(c.offset)));
+    }
+    if let Some(l) = layer {
+        rows.push(format!("Layer: {}", l.0));
+    }
+    if panel.0 != rows {
+        panel.0 = rows;
+    }
+}
+
+/// Snapshots the per-system counts from `Metrics` into the overlay table while it's shown.
+pub fn debug_system_counts_system(
+    debug_settings: Res<DebugSettings>,
+    metrics: Res<Metrics>,
+    mut table: ResMut<SystemCountsTable>,
+) {
+    if !debug_settings.show_system_counts || !metrics.is_changed() {
+        return;
+    }
+    table.0 = metrics
+        .system_count_rows()
+        .into_iter()
+        .chain(metrics.p95_rows())
+        .collect();
+}
+
+pub fn update_render_textures_size_system(
+    mut ev_resize: MessageReader<ResizeEvent>,
+    mut render_textures: ResMut<LayerTextures>,
+    mut recovery: ResMut<RenderRecovery>,
+    layer_settings: Res<LayerSettings>,
+    window_size: Res<WindowSize>,
+) {
+    // A changed render scale needs new textures as much as a resize does
+    let resized = ev_resize.read().last().map(|ev| ev.to);
+    let Some(window) = resized.or(layer_settings.is_changed().then_some(window_size.0)) else {
+        return;
+    };
+    // Failed layers are dropped, their systems recreate them on the retry
+    render_textures.0.retain(|&layer, rt| {
+        let size = layer_settings.get(layer).texture_size(window);
+        match OwnedRenderTexture::new(size.x, size.y) {
+            Ok(new_rt) => {
+                *rt = new_rt;
+                true
+            }
+            Err(err) => {
+                let error = RenderError::TextureAllocation {
+                    layer,
+                    size,
+                    reason: format!("{err:?}"),
+                };
+                println!("{error}");
+                recovery.pending_layers.insert(layer);
+                false
+            }
+        }
+    });
+}
+
+/// Quarantines entities whose `GlobalTransform` went NaN or infinite, logging each once,
+/// and lets them back in when they're finite again.
+pub fn quarantine_non_finite_system(
+    transforms: Query<
+        (
+            Entity,
+            &GlobalTransform,
+            Has<Quarantined>,
+            Option<&EntityName>,
+        ),
+        Changed<GlobalTransform>,
+    >,
+    mut commands: Commands,
+) {
+    for (entity, transform, quarantined, name) in transforms.iter() {
+        match (transform.is_finite(), quarantined) {
+            (false, false) => {
+                let label = entity_label(entity, name);
+                println!("{}", RenderError::NonFiniteTransform(label));
+                commands.entity(entity).insert(Quarantined);
+            }
+            (true, true) => {
+                commands.entity(entity).remove::<Quarantined>();
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn update_camera_offset(
+    mut ev_resize: MessageReader<ResizeEvent>,
+    camera: Single<(&mut Camera, Option<&mut CameraZoom>)>,
+    scaling: Res<CameraScalingMode>,
+    reference: Res<ReferenceResolution>,
+) {
+    let (mut camera, mut zoom) = camera.into_inner();
+    for ev in ev_resize.read() {
+        camera.offset = Vector2::new(ev.to.x as f32 / 2.0, ev.to.y as f32 / 2.0);
+        // Rescale relative to the old size so wheel zoom survives a resize
+        let (from, to) = (
+            scaling.zoom(reference.0, ev.from),
+            scaling.zoom(reference.0, ev.to),
+        );
+        // A minimized window reports a zero size
+        if from <= 0.0 || to <= 0.0 {
+            continue;
+        }
+        let ratio = to / from;
+        camera.zoom *= ratio;
+        if let Some(zoom) = zoom.as_mut() {
+            zoom.target *= ratio;
+        }
+    }
+}
+
+pub fn ensure_global_transform_system(
+    q: Query<(Entity, &Transform), Without<GlobalTransform>>,
+    mut commands: Commands,
+    mut metrics: ResMut<Metrics>,
+) {
+    let batch: Vec<(Entity, GlobalTransform)> = q
+        .iter()
+        .map(|(e, t)| (e, GlobalTransform::from_root(t)))
+        .collect();
+    metrics.global_transform_inserts = batch.len();
+    if !batch.is_empty() {
+        commands.try_insert_batch_if_new(batch);
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn sync_collider_with_sprite_system(
+    mut q: Query<
+        (&mut Collider, &Sprite, &GlobalTransform),
+        (
+            With<SyncColliderWithSprite>,
+            Or<(Changed<Sprite>, Changed<GlobalTransform>)>,
+        ),
+    >,
+) {
+    for (mut collider, sprite, global_transform) in q.iter_mut() {
+        // Capsules take their shape from the sprite, other kinds keep theirs
+        if let (ColliderKind::Capsule { .. }, SpriteKind::Rectangle { size, .. }) =
+            (&collider.kind, &sprite.kind)
+        {
+            let size = Vector2::new(size.0, size.1);
+            collider.kind = ColliderKind::capsule_fitting(size, global_transform.scale);
+        }
+        let origin = sprite.get_origin_vector();
+        collider.offset = collider.size(global_transform.scale) * origin;
+    }
+}
+
+/// Run condition: something moved, or a collider changed shape, since the system it guards
+/// last ran.
+#[allow(clippy::type_complexity)]
+pub fn any_transform_changed(
+    q: Query<
+        (),
+        Or<(
+            Changed<Transform>,
+            Changed<GlobalTransform>,
+            Changed<Collider>,
+        )>,
+    >,
+) -> bool {
+    !q.is_empty()
+}
+
+/// Run condition: the layer render textures are out of date.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn layers_need_redraw(
+    sprites: Query<
+        (),
+        Or<(
+            Changed<Sprite>,
+            Changed<Tint>,
+            Changed<GlobalTransform>,
+            Changed<Layer>,
+            Changed<Visibility>,
+            Added<OnScreen>,
+            Added<ScreenSpace>,
+            Changed<ZIndex>,
+            Changed<RenderOrder>,
+        )>,
+    >,
+    (mut removed_sprites, mut removed_on_screen, mut removed_layers): (
+        RemovedComponents<Sprite>,
+        RemovedComponents<OnScreen>,
+        RemovedComponents<Layer>,
+    ),
+    camera: Query<(), (With<ActiveCamera>, Changed<Camera>)>,
+    debug_settings: Res<DebugSettings>,
+    window_size: Res<WindowSize>,
+    textures: Res<TextureAssets>,
+    recovery: Res<RenderRecovery>,
+    debug_contacts: Option<Res<DebugContacts>>,
+    (inactive_layers, layer_settings, sort_mode): (
+        Res<InactiveLayers>,
+        Res<LayerSettings>,
+        Res<LayerSortMode>,
+    ),
+    tileset: Option<Res<TilesetResource>>,
+    mut had_tileset: Local<bool>,
+) -> bool {
+    // Tiles draw a placeholder without a tileset, so it showing up or going away counts
+    let tileset_swapped = std::mem::replace(&mut *had_tileset, tileset.is_some())
+        != tileset.is_some()
+        || tileset.is_some_and(|tileset| tileset.is_changed());
+    // Drain every reader, despawned or culled sprites are still in the cached textures
+    let removed = removed_sprites.read().count()
+        + removed_on_screen.read().count()
+        + removed_layers.read().count();
+    removed > 0
+        || !sprites.is_empty()
+        || !camera.is_empty()
+        || debug_settings.is_changed()
+        || window_size.is_changed()
+        || textures.is_changed()
+        || recovery.sprite_layers_pending()
+        || debug_contacts.is_some_and(|contacts| contacts.is_changed())
+        // Hidden layers weren't kept up to date
+        || inactive_layers.is_changed()
+        // A new scale or space recreates the textures blank
+        || layer_settings.is_changed()
+        || sort_mode.is_changed()
+        || tileset_swapped
+}
+
+/// Recomputes `FogVisibility` only when a vision caster moved, changed radius or went away,
+/// or the view moved. Only casters that can see into the view count, found through the render
+/// hash, so a caster needs a `Sprite` to clear fog.
+#[allow(clippy::type_complexity)]
+pub fn update_fog_system(
+    fog: Res<FogOfWar>,
+    mut visibility: ResMut<FogVisibility>,
+    changed: Query<
+        &VisionRadius,
+        (
+            With<Vision>,
+            Or<(
+                Changed<GlobalTransform>,
+                Changed<VisionRadius>,
+                Added<Vision>,
+            )>,
+        ),
+    >,
+    mut removed: RemovedComponents<Vision>,
+    casters: Query<(&GlobalTransform, &VisionRadius), With<Vision>>,
+    (render_hash, view): (Res<RenderHash>, Res<CameraViewRect>),
+    // Widest sight seen so far, how far outside the view a caster can still reach into it
+    mut max_radius: Local<f32>,
+) {
+    let any_removed = removed.read().count() > 0;
+    for radius in changed.iter() {
+        *max_radius = max_radius.max(radius.0);
+    }
+    if changed.is_empty() && !any_removed && !fog.is_changed() && !view.is_changed() {
+        return;
+    }
+
+    let reach = Rectangle {
+        x: view.0.x - *max_radius,
+        y: view.0.y - *max_radius,
+        width: view.0.width + *max_radius * 2.0,
+        height: view.0.height + *max_radius * 2.0,
+    };
+    let mut cells = HashSet::default();
+    for entity in render_hash.query(reach) {
+        if let Ok((transform, radius)) = casters.get(entity) {
+            cells.extend(fog.cells_in_radius(transform.position, radius.0));
+        }
+    }
+    visibility.set_if_neq(FogVisibility(cells));
+}
+
+pub fn fog_needs_redraw(
+    visibility: Res<FogVisibility>,
+    fog: Res<FogOfWar>,
+    camera: Query<(), (With<ActiveCamera>, Changed<Camera>)>,
+    window_size: Res<WindowSize>,
+    recovery: Res<RenderRecovery>,
+) -> bool {
+    visibility.is_changed()
+        || fog.is_changed()
+        || !camera.is_empty()
+        || window_size.is_changed()
+        || recovery.pending_layers.contains(&FOG_LAYER)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn lights_need_redraw(
+    lights: Query<
+        (),
+        (
+            With<PointLight>,
+            Or<(Changed<PointLight>, Changed<GlobalTransform>)>,
+        ),
+    >,
+    mut removed: RemovedComponents<PointLight>,
+    lighting: Res<Lighting>,
+    camera: Query<(), (With<ActiveCamera>, Changed<Camera>)>,
+    window_size: Res<WindowSize>,
+    recovery: Res<RenderRecovery>,
+) -> bool {
+    // Drain the removals either way so they don't count again next frame
+    removed.read().count() > 0
+        || !lights.is_empty()
+        || lighting.is_changed()
+        || !camera.is_empty()
+        || window_size.is_changed()
+        || recovery.pending_layers.contains(&LIGHTING_LAYER)
+}
+
+/// Turns collisions with a `DamageOnContact` entity into `DamageEvent`s. Either side of a
+/// collision can be the source, each (source, target) pair then waits out the cooldown.
+pub fn contact_damage_system(
+    time: Res<Time>,
+    mut ev_collisions: MessageReader<CollisionEvent>,
+    mut ev_damage: MessageWriter<DamageEvent>,
+    dealers: Query<&DamageOnContact>,
+    targets: Query<(), With<Health>>,
+    mut cooldowns: ResMut<DamageCooldowns>,
+) {
+    let dt = time.delta();
+    cooldowns.retain(|_, remaining| {
+        *remaining -= dt;
+        *remaining > 0.0
+    });
+    for ev in ev_collisions.read() {
+        for (source, target) in [(ev.a, ev.b), (ev.b, ev.a)] {
+            let Ok(damage) = dealers.get(source) else {
+                continue;
+            };
+            if !targets.contains(target) || cooldowns.contains_key(&(source, target)) {
+                continue;
+            }
+            cooldowns.insert((source, target), damage.cooldown);
+            ev_damage.write(DamageEvent {
+                target,
+                amount: damage.amount,
+                source: Some(source),
+            });
+        }
+    }
+}
+
+pub fn apply_damage_system(
+    mut ev_damage: MessageReader<DamageEvent>,
+    mut ev_death: MessageWriter<DeathEvent>,
+    mut health_q: Query<&mut Health>,
+) {
+    for ev in ev_damage.read() {
+        let Ok(mut health) = health_q.get_mut(ev.target) else {
+            continue;
+        };
+        // Already dead, don't report it twice
+        if health.current <= 0.0 {
+            continue;
+        }
+
+        health.current = (health.current - ev.amount).max(0.0);
+        if health.current == 0.0 {
+            ev_death.write(DeathEvent { entity: ev.target });
+        }
+    }
+}
+
+pub fn kill_dead_entities_system(mut ev_death: MessageReader<DeathEvent>, mut commands: Commands) {
+    for ev in ev_death.read() {
+        commands.entity(ev.entity).try_despawn();
+    }
+}
+
+pub fn update_health_bars_system(
+    mut bars: Query<(&mut Sprite, &HealthBar, &ChildOf)>,
+    health_q: Query<&Health, Changed<Health>>,
+) {
+    for (mut sprite, bar, child_of) in bars.iter_mut() {
+        let Ok(health) = health_q.get(child_of.parent()) else {
+            continue;
+        };
+        if let SpriteKind::Rectangle { size, .. } = &mut sprite.kind {
+            size.0 = bar.width * health.fraction();
+        }
+    }
+}
+
+// Observers dropping despawned entities from the spatial hashes so queries never hand them
+// out. Unlike `RemovedComponents` they see removals from any schedule, ticks or not.
+pub fn remove_collider_from_hashes(
+    remove: On<Remove, Collider>,
+    mut collision_hash: ResMut<CollisionHash>,
+    mut sweep: ResMut<CollisionSweep>,
+) {
+    collision_hash.remove(remove.entity);
+    sweep.remove(remove.entity);
+}
+
+pub fn remove_sprite_from_render_hash(
+    remove: On<Remove, Sprite>,
+    mut render_hash: ResMut<RenderHash>,
+) {
+    render_hash.remove(remove.entity);
+}
+
+pub fn add_spatial_hash_observers(world: &mut World) {
+    world.add_observer(remove_collider_from_hashes);
+    world.add_observer(remove_sprite_from_render_hash);
+}
+
+pub fn update_collision_hash_system(
+    mut spatial_hash: ResMut<CollisionHash>,
+    query: Query<
+        (Entity, &Collider, &GlobalTransform),
+        Or<(Changed<GlobalTransform>, Changed<Collider>)>,
+    >,
+    mut metrics: ResMut<Metrics>,
+) {
+    metric_scope!(metrics, "update_collision_hash");
+    let start = std::time::Instant::now();
+    let moves: Vec<(Entity, Rectangle)> = query
+        .iter()
+        .map(|(entity, collider, transform)| (entity, collider.aabb(transform)))
+        .collect();
+    metrics.collision_hash_updates = moves.len();
+    metrics.collision_hash_path = spatial_hash.apply_moves(moves);
+    metrics.collision_hash_time = start.elapsed();
+}
+
+pub fn update_render_hash_system(
+    mut spatial_hash: ResMut<RenderHash>,
+    query: Query<
+        (Entity, &Sprite, &GlobalTransform),
+        (
+            Changed<GlobalTransform>,
+            Without<ScreenSpace>,
+            Without<Quarantined>,
+        ),
+    >,
+    mut metrics: ResMut<Metrics>,
+) {
+    metric_scope!(metrics, "update_render_hash");
+    let start = std::time::Instant::now();
+    let moves: Vec<(Entity, Rectangle)> = query
+        .iter()
+        .filter_map(|(entity, sprite, transform)| Some((entity, sprite.bounds(transform)?)))
+        .collect();
+    metrics.render_hash_updates = moves.len();
+    metrics.render_hash_path = spatial_hash.apply_moves(moves);
+    metrics.render_hash_time = start.elapsed();
+}
+
+/// Only writes when the layer actually changes, so render layers aren't redrawn for nothing.
+pub fn dynamic_layer_system(
+    mut q: Query<(&Transform, &DynamicLayerAssignment, &mut Layer), Changed<Transform>>,
+) {
+    for (transform, assignment, mut layer) in q.iter_mut() {
+        layer.set_if_neq(Layer((assignment.layer_fn)(transform.position)));
+    }
+}
+
+/// Keeps `ScreenSpace` in sync with `LayerSettings`. Screen-space positions aren't world
+/// units, so those sprites leave the render hash instead of being culled against the camera.
+#[allow(clippy::type_complexity)]
+pub fn classify_screen_space_system(
+    settings: Res<LayerSettings>,
+    changed: Query<(Entity, &Layer, Has<ScreenSpace>, &Sprite, &GlobalTransform), Changed<Layer>>,
+    all: Query<(Entity, &Layer, Has<ScreenSpace>, &Sprite, &GlobalTransform)>,
+    mut render_hash: ResMut<RenderHash>,
+    mut commands: Commands,
+) {
+    let mut classify = |(entity, layer, is_screen_space, sprite, transform): (
+        Entity,
+        &Layer,
+        bool,
+        &Sprite,
+        &GlobalTransform,
+    )| {
+        let screen_space = settings.is_screen_space(layer.0);
+        if screen_space == is_screen_space {
+            return;
+        }
+        if screen_space {
+            render_hash.remove(entity);
+            commands.entity(entity).insert(ScreenSpace);
+        } else {
+            if let Some(rect) = sprite.bounds(transform) {
+                render_hash.move_entity(entity, rect);
+            }
+            commands.entity(entity).remove::<ScreenSpace>();
+        }
+    };
+    // Only a settings change can reclassify sprites whose layer stayed the same
+    if settings.is_changed() {
+        all.iter().for_each(&mut classify);
+    } else {
+        changed.iter().for_each(&mut classify);
+    }
+}
+
+/// Swaps the OS cursor for the `Cursor` sprite while `DebugSettings::hide_cursor` is on and
+/// keeps the sprite on the mouse. It's on a screen-space layer, so the window position is used
+/// as is, no camera involved.
+pub fn cursor_system(
+    debug_settings: Res<DebugSettings>,
+    window: Res<WindowResource>,
+    mut cursors: Query<(&mut Transform, &mut GlobalTransform, &mut Visibility), With<Cursor>>,
+) {
+    if debug_settings.is_changed() {
+        if debug_settings.hide_cursor {
+            window.hide_cursor();
+        } else {
+            window.show_cursor();
+        }
+    }
+    let visibility = if debug_settings.hide_cursor {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    let mouse = window.mouse_position();
+    for (mut transform, mut global, mut cursor_visibility) in cursors.iter_mut() {
+        cursor_visibility.set_if_neq(visibility);
+        // A changed transform redraws every layer, so leave it alone while the mouse rests
+        if global.position == mouse && transform.position == mouse {
+            continue;
+        }
+        // Rendering runs after the last transform propagation, set both so they agree
+        transform.position = mouse;
+        global.position = mouse;
+    }
+}
+
+pub fn layout_hud_strip_system(
+    window_size: Res<WindowSize>,
+    mut strips: Query<(&mut Transform, &mut Sprite), With<HudStrip>>,
+) {
+    if !window_size.is_changed() {
+        return;
+    }
+    let size = window_size.0.to_vector2();
+    for (mut transform, mut sprite) in strips.iter_mut() {
+        transform.position = Vector2::new(0.0, size.y - HUD_STRIP_HEIGHT);
+        sprite.kind = SpriteKind::Rectangle {
+            size: (size.x, HUD_STRIP_HEIGHT),
+            lines: false,
+        };
+    }
+}
+
+/// Keeps `DebugContacts` around only while the overlay is on and starts each tick's buffer.
+pub fn debug_contacts_tick_system(
+    debug_settings: Res<DebugSettings>,
+    debug_contacts: Option<ResMut<DebugContacts>>,
+    mut commands: Commands,
+) {
+    match (debug_settings.contacts, debug_contacts) {
+        (true, Some(mut debug_contacts)) => debug_contacts.begin_tick(),
+        (true, None) => {
+            let mut debug_contacts = DebugContacts::default();
+            debug_contacts.begin_tick();
+            commands.insert_resource(debug_contacts);
+        }
+        (false, Some(_)) => commands.remove_resource::<DebugContacts>(),
+        (false, None) => {}
+    }
+}
+
+/// Runs once per frame before physics, so the manifold holds every tick of the frame.
+pub fn clear_contact_manifold_system(mut manifold: ResMut<ContactManifold>) {
+    manifold.contacts.clear();
+}
+
+pub fn uses_sweep_prune(broadphase: Res<BroadphaseMethod>) -> bool {
+    *broadphase == BroadphaseMethod::SweepPrune
+}
+
+/// Keeps `CollisionSweep` in sync while it's the active broad phase. It goes stale while the
+/// spatial hash is used, so switching over (or a big batch of changes) rebuilds it.
+pub fn update_collision_sweep_system(
+    mut sweep: ResMut<CollisionSweep>,
+    broadphase: Res<BroadphaseMethod>,
+    changed: Query<
+        (Entity, &Collider, &GlobalTransform),
+        Or<(Changed<GlobalTransform>, Changed<Collider>)>,
+    >,
+    all: Query<(Entity, &Collider, &GlobalTransform)>,
+) {
+    if broadphase.is_changed() || changed.iter().count() > sweep.len() / 4 {
+        sweep.rebuild(
+            all.iter()
+                .map(|(entity, collider, transform)| (entity, collider.aabb(transform))),
+        );
+        return;
+    }
+
+    for (entity, collider, transform) in changed.iter() {
+        sweep.move_entity(entity, collider.aabb(transform));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_ecs::component::ComponentId;
+
+    #[derive(Resource, Default)]
+    struct RunCount(u32);
+
+    fn count_runs(mut count: ResMut<RunCount>) {
+        count.0 += 1;
+    }
+
+    /// Everything the physics systems read, ticking at 64Hz. Tests insert over it to change
+    /// a setting.
+    fn physics_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(Messages::<CollisionEvent>::default());
+        world.init_resource::<CollisionSweep>();
+        world.init_resource::<BroadphaseMethod>();
+        world.init_resource::<BakedStaticColliders>();
+        world.init_resource::<ContactManifold>();
+        world.insert_resource(Time::new(64.0));
+        world.init_resource::<Metrics>();
+        world.init_resource::<PhysicsSettings>();
+        world
+    }
+
+    #[test]
+    fn test_any_transform_changed() {
+        let mut world = World::new();
+        world.init_resource::<RunCount>();
+        let entity = world.spawn(Transform::default()).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(count_runs.run_if(any_transform_changed));
+
+        // Freshly added transforms count as changed
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+
+        // Nothing moved, the system is skipped
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+
+        world.get_mut::<Transform>(entity).unwrap().position.x += 1.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 2);
+
+        // A resized collider moves its bounds without moving the entity
+        world.entity_mut(entity).insert(Collider::default());
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 3);
+        world.get_mut::<Collider>(entity).unwrap().offset.x += 1.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 4);
+    }
+
+    fn spawn_box(world: &mut World, position: Vector2) -> Entity {
+        world
+            .spawn((
+                Sprite {
+                    origin: SpriteOrigin::TopLeft,
+                    ..Default::default()
+                },
+                Transform::default().with_position(position),
+                GlobalTransform::default(),
+                Layer::default(),
+                Velocity::default(),
+                Collider::default(),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn test_resting_boxes_sleep_and_wake() {
+        let mut world = physics_world();
+        world.insert_resource(PhysicsSettings {
+            sleep_ticks: 3,
+            ..Default::default()
+        });
+
+        let top = spawn_box(&mut world, Vector2::new(0.0, 0.0));
+        let middle = spawn_box(&mut world, Vector2::new(0.0, 33.0));
+        let bottom = spawn_box(&mut world, Vector2::new(0.0, 66.0));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                wake_system,
+                apply_velocity_system,
+                update_global_transforms_system,
+                update_collision_hash_system,
+                sleep_system,
+                update_messages_system::<CollisionEvent>,
+            )
+                .chain(),
+        );
+
+        for _ in 0..6 {
+            schedule.run(&mut world);
+        }
+        for e in [top, middle, bottom] {
+            assert!(world.get::<Sleeping>(e).is_some());
+        }
+
+        // Push the bottom box into the middle one, 4 units in one 64Hz tick
+        world.get_mut::<Velocity>(bottom).unwrap().0 = Vector2::new(0.0, -256.0);
+        schedule.run(&mut world);
+        assert!(world.get::<Sleeping>(bottom).is_none());
+        assert!(world.get::<Sleeping>(middle).is_some());
+
+        // The collision from the push wakes the neighbor on the next tick
+        schedule.run(&mut world);
+        assert!(world.get::<Sleeping>(middle).is_none());
+        assert!(world.get::<Sleeping>(top).is_some());
+    }
+
+    #[test]
+    fn test_collision_hash_ignores_decorations() {
+        let mut world = World::new();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<Metrics>();
+
+        let decoration = world
+            .spawn((Sprite::default(), GlobalTransform::default()))
+            .id();
+        let wall = world
+            .spawn((Collider::default(), GlobalTransform::default()))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((update_collision_hash_system, update_render_hash_system));
+        schedule.run(&mut world);
+
+        let collision_hash = world.resource::<CollisionHash>();
+        assert!(collision_hash.contains(wall));
+        assert!(!collision_hash.contains(decoration));
+
+        let render_hash = world.resource::<RenderHash>();
+        assert!(render_hash.contains(decoration));
+        assert!(!render_hash.contains(wall));
+    }
+
+    #[test]
+    fn test_world_stats_track_spawns_and_despawns() {
+        let mut world = World::new();
+        world.init_resource::<WorldStats>();
+        add_world_stats_observers(&mut world);
+
+        let sprites: Vec<Entity> = world
+            .spawn_batch((0..10).map(|_| Sprite::default()))
+            .collect();
+        world.spawn(Transform::default());
+        assert_eq!(world.resource::<WorldStats>().sprites, 10);
+        assert_eq!(world.resource::<WorldStats>().on_screen, 0);
+
+        for &e in &sprites[..3] {
+            world.despawn(e);
+        }
+        for &e in &sprites[3..7] {
+            world.entity_mut(e).insert(OnScreen);
+        }
+        assert_eq!(world.resource::<WorldStats>().sprites, 7);
+        assert_eq!(world.resource::<WorldStats>().on_screen, 4);
+
+        // Losing OnScreen and despawning while on screen both count
+        world.entity_mut(sprites[3]).remove::<OnScreen>();
+        world.despawn(sprites[4]);
+        world.spawn_batch((0..5).map(|_| (Sprite::default(), OnScreen)));
+        assert_eq!(world.resource::<WorldStats>().sprites, 11);
+        assert_eq!(world.resource::<WorldStats>().on_screen, 7);
+
+        // Re-inserting doesn't count twice, a spawn and despawn cancels out
+        world.entity_mut(sprites[5]).insert(OnScreen);
+        let short_lived = world.spawn((Sprite::default(), OnScreen)).id();
+        world.despawn(short_lived);
+        assert_eq!(world.resource::<WorldStats>().sprites, 11);
+        assert_eq!(world.resource::<WorldStats>().on_screen, 7);
+    }
+
+    #[test]
+    fn test_name_registry_follows_names() {
+        let mut world = World::new();
+        world.init_resource::<NameRegistry>();
+        add_name_registry_observers(&mut world);
+        let name = |name: &str| EntityName(name.to_owned());
+        let find = |world: &World, name| world.resource::<NameRegistry>().find(name);
+
+        let player = world.spawn(name("player")).id();
+        let chest = world.spawn((Transform::default(), name("chest"))).id();
+        assert_eq!(find(&world, "player"), Some(player));
+        assert_eq!(find(&world, "chest"), Some(chest));
+        assert_eq!(find(&world, "nobody"), None);
+
+        // Renaming moves it, despawning cleans up
+        world.entity_mut(chest).insert(name("box"));
+        assert_eq!(find(&world, "chest"), None);
+        assert_eq!(find(&world, "box"), Some(chest));
+        world.despawn(player);
+        assert_eq!(find(&world, "player"), None);
+        world.entity_mut(chest).remove::<EntityName>();
+        assert_eq!(find(&world, "box"), None);
+    }
+
+    #[test]
+    fn test_name_registry_duplicates_find_the_latest() {
+        let mut world = World::new();
+        world.init_resource::<NameRegistry>();
+        add_name_registry_observers(&mut world);
+        let spawn = |world: &mut World| world.spawn(EntityName("enemy".to_owned())).id();
+        let find = |world: &World| world.resource::<NameRegistry>().find("enemy");
+
+        let first = spawn(&mut world);
+        let second = spawn(&mut world);
+        let third = spawn(&mut world);
+        assert_eq!(find(&world), Some(third));
+
+        // The latest goes, the one before it takes over, the rest don't matter
+        world.despawn(third);
+        assert_eq!(find(&world), Some(second));
+        world.despawn(first);
+        assert_eq!(find(&world), Some(second));
+        world.despawn(second);
+        assert_eq!(find(&world), None);
+    }
+
+    #[derive(Resource, Default)]
+    struct Deaths(Vec<Entity>);
+
+    fn record_deaths(mut ev_death: MessageReader<DeathEvent>, mut deaths: ResMut<Deaths>) {
+        deaths.0.extend(ev_death.read().map(|ev| ev.entity));
+    }
+
+    #[test]
+    fn test_damage_and_death() {
+        let mut world = World::new();
+        world.insert_resource(Messages::<DamageEvent>::default());
+        world.insert_resource(Messages::<DeathEvent>::default());
+        world.init_resource::<Deaths>();
+
+        let target = world.spawn(Health::new(10.0)).id();
+        let bystander = world.spawn(Health::new(10.0)).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                apply_damage_system,
+                record_deaths,
+                kill_dead_entities_system,
+            )
+                .chain(),
+        );
+
+        world.write_message(DamageEvent {
+            target,
+            amount: 3.5,
+            source: Some(bystander),
+        });
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Health>(target).unwrap().current, 6.5);
+        assert_eq!(world.get::<Health>(bystander).unwrap().current, 10.0);
+        assert!(world.resource::<Deaths>().0.is_empty());
+
+        // Overkill clamps to zero and only one death is reported
+        for amount in [5.0, 5.0, 5.0] {
+            world.write_message(DamageEvent {
+                target,
+                amount,
+                source: None,
+            });
+        }
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Deaths>().0, vec![target]);
+        assert!(world.get_entity(target).is_err());
+        assert!(world.get_entity(bystander).is_ok());
+    }
+
+    #[test]
+    fn test_contact_damage_cooldown_and_death() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.insert_resource(Messages::<CollisionEvent>::default());
+        world.insert_resource(Messages::<DamageEvent>::default());
+        world.insert_resource(Messages::<DeathEvent>::default());
+        world.init_resource::<DamageCooldowns>();
+        world.init_resource::<Deaths>();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<CollisionSweep>();
+        world.init_resource::<Metrics>();
+        add_spatial_hash_observers(&mut world);
+
+        // A quarter second cooldown is 16 ticks
+        let spikes = world
+            .spawn(DamageOnContact {
+                amount: 4.0,
+                cooldown: 0.25,
+            })
+            .id();
+        let player = world
+            .spawn((
+                SpriteBundle::new(Sprite::default(), Transform::default()),
+                Collider::default(),
+                Health::new(10.0),
+            ))
+            .id();
+        let bar = world
+            .spawn((
+                SpriteBundle::new(
+                    Sprite {
+                        kind: SpriteKind::Rectangle {
+                            size: (30.0, 4.0),
+                            lines: false,
+                        },
+                        ..Default::default()
+                    },
+                    Transform::default(),
+                ),
+                HealthBar { width: 30.0 },
+                ChildOf(player),
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_collision_hash_system,
+                update_render_hash_system,
+                contact_damage_system,
+                apply_damage_system,
+                update_health_bars_system,
+                record_deaths,
+                kill_dead_entities_system,
+            )
+                .chain(),
+        );
+        let mut touch = |world: &mut World| {
+            // The mover reports the hit, the dealer can be on either side
+            world.write_message(CollisionEvent {
+                a: player,
+                b: spikes,
+            });
+            schedule.run(world);
+            world.resource_mut::<Messages<CollisionEvent>>().update();
+        };
+        let bar_width = |world: &World| match world.get::<Sprite>(bar).unwrap().kind {
+            SpriteKind::Rectangle { size, .. } => size.0,
+            _ => unreachable!(),
+        };
+
+        touch(&mut world);
+        assert_eq!(world.get::<Health>(player).unwrap().current, 6.0);
+        assert_eq!(bar_width(&world), 18.0);
+        assert!(world.resource::<CollisionHash>().contains(player));
+
+        // Touching every tick doesn't hurt again until the cooldown runs out
+        for _ in 0..15 {
+            touch(&mut world);
+        }
+        assert_eq!(world.get::<Health>(player).unwrap().current, 6.0);
+        touch(&mut world);
+        assert_eq!(world.get::<Health>(player).unwrap().current, 2.0);
+        assert!(world.resource::<Deaths>().0.is_empty());
+
+        // The killing blow despawns the player and its bar, and clears both hashes
+        for _ in 0..16 {
+            touch(&mut world);
+        }
+        assert_eq!(world.resource::<Deaths>().0, vec![player]);
+        assert!(world.get_entity(player).is_err());
+        assert!(world.get_entity(bar).is_err());
+        // Removals are picked up by the next run
+        touch(&mut world);
+        assert!(!world.resource::<CollisionHash>().contains(player));
+        assert!(!world.resource::<RenderHash>().contains(bar));
+    }
+
+    #[derive(Resource, Default)]
+    struct FogUpdates(u32);
+
+    fn count_fog_updates(visibility: Res<FogVisibility>, mut updates: ResMut<FogUpdates>) {
+        if visibility.is_changed() {
+            updates.0 += 1;
+        }
+    }
+
+    #[test]
+    fn test_fog_only_updates_when_casters_change() {
+        let mut world = World::new();
+        world.insert_resource(FogOfWar {
+            resolution: 10,
+            ..Default::default()
+        });
+        world.init_resource::<FogVisibility>();
+        world.init_resource::<FogUpdates>();
+        world.insert_resource(CameraViewRect(Rectangle {
+            x: -100.0,
+            y: -100.0,
+            width: 200.0,
+            height: 200.0,
+        }));
+
+        let at = |x, y| {
+            GlobalTransform::from_root(&Transform::default().with_position(Vector2::new(x, y)))
+        };
+        let point = |x, y| Rectangle {
+            x,
+            y,
+            width: 1.0,
+            height: 1.0,
+        };
+        let caster = world.spawn((Vision, VisionRadius(8.0), at(5.0, 5.0))).id();
+        // Moving things without vision never touch the fog
+        let bystander = world.spawn(at(500.0, 500.0)).id();
+        // Too far from the view to see into it, never looked at
+        let distant = world
+            .spawn((Vision, VisionRadius(8.0), at(500.0, 5.0)))
+            .id();
+        let mut hash = SpatialHash::flat(96.0);
+        hash.insert(caster, point(5.0, 5.0));
+        hash.insert(bystander, point(500.0, 500.0));
+        hash.insert(distant, point(500.0, 5.0));
+        world.insert_resource(RenderHash(hash));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((update_fog_system, count_fog_updates).chain());
+
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FogUpdates>().0, 1);
+        assert!(world.resource::<FogVisibility>().0.contains(&(0, 0)));
+        assert!(!world.resource::<FogVisibility>().0.contains(&(50, 50)));
+        assert!(!world.resource::<FogVisibility>().0.contains(&(50, 0)));
+
+        schedule.run(&mut world);
+        world
+            .get_mut::<GlobalTransform>(bystander)
+            .unwrap()
+            .position
+            .x += 10.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FogUpdates>().0, 1);
+
+        world.get_mut::<GlobalTransform>(caster).unwrap().position.x += 20.0;
+        world
+            .resource_mut::<RenderHash>()
+            .update(caster, point(25.0, 5.0));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FogUpdates>().0, 2);
+        assert!(world.resource::<FogVisibility>().0.contains(&(2, 0)));
+        assert!(!world.resource::<FogVisibility>().0.contains(&(0, 0)));
+
+        // Panning over to the distant caster brings its sight in
+        world.resource_mut::<CameraViewRect>().0.x = 400.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FogUpdates>().0, 3);
+        assert!(world.resource::<FogVisibility>().0.contains(&(50, 0)));
+        assert!(!world.resource::<FogVisibility>().0.contains(&(2, 0)));
+
+        world.resource_mut::<CameraViewRect>().0.x = -100.0;
+        world.despawn(caster);
+        world.resource_mut::<RenderHash>().remove(caster);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<FogUpdates>().0, 4);
+        assert!(world.resource::<FogVisibility>().0.is_empty());
+    }
+
+    #[test]
+    fn test_bundles_index_and_collide() {
+        let mut world = physics_world();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        let mut debug_contacts = DebugContacts::default();
+        debug_contacts.begin_tick();
+        world.insert_resource(debug_contacts);
+
+        let wall = world
+            .spawn(StaticColliderBundle::new(
+                Collider::default(),
+                Transform::default().with_position(Vector2::new(50.0, 0.0)),
+            ))
+            .id();
+        let mover = world
+            .spawn((
+                SpriteBundle::new(
+                    Sprite {
+                        origin: SpriteOrigin::TopLeft,
+                        ..Default::default()
+                    },
+                    Transform::default().with_position(Vector2::new(10.0, 0.0)),
+                ),
+                Collider::default(),
+                Velocity(Vector2::new(1280.0, 0.0)),
+            ))
+            .id();
+        let label = world
+            .spawn(TextBundle::new(
+                Text::new("label", 20, Color::WHITE),
+                Transform::default().with_position(Vector2::new(5.0, 6.0)),
+            ))
+            .id();
+
+        // Bundles start with a matching GlobalTransform, no propagation pass needed
+        assert_eq!(world.get::<GlobalTransform>(label).unwrap().position.x, 5.0);
+        assert_eq!(world.get::<GlobalTransform>(wall).unwrap().position.x, 50.0);
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_collision_hash_system,
+                update_render_hash_system,
+                apply_velocity_system,
+                update_global_transforms_system,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+
+        // The invisible wall is only in the collision hash
+        assert!(world.resource::<CollisionHash>().contains(wall));
+        assert!(!world.resource::<RenderHash>().contains(wall));
+        assert!(world.resource::<RenderHash>().contains(mover));
+        assert!(!world.resource::<RenderHash>().contains(label));
+
+        // The mover is stopped flush against the wall instead of passing through it
+        let x = world.get::<Transform>(mover).unwrap().position.x;
+        assert_eq!(x, 50.0 - 32.0);
+        assert_eq!(world.get::<Visibility>(mover), Some(&Visibility::Visible));
+
+        // The hit is recorded with the overlap from before the push-out
+        let manifold = world.resource::<ContactManifold>();
+        assert_eq!(manifold.contacts.len(), 1);
+        let contact = manifold.contacts[0];
+        assert_eq!((contact.entity_a, contact.entity_b), (mover, wall));
+        assert_eq!((contact.normal.x, contact.normal.y), (-1.0, 0.0));
+        assert_eq!(contact.depth, 12.0);
+        assert_eq!((contact.point.x, contact.point.y), (56.0, 16.0));
+
+        // The same contact is kept for debug drawing, at full alpha on its first tick
+        let recorded: Vec<_> = world.resource::<DebugContacts>().faded().collect();
+        assert_eq!(recorded.len(), 1);
+        assert_eq!(
+            (recorded[0].0.entity_a, recorded[0].0.entity_b),
+            (mover, wall)
+        );
+        assert_eq!(recorded[0].1, 1.0);
+
+        let mut clear = Schedule::default();
+        clear.add_systems(clear_contact_manifold_system);
+        clear.run(&mut world);
+        assert!(world.resource::<ContactManifold>().contacts.is_empty());
+    }
+
+    #[derive(Resource, Default)]
+    struct TextChanges(u32);
+
+    fn count_text_changes(changed: Query<(), Changed<Text>>, mut changes: ResMut<TextChanges>) {
+        changes.0 += changed.iter().count() as u32;
+    }
+
+    #[test]
+    fn test_text_only_changes_with_content() {
+        let mut world = World::new();
+        world.init_resource::<WorldStats>();
+        world.init_resource::<TextChanges>();
+        world.spawn((
+            Camera(Camera2D {
+                zoom: 1.0,
+                ..Default::default()
+            }),
+            ActiveCamera,
+        ));
+        let count = world
+            .spawn((Text::new("", 20, Color::WHITE), CountText))
+            .id();
+        let on_screen = world
+            .spawn((Text::new("", 20, Color::WHITE), OnScreenText))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                (update_count_text_system, update_on_screen_text_system),
+                count_text_changes,
+            )
+                .chain(),
+        );
+
+        // First run fills both texts, spawning already counts as a change
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<TextChanges>().0, 2);
+        assert_eq!(world.get::<Text>(count).unwrap().content, "Count: 0");
+
+        for _ in 0..3 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(world.resource::<TextChanges>().0, 2);
+
+        // Same values written again don't flag anything
+        world.resource_mut::<WorldStats>().set_changed();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<TextChanges>().0, 2);
+
+        world.resource_mut::<WorldStats>().on_screen = 7;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<TextChanges>().0, 3);
+        assert_eq!(
+            world.get::<Text>(on_screen).unwrap().content,
+            "On Screen: 7 1.00"
+        );
+        assert_eq!(world.get::<Text>(count).unwrap().content, "Count: 0");
+
+        // The player, its health bar and the HUD aren't part of the count
+        add_world_stats_observers(&mut world);
+        let sprite = || SpriteBundle::new(Sprite::default(), Transform::default());
+        world.spawn(sprite());
+        world.spawn(sprite());
+        let player = world.spawn((sprite(), Player)).id();
+        world.spawn((sprite(), HealthBar { width: 30.0 }, ChildOf(player)));
+        world.spawn((sprite(), ScreenSpace));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<WorldStats>().sprites, 5);
+        assert_eq!(world.get::<Text>(count).unwrap().content, "Count: 2");
+    }
+
+    #[test]
+    fn test_force_fields_push_overlapping_movers() {
+        let mut world = World::new();
+        world.insert_resource(CollisionHash(SpatialHash::flat(96.0)));
+        world.insert_resource(Time::new(64.0));
+        let field_collider = || Collider {
+            kind: ColliderKind::Rectangle(Vector2::new(100.0, 100.0)),
+            ..Default::default()
+        };
+        let spawn_field = |world: &mut World, falloff| {
+            let field = world
+                .spawn((
+                    StaticColliderBundle::new(field_collider(), Transform::default()),
+                    ForceField {
+                        force: Vector2::new(640.0, 0.0),
+                        falloff,
+                    },
+                ))
+                .id();
+            let area = field_collider().aabb(&GlobalTransform::default());
+            world.resource_mut::<CollisionHash>().insert(field, area);
+            field
+        };
+        // 10x10 movers centered on `center`
+        let spawn_mover = |world: &mut World, center: Vector2| {
+            let collider = Collider {
+                kind: ColliderKind::Rectangle(Vector2::new(10.0, 10.0)),
+                offset: Vector2::new(5.0, 5.0),
+            };
+            let transform = Transform::default().with_position(center);
+            let global = GlobalTransform::from_root(&transform);
+            let rect = collider.aabb(&global);
+            let mover = world
+                .spawn((transform, global, collider, Velocity::default()))
+                .id();
+            world.resource_mut::<CollisionHash>().insert(mover, rect);
+            mover
+        };
+        let velocity = |world: &World, mover| world.get::<Velocity>(mover).unwrap().0;
+
+        let field = spawn_field(&mut world, ForceFalloff::Linear);
+        let center = spawn_mover(&mut world, Vector2::new(50.0, 50.0));
+        let halfway = spawn_mover(&mut world, Vector2::new(75.0, 50.0));
+        let outside = spawn_mover(&mut world, Vector2::new(300.0, 50.0));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(apply_force_fields_system);
+        schedule.run(&mut world);
+        // 640 px/s² over a 64th of a second, scaled by the falloff
+        assert_eq!(velocity(&world, center), Vector2::new(10.0, 0.0));
+        assert_eq!(velocity(&world, halfway), Vector2::new(5.0, 0.0));
+        assert_eq!(velocity(&world, outside), Vector2::new(0.0, 0.0));
+
+        // Without falloff the edge gets it all too
+        world.despawn(field);
+        world.resource_mut::<CollisionHash>().remove(field);
+        spawn_field(&mut world, ForceFalloff::None);
+        let edge = spawn_mover(&mut world, Vector2::new(98.0, 50.0));
+        schedule.run(&mut world);
+        assert_eq!(velocity(&world, edge), Vector2::new(10.0, 0.0));
+        assert_eq!(velocity(&world, outside), Vector2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn test_sweep_prune_broadphase_blocks_movers() {
+        let mut world = physics_world();
+        // The hash stays empty, so only the sweep can report the wall
+        world.insert_resource(BroadphaseMethod::SweepPrune);
+
+        let wall = world
+            .spawn(StaticColliderBundle::new(
+                Collider::default(),
+                Transform::default().with_position(Vector2::new(40.0, 0.0)),
+            ))
+            .id();
+        let mover = world
+            .spawn((
+                Transform::default(),
+                GlobalTransform::default(),
+                Collider::default(),
+                Velocity(Vector2::new(1280.0, 0.0)),
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_collision_sweep_system.run_if(uses_sweep_prune),
+                apply_velocity_system,
+                update_global_transforms_system,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+        assert!(world.resource::<CollisionSweep>().contains(wall));
+        assert!(world.resource::<CollisionHash>().is_empty());
+        assert_eq!(world.get::<Transform>(mover).unwrap().position.x, 8.0);
+
+        // Despawned colliders leave the sweep too
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        add_spatial_hash_observers(&mut world);
+        world.despawn(wall);
+        assert!(!world.resource::<CollisionSweep>().contains(wall));
+    }
+
+    #[test]
+    fn test_velocity_is_per_second() {
+        for rate in [30.0, 120.0] {
+            let mut world = physics_world();
+            world.insert_resource(Time::new(rate));
+
+            let ghost = world
+                .spawn((
+                    Transform::default(),
+                    GlobalTransform::default(),
+                    Velocity(Vector2::new(60.0, 0.0)),
+                ))
+                .id();
+            let solid = world
+                .spawn((
+                    Transform::default().with_position(Vector2::new(0.0, 500.0)),
+                    GlobalTransform::default(),
+                    Collider::default(),
+                    Velocity(Vector2::new(60.0, 0.0)),
+                ))
+                .id();
+
+            let mut schedule = Schedule::default();
+            schedule.add_systems((apply_velocity_system, update_global_transforms_system).chain());
+            for _ in 0..rate as u32 {
+                schedule.run(&mut world);
+            }
+
+            for entity in [ghost, solid] {
+                let x = world.get::<Transform>(entity).unwrap().position.x;
+                assert!((x - 60.0).abs() < 1e-3, "{rate}Hz moved {x}");
+            }
+        }
+    }
+
+    /// `cargo test --release bench_broad_phase -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_broad_phase_ignores_decorations() {
+        const MOVERS: usize = 10_000;
+        const TICKS: usize = 60;
+        let grid = |i: usize| Vector2::new(35.0 * (i % 100) as f32, 35.0 * (i / 100) as f32);
+
+        for decorations in [0, 400_000] {
+            let mut world = physics_world();
+            world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+
+            world.spawn_batch((0..MOVERS).map(|i| {
+                (
+                    Transform::default().with_position(grid(i)),
+                    GlobalTransform::default(),
+                    Collider::default(),
+                    Velocity(Vector2::new(60.0, 0.0)),
+                )
+            }));
+            // Sprites only, packed between the movers
+            world.spawn_batch((0..decorations).map(|i| {
+                let position = grid(i % MOVERS) + Vector2::new(16.0, 16.0);
+                SpriteBundle::new(
+                    Sprite::default(),
+                    Transform::default().with_position(position),
+                )
+            }));
+            let mut index = Schedule::default();
+            index.add_systems(
+                (
+                    update_global_transforms_system,
+                    update_collision_hash_system,
+                    update_render_hash_system,
+                )
+                    .chain(),
+            );
+            index.run(&mut world);
+
+            let mut physics = Schedule::default();
+            physics.add_systems(apply_velocity_system);
+            let mut elapsed = std::time::Duration::ZERO;
+            for _ in 0..TICKS {
+                let start = std::time::Instant::now();
+                physics.run(&mut world);
+                elapsed += start.elapsed();
+                index.run(&mut world);
+            }
+            println!("{decorations} decorations: {elapsed:?} moving for {TICKS} ticks");
+        }
+    }
+
+    #[test]
+    fn test_world_bounds_policies_at_edges_and_corners() {
+        // Bounds are 0..100 on both axes, one step out past each edge and corner
+        let outside = [
+            (-10.0, 50.0),
+            (110.0, 50.0),
+            (50.0, -10.0),
+            (50.0, 110.0),
+            (-10.0, -10.0),
+            (110.0, -10.0),
+            (-10.0, 110.0),
+            (110.0, 110.0),
+        ];
+        let run = |behavior: Option<BoundsBehavior>, default: Option<BoundsBehavior>| {
+            let mut world = World::new();
+            world.insert_resource(WorldBounds(Rectangle {
+                x: 0.0,
+                y: 0.0,
+                width: 100.0,
+                height: 100.0,
+            }));
+            world.insert_resource(PhysicsSettings {
+                bounds_behavior: default,
+                ..Default::default()
+            });
+            world.insert_resource(CollisionHash(SpatialHash::flat(16.0)));
+            world.init_resource::<Metrics>();
+            let movers: Vec<Entity> = outside
+                .iter()
+                .map(|&(x, y)| {
+                    let transform = Transform::default().with_position(Vector2::new(x, y));
+                    let mut mover = world.spawn((
+                        transform,
+                        GlobalTransform::from_root(&transform),
+                        Velocity(Vector2::new(x - 50.0, y - 50.0)),
+                        Collider {
+                            kind: ColliderKind::Rectangle(Vector2::new(1.0, 1.0)),
+                            ..Default::default()
+                        },
+                    ));
+                    if let Some(behavior) = behavior {
+                        mover.insert(behavior);
+                    }
+                    mover.id()
+                })
+                .collect();
+            let mut schedule = Schedule::default();
+            schedule.add_systems(
+                (
+                    update_collision_hash_system,
+                    enforce_world_bounds_system,
+                    update_global_transforms_system,
+                    update_collision_hash_system,
+                )
+                    .chain(),
+            );
+            schedule.run(&mut world);
+            (world, movers)
+        };
+        let state = |world: &World, entity: Entity| {
+            let position = world.get::<Transform>(entity)?.position;
+            let velocity = world.get::<Velocity>(entity)?.0;
+            Some((position.x, position.y, velocity.x, velocity.y))
+        };
+
+        // The component wins over the default
+        let (world, movers) = run(Some(BoundsBehavior::Clamp), Some(BoundsBehavior::Despawn));
+        let clamped = [
+            (0.0, 50.0, 0.0, 0.0),
+            (100.0, 50.0, 0.0, 0.0),
+            (50.0, 0.0, 0.0, 0.0),
+            (50.0, 100.0, 0.0, 0.0),
+            (0.0, 0.0, 0.0, 0.0),
+            (100.0, 0.0, 0.0, 0.0),
+            (0.0, 100.0, 0.0, 0.0),
+            (100.0, 100.0, 0.0, 0.0),
+        ];
+        for (&mover, expected) in movers.iter().zip(clamped) {
+            assert_eq!(state(&world, mover), Some(expected));
+        }
+
+        let (world, movers) = run(None, Some(BoundsBehavior::Wrap));
+        let wrapped = [
+            (90.0, 50.0),
+            (10.0, 50.0),
+            (50.0, 90.0),
+            (50.0, 10.0),
+            (90.0, 90.0),
+            (10.0, 90.0),
+            (90.0, 10.0),
+            (10.0, 10.0),
+        ];
+        let hash = world.resource::<CollisionHash>();
+        for ((&mover, (x, y)), old) in movers.iter().zip(wrapped).zip(outside) {
+            let (px, py, vx, vy) = state(&world, mover).unwrap();
+            assert_eq!((px, py), (x, y));
+            // Wrapping keeps the velocity
+            assert_eq!((vx, vy), (old.0 - 50.0, old.1 - 50.0));
+            let at = |(x, y): (f32, f32)| Rectangle {
+                x,
+                y,
+                width: 1.0,
+                height: 1.0,
+            };
+            assert!(hash.query_strict(at((x, y))).contains(&mover));
+            assert!(!hash.query_strict(at(old)).contains(&mover));
+        }
+
+        let (world, movers) = run(Some(BoundsBehavior::Despawn), None);
+        assert!(movers.iter().all(|&mover| world.get_entity(mover).is_err()));
+        let (world, movers) = run(None, None);
+        for (&mover, (x, y)) in movers.iter().zip(outside) {
+            assert_eq!(state(&world, mover).map(|s| (s.0, s.1)), Some((x, y)));
+        }
+    }
+
+    #[test]
+    fn test_sync_fits_capsules_to_rectangle_sprites() {
+        let mut world = World::new();
+        let sprite = |width, height| Sprite {
+            kind: SpriteKind::Rectangle {
+                size: (width, height),
+                lines: false,
+            },
+            origin: SpriteOrigin::Bottom,
+            ..Default::default()
+        };
+        let capsule = world
+            .spawn((
+                SpriteBundle::new(sprite(20.0, 60.0), Transform::default()),
+                Collider {
+                    kind: ColliderKind::Capsule {
+                        radius: 1.0,
+                        half_height: 1.0,
+                    },
+                    ..Default::default()
+                },
+                SyncColliderWithSprite,
+            ))
+            .id();
+        let boxed = world
+            .spawn((
+                SpriteBundle::new(sprite(20.0, 60.0), Transform::default()),
+                Collider::default(),
+                SyncColliderWithSprite,
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(sync_collider_with_sprite_system);
+        schedule.run(&mut world);
+
+        let collider = world.get::<Collider>(capsule).unwrap();
+        let ColliderKind::Capsule {
+            radius,
+            half_height,
+        } = collider.kind
+        else {
+            panic!("capsule became {:?}", collider.kind);
+        };
+        assert_eq!((radius, half_height), (10.0, 20.0));
+        // Bottom origin, the feet stay on the transform
+        assert_eq!((collider.offset.x, collider.offset.y), (10.0, 60.0));
+        // Rectangles are left to whoever set them
+        let collider = world.get::<Collider>(boxed).unwrap();
+        assert!(matches!(collider.kind, ColliderKind::Rectangle(size) if size.x == 32.0));
+    }
+
+    #[test]
+    fn test_capsule_walks_over_tile_seam() {
+        let walk = |kind: ColliderKind| {
+            let mut world = physics_world();
+            // The second tile sits a pixel higher than the one the mover stands on
+            for (x, y) in [(-100.0, 40.0), (20.0, 39.0)] {
+                world.spawn(StaticColliderBundle::new(
+                    Collider {
+                        kind: ColliderKind::Rectangle(Vector2::new(120.0, 10.0)),
+                        ..Default::default()
+                    },
+                    Transform::default().with_position(Vector2::new(x, y)),
+                ));
+            }
+            let transform = Transform::default();
+            let mover = world
+                .spawn((
+                    transform,
+                    GlobalTransform::from_root(&transform),
+                    Collider {
+                        kind,
+                        ..Default::default()
+                    },
+                    Velocity(Vector2::new(64.0, 0.0)),
+                ))
+                .id();
+
+            let mut schedule = Schedule::default();
+            schedule.add_systems(
+                (
+                    update_collision_hash_system,
+                    apply_velocity_system,
+                    update_global_transforms_system,
+                )
+                    .chain(),
+            );
+            for _ in 0..30 {
+                schedule.run(&mut world);
+            }
+            let position = world.get::<Transform>(mover).unwrap().position;
+            (position.x, position.y)
+        };
+
+        // Same 20x40 bounds, the box snags on the seam and the capsule rides up onto it
+        let (x, _) = walk(ColliderKind::Rectangle(Vector2::new(20.0, 40.0)));
+        assert_eq!(x, 0.0);
+        let (x, y) = walk(ColliderKind::Capsule {
+            radius: 10.0,
+            half_height: 10.0,
+        });
+        assert!(x > 25.0, "capsule stopped at {x}");
+        assert!(y + 40.0 <= 39.0 + 1e-3, "capsule sank into the tile at {y}");
+    }
+
+    #[test]
+    fn test_dynamic_layer_follows_position() {
+        let mut world = World::new();
+        let entity = world
+            .spawn((
+                SpriteBundle::new(
+                    Sprite::default(),
+                    Transform::default().with_position(Vector2::new(0.0, 250.0)),
+                ),
+                DynamicLayerAssignment {
+                    layer_fn: |pos| (pos.y / 100.0) as u32,
+                },
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(dynamic_layer_system);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Layer>(entity), Some(&Layer(2)));
+
+        // Moving within the same band leaves the layer untouched
+        world.clear_trackers();
+        world.get_mut::<Transform>(entity).unwrap().position.y = 290.0;
+        schedule.run(&mut world);
+        assert!(
+            !world
+                .entity(entity)
+                .get_ref::<Layer>()
+                .unwrap()
+                .is_changed()
+        );
+
+        world.get_mut::<Transform>(entity).unwrap().position.y = 420.0;
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Layer>(entity), Some(&Layer(4)));
+    }
+
+    #[derive(Resource, Default)]
+    struct ViewEvents(Vec<(bool, Entity)>);
+
+    fn record_view_events(
+        mut ev_entered: MessageReader<EnteredView>,
+        mut ev_left: MessageReader<LeftView>,
+        mut events: ResMut<ViewEvents>,
+    ) {
+        events.0.extend(ev_entered.read().map(|ev| (true, ev.0)));
+        events.0.extend(ev_left.read().map(|ev| (false, ev.0)));
+    }
+
+    #[test]
+    fn test_view_events_follow_camera() {
+        let mut world = World::new();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(Messages::<EnteredView>::default());
+        world.insert_resource(Messages::<LeftView>::default());
+        world.insert_resource(Time::new(64.0));
+        world.init_resource::<Metrics>();
+        world.init_resource::<ViewEvents>();
+        let camera = world
+            .spawn((
+                Camera(Camera2D {
+                    offset: Vector2::new(50.0, 50.0),
+                    zoom: 1.0,
+                    ..Default::default()
+                }),
+                ActiveCamera,
+            ))
+            .id();
+        // One entity every 300 units, the view only ever covers one of them
+        let grid: Vec<Entity> = (0..3)
+            .map(|i| {
+                world
+                    .spawn((
+                        SpriteBundle::new(
+                            Sprite {
+                                origin: SpriteOrigin::TopLeft,
+                                ..Default::default()
+                            },
+                            Transform::default().with_position(Vector2::new(i as f32 * 300.0, 0.0)),
+                        ),
+                        FlashOnEnterView,
+                    ))
+                    .id()
+            })
+            .collect();
+        // The last one already has a tint of its own
+        world.entity_mut(grid[2]).insert(Tint(Color::BLUE));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_render_hash_system,
+                compute_camera_view_system,
+                update_on_screen_system,
+                (flash_on_enter_view_system, tick_view_flash_system).chain(),
+                record_view_events,
+            )
+                .chain(),
+        );
+        let mut step = |world: &mut World, target_x: f32| {
+            world.get_mut::<Camera>(camera).unwrap().0.target = Vector2::new(target_x, 0.0);
+            schedule.run(world);
+            std::mem::take(&mut world.resource_mut::<ViewEvents>().0)
+        };
+
+        assert_eq!(step(&mut world, 0.0), vec![(true, grid[0])]);
+        assert!(world.get::<OnScreen>(grid[0]).is_some());
+        assert!(world.get::<Tint>(grid[0]).is_some());
+        assert_eq!(
+            step(&mut world, 300.0),
+            vec![(true, grid[1]), (false, grid[0])]
+        );
+        assert_eq!(
+            step(&mut world, 600.0),
+            vec![(true, grid[2]), (false, grid[1])]
+        );
+        assert!(world.get::<OnScreen>(grid[1]).is_none());
+        assert!(step(&mut world, 600.0).is_empty());
+
+        // Out and back before the system runs is no flip at all
+        world.get_mut::<Camera>(camera).unwrap().0.target = Vector2::new(5000.0, 0.0);
+        assert!(step(&mut world, 600.0).is_empty());
+
+        // Hiding counts as leaving the view
+        *world.get_mut::<Visibility>(grid[2]).unwrap() = Visibility::Hidden;
+        assert_eq!(step(&mut world, 600.0), vec![(false, grid[2])]);
+        *world.get_mut::<Visibility>(grid[2]).unwrap() = Visibility::Visible;
+        assert_eq!(step(&mut world, 600.0), vec![(true, grid[2])]);
+
+        // The flash from entering lasts half a second of ticks, the first one included
+        for _ in 0..30 {
+            step(&mut world, 600.0);
+        }
+        let tint = |world: &World| world.get::<Tint>(grid[2]).map(|tint| tint.0.b);
+        assert_eq!(tint(&world), Some(Color::GREEN.b));
+        step(&mut world, 600.0);
+        assert_eq!(tint(&world), Some(Color::BLUE.b));
+        assert!(world.get::<ViewFlash>(grid[2]).is_none());
+        // Without its own tint the flash leaves none behind
+        assert!(world.get::<Tint>(grid[0]).is_none());
+
+        // Only opted in entities flash
+        world.entity_mut(grid[1]).remove::<FlashOnEnterView>();
+        assert_eq!(
+            step(&mut world, 300.0),
+            vec![(true, grid[1]), (false, grid[2])]
+        );
+        assert!(world.get::<ViewFlash>(grid[1]).is_none());
+    }
+
+    /// Two movers charging at each other between walls, returns their x after one 64Hz tick.
+    fn squeeze(
+        priorities: (i32, i32),
+        spawn_right_first: bool,
+        order: ResolutionOrder,
+    ) -> (f32, f32) {
+        let mut world = physics_world();
+        world.insert_resource(PhysicsSettings {
+            resolution_order: order,
+            ..Default::default()
+        });
+
+        for x in [0.0, 200.0] {
+            world.spawn(StaticColliderBundle::new(
+                Collider::default(),
+                Transform::default().with_position(Vector2::new(x, 0.0)),
+            ));
+        }
+        // 64 units per tick each, but only 104 units of room between them
+        let mut spawn_mover = |x: f32, speed: f32, priority: i32| {
+            let transform = Transform::default().with_position(Vector2::new(x, 0.0));
+            world
+                .spawn((
+                    transform,
+                    GlobalTransform::from_root(&transform),
+                    Collider::default(),
+                    Velocity(Vector2::new(speed, 0.0)),
+                    CollisionPriority(priority),
+                ))
+                .id()
+        };
+        let (left, right) = if spawn_right_first {
+            let right = spawn_mover(168.0, -4096.0, priorities.1);
+            (spawn_mover(32.0, 4096.0, priorities.0), right)
+        } else {
+            let left = spawn_mover(32.0, 4096.0, priorities.0);
+            (left, spawn_mover(168.0, -4096.0, priorities.1))
+        };
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((update_collision_hash_system, apply_velocity_system).chain());
+        schedule.run(&mut world);
+
+        let x = |e: Entity| world.get::<Transform>(e).unwrap().position.x;
+        (x(left), x(right))
+    }
+
+    #[test]
+    fn test_collision_priority_decides_squeezes() {
+        // The higher priority mover gets its full step, whatever the spawn order
+        for spawn_right_first in [false, true] {
+            let order = ResolutionOrder::Priority;
+            assert_eq!(squeeze((5, 0), spawn_right_first, order), (96.0, 128.0));
+            assert_eq!(squeeze((0, 5), spawn_right_first, order), (72.0, 104.0));
+        }
+
+        // Same sizes under mass ordering, the older entity goes first
+        let order = ResolutionOrder::Mass;
+        assert_eq!(squeeze((0, 5), false, order), (96.0, 128.0));
+        assert_eq!(squeeze((0, 5), true, order), (72.0, 104.0));
+    }
+
+    #[test]
+    fn test_compound_collider_moves_as_one_body() {
+        let mut world = physics_world();
+
+        // A wall whose bottom right corner the L has to get around
+        world.spawn(StaticColliderBundle::new(
+            Collider {
+                kind: ColliderKind::Rectangle(Vector2::new(100.0, 130.0)),
+                ..Default::default()
+            },
+            Transform::default().with_position(Vector2::new(100.0, -100.0)),
+        ));
+        // An L: a 20x60 bar with a 60x20 foot sticking out to the right below the wall
+        let root = world
+            .spawn((
+                Transform::default(),
+                GlobalTransform::default(),
+                Velocity(Vector2::new(640.0, 64.0)),
+                CompoundCollider,
+            ))
+            .id();
+        let mut part = |size: Vector2, at: Vector2| {
+            world
+                .spawn((
+                    Transform::default().with_position(at),
+                    GlobalTransform::default(),
+                    Collider {
+                        kind: ColliderKind::Rectangle(size),
+                        ..Default::default()
+                    },
+                    ChildOf(root),
+                ))
+                .id()
+        };
+        part(Vector2::new(20.0, 60.0), Vector2::new(0.0, 0.0));
+        let foot = part(Vector2::new(60.0, 20.0), Vector2::new(0.0, 40.0));
+
+        // Baked like in the real schedule, the parts mustn't end up as walls of their own
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_global_transforms_system,
+                update_collision_hash_system,
+                crate::bake::mark_bake_dirty_system,
+                crate::bake::bake_static_colliders_system.run_if(crate::bake::bake_is_dirty),
+                apply_velocity_system,
+            )
+                .chain(),
+        );
+        let position = |world: &World| world.get::<Transform>(root).unwrap().position;
+
+        // The bar stops against the wall while the foot already reaches under it, the
+        // bounds alone would have stopped the whole L at x = 40
+        for _ in 0..30 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world), Vector2::new(80.0, 30.0));
+        assert!(!world.resource::<BakedStaticColliders>().contains(foot));
+        // Only the wall's bake, the moving parts don't dirty it every tick
+        assert!(!world.resource::<BakedStaticColliders>().dirty);
+
+        // Once the bar clears the corner the whole L carries on
+        for _ in 0..10 {
+            schedule.run(&mut world);
+        }
+        assert_eq!(position(&world), Vector2::new(180.0, 40.0));
+        // The parts follow through the hierarchy
+        schedule.run(&mut world);
+        assert_eq!(
+            world.get::<GlobalTransform>(foot).unwrap().position,
+            Vector2::new(180.0, 80.0)
+        );
+    }
+
+    #[test]
+    fn test_drag_keeps_grab_offset_in_parent_space() {
+        let entity = Entity::PLACEHOLDER;
+        // Grabbed 10 right and 5 below its origin
+        let drag = Drag::grab(entity, Vector2::new(100.0, 50.0), Vector2::new(110.0, 55.0));
+        assert_eq!(drag.offset, Vector2::new(-10.0, -5.0));
+        let target = drag.target(Vector2::new(210.0, 155.0));
+        assert_eq!(target, Vector2::new(200.0, 150.0));
+
+        // Roots take the world position as is, children one relative to their parent
+        assert_eq!(world_to_local(target, None), target);
+        let parent = GlobalTransform {
+            position: Vector2::new(150.0, 100.0),
+            rotation: 90.0,
+            scale: Vector2::new(2.0, 2.0),
+        };
+        let local = world_to_local(target, Some(&parent));
+        assert_eq!(local, Vector2::new(50.0, 50.0));
+        // Propagating it back lands on the cursor target
+        let child = Transform::default().with_position(local);
+        assert_eq!(
+            GlobalTransform::from_local(&parent, &child).position,
+            target
+        );
+    }
+
+    #[test]
+    fn test_archetype_stats() {
+        let mut world = World::new();
+        world.init_resource::<Metrics>();
+        for _ in 0..3 {
+            world.spawn((Velocity::default(), Player));
+        }
+        world.spawn(Velocity::default());
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(archetype_stats_system);
+        schedule.run(&mut world);
+
+        let velocity = world.component_id::<Velocity>().unwrap();
+        // Required by Velocity, so it's in both archetypes
+        let sleep_timer = world.component_id::<SleepTimer>().unwrap();
+        let player = world.component_id::<Player>().unwrap();
+        let find = |world: &World, ids: &[ComponentId]| {
+            world
+                .resource::<Metrics>()
+                .archetype_stats
+                .iter()
+                .find(|stat| {
+                    stat.components.len() == ids.len()
+                        && ids.iter().all(|id| stat.components.contains(id))
+                })
+                .cloned()
+                .unwrap()
+        };
+        let players = find(&world, &[velocity, sleep_timer, player]);
+        assert_eq!(players.entity_count, 3);
+        // Player is a marker and takes no space
+        let row_bytes = std::mem::size_of::<Velocity>() + std::mem::size_of::<SleepTimer>();
+        assert_eq!(players.row_bytes, row_bytes);
+        assert_eq!(players.estimated_bytes(), 3 * row_bytes);
+        assert_eq!(find(&world, &[velocity, sleep_timer]).entity_count, 1);
+
+        let top = top_archetypes(&world.resource::<Metrics>().archetype_stats, 1);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].id, players.id);
+
+        // Known archetypes keep their row and only update the count
+        let archetypes = world.resource::<Metrics>().archetype_stats.len();
+        world.spawn((Velocity::default(), Player));
+        schedule.run(&mut world);
+        assert_eq!(
+            world.resource::<Metrics>().archetype_stats.len(),
+            archetypes
+        );
+        assert_eq!(
+            find(&world, &[velocity, sleep_timer, player]).entity_count,
+            4
+        );
+    }
+
+    #[test]
+    fn test_lights_need_redraw() {
+        let mut world = World::new();
+        world.init_resource::<Lighting>();
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<RenderRecovery>();
+        world.init_resource::<RunCount>();
+        let light = PointLight {
+            color: Color::WHITE,
+            radius: 50.0,
+            intensity: 1.0,
+        };
+        let lamp = world.spawn((light, GlobalTransform::default())).id();
+        let crate_box = world.spawn(GlobalTransform::default()).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(count_runs.run_if(lights_need_redraw));
+
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+
+        // Only lights moving matters
+        world
+            .get_mut::<GlobalTransform>(crate_box)
+            .unwrap()
+            .position
+            .x += 5.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+        world.get_mut::<GlobalTransform>(lamp).unwrap().position.x += 5.0;
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 2);
+
+        // Removing a light redraws once
+        world.entity_mut(lamp).remove::<PointLight>();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 3);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 3);
+
+        world.resource_mut::<Lighting>().ambient = Color::new(10, 10, 10, 255);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 4);
+    }
+
+    #[test]
+    fn test_layers_need_redraw() {
+        let mut world = World::new();
+        world.insert_resource(DebugSettings {
+            origins: false,
+            colliders: false,
+            visualize_culling: false,
+            show_system_counts: false,
+            contacts: false,
+            show_fps_graph: false,
+            inspector: false,
+            hide_cursor: false,
+            drag: false,
+        });
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<TextureAssets>();
+        world.init_resource::<RenderRecovery>();
+        world.init_resource::<InactiveLayers>();
+        world.init_resource::<LayerSettings>();
+        world.init_resource::<LayerSortMode>();
+        world.init_resource::<RunCount>();
+        let sprite = |world: &mut World| {
+            world
+                .spawn((
+                    SpriteBundle::new(Sprite::default(), Transform::default()),
+                    OnScreen,
+                ))
+                .id()
+        };
+        let (gone, culled, moved) = (sprite(&mut world), sprite(&mut world), sprite(&mut world));
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(count_runs.run_if(layers_need_redraw));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 1);
+
+        // Each way of leaving the layers redraws once
+        world.despawn(gone);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 2);
+        world.entity_mut(culled).remove::<OnScreen>();
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 3);
+        world.entity_mut(moved).remove::<Layer>();
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 4);
+
+        // Resizing or rescaling a layer leaves its new texture blank until redrawn
+        world.resource_mut::<WindowSize>().0 = Vector2i { x: 200, y: 100 };
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 5);
+        world.resource_mut::<LayerSettings>().0.insert(
+            0,
+            LayerConfig {
+                render_scale: 0.5,
+                ..Default::default()
+            },
+        );
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 6);
+
+        // So does anything reordering the sprites
+        world.entity_mut(culled).insert(RenderOrder(3));
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 7);
+        *world.resource_mut::<LayerSortMode>() = LayerSortMode::Manual;
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<RunCount>().0, 8);
+    }
+
+    #[test]
+    fn test_screen_space_sprites_skip_culling() {
+        let mut world = World::new();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.init_resource::<LayerSettings>();
+        world.init_resource::<Metrics>();
+        let spawn = |world: &mut World, layer: u32| {
+            world
+                .spawn(SpriteBundle {
+                    layer: Layer(layer),
+                    ..SpriteBundle::new(Sprite::default(), Transform::default())
+                })
+                .id()
+        };
+        let world_sprite = spawn(&mut world, 0);
+        let hud = spawn(&mut world, HUD_LAYER);
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems((classify_screen_space_system, update_render_hash_system).chain());
+        let indexed = |world: &World, e: Entity| world.resource::<RenderHash>().contains(e);
+
+        schedule.run(&mut world);
+        assert!(world.get::<ScreenSpace>(hud).is_some());
+        assert!(world.get::<ScreenSpace>(world_sprite).is_none());
+        assert!(!indexed(&world, hud));
+        assert!(indexed(&world, world_sprite));
+
+        // Moving off the HUD layer puts it back in the hash straight away
+        world.get_mut::<Layer>(hud).unwrap().0 = 1;
+        schedule.run(&mut world);
+        assert!(world.get::<ScreenSpace>(hud).is_none());
+        assert!(indexed(&world, hud));
+
+        // Flagging a layer reclassifies sprites that never changed
+        world.resource_mut::<LayerSettings>().0.insert(
+            0,
+            LayerConfig {
+                screen_space: true,
+                ..Default::default()
+            },
+        );
+        schedule.run(&mut world);
+        assert!(world.get::<ScreenSpace>(world_sprite).is_some());
+        assert!(!indexed(&world, world_sprite));
+
+        // A screen-space sprite moving doesn't get indexed again
+        world
+            .get_mut::<GlobalTransform>(world_sprite)
+            .unwrap()
+            .position
+            .x += 10.0;
+        schedule.run(&mut world);
+        assert!(!indexed(&world, world_sprite));
+    }
+
+    #[test]
+    fn test_non_finite_transforms_are_quarantined() {
+        let mut world = World::new();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(Messages::<EnteredView>::default());
+        world.insert_resource(Messages::<LeftView>::default());
+        world.init_resource::<Metrics>();
+        world.spawn((
+            Camera(Camera2D {
+                offset: Vector2::new(50.0, 50.0),
+                zoom: 1.0,
+                ..Default::default()
+            }),
+            ActiveCamera,
+        ));
+        let fine = world
+            .spawn(SpriteBundle::new(Sprite::default(), Transform::default()))
+            .id();
+        let broken = world
+            .spawn(SpriteBundle::new(
+                Sprite::default(),
+                Transform::default().with_position(Vector2::new(f32::NAN, 0.0)),
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                quarantine_non_finite_system,
+                update_render_hash_system,
+                compute_camera_view_system,
+                update_on_screen_system,
+            )
+                .chain(),
+        );
+
+        // The frame completes, the NaN sprite just stays out of the index
+        schedule.run(&mut world);
+        assert!(world.get::<Quarantined>(broken).is_some());
+        assert!(world.get::<Quarantined>(fine).is_none());
+        assert!(!world.resource::<RenderHash>().contains(broken));
+        assert!(world.get::<OnScreen>(fine).is_some());
+
+        // Still broken, nothing new happens
+        world.get_mut::<GlobalTransform>(broken).unwrap().position.y = f32::INFINITY;
+        schedule.run(&mut world);
+        assert!(world.get::<Quarantined>(broken).is_some());
+
+        world.get_mut::<GlobalTransform>(broken).unwrap().position = Vector2::new(0.0, 0.0);
+        schedule.run(&mut world);
+        assert!(world.get::<Quarantined>(broken).is_none());
+        assert!(world.resource::<RenderHash>().contains(broken));
+    }
+
+    #[test]
+    fn test_camera_follows_interpolated_target() {
+        let mut world = World::new();
+        world.insert_resource(Time::new(64.0));
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<CameraFraming>();
+        let camera = world
+            .spawn((
+                Camera(Camera2D {
+                    zoom: 1.0,
+                    ..Default::default()
+                }),
+                CameraInterpolation::default(),
+                ActiveCamera,
+            ))
+            .id();
+        let target = world.spawn((Transform::default(), CameraTarget)).id();
+
+        let mut physics = Schedule::default();
+        physics.add_systems(record_camera_target_system);
+        let mut render = Schedule::default();
+        render.add_systems(camera_interpolation_system);
+
+        physics.run(&mut world);
+        world.get_mut::<Transform>(target).unwrap().position = Vector2::new(8.0, -4.0);
+        physics.run(&mut world);
+
+        for (alpha, expected) in [(0.25, (2.0, -1.0)), (0.75, (6.0, -3.0))] {
+            world.insert_resource(PhysicsAlpha(alpha));
+            render.run(&mut world);
+            let target = world.get::<Camera>(camera).unwrap().target;
+            assert_eq!((target.x, target.y), expected);
+        }
+
+        // Zoom isn't interpolated
+        assert_eq!(world.get::<Camera>(camera).unwrap().zoom, 1.0);
+    }
+
+    #[test]
+    fn test_hidden_wall_is_not_drawn_but_still_blocks() {
+        let mut world = physics_world();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.insert_resource(WindowSize(Vector2i { x: 200, y: 200 }));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(Messages::<EnteredView>::default());
+        world.insert_resource(Messages::<LeftView>::default());
+        world.spawn((
+            Camera(Camera2D {
+                offset: Vector2::new(100.0, 100.0),
+                zoom: 1.0,
+                ..Default::default()
+            }),
+            ActiveCamera,
+        ));
+
+        let wall = world
+            .spawn((
+                SpriteBundle::new(
+                    Sprite {
+                        origin: SpriteOrigin::TopLeft,
+                        ..Default::default()
+                    },
+                    Transform::default().with_position(Vector2::new(40.0, 0.0)),
+                ),
+                Collider::default(),
+            ))
+            .id();
+        world.commands().entity(wall).hide();
+        world.flush();
+        let mover = world
+            .spawn((
+                Transform::default(),
+                GlobalTransform::default(),
+                Collider::default(),
+                Velocity(Vector2::new(1280.0, 0.0)),
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_collision_hash_system,
+                update_render_hash_system,
+                compute_camera_view_system,
+                update_on_screen_system,
+                apply_velocity_system,
+                update_global_transforms_system,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+
+        // Still indexed and solid, but never handed to the layer draw lists
+        assert!(world.resource::<RenderHash>().contains(wall));
+        assert!(world.get::<OnScreen>(wall).is_none());
+        assert_eq!(world.get::<Transform>(mover).unwrap().position.x, 8.0);
+
+        world.commands().entity(wall).show();
+        world.flush();
+        schedule.run(&mut world);
+        assert!(world.get::<OnScreen>(wall).is_some());
+        assert!(Visibility::is_shown(world.get::<Visibility>(wall)));
+    }
+
+    #[test]
+    fn test_hierarchies_straddling_the_view_cull_together() {
+        let setup = |inherit_from_root: bool| {
+            let mut world = World::new();
+            world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+            world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+            world.init_resource::<CameraViewRect>();
+            world.insert_resource(Messages::<EnteredView>::default());
+            world.insert_resource(Messages::<LeftView>::default());
+            world.insert_resource(CullingSettings { inherit_from_root });
+            world.init_resource::<Metrics>();
+            world.spawn((
+                Camera(Camera2D {
+                    offset: Vector2::new(50.0, 50.0),
+                    zoom: 1.0,
+                    ..Default::default()
+                }),
+                ActiveCamera,
+            ));
+            let sprite = || Sprite {
+                origin: SpriteOrigin::TopLeft,
+                ..Default::default()
+            };
+            // The view ends at x = 100, the parent crosses it and the child is past it
+            let parent = world
+                .spawn(SpriteBundle::new(
+                    sprite(),
+                    Transform::default().with_position(Vector2::new(90.0, 0.0)),
+                ))
+                .id();
+            let child = world
+                .spawn((
+                    SpriteBundle::new(
+                        sprite(),
+                        Transform::default().with_position(Vector2::new(40.0, 0.0)),
+                    ),
+                    ChildOf(parent),
+                ))
+                .id();
+            let mut schedule = Schedule::default();
+            schedule.add_systems(
+                (
+                    update_global_transforms_system,
+                    update_render_hash_system,
+                    update_subtree_bounds_system,
+                    compute_camera_view_system,
+                    update_on_screen_system,
+                )
+                    .chain(),
+            );
+            schedule.run(&mut world);
+            (world, schedule, parent, child)
+        };
+        let on_screen = |world: &World, parent: Entity, child: Entity| {
+            (
+                world.get::<OnScreen>(parent).is_some(),
+                world.get::<OnScreen>(child).is_some(),
+            )
+        };
+
+        let (world, _, parent, child) = setup(false);
+        assert_eq!(on_screen(&world, parent, child), (true, false));
+
+        let (mut world, mut schedule, parent, child) = setup(true);
+        assert_eq!(on_screen(&world, parent, child), (true, true));
+        // Only the child in view keeps the parent too
+        world.get_mut::<Transform>(parent).unwrap().position.x = -150.0;
+        schedule.run(&mut world);
+        assert_eq!(on_screen(&world, parent, child), (true, true));
+        // And both leave together
+        world.get_mut::<Transform>(parent).unwrap().position.x = 150.0;
+        schedule.run(&mut world);
+        assert_eq!(on_screen(&world, parent, child), (false, false));
+    }
+
+    #[test]
+    fn test_sprite_just_outside_view_is_not_on_screen() {
+        let mut world = World::new();
+        world.insert_resource(RenderHash(SpatialHash::flat(96.0)));
+        world.insert_resource(WindowSize(Vector2i { x: 100, y: 100 }));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(Messages::<EnteredView>::default());
+        world.insert_resource(Messages::<LeftView>::default());
+        world.init_resource::<Metrics>();
+        world.spawn((
+            Camera(Camera2D {
+                offset: Vector2::new(50.0, 50.0),
+                zoom: 1.0,
+                ..Default::default()
+            }),
+            ActiveCamera,
+        ));
+        // The culling rect starts at x = -100, both boxes share its leftmost cell
+        let spawn_box = |world: &mut World, x: f32| {
+            world
+                .spawn(SpriteBundle::new(
+                    Sprite {
+                        origin: SpriteOrigin::TopLeft,
+                        ..Default::default()
+                    },
+                    Transform::default().with_position(Vector2::new(x, 0.0)),
+                ))
+                .id()
+        };
+        let outside = spawn_box(&mut world, -140.0);
+        let straddling = spawn_box(&mut world, -120.0);
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_render_hash_system,
+                compute_camera_view_system,
+                update_on_screen_system,
+            )
+                .chain(),
+        );
+        schedule.run(&mut world);
+
+        // The hash alone would report it
+        let view_rect = *world.resource::<CameraViewRect>();
+        let view_rect = view_rect.0;
+        assert!(
+            world
+                .resource::<RenderHash>()
+                .query(view_rect)
+                .contains(&outside)
+        );
+        assert!(world.get::<OnScreen>(outside).is_none());
+        assert!(world.get::<OnScreen>(straddling).is_some());
+    }
+
+    #[test]
+    fn test_trigger_camera_transition_moves_the_target() {
+        let mut world = World::new();
+        world.spawn((
+            Camera(Camera2D {
+                target: Vector2::new(5.0, 5.0),
+                zoom: 1.0,
+                ..Default::default()
+            }),
+            ActiveCamera,
+        ));
+        let old = world.spawn((Transform::default(), CameraTarget)).id();
+        let new = world.spawn(Transform::default()).id();
+
+        trigger_camera_transition(&mut world, new, 2.0);
+        assert!(world.get::<CameraTarget>(old).is_none());
+        assert!(world.get::<CameraTarget>(new).is_some());
+        let transition = world.resource::<CameraTransition>();
+        assert_eq!((transition.from.x, transition.from.y), (5.0, 5.0));
+        assert_eq!(
+            (transition.to, transition.elapsed, transition.duration),
+            (new, 0.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn test_batched_on_screen_changes_match_per_entity_commands() {
+        use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+        let mut rng = SmallRng::seed_from_u64(7);
+        let mut batched = World::new();
+        let mut per_entity = World::new();
+        let mut entities = Vec::new();
+        for i in 0..500 {
+            let on_screen = rng.gen_bool(0.5);
+            let a = batched.spawn_empty().id();
+            let b = per_entity.spawn_empty().id();
+            assert_eq!(a, b);
+            // Spread over a few archetypes, so the removals are regrouped
+            if i % 3 != 0 {
+                batched.entity_mut(a).insert(Layer(i % 3));
+                per_entity.entity_mut(b).insert(Layer(i % 3));
+            }
+            if i % 5 == 0 {
+                batched.entity_mut(a).insert(Velocity::default());
+                per_entity.entity_mut(b).insert(Velocity::default());
+            }
+            if on_screen {
+                batched.entity_mut(a).insert(OnScreen);
+                per_entity.entity_mut(b).insert(OnScreen);
+            }
+            entities.push((a, on_screen));
+        }
+        let (mut entered, mut left) = (Vec::new(), Vec::new());
+        for &(entity, on_screen) in &entities {
+            if rng.gen_bool(0.3) {
+                if on_screen {
+                    left.push(entity);
+                } else {
+                    entered.push(entity);
+                }
+            }
+        }
+        // Some are despawned before the changes are applied
+        for &(entity, _) in entities.iter().step_by(37) {
+            batched.despawn(entity);
+            per_entity.despawn(entity);
+        }
+
+        apply_on_screen_changes(&mut batched, entered.clone(), left.clone());
+        let mut commands = per_entity.commands();
+        for &entity in &entered {
+            commands.entity(entity).try_insert(OnScreen);
+        }
+        for &entity in &left {
+            commands.entity(entity).try_remove::<OnScreen>();
+        }
+        per_entity.flush();
+
+        // Moving entities between archetypes mustn't mix up the rest of their components
+        let state = |world: &World, entity| {
+            world.get_entity(entity).ok().map(|e| {
+                (
+                    e.contains::<OnScreen>(),
+                    e.get::<Layer>().copied(),
+                    e.contains::<Velocity>(),
+                )
+            })
+        };
+        for &(entity, _) in &entities {
+            assert_eq!(state(&batched, entity), state(&per_entity, entity));
+        }
+    }
+
+    #[test]
+    fn test_clamp_velocity_only_touches_capped_movers() {
+        let mut world = World::new();
+        let fast = world
+            .spawn((Velocity(Vector2::new(3000.0, 4000.0)), MaxSpeed(500.0)))
+            .id();
+        let still = world
+            .spawn((Velocity(Vector2::new(0.0, 0.0)), MaxSpeed(500.0)))
+            .id();
+        let uncapped = world.spawn(Velocity(Vector2::new(3000.0, 4000.0))).id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(clamp_velocity_system);
+        schedule.run(&mut world);
+
+        let velocity = |entity| {
+            let v = world.get::<Velocity>(entity).unwrap().0;
+            (v.x, v.y)
+        };
+        assert_eq!(velocity(fast), (300.0, 400.0));
+        assert_eq!(velocity(still), (0.0, 0.0));
+        assert_eq!(velocity(uncapped), (3000.0, 4000.0));
+    }
+
+    #[test]
+    fn test_static_transforms_are_skipped_until_moved() {
+        let mut world = World::new();
+        world.insert_resource(StaticTransformSettings { after_frames: 2 });
+        let at = |x| Transform::default().with_position(Vector2::new(x, 0.0));
+        let still = world.spawn((at(10.0), GlobalTransform::default())).id();
+        let parent = world.spawn((at(20.0), GlobalTransform::default())).id();
+        world.spawn((at(1.0), GlobalTransform::default(), ChildOf(parent)));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                update_global_transforms_system,
+                mark_static_transforms_system.run_if(static_transform_check_due),
+            )
+                .chain(),
+        );
+        let global_x =
+            |world: &World, entity| world.get::<GlobalTransform>(entity).unwrap().position.x;
+
+        // Everything counts as changed on the first check, so it takes a second one
+        for _ in 0..2 {
+            schedule.run(&mut world);
+        }
+        assert!(world.get::<StaticTransform>(still).is_none());
+        for _ in 0..2 {
+            schedule.run(&mut world);
+        }
+        assert!(world.get::<StaticTransform>(still).is_some());
+        assert!(world.get::<StaticTransform>(parent).is_none());
+
+        // Skipped while untouched, even if its global transform went stale
+        world.get_mut::<GlobalTransform>(still).unwrap().position.x = 0.0;
+        schedule.run(&mut world);
+        assert_eq!(global_x(&world, still), 0.0);
+
+        // Moving it is picked up straight away, and the next check demotes it
+        world.get_mut::<Transform>(still).unwrap().position.x = 30.0;
+        schedule.run(&mut world);
+        assert_eq!(global_x(&world, still), 30.0);
+        assert!(world.get::<StaticTransform>(still).is_none());
+    }
+
+    #[test]
+    fn test_camera_view_follows_camera_and_window() {
+        let mut world = World::new();
+        world.insert_resource(WindowSize(Vector2i { x: 200, y: 100 }));
+        world.init_resource::<CameraViewRect>();
+        world.insert_resource(CameraViewMargin(0.0));
+        let camera = world
+            .spawn((
+                Camera(Camera2D {
+                    target: Vector2::new(1000.0, 0.0),
+                    offset: Vector2::new(100.0, 50.0),
+                    zoom: 0.5,
+                    ..Default::default()
+                }),
+                ActiveCamera,
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(compute_camera_view_system);
+        let mut view = |world: &mut World| {
+            schedule.run(world);
+            let view = world.resource::<CameraViewRect>().0;
+            (view.x, view.y, view.width, view.height)
+        };
+
+        assert_eq!(view(&mut world), (800.0, -100.0, 400.0, 200.0));
+        // Resized, `update_camera_offset` recenters the offset
+        world.insert_resource(WindowSize(Vector2i { x: 400, y: 300 }));
+        world.get_mut::<Camera>(camera).unwrap().offset = Vector2::new(200.0, 150.0);
+        assert_eq!(view(&mut world), (600.0, -300.0, 800.0, 600.0));
+        // Zoomed in past 1
+        world.get_mut::<Camera>(camera).unwrap().zoom = 4.0;
+        assert_eq!(view(&mut world), (950.0, -37.5, 100.0, 75.0));
+        // Without a margin resource it's grown like the culling rect always was
+        world.remove_resource::<CameraViewMargin>();
+        assert_eq!(view(&mut world), (900.0, -75.0, 200.0, 150.0));
+    }
+
+    #[test]
+    fn test_entity_at_picks_the_topmost_sprite() {
+        let mut world = World::new();
+        let mut hash = SpatialHash::flat(96.0);
+        let rect = |x, y, size| Rectangle {
+            x,
+            y,
+            width: size,
+            height: size,
+        };
+        let mut sprite = |layer, y, order| {
+            let transform = Transform::default().with_position(Vector2::new(0.0, y));
+            world
+                .spawn((
+                    Layer(layer),
+                    GlobalTransform::from_root(&transform),
+                    RenderOrder(order),
+                ))
+                .id()
+        };
+        // Spawned first, so the lowest id of its layer
+        let front = sprite(1, 70.0, 0);
+        let background = sprite(0, 0.0, 0);
+        let back = sprite(1, 40.0, 1);
+        let high = sprite(2, 50.0, 0);
+        // Same y as `front`, RenderOrder puts it behind
+        let tied = sprite(1, 70.0, -1);
+        hash.insert(background, rect(0.0, 0.0, 200.0));
+        hash.insert(high, rect(50.0, 50.0, 10.0));
+        hash.insert(back, rect(40.0, 40.0, 40.0));
+        hash.insert(front, rect(70.0, 70.0, 30.0));
+        hash.insert(tied, rect(90.0, 90.0, 30.0));
+        let mut sprites = world.query::<DrawKeyData>();
+        let sprites = sprites.query(&world);
+        let at = |mode, x, y| {
+            entity_at(&hash, Vector2::new(x, y), mode, |entity| {
+                draw_key(&sprites, entity)
+            })
+        };
+
+        let y_sort = LayerSortMode::YSort;
+        assert_eq!(at(y_sort, 55.0, 55.0), Some(high));
+        assert_eq!(at(y_sort, 45.0, 45.0), Some(back));
+        // Same layer, lower on screen is drawn on top whatever the spawn order
+        assert_eq!(at(y_sort, 75.0, 75.0), Some(front));
+        assert_eq!(at(y_sort, 95.0, 95.0), Some(front));
+        // Manual only goes by RenderOrder
+        assert_eq!(at(LayerSortMode::Manual, 75.0, 75.0), Some(back));
+        assert_eq!(at(LayerSortMode::Manual, 95.0, 95.0), Some(front));
+        // Same cell as the others, but only the background covers it
+        assert_eq!(at(y_sort, 150.0, 20.0), Some(background));
+        assert_eq!(at(y_sort, -10.0, 5.0), None);
+    }
+
+    #[test]
+    fn test_inspector_lists_components_and_forgets_despawned() {
+        let mut world = World::new();
+        world.insert_resource(DebugSettings {
+            origins: false,
+            colliders: false,
+            visualize_culling: false,
+            show_system_counts: false,
+            contacts: false,
+            show_fps_graph: false,
+            inspector: true,
+            hide_cursor: false,
+            drag: false,
+        });
+        world.init_resource::<InspectorPanel>();
+        let entity = world
+            .spawn((
+                Transform::default().with_position(Vector2::new(1.5, -2.0)),
+                Velocity(Vector2::new(3.0, 0.0)),
+                Collider::default(),
+                Layer(4),
+                EntityName("crate".to_owned()),
+            ))
+            .id();
+        world.insert_resource(SelectedEntity(Some(entity)));
+        let mut schedule = Schedule::default();
+        schedule.add_systems(inspect_selected_entity_system);
+        schedule.run(&mut world);
+
+        let rows = world.resource::<InspectorPanel>().0.clone();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[0], format!("Entity crate ({entity})"));
+        assert_eq!(rows[1], "Transform: (1.5, -2.0) rot 0.0 scale (1.0, 1.0)");
+        assert_eq!(rows[2], "Velocity: (3.0, 0.0)");
+        assert_eq!(rows[4], "Layer: 4");
+
+        // Off, the panel empties but the selection survives
+        world.resource_mut::<DebugSettings>().inspector = false;
+        schedule.run(&mut world);
+        assert!(world.resource::<InspectorPanel>().0.is_empty());
+        assert_eq!(world.resource::<SelectedEntity>().0, Some(entity));
+
+        world.resource_mut::<DebugSettings>().inspector = true;
+        world.despawn(entity);
+        schedule.run(&mut world);
+        assert!(world.resource::<InspectorPanel>().0.is_empty());
+        assert_eq!(world.resource::<SelectedEntity>().0, None);
+    }
+
+    #[test]
+    fn test_resize_keeps_visible_world_width() {
+        let mut world = World::new();
+        world.insert_resource(Messages::<ResizeEvent>::default());
+        world.insert_resource(ReferenceResolution(Vector2i { x: 1024, y: 768 }));
+        world.insert_resource(CameraScalingMode::KeepWidth);
+        let camera = world
+            .spawn((
+                Camera(Camera2D {
+                    offset: Vector2::new(512.0, 384.0),
+                    zoom: 1.5,
+                    ..Default::default()
+                }),
+                CameraZoom {
+                    target: 1.5,
+                    ..Default::default()
+                },
+            ))
+            .id();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(update_camera_offset);
+        let mut resize = |world: &mut World, from: (i32, i32), to: (i32, i32)| {
+            world.write_message(ResizeEvent {
+                from: Vector2i {
+                    x: from.0,
+                    y: from.1,
+                },
+                to: Vector2i { x: to.0, y: to.1 },
+            });
+            schedule.run(world);
+            let camera = world.get::<Camera>(camera).unwrap();
+            let view = camera_viewport_rect(camera, Vector2i { x: to.0, y: to.1 });
+            (view.width, camera.offset.x, camera.offset.y)
+        };
+
+        // 1024 / 1.5 world units across, before and after doubling the width
+        let width = 1024.0 / 1.5;
+        assert_eq!(
+            resize(&mut world, (1024, 768), (2048, 768)),
+            (width, 1024.0, 384.0)
+        );
+        assert_eq!(world.get::<Camera>(camera).unwrap().zoom, 3.0);
+        assert_eq!(world.get::<CameraZoom>(camera).unwrap().target, 3.0);
+        // A minimized window doesn't touch the zoom, restoring it still lands on the same view
+        assert_eq!(resize(&mut world, (2048, 768), (0, 0)).0, 0.0);
+        assert_eq!(world.get::<Camera>(camera).unwrap().zoom, 3.0);
+        assert_eq!(resize(&mut world, (0, 0), (2048, 768)).0, width);
 
-        spatial_hash.update(entity, rect);
+        // Stretch leaves the zoom alone, so the same pixels show twice the world
+        world.insert_resource(CameraScalingMode::Stretch);
+        assert_eq!(resize(&mut world, (2048, 768), (4096, 768)).0, width * 2.0);
     }
 }
@@ -0,0 +1,64 @@
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use bevy_ecs::prelude::*;
+use rustyray::prelude::*;
+
+/// One texture cut into a grid of equally sized tiles, which every `SpriteKind::Tile` draws
+/// from, so a whole tilemap shares one GPU texture.
+#[derive(Resource, Clone)]
+pub struct TilesetResource {
+    pub texture: Arc<OwnedTexture>,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Tiles per row, ids run left to right then top to bottom.
+    pub columns: u32,
+}
+
+impl TilesetResource {
+    /// Fits as many columns as the texture is wide.
+    pub fn new(texture: OwnedTexture, tile_width: u32, tile_height: u32) -> Self {
+        let columns = (texture.size().x as u32 / tile_width.max(1)).max(1);
+        Self {
+            texture: Arc::new(texture),
+            tile_width,
+            tile_height,
+            columns,
+        }
+    }
+
+    pub fn source_rect_for_tile(&self, tile_id: u32) -> Rectangle {
+        tile_source_rect(tile_id, (self.tile_width, self.tile_height), self.columns)
+    }
+}
+
+/// Where `tile_id` sits in a texture of `tile_size` tiles, `columns` to a row.
+pub fn tile_source_rect(tile_id: u32, tile_size: (u32, u32), columns: u32) -> Rectangle {
+    let columns = columns.max(1);
+    Rectangle {
+        x: ((tile_id % columns) * tile_size.0) as f32,
+        y: ((tile_id / columns) * tile_size.1) as f32,
+        width: tile_size.0 as f32,
+        height: tile_size.1 as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_source_rects() {
+        let rect = |id| {
+            let rect = tile_source_rect(id, (16, 16), 8);
+            (rect.x, rect.y, rect.width, rect.height)
+        };
+        assert_eq!(rect(0), (0.0, 0.0, 16.0, 16.0));
+        assert_eq!(rect(1), (16.0, 0.0, 16.0, 16.0));
+        // Last of the first row, then wrapping to the second
+        assert_eq!(rect(7), (112.0, 0.0, 16.0, 16.0));
+        assert_eq!(rect(8), (0.0, 16.0, 16.0, 16.0));
+        assert_eq!(rect(19), (48.0, 32.0, 16.0, 16.0));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Seconds of samples the rolling statistics cover.
+pub const TIMING_WINDOW: f32 = 5.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingSummary {
+    pub min: Duration,
+    pub avg: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct TimingStats {
+    /// `(elapsed, duration)` of the samples still in the window, oldest first.
+    samples: VecDeque<(f32, Duration)>,
+    /// Longest sample ever recorded, and the tick it was recorded on.
+    pub all_time_max: Option<(Duration, u64)>,
+}
+
+impl TimingStats {
+    fn push(&mut self, sample: (f32, Duration), tick: u64) {
+        if self.all_time_max.is_none_or(|(max, _)| sample.1 > max) {
+            self.all_time_max = Some((sample.1, tick));
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn expire(&mut self, before: f32) {
+        while self.samples.front().is_some_and(|&(at, _)| at < before) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Statistics over the samples in the window, `None` once they've all expired.
+    pub fn summary(&self) -> Option<TimingSummary> {
+        let mut sorted = self.samples.iter().map(|&(_, d)| d).collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let total: Duration = sorted.iter().sum();
+        // Nearest rank
+        let rank = (sorted.len() as f32 * 0.95).ceil() as usize;
+        Some(TimingSummary {
+            min,
+            avg: total / sorted.len() as u32,
+            p95: sorted[rank.saturating_sub(1)],
+            max,
+        })
+    }
+}
+
+/// Rolling statistics per named timing. A tick is one `advance`, which `Metrics` does once
+/// per frame.
+#[derive(Debug)]
+pub struct Timings {
+    /// Seconds a sample counts towards the summaries.
+    pub window: f32,
+    elapsed: f32,
+    tick: u64,
+    stats: BTreeMap<&'static str, TimingStats>,
+}
+
+impl Default for Timings {
+    fn default() -> Self {
+        Self {
+            window: TIMING_WINDOW,
+            elapsed: 0.0,
+            tick: 0,
+            stats: BTreeMap::new(),
+        }
+    }
+}
+
+impl Timings {
+    /// Moves the clock on and drops the samples that fell out of the window.
+    pub fn advance(&mut self, dt: f32) {
+        self.elapsed += dt;
+        self.tick += 1;
+        let before = self.elapsed - self.window;
+        self.stats
+            .values_mut()
+            .for_each(|stats| stats.expire(before));
+    }
+
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        let tick = self.tick;
+        let sample = (self.elapsed, duration);
+        self.stats.entry(name).or_default().push(sample, tick);
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TimingStats> {
+        self.stats.get(name)
+    }
+
+    /// By name.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &TimingStats)> {
+        self.stats.iter().map(|(&name, stats)| (name, stats))
+    }
+}
+
+/// Shared, so a scope can record while its system keeps writing the rest of `Metrics`.
+#[derive(Debug, Clone, Default)]
+pub struct TimingsHandle(Arc<Mutex<Timings>>);
+
+impl TimingsHandle {
+    pub fn lock(&self) -> MutexGuard<'_, Timings> {
+        // A panic mid-record leaves nothing half written worth refusing
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Records the time from creation until it's dropped, see `metric_scope!`.
+pub struct MetricScope {
+    timings: TimingsHandle,
+    name: &'static str,
+    start: Instant,
+}
+
+impl MetricScope {
+    pub fn new(timings: &TimingsHandle, name: &'static str) -> Self {
+        Self {
+            timings: timings.clone(),
+            name,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for MetricScope {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        self.timings.lock().record(self.name, elapsed);
+    }
+}
+
+/// `metric_scope!(metrics, "apply_velocity")` times the rest of the enclosing block into
+/// `Metrics::timings`. Works without the `trace` feature.
+macro_rules! metric_scope {
+    ($metrics:expr, $name:expr) => {
+        let _metric_scope = $crate::timings::MetricScope::new(&$metrics.timings, $name);
+    };
+}
+pub(crate) use metric_scope;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rolling_stats_over_the_window() {
+        let mut timings = Timings {
+            window: 1.0,
+            ..Default::default()
+        };
+        let ms = Duration::from_millis;
+        // Four ticks a second, one sample each: 1..=20 ms, with a spike of 50 ms on tick 3
+        for i in 1..=20 {
+            let sample = if i == 3 { 50 } else { i };
+            timings.record("physics", ms(sample));
+            timings.advance(0.25);
+        }
+        let stats = timings.get("physics").unwrap();
+        // Samples recorded at 4.0 (17 ms) through 4.75 (20 ms) are inside the last second
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.min, ms(17));
+        assert_eq!(summary.max, ms(20));
+        assert_eq!(summary.avg, Duration::from_micros(18_500));
+        assert_eq!(summary.p95, ms(20));
+        // The spike fell out of the window, not out of the all-time max
+        assert_eq!(stats.all_time_max, Some((ms(50), 2)));
+        assert_eq!(timings.tick(), 20);
+
+        // p95 by nearest rank: 19 of 20 samples sit at or below it
+        let mut timings = Timings {
+            window: 100.0,
+            ..Default::default()
+        };
+        for i in 1..=20 {
+            timings.record("render", ms(i));
+        }
+        let summary = timings.get("render").unwrap().summary().unwrap();
+        assert_eq!(summary.p95, ms(19));
+        assert_eq!(summary.avg, Duration::from_micros(10_500));
+
+        // Nothing in the window, only the all-time max is left
+        timings.advance(200.0);
+        let stats = timings.get("render").unwrap();
+        assert!(stats.summary().is_none());
+        assert_eq!(stats.all_time_max, Some((ms(20), 0)));
+    }
+
+    #[test]
+    fn test_metric_scope_records_on_drop() {
+        struct Holder {
+            timings: TimingsHandle,
+        }
+        let holder = Holder {
+            timings: TimingsHandle::default(),
+        };
+        {
+            metric_scope!(holder, "scope");
+            assert!(holder.timings.lock().get("scope").is_none());
+        }
+        let timings = holder.timings.lock();
+        assert_eq!(
+            timings.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            ["scope"]
+        );
+        assert!(timings.get("scope").unwrap().summary().is_some());
+    }
+}
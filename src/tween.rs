@@ -0,0 +1,216 @@
+#![allow(dead_code)]
+
+use bevy_ecs::component::Mutable;
+use bevy_ecs::prelude::*;
+
+use crate::resources::Time;
+
+/// Builds the field accessor for a `Tween`, e.g. `tween_field!(Transform, position.x)`.
+#[allow(unused_macros)]
+macro_rules! tween_field {
+    ($ty:ty, $($field:ident).+) => {
+        (|target: &mut $ty| -> &mut f32 { &mut target.$($field).+ }) as fn(&mut $ty) -> &mut f32
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use tween_field;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EasingFn {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOutCubic,
+}
+
+impl EasingFn {
+    /// Maps progress in `0..=1` to eased progress, both ends are fixed.
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            EasingFn::Linear => t,
+            EasingFn::EaseIn => t * t * t,
+            EasingFn::EaseOut => 1.0 - (1.0 - t).powi(3),
+            EasingFn::EaseInOutCubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// What happens once `elapsed` reaches `duration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TweenAction {
+    /// Leave the field at `to` and drop the tween.
+    #[default]
+    Remove,
+    Loop,
+    /// Swap `from` and `to` and play it back.
+    PingPong,
+}
+
+/// Animates one `f32` inside `T`, picked by `target_field` since there's no reflection.
+#[derive(Component)]
+pub struct Tween<T: Component> {
+    pub target_field: fn(&mut T) -> &mut f32,
+    pub from: f32,
+    pub to: f32,
+    pub duration: f32,
+    pub elapsed: f32,
+    pub easing: EasingFn,
+    pub on_complete: TweenAction,
+}
+
+impl<T: Component> Tween<T> {
+    pub fn new(target_field: fn(&mut T) -> &mut f32, from: f32, to: f32, duration: f32) -> Self {
+        Self {
+            target_field,
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+            easing: EasingFn::default(),
+            on_complete: TweenAction::default(),
+        }
+    }
+
+    pub fn with_easing(mut self, easing: EasingFn) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    pub fn with_on_complete(mut self, on_complete: TweenAction) -> Self {
+        self.on_complete = on_complete;
+        self
+    }
+
+    /// Steps the tween by `dt` and returns the value to write, plus whether it's finished
+    /// and should be removed.
+    pub fn advance(&mut self, dt: f32) -> (f32, bool) {
+        self.elapsed += dt;
+        if self.duration <= 0.0 || self.elapsed >= self.duration {
+            match self.on_complete {
+                TweenAction::Remove => {
+                    self.elapsed = self.duration.max(0.0);
+                    return (self.to, true);
+                }
+                TweenAction::Loop => {
+                    self.elapsed = self.wrapped_elapsed();
+                }
+                TweenAction::PingPong => {
+                    self.elapsed = self.wrapped_elapsed();
+                    std::mem::swap(&mut self.from, &mut self.to);
+                }
+            }
+        }
+
+        (self.value(), false)
+    }
+
+    fn wrapped_elapsed(&self) -> f32 {
+        if self.duration > 0.0 {
+            self.elapsed % self.duration
+        } else {
+            0.0
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        let t = if self.duration > 0.0 {
+            self.elapsed / self.duration
+        } else {
+            1.0
+        };
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+}
+
+/// Add once per animated component type, e.g. `tween_system::<Transform>`.
+pub fn tween_system<T: Component<Mutability = Mutable>>(
+    time: Res<Time>,
+    mut tweens: Query<(Entity, &mut T, &mut Tween<T>)>,
+    mut commands: Commands,
+) {
+    for (entity, mut target, mut tween) in tweens.iter_mut() {
+        let (value, finished) = tween.advance(time.delta());
+        *(tween.target_field)(&mut target) = value;
+        if finished {
+            commands.entity(entity).remove::<Tween<T>>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Transform;
+
+    #[test]
+    fn test_easing_endpoints() {
+        for easing in [
+            EasingFn::Linear,
+            EasingFn::EaseIn,
+            EasingFn::EaseOut,
+            EasingFn::EaseInOutCubic,
+        ] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+        }
+        assert_eq!(EasingFn::EaseInOutCubic.apply(0.5), 0.5);
+        assert!(EasingFn::EaseIn.apply(0.25) < 0.25);
+        assert!(EasingFn::EaseOut.apply(0.25) > 0.25);
+    }
+
+    #[test]
+    fn test_tween_actions() {
+        let mut tween =
+            Tween::<Transform>::new(tween_field!(Transform, position.x), 0.0, 10.0, 1.0);
+        assert_eq!(tween.advance(0.5), (5.0, false));
+        assert_eq!(tween.advance(0.75), (10.0, true));
+
+        let mut looping =
+            Tween::<Transform>::new(tween_field!(Transform, rotation), 0.0, 10.0, 1.0)
+                .with_on_complete(TweenAction::Loop);
+        looping.advance(1.25);
+        assert_eq!(looping.value(), 2.5);
+
+        let mut ping_pong =
+            Tween::<Transform>::new(tween_field!(Transform, scale.y), 0.0, 10.0, 1.0)
+                .with_on_complete(TweenAction::PingPong);
+        assert_eq!(ping_pong.advance(1.25), (7.5, false));
+        assert_eq!(ping_pong.advance(0.5), (2.5, false));
+    }
+
+    #[test]
+    fn test_tween_system_writes_field_and_removes() {
+        let mut world = World::new();
+        world.insert_resource(Time {
+            delta: 0.25,
+            accumulator: 0.0,
+        });
+        let entity = world
+            .spawn((
+                Transform::default(),
+                Tween::<Transform>::new(tween_field!(Transform, position.y), 100.0, 200.0, 0.5)
+                    .with_easing(EasingFn::EaseInOutCubic),
+            ))
+            .id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(tween_system::<Transform>);
+
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Transform>(entity).unwrap().position.y, 150.0);
+        assert_eq!(world.get::<Transform>(entity).unwrap().position.x, 0.0);
+        assert!(world.get::<Tween<Transform>>(entity).is_some());
+
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Transform>(entity).unwrap().position.y, 200.0);
+        assert!(world.get::<Tween<Transform>>(entity).is_none());
+    }
+}
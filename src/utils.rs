@@ -0,0 +1,41 @@
+//! Hash collections used across the crate. Keys are small integers and entities,
+//! so the Fx hasher beats the default SipHash and nothing here needs DoS resistance.
+
+pub type HashMap<K, V> = rustc_hash::FxHashMap<K, V>;
+pub type HashSet<T> = rustc_hash::FxHashSet<T>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `cargo test --release bench_cell_lookup -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_cell_lookup_fx_vs_std() {
+        const SIDE: i32 = 500;
+        const ROUNDS: usize = 20;
+        let cells = || (0..SIDE).flat_map(|x| (0..SIDE).map(move |y| (x, y)));
+        let fx: HashMap<(i32, i32), Vec<u32>> = cells().map(|cell| (cell, vec![0])).collect();
+        let sip: std::collections::HashMap<(i32, i32), Vec<u32>> =
+            cells().map(|cell| (cell, vec![0])).collect();
+
+        // Same lookups the spatial hash does per query, hits and misses alike
+        let start = std::time::Instant::now();
+        let mut found = 0;
+        for _ in 0..ROUNDS {
+            found += cells()
+                .filter(|cell| fx.contains_key(&(cell.0 - 1, cell.1)))
+                .count();
+        }
+        println!("fx: {:?}, {found} hits", start.elapsed());
+
+        let start = std::time::Instant::now();
+        let mut found = 0;
+        for _ in 0..ROUNDS {
+            found += cells()
+                .filter(|cell| sip.contains_key(&(cell.0 - 1, cell.1)))
+                .count();
+        }
+        println!("sip: {:?}, {found} hits", start.elapsed());
+    }
+}